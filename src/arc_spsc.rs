@@ -0,0 +1,185 @@
+use std::sync::Arc;
+
+use crate::role;
+use crate::spsc;
+
+// Channel for `Arc<T>` payloads that keeps the RT side from dropping the
+// last reference. The receiver holds on to the most recent `Arc` it got,
+// and when the next one replaces it the old one goes back to the control
+// side instead of being dropped, so any free happens in `collect` (or the
+// next `send`) on the control thread.
+//
+// Clones taken on the RT side must be dropped before the receiver moves
+// on; once the receiver has handed its reference back, such a clone may be
+// the last one.
+pub struct ArcSender<T> {
+    to_rt: spsc::Sender<Arc<T>>,
+    from_rt: spsc::Receiver<Arc<T>>,
+    in_flight: usize,
+    slots: usize,
+}
+
+pub struct ArcReceiver<T> {
+    from_ctrl: spsc::Receiver<Arc<T>>,
+    to_ctrl: spsc::Sender<Arc<T>>,
+    current: Option<Arc<T>>,
+}
+
+impl<T> ArcSender<T> {
+    // Gives the value back if the queue is full or the receiver has been
+    // dropped.
+    pub fn send(&mut self, value: Arc<T>) -> Result<(), Arc<T>> {
+        role::assert_not_rt("ArcSender::send");
+        self.collect();
+        if self.in_flight == self.slots {
+            return Err(value);
+        }
+
+        self.to_rt.try_send(value).map_err(|e| e.into_inner())?;
+        self.in_flight += 1;
+        Ok(())
+    }
+
+    // Drops the references the receiver has handed back. Returns how many
+    // there were.
+    pub fn collect(&mut self) -> usize {
+        role::assert_not_rt("ArcSender::collect");
+        let mut collected = 0;
+        while self.from_rt.try_recv().is_ok() {
+            collected += 1;
+        }
+        self.in_flight -= collected;
+        collected
+    }
+
+    // References sent and not yet collected, including the one the
+    // receiver currently holds.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+    }
+
+    pub fn is_receiver_active(&self) -> bool {
+        self.to_rt.is_receiver_active()
+    }
+}
+
+impl<T> ArcReceiver<T> {
+    // Moves on to the next queued value, handing the current one back.
+    pub fn try_recv(&mut self) -> Option<&Arc<T>> {
+        let next = self.from_ctrl.try_recv().ok()?;
+        self.release(next);
+        self.current.as_ref()
+    }
+
+    // Skips to the most recently sent value, handing back everything
+    // before it.
+    pub fn recv_latest(&mut self) -> Option<&Arc<T>> {
+        while let Ok(next) = self.from_ctrl.try_recv() {
+            self.release(next);
+        }
+        self.current.as_ref()
+    }
+
+    pub fn current(&self) -> Option<&Arc<T>> {
+        self.current.as_ref()
+    }
+
+    pub fn size(&self) -> usize {
+        self.from_ctrl.size()
+    }
+
+    pub fn is_sender_active(&self) -> bool {
+        self.from_ctrl.is_sender_active()
+    }
+
+    fn release(&mut self, next: Arc<T>) {
+        // The return queue has a slot for every reference the sender lets
+        // out, so this only fails once the sender is gone, in which case
+        // the reference is dropped here during teardown.
+        if let Some(old) = self.current.replace(next) {
+            let _ = self.to_ctrl.try_send(old);
+        }
+    }
+}
+
+impl<T> Drop for ArcReceiver<T> {
+    fn drop(&mut self) {
+        if let Some(current) = self.current.take() {
+            let _ = self.to_ctrl.try_send(current);
+        }
+    }
+}
+
+// `size` is the number of values that can be queued; the receiver's current
+// value counts against it until it's handed back and collected.
+pub fn arc_channel<T>(size: usize) -> (ArcSender<T>, ArcReceiver<T>) {
+    let (to_rt, from_ctrl) = spsc::channel(size);
+    let (to_ctrl, from_rt) = spsc::channel(size + 1);
+
+    (
+        ArcSender {
+            to_rt,
+            from_rt,
+            in_flight: 0,
+            slots: size + 1,
+        },
+        ArcReceiver {
+            from_ctrl,
+            to_ctrl,
+            current: None,
+        },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn last_reference_dropped_on_control_side() {
+        let (mut send, mut recv) = arc_channel(2);
+        let first = Arc::new(1);
+        send.send(first.clone()).unwrap();
+        send.send(Arc::new(2)).unwrap();
+
+        assert_eq!(**recv.try_recv().unwrap(), 1);
+        drop(first);
+        assert_eq!(**recv.try_recv().unwrap(), 2);
+
+        // The first value is waiting for the control side, not freed.
+        assert_eq!(send.in_flight(), 2);
+        assert_eq!(send.collect(), 1);
+        assert_eq!(send.in_flight(), 1);
+        assert_eq!(recv.current().map(|v| **v), Some(2));
+    }
+
+    #[test]
+    fn latest_and_full() {
+        let (mut send, mut recv) = arc_channel(2);
+        send.send(Arc::new(0)).unwrap();
+        send.send(Arc::new(1)).unwrap();
+        assert_eq!(send.send(Arc::new(2)), Err(Arc::new(2)));
+
+        assert_eq!(recv.recv_latest().map(|v| **v), Some(1));
+        assert_eq!(send.collect(), 1);
+
+        // The receiver's current value still counts.
+        send.send(Arc::new(2)).unwrap();
+        send.send(Arc::new(3)).unwrap();
+        assert_eq!(send.in_flight(), 3);
+        assert_eq!(send.send(Arc::new(4)), Err(Arc::new(4)));
+    }
+
+    #[test]
+    fn receiver_drop_hands_back() {
+        let (mut send, mut recv) = arc_channel(1);
+        let value = Arc::new(());
+        send.send(value.clone()).unwrap();
+        recv.try_recv().unwrap();
+        drop(recv);
+
+        assert_eq!(Arc::strong_count(&value), 2);
+        assert_eq!(send.collect(), 1);
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+}
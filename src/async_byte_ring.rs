@@ -0,0 +1,274 @@
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures_io::{AsyncRead, AsyncWrite};
+
+use crate::async_spsc::Shared;
+use crate::byte_ring::{self, ByteReader, ByteWriter};
+
+// `AsyncRead`/`AsyncWrite` ends for the byte ring, for bridging async
+// network tasks and an RT byte stream. Same arrangement as `async_spsc`: the
+// RT end stays wait-free and wakes the async end after making progress.
+
+// RT -> async.
+pub fn from_rt(capacity: usize) -> (WakingByteWriter, AsyncByteReader) {
+    let (writer, reader) = byte_ring::byte_ring(capacity);
+    let shared = Arc::new(Shared::new());
+
+    (
+        WakingByteWriter {
+            writer,
+            shared: shared.clone(),
+        },
+        AsyncByteReader { reader, shared },
+    )
+}
+
+// async -> RT.
+pub fn to_rt(capacity: usize) -> (AsyncByteWriter, WakingByteReader) {
+    let (writer, reader) = byte_ring::byte_ring(capacity);
+    let shared = Arc::new(Shared::new());
+
+    (
+        AsyncByteWriter {
+            writer,
+            shared: shared.clone(),
+        },
+        WakingByteReader { reader, shared },
+    )
+}
+
+pub struct WakingByteWriter {
+    writer: ByteWriter,
+    shared: Arc<Shared>,
+}
+
+impl WakingByteWriter {
+    pub fn write(&mut self, data: &[u8]) -> usize {
+        let written = self.writer.write(data);
+        if written > 0 {
+            self.shared.waker.wake();
+        }
+        written
+    }
+
+    pub fn write_slice(&mut self) -> &mut [u8] {
+        self.writer.write_slice()
+    }
+
+    pub fn commit(&mut self, len: usize) {
+        self.writer.commit(len);
+        self.shared.waker.wake();
+    }
+
+    pub fn available_write(&self) -> usize {
+        self.writer.available_write()
+    }
+}
+
+impl Drop for WakingByteWriter {
+    fn drop(&mut self) {
+        self.shared.close();
+    }
+}
+
+pub struct AsyncByteReader {
+    reader: ByteReader,
+    shared: Arc<Shared>,
+}
+
+impl AsyncRead for AsyncByteReader {
+    // Returns 0 once the RT end is dropped and everything has been read.
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        let read = this.reader.read(buf);
+        if read > 0 {
+            return Poll::Ready(Ok(read));
+        }
+
+        this.shared.waker.register(cx.waker());
+
+        let closed = this.shared.is_closed();
+        match this.reader.read(buf) {
+            0 if !closed => Poll::Pending,
+            read => Poll::Ready(Ok(read)),
+        }
+    }
+}
+
+pub struct AsyncByteWriter {
+    writer: ByteWriter,
+    shared: Arc<Shared>,
+}
+
+impl AsyncWrite for AsyncByteWriter {
+    // Fails with `BrokenPipe` once the RT end is dropped.
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        for attempt in 0..2 {
+            if this.shared.is_closed() {
+                return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+            }
+
+            let written = this.writer.write(buf);
+            if written > 0 {
+                return Poll::Ready(Ok(written));
+            }
+
+            if attempt == 0 {
+                this.shared.waker.register(cx.waker());
+            }
+        }
+
+        Poll::Pending
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+pub struct WakingByteReader {
+    reader: ByteReader,
+    shared: Arc<Shared>,
+}
+
+impl WakingByteReader {
+    pub fn read(&mut self, out: &mut [u8]) -> usize {
+        let read = self.reader.read(out);
+        if read > 0 {
+            self.shared.waker.wake();
+        }
+        read
+    }
+
+    pub fn read_slice(&self) -> &[u8] {
+        self.reader.read_slice()
+    }
+
+    pub fn consume(&mut self, len: usize) {
+        self.reader.consume(len);
+        self.shared.waker.wake();
+    }
+
+    pub fn available_read(&self) -> usize {
+        self.reader.available_read()
+    }
+}
+
+impl Drop for WakingByteReader {
+    fn drop(&mut self) {
+        self.shared.close();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::future::{poll_fn, Future};
+    use std::task::{Wake, Waker};
+    use std::thread::{self, Thread};
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            thread::park();
+        }
+    }
+
+    #[test]
+    fn read_from_rt() {
+        let (mut writer, mut reader) = from_rt(8);
+
+        let rt = thread::spawn(move || {
+            let data: Vec<u8> = (0..100).collect();
+            let mut sent = 0;
+            while sent < data.len() {
+                sent += writer.write(&data[sent..]);
+                thread::yield_now();
+            }
+        });
+
+        let received = block_on(async {
+            let mut received = Vec::new();
+            let mut buf = [0; 16];
+            loop {
+                let n = poll_fn(|cx| Pin::new(&mut reader).poll_read(cx, &mut buf))
+                    .await
+                    .unwrap();
+                if n == 0 {
+                    break;
+                }
+                received.extend_from_slice(&buf[..n]);
+            }
+            received
+        });
+
+        rt.join().unwrap();
+        assert_eq!(received, (0..100).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn write_to_rt() {
+        let (mut writer, mut reader) = to_rt(8);
+
+        let rt = thread::spawn(move || {
+            let mut received = Vec::new();
+            let mut buf = [0; 16];
+            while received.len() < 100 {
+                let n = reader.read(&mut buf);
+                received.extend_from_slice(&buf[..n]);
+                thread::yield_now();
+            }
+            received
+        });
+
+        block_on(async {
+            let data: Vec<u8> = (0..100).collect();
+            let mut sent = 0;
+            while sent < data.len() {
+                sent += poll_fn(|cx| Pin::new(&mut writer).poll_write(cx, &data[sent..]))
+                    .await
+                    .unwrap();
+            }
+        });
+
+        assert_eq!(rt.join().unwrap(), (0..100).collect::<Vec<u8>>());
+
+        let err = block_on(poll_fn(|cx| Pin::new(&mut writer).poll_write(cx, &[1]))).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+    }
+}
@@ -0,0 +1,512 @@
+use std::cell::UnsafeCell;
+use std::future::Future;
+#[cfg(feature = "tokio")]
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+
+use futures_core::Stream;
+use futures_sink::Sink;
+
+use crate::spsc::{self, TryRecvError, TrySendError};
+
+#[cfg(feature = "tokio")]
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+// Bridges between an RT thread and an async control plane. The async end
+// registers its waker in a slot shared with the RT end; the RT end keeps
+// using the wait-free `try_*` calls and only takes the waker out of the
+// slot and wakes it after making progress. It never parks or waits for
+// the async side. Whatever the executor does inside `Waker::wake` does run
+// on the RT thread though, so prefer executors with a cheap wake.
+
+// RT -> async: `AsyncReceiver` implements `Stream`.
+pub fn from_rt<T>(size: usize) -> (WakingSender<T>, AsyncReceiver<T>) {
+    let (sender, receiver) = spsc::channel(size);
+    let shared = Arc::new(Shared::new());
+
+    (
+        WakingSender {
+            sender,
+            shared: shared.clone(),
+        },
+        AsyncReceiver { receiver, shared },
+    )
+}
+
+// async -> RT: `AsyncSender` implements `Sink`.
+pub fn to_rt<T>(size: usize) -> (AsyncSender<T>, WakingReceiver<T>) {
+    let (sender, receiver) = spsc::channel(size);
+    let shared = Arc::new(Shared::new());
+
+    (
+        AsyncSender {
+            sender,
+            shared: shared.clone(),
+        },
+        WakingReceiver { receiver, shared },
+    )
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Closed;
+
+pub(crate) struct Shared {
+    pub(crate) waker: AtomicWaker,
+    // Set by the RT end when it's dropped.
+    closed: AtomicBool,
+}
+
+impl Shared {
+    pub(crate) fn new() -> Self {
+        Shared {
+            waker: AtomicWaker::new(),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    pub(crate) fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        self.waker.wake();
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+}
+
+pub struct WakingSender<T> {
+    sender: spsc::Sender<T>,
+    shared: Arc<Shared>,
+}
+
+impl<T> WakingSender<T> {
+    pub fn try_send(&mut self, value: T) -> Result<(), TrySendError<T>> {
+        self.sender.try_send(value)?;
+        self.shared.waker.wake();
+        Ok(())
+    }
+
+    // Sends as many values from `data` as fit, waking the async end if any
+    // did. Returns the number sent.
+    pub fn write_slice(&mut self, data: &[T]) -> usize
+    where
+        T: Copy,
+    {
+        let written = self.sender.write_slice(data);
+        if written > 0 {
+            self.shared.waker.wake();
+        }
+        written
+    }
+
+    pub fn size(&self) -> usize {
+        self.sender.size()
+    }
+}
+
+impl<T> Drop for WakingSender<T> {
+    fn drop(&mut self) {
+        self.shared.close();
+    }
+}
+
+pub struct AsyncReceiver<T> {
+    receiver: spsc::Receiver<T>,
+    shared: Arc<Shared>,
+}
+
+impl<T> AsyncReceiver<T> {
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        self.receiver.try_recv()
+    }
+
+    // Resolves to `None` once the RT end is dropped and the queue drained.
+    pub fn recv(&mut self) -> impl Future<Output = Option<T>> + '_ {
+        std::future::poll_fn(move |cx| self.poll_recv(cx))
+    }
+
+    pub fn poll_recv(&mut self, cx: &mut Context) -> Poll<Option<T>> {
+        if let Ok(value) = self.receiver.try_recv() {
+            return Poll::Ready(Some(value));
+        }
+
+        self.shared.waker.register(cx.waker());
+
+        // Read the flag before the queue so that a value sent right before
+        // the sender was dropped isn't lost.
+        let closed = self.shared.is_closed();
+        match self.receiver.try_recv() {
+            Ok(value) => Poll::Ready(Some(value)),
+            Err(TryRecvError::Empty) if !closed => Poll::Pending,
+            Err(_) => Poll::Ready(None),
+        }
+    }
+}
+
+impl<T> Stream for AsyncReceiver<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<T>> {
+        self.get_mut().poll_recv(cx)
+    }
+}
+
+pub struct AsyncSender<T> {
+    sender: spsc::Sender<T>,
+    shared: Arc<Shared>,
+}
+
+impl<T> AsyncSender<T> {
+    pub fn try_send(&mut self, value: T) -> Result<(), TrySendError<T>> {
+        self.sender.try_send(value)
+    }
+
+    // Waits for space. Gives the value back if the RT end has been dropped.
+    pub async fn send(&mut self, value: T) -> Result<(), T> {
+        match std::future::poll_fn(|cx| self.poll_ready(cx)).await {
+            Ok(()) => self
+                .sender
+                .try_send(value)
+                .map_err(TrySendError::into_inner),
+            Err(Closed) => Err(value),
+        }
+    }
+
+    pub fn poll_ready(&mut self, cx: &mut Context) -> Poll<Result<(), Closed>> {
+        if self.shared.is_closed() {
+            return Poll::Ready(Err(Closed));
+        }
+        if self.sender.size() > 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        self.shared.waker.register(cx.waker());
+
+        if self.shared.is_closed() {
+            Poll::Ready(Err(Closed))
+        } else if self.sender.size() > 0 {
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<T> Sink<T> for AsyncSender<T> {
+    type Error = Closed;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Closed>> {
+        self.get_mut().poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Closed> {
+        self.get_mut().sender.try_send(item).map_err(|_| Closed)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Result<(), Closed>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Result<(), Closed>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+pub struct WakingReceiver<T> {
+    receiver: spsc::Receiver<T>,
+    shared: Arc<Shared>,
+}
+
+impl<T> WakingReceiver<T> {
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let value = self.receiver.try_recv()?;
+        self.shared.waker.wake();
+        Ok(value)
+    }
+
+    pub fn read_slice(&mut self, out: &mut [T]) -> usize
+    where
+        T: Copy,
+    {
+        let read = self.receiver.read_slice(out);
+        if read > 0 {
+            self.shared.waker.wake();
+        }
+        read
+    }
+
+    pub fn size(&self) -> usize {
+        self.receiver.size()
+    }
+}
+
+impl<T> Drop for WakingReceiver<T> {
+    fn drop(&mut self) {
+        self.shared.close();
+    }
+}
+
+// tokio's `AsyncRead`/`AsyncWrite` on the async ends of byte channels, so
+// an RT capture thread can feed a tokio network task directly. The RT ends
+// move bytes with `write_slice`/`read_slice`.
+#[cfg(feature = "tokio")]
+impl AsyncRead for AsyncReceiver<u8> {
+    // Reads nothing once the RT end is dropped and everything has been read.
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut ReadBuf,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        let read = this.receiver.read_slice(buf.initialize_unfilled());
+        if read > 0 {
+            buf.advance(read);
+            return Poll::Ready(Ok(()));
+        }
+
+        this.shared.waker.register(cx.waker());
+
+        let closed = this.shared.is_closed();
+        match this.receiver.read_slice(buf.initialize_unfilled()) {
+            0 if !closed => Poll::Pending,
+            read => {
+                buf.advance(read);
+                Poll::Ready(Ok(()))
+            }
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncWrite for AsyncSender<u8> {
+    // Fails with `BrokenPipe` once the RT end is dropped.
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        for attempt in 0..2 {
+            if this.shared.is_closed() {
+                return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+            }
+
+            let written = this.sender.write_slice(buf);
+            if written > 0 {
+                return Poll::Ready(Ok(written));
+            }
+
+            if attempt == 0 {
+                this.shared.waker.register(cx.waker());
+            }
+        }
+
+        Poll::Pending
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+// Single waker slot, same protocol as the one in futures-util: `register`
+// and `wake` race through a small state machine instead of a lock, so
+// `wake` never blocks.
+const WAITING: usize = 0;
+const REGISTERING: usize = 1;
+const WAKING: usize = 2;
+
+pub(crate) struct AtomicWaker {
+    state: AtomicUsize,
+    waker: UnsafeCell<Option<Waker>>,
+}
+
+unsafe impl Send for AtomicWaker {}
+unsafe impl Sync for AtomicWaker {}
+
+impl AtomicWaker {
+    fn new() -> Self {
+        AtomicWaker {
+            state: AtomicUsize::new(WAITING),
+            waker: UnsafeCell::new(None),
+        }
+    }
+
+    pub(crate) fn register(&self, waker: &Waker) {
+        match self
+            .state
+            .compare_exchange(WAITING, REGISTERING, Ordering::Acquire, Ordering::Acquire)
+            .unwrap_or_else(|state| state)
+        {
+            WAITING => {
+                let slot = unsafe { &mut *self.waker.get() };
+                if !slot.as_ref().is_some_and(|w| w.will_wake(waker)) {
+                    *slot = Some(waker.clone());
+                }
+
+                if self
+                    .state
+                    .compare_exchange(REGISTERING, WAITING, Ordering::AcqRel, Ordering::Acquire)
+                    .is_err()
+                {
+                    // A wake came in while registering.
+                    let waker = slot.take();
+                    self.state.swap(WAITING, Ordering::AcqRel);
+                    if let Some(waker) = waker {
+                        waker.wake();
+                    }
+                }
+            }
+            WAKING => waker.wake_by_ref(),
+            _ => {}
+        }
+    }
+
+    pub(crate) fn wake(&self) {
+        if self.state.fetch_or(WAKING, Ordering::AcqRel) == WAITING {
+            let waker = unsafe { (*self.waker.get()).take() };
+            self.state.fetch_and(!WAKING, Ordering::Release);
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::task::Wake;
+    use std::thread::{self, Thread};
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            thread::park();
+        }
+    }
+
+    #[test]
+    fn stream_from_rt() {
+        let (mut send, mut recv) = from_rt(4);
+
+        let rt = thread::spawn(move || {
+            for i in 0..100 {
+                while send.try_send(i).is_err() {
+                    thread::yield_now();
+                }
+            }
+        });
+
+        let received = block_on(async {
+            let mut received = Vec::new();
+            while let Some(value) = recv.recv().await {
+                received.push(value);
+            }
+            received
+        });
+
+        rt.join().unwrap();
+        assert_eq!(received, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn send_to_rt() {
+        let (mut send, mut recv) = to_rt(2);
+
+        let rt = thread::spawn(move || {
+            let mut received = Vec::new();
+            while received.len() < 100 {
+                match recv.try_recv() {
+                    Ok(value) => received.push(value),
+                    Err(_) => thread::yield_now(),
+                }
+            }
+            received
+        });
+
+        block_on(async {
+            for i in 0..100 {
+                send.send(i).await.unwrap();
+            }
+        });
+
+        assert_eq!(rt.join().unwrap(), (0..100).collect::<Vec<_>>());
+        assert_eq!(block_on(send.send(100)), Err(100));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn tokio_bytes() {
+        use std::future::poll_fn;
+
+        let (mut send, mut recv) = from_rt::<u8>(8);
+        let rt = thread::spawn(move || {
+            let data: Vec<u8> = (0..100).collect();
+            let mut sent = 0;
+            while sent < data.len() {
+                sent += send.write_slice(&data[sent..]);
+                thread::yield_now();
+            }
+        });
+
+        let received = block_on(async {
+            let mut received = Vec::new();
+            let mut storage = [0; 16];
+            loop {
+                let mut buf = ReadBuf::new(&mut storage);
+                poll_fn(|cx| Pin::new(&mut recv).poll_read(cx, &mut buf))
+                    .await
+                    .unwrap();
+                if buf.filled().is_empty() {
+                    break;
+                }
+                received.extend_from_slice(buf.filled());
+            }
+            received
+        });
+        rt.join().unwrap();
+        assert_eq!(received, (0..100).collect::<Vec<u8>>());
+
+        let (mut send, mut recv) = to_rt::<u8>(4);
+        block_on(async {
+            let written = poll_fn(|cx| Pin::new(&mut send).poll_write(cx, b"hello"))
+                .await
+                .unwrap();
+            assert_eq!(written, 4);
+        });
+        let mut out = [0; 8];
+        assert_eq!(recv.read_slice(&mut out), 4);
+        assert_eq!(&out[..4], b"hell");
+
+        drop(recv);
+        let err = block_on(poll_fn(|cx| Pin::new(&mut send).poll_write(cx, b"o"))).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+    }
+}
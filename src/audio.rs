@@ -0,0 +1,182 @@
+use std::mem::MaybeUninit;
+
+use crate::role;
+use crate::spsc;
+
+// Multichannel audio over an spsc ring, counted in frames (one sample per
+// channel) instead of samples. Samples are queued interleaved; either end
+// can be interleaved or planar, and only whole frames ever go in or come
+// out, so the two ends never disagree about where a frame starts.
+pub struct AudioWriter<S> {
+    sender: spsc::Sender<S>,
+    channels: usize,
+}
+
+pub struct AudioReader<S> {
+    receiver: spsc::Receiver<S>,
+    channels: usize,
+}
+
+impl<S: Copy> AudioWriter<S> {
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    // Frames that can be written right now.
+    pub fn writable_frames(&self) -> usize {
+        self.sender.size() / self.channels
+    }
+
+    // Queues as many whole frames of `samples` as fit. Returns the number
+    // of frames written.
+    pub fn write_interleaved(&mut self, samples: &[S]) -> usize {
+        assert_eq!(
+            samples.len() % self.channels,
+            0,
+            "Partial frame in interleaved samples"
+        );
+
+        let frames = (samples.len() / self.channels).min(self.writable_frames());
+        let written = self.sender.write_slice(&samples[..frames * self.channels]);
+        debug_assert_eq!(written, frames * self.channels);
+        frames
+    }
+
+    // Like `write_interleaved`, from one slice per channel. Frames past the
+    // end of the shortest slice are not written.
+    pub fn write_deinterleaved(&mut self, planes: &[&[S]]) -> usize {
+        assert_eq!(planes.len(), self.channels, "Channel count mismatch");
+
+        let shortest = planes.iter().map(|plane| plane.len()).min().unwrap_or(0);
+        let frames = shortest.min(self.writable_frames());
+        let channels = self.channels;
+
+        // The room was checked above and only this end writes, so this
+        // doesn't fail.
+        let mut chunk = match self.sender.write_chunk_uninit(frames * channels) {
+            Ok(chunk) => chunk,
+            Err(_) => return 0,
+        };
+        let (first, second) = chunk.as_mut_slices();
+        for (i, slot) in first.iter_mut().chain(second).enumerate() {
+            *slot = MaybeUninit::new(planes[i % channels][i / channels]);
+        }
+        unsafe { chunk.commit(frames * channels) };
+        frames
+    }
+}
+
+impl<S: Copy> AudioReader<S> {
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    // Whole frames queued.
+    pub fn readable_frames(&self) -> usize {
+        self.receiver.size() / self.channels
+    }
+
+    // Fills `samples` with as many whole frames as are queued and fit.
+    // Returns the number of frames read.
+    pub fn read_interleaved(&mut self, samples: &mut [S]) -> usize {
+        assert_eq!(
+            samples.len() % self.channels,
+            0,
+            "Partial frame in interleaved samples"
+        );
+
+        let frames = (samples.len() / self.channels).min(self.readable_frames());
+        let read = self
+            .receiver
+            .read_slice(&mut samples[..frames * self.channels]);
+        debug_assert_eq!(read, frames * self.channels);
+        frames
+    }
+
+    // Like `read_interleaved`, into one slice per channel. Reads at most as
+    // many frames as the shortest slice holds.
+    pub fn read_deinterleaved(&mut self, planes: &mut [&mut [S]]) -> usize {
+        assert_eq!(planes.len(), self.channels, "Channel count mismatch");
+
+        let shortest = planes.iter().map(|plane| plane.len()).min().unwrap_or(0);
+        let frames = shortest.min(self.readable_frames());
+        let channels = self.channels;
+
+        let chunk = match self.receiver.read_chunk(frames * channels) {
+            Ok(chunk) => chunk,
+            Err(_) => return 0,
+        };
+        let (first, second) = chunk.as_slices();
+        for (i, &sample) in first.iter().chain(second).enumerate() {
+            planes[i % channels][i / channels] = sample;
+        }
+        frames
+    }
+}
+
+// A ring holding at least `frames` frames of `channels` samples each.
+pub fn audio_ring<S>(channels: usize, frames: usize) -> (AudioWriter<S>, AudioReader<S>) {
+    role::assert_not_rt("audio_ring");
+    assert!(channels > 0, "Channel count must be positive");
+
+    let (sender, receiver) = spsc::channel(channels * frames);
+    (
+        AudioWriter { sender, channels },
+        AudioReader { receiver, channels },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn interleaved() {
+        let (mut writer, mut reader) = audio_ring::<f32>(2, 4);
+        assert_eq!(writer.writable_frames(), 4);
+
+        assert_eq!(
+            writer.write_interleaved(&[1.0, -1.0, 2.0, -2.0, 3.0, -3.0]),
+            3
+        );
+        assert_eq!(writer.write_interleaved(&[4.0, -4.0, 5.0, -5.0]), 1);
+        assert_eq!(reader.readable_frames(), 4);
+
+        let mut out = [0.0; 6];
+        assert_eq!(reader.read_interleaved(&mut out), 3);
+        assert_eq!(out, [1.0, -1.0, 2.0, -2.0, 3.0, -3.0]);
+        assert_eq!(reader.readable_frames(), 1);
+    }
+
+    #[test]
+    fn planar() {
+        let (mut writer, mut reader) = audio_ring::<f64>(3, 4);
+        let mut out = [0.0; 12];
+
+        // Move the counters so the next frames wrap around the end of the
+        // 16 slots.
+        assert_eq!(writer.write_interleaved(&[0.0; 12]), 4);
+        assert_eq!(reader.read_interleaved(&mut out), 4);
+
+        let planes: [&[f64]; 3] = [&[1.0, 2.0, 3.0], &[10.0, 20.0, 30.0], &[100.0, 200.0]];
+        assert_eq!(writer.write_deinterleaved(&planes), 2);
+
+        let (mut a, mut b, mut c) = ([0.0; 4], [0.0; 4], [0.0; 4]);
+        assert_eq!(
+            reader.read_deinterleaved(&mut [&mut a[..], &mut b[..], &mut c[..]]),
+            2
+        );
+        assert_eq!(
+            (&a[..2], &b[..2], &c[..2]),
+            (&[1.0, 2.0][..], &[10.0, 20.0][..], &[100.0, 200.0][..])
+        );
+        assert_eq!(reader.readable_frames(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Partial frame")]
+    fn partial_frame() {
+        let (mut writer, _reader) = audio_ring::<f32>(2, 4);
+        writer.write_interleaved(&[1.0, 2.0, 3.0]);
+    }
+}
@@ -0,0 +1,284 @@
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::mem::MaybeUninit;
+use std::ptr;
+use std::sync::atomic::{fence, AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::role;
+
+// Single-producer, multi-consumer channel where every receiver sees every
+// message, for fanning control events out to several RT threads. Storage is
+// a fixed ring of `Copy` values; the sender never waits for receivers and
+// overwrites the oldest message when the ring is full. Each slot is guarded
+// like a `SeqLock`, stamped with the number of the message it holds, so a
+// receiver that falls more than a ring's length behind notices that its
+// next message was overwritten, skips to the oldest one still stored and
+// reports how many it lost.
+//
+// The capacity is rounded up to a power of two.
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+    position: usize,
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    position: usize,
+    overruns: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecvError {
+    Empty,
+    // The receiver fell behind and this many messages were overwritten
+    // before it got to them. It has moved on to the oldest stored message.
+    Lagged(u64),
+    Disconnected,
+}
+
+struct Slot<T> {
+    // Twice the number of the message in the slot plus two once written,
+    // plus one while being written.
+    stamp: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+struct Shared<T> {
+    slots: Box<[Slot<T>]>,
+    written: AtomicUsize,
+    sender_alive: AtomicBool,
+}
+
+unsafe impl<T: Copy + Send> Sync for Shared<T> {}
+unsafe impl<T: Copy + Send> Send for Shared<T> {}
+
+impl<T: Copy> Sender<T> {
+    pub fn send(&mut self, value: T) {
+        let shared = &*self.shared;
+        let slot = &shared.slots[self.position & (shared.slots.len() - 1)];
+        let stamp = self.position.wrapping_mul(2);
+
+        slot.stamp.store(stamp.wrapping_add(1), Ordering::Relaxed);
+        fence(Ordering::Release);
+        unsafe { ptr::write_volatile(slot.value.get(), MaybeUninit::new(value)) };
+        slot.stamp.store(stamp.wrapping_add(2), Ordering::Release);
+
+        self.position = self.position.wrapping_add(1);
+        shared.written.store(self.position, Ordering::Release);
+    }
+
+    // A receiver that starts with the next message sent.
+    pub fn subscribe(&self) -> Receiver<T> {
+        role::assert_not_rt("broadcast::Sender::subscribe");
+        Receiver {
+            shared: self.shared.clone(),
+            position: self.position,
+            overruns: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.slots.len()
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.shared.sender_alive.store(false, Ordering::Release);
+    }
+}
+
+impl<T: Copy> Receiver<T> {
+    pub fn try_recv(&mut self) -> Result<T, RecvError> {
+        let shared = &*self.shared;
+        let sender_alive = shared.sender_alive.load(Ordering::Acquire);
+        let slot = &shared.slots[self.position & (shared.slots.len() - 1)];
+        let expected = self.position.wrapping_mul(2).wrapping_add(2);
+
+        let before = slot.stamp.load(Ordering::Acquire);
+        if before == expected {
+            let value = unsafe { ptr::read_volatile(slot.value.get()) };
+            fence(Ordering::Acquire);
+            if slot.stamp.load(Ordering::Relaxed) == before {
+                self.position = self.position.wrapping_add(1);
+                return Ok(unsafe { value.assume_init() });
+            }
+        } else if (before.wrapping_sub(expected) as isize) < 0 {
+            // Not written yet, or still being written.
+            return Err(if sender_alive {
+                RecvError::Empty
+            } else {
+                RecvError::Disconnected
+            });
+        }
+
+        // Overwritten, possibly while copying.
+        Err(RecvError::Lagged(self.skip_to_oldest()))
+    }
+
+    // Messages sent that this receiver hasn't got to yet, including any
+    // that were already overwritten.
+    pub fn lag(&self) -> usize {
+        self.shared
+            .written
+            .load(Ordering::Acquire)
+            .wrapping_sub(self.position)
+    }
+
+    // Total messages this receiver has lost to overwriting.
+    pub fn overruns(&self) -> u64 {
+        self.overruns
+    }
+
+    pub fn is_sender_active(&self) -> bool {
+        self.shared.sender_alive.load(Ordering::Relaxed)
+    }
+
+    fn skip_to_oldest(&mut self) -> u64 {
+        // Leave a slot of headroom for the message the sender may be
+        // writing right now.
+        let written = self.shared.written.load(Ordering::Acquire);
+        let oldest = written.wrapping_sub(self.shared.slots.len() - 1);
+        let lost = oldest.wrapping_sub(self.position) as u64;
+
+        self.position = oldest;
+        self.overruns += lost;
+        lost
+    }
+}
+
+// Each clone keeps its own position and counts, starting from where the
+// original is.
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        role::assert_not_rt("broadcast::Receiver::clone");
+        Receiver {
+            shared: self.shared.clone(),
+            position: self.position,
+            overruns: self.overruns,
+        }
+    }
+}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecvError::Empty => f.write_str("no message available"),
+            RecvError::Lagged(lost) => write!(f, "receiver lagged behind, {} messages lost", lost),
+            RecvError::Disconnected => f.write_str("sender disconnected"),
+        }
+    }
+}
+
+impl std::error::Error for RecvError {}
+
+pub fn channel<T: Copy>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    role::assert_not_rt("broadcast::channel");
+    assert!(capacity > 1, "Broadcast channel needs at least two slots");
+
+    let slots = (0..capacity.next_power_of_two())
+        .map(|_| Slot {
+            stamp: AtomicUsize::new(0),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        })
+        .collect();
+    let sender = Sender {
+        shared: Arc::new(Shared {
+            slots,
+            written: AtomicUsize::new(0),
+            sender_alive: AtomicBool::new(true),
+        }),
+        position: 0,
+    };
+    let receiver = sender.subscribe();
+
+    (sender, receiver)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn every_receiver_sees_every_message() {
+        let (mut send, mut first) = channel(4);
+        let mut second = send.subscribe();
+
+        send.send(1);
+        send.send(2);
+        for recv in [&mut first, &mut second] {
+            assert_eq!(recv.lag(), 2);
+            assert_eq!(recv.try_recv(), Ok(1));
+            assert_eq!(recv.try_recv(), Ok(2));
+            assert_eq!(recv.try_recv(), Err(RecvError::Empty));
+        }
+
+        let mut late = send.subscribe();
+        send.send(3);
+        assert_eq!(late.try_recv(), Ok(3));
+        assert_eq!(first.try_recv(), Ok(3));
+    }
+
+    #[test]
+    fn overrun() {
+        let (mut send, mut recv) = channel(4);
+        for i in 0..10 {
+            send.send(i);
+        }
+
+        assert_eq!(recv.lag(), 10);
+        assert_eq!(recv.try_recv(), Err(RecvError::Lagged(7)));
+        assert_eq!(recv.overruns(), 7);
+        assert_eq!(recv.try_recv(), Ok(7));
+        assert_eq!(recv.try_recv(), Ok(8));
+        assert_eq!(recv.try_recv(), Ok(9));
+        assert_eq!(recv.try_recv(), Err(RecvError::Empty));
+    }
+
+    #[test]
+    fn disconnected() {
+        let (mut send, mut recv) = channel(2);
+        send.send(1);
+        drop(send);
+        assert_eq!(recv.try_recv(), Ok(1));
+        assert_eq!(recv.try_recv(), Err(RecvError::Disconnected));
+    }
+
+    #[test]
+    fn concurrent_receivers() {
+        let (mut send, recv) = channel::<u64>(64);
+        let receivers: Vec<_> = (0..3)
+            .map(|_| {
+                let mut recv = recv.clone();
+                std::thread::spawn(move || {
+                    let mut last = None;
+                    let mut received = 0;
+                    loop {
+                        match recv.try_recv() {
+                            Ok(value) => {
+                                assert!(last.is_none_or(|last| value > last));
+                                last = Some(value);
+                                received += 1;
+                            }
+                            Err(RecvError::Empty) => std::thread::yield_now(),
+                            Err(RecvError::Lagged(_)) => {}
+                            Err(RecvError::Disconnected) => break,
+                        }
+                    }
+                    received + recv.overruns()
+                })
+            })
+            .collect();
+        drop(recv);
+
+        for i in 0..10_000 {
+            send.send(i);
+        }
+        drop(send);
+
+        for receiver in receivers {
+            assert_eq!(receiver.join().unwrap(), 10_000);
+        }
+    }
+}
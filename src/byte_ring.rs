@@ -0,0 +1,846 @@
+use std::cell::UnsafeCell;
+use std::cmp;
+use std::io::{self, IoSlice, IoSliceMut, Read, Write};
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::role;
+use crate::wait::WaitStrategy;
+
+// Single-producer single-consumer byte ring. Besides the copying `write` and
+// `read`, each side can borrow the free or filled space directly through
+// `write_slice`/`commit` and `read_slice`/`consume`.
+//
+// A plain ring hands out at most the part up to the wrap point. The mirrored
+// ring from `mirrored_byte_ring` maps the same pages twice back to back, so
+// the region past the end aliases the start and every slice is contiguous.
+// Where that mapping isn't available it falls back to a buffer of twice the
+// capacity in which `commit` copies every write into the other half too,
+// which keeps the slices contiguous at the cost of the copy.
+pub struct ByteWriter {
+    ring: Arc<ByteRing>,
+}
+
+pub struct ByteReader {
+    ring: Arc<ByteRing>,
+}
+
+struct ByteRing {
+    storage: Storage,
+    // Byte positions, counted modulo twice the capacity so that full and
+    // empty differ without the counters ever overflowing; the capacity is
+    // arbitrary, so letting them wrap around `usize` would make the offset
+    // jump at the wrap.
+    read: AtomicUsize,
+    write: AtomicUsize,
+}
+
+enum Storage {
+    Heap(Box<[UnsafeCell<u8>]>),
+    #[cfg(any(unix, windows))]
+    Mirrored(Mirror),
+    // Two copies of the contents back to back.
+    Doubled(Box<[UnsafeCell<u8>]>),
+}
+
+unsafe impl Send for ByteRing {}
+unsafe impl Sync for ByteRing {}
+
+impl Storage {
+    fn ptr(&self) -> *mut u8 {
+        match self {
+            Storage::Heap(buffer) | Storage::Doubled(buffer) => buffer.as_ptr() as *mut u8,
+            #[cfg(any(unix, windows))]
+            Storage::Mirrored(mirror) => mirror.ptr,
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        match self {
+            Storage::Heap(buffer) => buffer.len(),
+            #[cfg(any(unix, windows))]
+            Storage::Mirrored(mirror) => mirror.len,
+            Storage::Doubled(buffer) => buffer.len() / 2,
+        }
+    }
+
+    fn is_mirrored(&self) -> bool {
+        match self {
+            Storage::Heap(_) => false,
+            #[cfg(any(unix, windows))]
+            Storage::Mirrored(_) => true,
+            Storage::Doubled(_) => true,
+        }
+    }
+
+    // Makes the `len` bytes just written at `offset` show up in both halves
+    // of a doubled buffer.
+    fn mirror_written(&self, offset: usize, len: usize) {
+        let Storage::Doubled(buffer) = self else {
+            return;
+        };
+
+        let capacity = buffer.len() / 2;
+        let base = buffer.as_ptr() as *mut u8;
+        // The part before the midpoint goes to the upper half, the part
+        // past it to the lower half.
+        let lower = cmp::min(len, capacity - offset);
+        unsafe {
+            ptr::copy_nonoverlapping(base.add(offset), base.add(offset + capacity), lower);
+            ptr::copy_nonoverlapping(base.add(capacity), base, len - lower);
+        }
+    }
+}
+
+impl ByteRing {
+    fn new(storage: Storage) -> Self {
+        ByteRing {
+            storage,
+            read: AtomicUsize::new(0),
+            write: AtomicUsize::new(0),
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        self.storage.capacity()
+    }
+
+    fn offset(&self, position: usize) -> usize {
+        let capacity = self.capacity();
+        if position >= capacity {
+            position - capacity
+        } else {
+            position
+        }
+    }
+
+    fn advance(&self, position: usize, len: usize) -> usize {
+        let period = 2 * self.capacity();
+        let position = position + len;
+        if position >= period {
+            position - period
+        } else {
+            position
+        }
+    }
+
+    // Bytes queued: from the read to the write position.
+    fn queued(&self, read: usize, write: usize) -> usize {
+        if write >= read {
+            write - read
+        } else {
+            write + 2 * self.capacity() - read
+        }
+    }
+
+    // Length of the contiguous run starting at `offset` that stays within
+    // the mapping.
+    fn contiguous(&self, offset: usize, len: usize) -> usize {
+        if self.storage.is_mirrored() {
+            len
+        } else {
+            cmp::min(len, self.capacity() - offset)
+        }
+    }
+
+    fn slice(&self, position: usize, len: usize) -> (*mut u8, usize) {
+        let offset = self.offset(position);
+        let len = self.contiguous(offset, len);
+        (unsafe { self.storage.ptr().add(offset) }, len)
+    }
+
+    // The run at `position` plus whatever continues from the start of the
+    // buffer; the second part is always empty for a mirrored ring.
+    fn regions(&self, position: usize, len: usize) -> ((*mut u8, usize), (*mut u8, usize)) {
+        let first = self.slice(position, len);
+        (first, (self.storage.ptr(), len - first.1))
+    }
+}
+
+impl ByteWriter {
+    pub fn write(&mut self, mut data: &[u8]) -> usize {
+        let mut written = 0;
+        while !data.is_empty() {
+            let slice = self.write_slice();
+            let len = cmp::min(slice.len(), data.len());
+            if len == 0 {
+                break;
+            }
+            slice[..len].copy_from_slice(&data[..len]);
+            self.commit(len);
+            data = &data[len..];
+            written += len;
+        }
+        written
+    }
+
+    // Writes the buffers in order until the ring is full.
+    pub fn write_vectored(&mut self, bufs: &[IoSlice]) -> usize {
+        let mut written = 0;
+        for buf in bufs {
+            let len = self.write(buf);
+            written += len;
+            if len < buf.len() {
+                break;
+            }
+        }
+        written
+    }
+
+    // All free space, as up to two regions in ring order. Fill them and
+    // `commit` the total.
+    pub fn chunks_mut(&mut self) -> (&mut [u8], &mut [u8]) {
+        let ring = &self.ring;
+        let write = ring.write.load(Ordering::Relaxed);
+        let free = ring.capacity() - ring.queued(ring.read.load(Ordering::Acquire), write);
+        let ((a, a_len), (b, b_len)) = ring.regions(write, free);
+        unsafe {
+            (
+                std::slice::from_raw_parts_mut(a, a_len),
+                std::slice::from_raw_parts_mut(b, b_len),
+            )
+        }
+    }
+
+    // The free space after the write position, up to the wrap point unless
+    // the ring is mirrored.
+    pub fn write_slice(&mut self) -> &mut [u8] {
+        let ring = &self.ring;
+        let write = ring.write.load(Ordering::Relaxed);
+        let free = ring.capacity() - ring.queued(ring.read.load(Ordering::Acquire), write);
+        let (ptr, len) = ring.slice(write, free);
+        unsafe { std::slice::from_raw_parts_mut(ptr, len) }
+    }
+
+    // Publishes `len` bytes written through `write_slice`.
+    pub fn commit(&mut self, len: usize) {
+        assert!(len <= self.available_write(), "Commit past free space");
+        let ring = &self.ring;
+        let write = ring.write.load(Ordering::Relaxed);
+        ring.storage.mirror_written(ring.offset(write), len);
+        ring.write
+            .store(ring.advance(write, len), Ordering::Release);
+    }
+
+    pub fn available_write(&self) -> usize {
+        let ring = &self.ring;
+        let queued = ring.queued(
+            ring.read.load(Ordering::Acquire),
+            ring.write.load(Ordering::Relaxed),
+        );
+        ring.capacity() - queued
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.ring.capacity()
+    }
+
+    pub fn is_mirrored(&self) -> bool {
+        self.ring.storage.is_mirrored()
+    }
+
+    pub fn is_reader_active(&self) -> bool {
+        Arc::strong_count(&self.ring) == 2
+    }
+}
+
+impl ByteReader {
+    pub fn read(&mut self, mut out: &mut [u8]) -> usize {
+        let mut read = 0;
+        while !out.is_empty() {
+            let slice = self.read_slice();
+            let len = cmp::min(slice.len(), out.len());
+            if len == 0 {
+                break;
+            }
+            out[..len].copy_from_slice(&slice[..len]);
+            self.consume(len);
+            out = &mut out[len..];
+            read += len;
+        }
+        read
+    }
+
+    // Fills the buffers in order until the ring is empty.
+    pub fn read_vectored(&mut self, bufs: &mut [IoSliceMut]) -> usize {
+        let mut read = 0;
+        for buf in bufs {
+            let len = self.read(buf);
+            read += len;
+            if len < buf.len() {
+                break;
+            }
+        }
+        read
+    }
+
+    // All queued bytes, as up to two regions in ring order. `consume` what
+    // was used.
+    pub fn chunks(&self) -> (&[u8], &[u8]) {
+        let ring = &self.ring;
+        let read = ring.read.load(Ordering::Relaxed);
+        let queued = ring.queued(read, ring.write.load(Ordering::Acquire));
+        let ((a, a_len), (b, b_len)) = ring.regions(read, queued);
+        unsafe {
+            (
+                std::slice::from_raw_parts(a, a_len),
+                std::slice::from_raw_parts(b, b_len),
+            )
+        }
+    }
+
+    // The queued bytes after the read position, up to the wrap point unless
+    // the ring is mirrored.
+    pub fn read_slice(&self) -> &[u8] {
+        let ring = &self.ring;
+        let read = ring.read.load(Ordering::Relaxed);
+        let queued = ring.queued(read, ring.write.load(Ordering::Acquire));
+        let (ptr, len) = ring.slice(read, queued);
+        unsafe { std::slice::from_raw_parts(ptr, len) }
+    }
+
+    pub fn consume(&mut self, len: usize) {
+        assert!(len <= self.available_read(), "Consume past queued bytes");
+        let ring = &self.ring;
+        let read = ring.read.load(Ordering::Relaxed);
+        ring.read.store(ring.advance(read, len), Ordering::Release);
+    }
+
+    pub fn available_read(&self) -> usize {
+        let ring = &self.ring;
+        ring.queued(
+            ring.read.load(Ordering::Relaxed),
+            ring.write.load(Ordering::Acquire),
+        )
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.ring.capacity()
+    }
+
+    pub fn is_mirrored(&self) -> bool {
+        self.ring.storage.is_mirrored()
+    }
+
+    pub fn is_writer_active(&self) -> bool {
+        Arc::strong_count(&self.ring) == 2
+    }
+}
+
+// Blocking `std::io` adapters for the non-RT side. Both wait for the other
+// end to make progress rather than returning short: `read` returns 0 only
+// once the writer is gone and everything has been read, and `write` fails
+// with `BrokenPipe` once the reader is gone.
+impl Write for ByteWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Write::write_vectored(self, &[IoSlice::new(buf)])
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice]) -> io::Result<usize> {
+        role::assert_not_rt("ByteWriter::write");
+
+        if bufs.iter().all(|buf| buf.is_empty()) {
+            return Ok(0);
+        }
+
+        let mut waiter = WaitStrategy::default().waiter();
+        loop {
+            if !self.is_reader_active() {
+                return Err(io::ErrorKind::BrokenPipe.into());
+            }
+
+            let written = ByteWriter::write_vectored(self, bufs);
+            if written > 0 {
+                return Ok(written);
+            }
+
+            waiter.wait();
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Read for ByteReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Read::read_vectored(self, &mut [IoSliceMut::new(buf)])
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut]) -> io::Result<usize> {
+        role::assert_not_rt("ByteReader::read");
+
+        if bufs.iter().all(|buf| buf.is_empty()) {
+            return Ok(0);
+        }
+
+        let mut waiter = WaitStrategy::default().waiter();
+        loop {
+            // Checked before reading so that bytes written right before the
+            // writer was dropped aren't missed.
+            let writer_active = self.is_writer_active();
+
+            let read = ByteReader::read_vectored(self, bufs);
+            if read > 0 || !writer_active {
+                return Ok(read);
+            }
+
+            waiter.wait();
+        }
+    }
+}
+
+fn split(ring: ByteRing) -> (ByteWriter, ByteReader) {
+    let ring = Arc::new(ring);
+    (ByteWriter { ring: ring.clone() }, ByteReader { ring })
+}
+
+pub fn byte_ring(capacity: usize) -> (ByteWriter, ByteReader) {
+    role::assert_not_rt("byte_ring");
+    assert!(capacity > 0, "Capacity must be non-zero");
+
+    let buffer = (0..capacity).map(|_| UnsafeCell::new(0)).collect();
+    split(ByteRing::new(Storage::Heap(buffer)))
+}
+
+// Rounds `min_capacity` up to a whole number of pages (of the allocation
+// granularity on Windows). Without a way to map memory twice the ring is
+// `emulated_mirrored_byte_ring` instead.
+pub fn mirrored_byte_ring(min_capacity: usize) -> io::Result<(ByteWriter, ByteReader)> {
+    role::assert_not_rt("mirrored_byte_ring");
+
+    #[cfg(any(unix, windows))]
+    {
+        let mirror = Mirror::new(min_capacity)?;
+        Ok(split(ByteRing::new(Storage::Mirrored(mirror))))
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        Ok(emulated_mirrored_byte_ring(min_capacity))
+    }
+}
+
+// Contiguous slices like `mirrored_byte_ring`, but with an ordinary
+// allocation of twice the capacity and a copy of every commit.
+pub fn emulated_mirrored_byte_ring(capacity: usize) -> (ByteWriter, ByteReader) {
+    role::assert_not_rt("emulated_mirrored_byte_ring");
+    assert!(capacity > 0, "Capacity must be non-zero");
+
+    let buffer = (0..2 * capacity).map(|_| UnsafeCell::new(0)).collect();
+    split(ByteRing::new(Storage::Doubled(buffer)))
+}
+
+#[cfg(any(unix, windows))]
+struct Mirror {
+    ptr: *mut u8,
+    len: usize,
+}
+
+#[cfg(unix)]
+impl Mirror {
+    fn new(min_len: usize) -> io::Result<Self> {
+        let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let len = cmp::max(min_len, 1).div_ceil(page) * page;
+
+        unsafe {
+            let fd = Mirror::open()?;
+            let result = Mirror::map(fd, len);
+            libc::close(fd);
+            result
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    unsafe fn open() -> io::Result<libc::c_int> {
+        let fd = libc::memfd_create(
+            b"rt_utils_byte_ring\0".as_ptr() as *const libc::c_char,
+            libc::MFD_CLOEXEC,
+        );
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(fd)
+    }
+
+    // No memfd: a POSIX shared memory object, unlinked right away so only
+    // the descriptor keeps it alive.
+    #[cfg(not(target_os = "linux"))]
+    unsafe fn open() -> io::Result<libc::c_int> {
+        use std::sync::atomic::AtomicU32;
+
+        static NEXT: AtomicU32 = AtomicU32::new(0);
+        // `shm_open` is variadic on Apple targets, where the mode has to go
+        // in as a full `c_uint`.
+        #[cfg(target_vendor = "apple")]
+        const MODE: libc::c_uint = 0o600;
+        #[cfg(not(target_vendor = "apple"))]
+        const MODE: libc::mode_t = 0o600;
+
+        loop {
+            let name = format!(
+                "/rt_utils_byte_ring.{}.{}\0",
+                std::process::id(),
+                NEXT.fetch_add(1, Ordering::Relaxed)
+            );
+            let name = name.as_ptr() as *const libc::c_char;
+            let fd = libc::shm_open(name, libc::O_RDWR | libc::O_CREAT | libc::O_EXCL, MODE);
+            if fd >= 0 {
+                libc::shm_unlink(name);
+                return Ok(fd);
+            }
+
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::AlreadyExists {
+                return Err(err);
+            }
+        }
+    }
+
+    unsafe fn map(fd: libc::c_int, len: usize) -> io::Result<Self> {
+        if libc::ftruncate(fd, len as libc::off_t) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // Reserve twice the length, then map the file over both halves.
+        let base = libc::mmap(
+            ptr::null_mut(),
+            2 * len,
+            libc::PROT_NONE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+            -1,
+            0,
+        );
+        if base == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mirror = Mirror {
+            ptr: base as *mut u8,
+            len,
+        };
+
+        for half in 0..2 {
+            let addr = libc::mmap(
+                mirror.ptr.add(half * len) as *mut libc::c_void,
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_FIXED,
+                fd,
+                0,
+            );
+            if addr == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        Ok(mirror)
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Mirror {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr as *mut libc::c_void, 2 * self.len);
+        }
+    }
+}
+
+#[cfg(windows)]
+mod win {
+    use std::ffi::c_void;
+
+    pub type Handle = *mut c_void;
+
+    pub const INVALID_HANDLE_VALUE: Handle = -1isize as Handle;
+    pub const PAGE_NOACCESS: u32 = 0x01;
+    pub const PAGE_READWRITE: u32 = 0x04;
+    pub const MEM_RESERVE: u32 = 0x2000;
+    pub const MEM_RELEASE: u32 = 0x8000;
+    pub const FILE_MAP_ALL_ACCESS: u32 = 0xf001f;
+
+    #[repr(C)]
+    pub struct SystemInfo {
+        pub processor_architecture: u16,
+        pub reserved: u16,
+        pub page_size: u32,
+        pub minimum_application_address: *mut c_void,
+        pub maximum_application_address: *mut c_void,
+        pub active_processor_mask: usize,
+        pub number_of_processors: u32,
+        pub processor_type: u32,
+        pub allocation_granularity: u32,
+        pub processor_level: u16,
+        pub processor_revision: u16,
+    }
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        pub fn GetSystemInfo(info: *mut SystemInfo);
+        pub fn CreateFileMappingW(
+            file: Handle,
+            attributes: *mut c_void,
+            protect: u32,
+            size_high: u32,
+            size_low: u32,
+            name: *const u16,
+        ) -> Handle;
+        pub fn CloseHandle(handle: Handle) -> i32;
+        pub fn VirtualAlloc(
+            address: *mut c_void,
+            size: usize,
+            allocation_type: u32,
+            protect: u32,
+        ) -> *mut c_void;
+        pub fn VirtualFree(address: *mut c_void, size: usize, free_type: u32) -> i32;
+        pub fn MapViewOfFileEx(
+            mapping: Handle,
+            access: u32,
+            offset_high: u32,
+            offset_low: u32,
+            len: usize,
+            address: *mut c_void,
+        ) -> *mut c_void;
+        pub fn UnmapViewOfFile(address: *const c_void) -> i32;
+    }
+}
+
+#[cfg(windows)]
+impl Mirror {
+    // Views can only be placed at free addresses, so the reservation that
+    // finds room for both has to be released before mapping them, and
+    // another thread may take the space in between; retry a few times.
+    const ATTEMPTS: usize = 16;
+
+    fn new(min_len: usize) -> io::Result<Self> {
+        use std::mem::MaybeUninit;
+
+        let granularity = unsafe {
+            let mut info = MaybeUninit::<win::SystemInfo>::uninit();
+            win::GetSystemInfo(info.as_mut_ptr());
+            info.assume_init().allocation_granularity as usize
+        };
+        let len = cmp::max(min_len, 1).div_ceil(granularity) * granularity;
+
+        unsafe {
+            let mapping = win::CreateFileMappingW(
+                win::INVALID_HANDLE_VALUE,
+                ptr::null_mut(),
+                win::PAGE_READWRITE,
+                (len as u64 >> 32) as u32,
+                len as u32,
+                ptr::null(),
+            );
+            if mapping.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+
+            let mut result = Err(io::Error::from(io::ErrorKind::AddrInUse));
+            for _ in 0..Self::ATTEMPTS {
+                result = Mirror::map(mapping, len);
+                if result.is_ok() {
+                    break;
+                }
+            }
+            // The views keep the mapping alive.
+            win::CloseHandle(mapping);
+            result
+        }
+    }
+
+    unsafe fn map(mapping: win::Handle, len: usize) -> io::Result<Self> {
+        let base = win::VirtualAlloc(
+            ptr::null_mut(),
+            2 * len,
+            win::MEM_RESERVE,
+            win::PAGE_NOACCESS,
+        );
+        if base.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        win::VirtualFree(base, 0, win::MEM_RELEASE);
+
+        let base = base as *mut u8;
+        let lower = win::MapViewOfFileEx(mapping, win::FILE_MAP_ALL_ACCESS, 0, 0, len, base.cast());
+        if lower.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        let upper = win::MapViewOfFileEx(
+            mapping,
+            win::FILE_MAP_ALL_ACCESS,
+            0,
+            0,
+            len,
+            base.add(len).cast(),
+        );
+        if upper.is_null() {
+            let err = io::Error::last_os_error();
+            win::UnmapViewOfFile(lower);
+            return Err(err);
+        }
+
+        Ok(Mirror { ptr: base, len })
+    }
+}
+
+#[cfg(windows)]
+impl Drop for Mirror {
+    fn drop(&mut self) {
+        unsafe {
+            win::UnmapViewOfFile(self.ptr.cast());
+            win::UnmapViewOfFile(self.ptr.add(self.len).cast());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn wraps() {
+        let (mut writer, mut reader) = byte_ring(8);
+        let mut out = [0; 8];
+
+        assert_eq!(writer.write(&[1, 2, 3, 4, 5, 6]), 6);
+        assert_eq!(reader.read(&mut out[..4]), 4);
+
+        assert_eq!(writer.write(&[7, 8, 9, 10, 11, 12, 13]), 6);
+        assert_eq!(writer.available_write(), 0);
+        assert_eq!(reader.read_slice(), &[5, 6, 7, 8]);
+
+        assert_eq!(reader.read(&mut out), 8);
+        assert_eq!(out, [5, 6, 7, 8, 9, 10, 11, 12]);
+        assert_eq!(reader.available_read(), 0);
+    }
+
+    #[test]
+    fn vectored() {
+        let (mut writer, mut reader) = byte_ring(8);
+
+        writer.write(&[0; 5]);
+        reader.consume(5);
+
+        let written =
+            writer.write_vectored(&[IoSlice::new(&[1, 2, 3]), IoSlice::new(&[4, 5, 6, 7])]);
+        assert_eq!(written, 7);
+        assert_eq!(reader.chunks(), (&[1, 2, 3][..], &[4, 5, 6, 7][..]));
+
+        let (mut a, mut b) = ([0; 2], [0; 8]);
+        let read = reader.read_vectored(&mut [IoSliceMut::new(&mut a), IoSliceMut::new(&mut b)]);
+        assert_eq!(read, 7);
+        assert_eq!((a, &b[..5]), ([1, 2], &[3, 4, 5, 6, 7][..]));
+
+        let (a, b) = writer.chunks_mut();
+        assert_eq!(a.len() + b.len(), 8);
+    }
+
+    #[test]
+    fn std_io() {
+        let (mut writer, mut reader) = byte_ring(16);
+        let data: Vec<u8> = (0..=255).collect();
+
+        let producer = std::thread::spawn(move || {
+            Write::write_all(&mut writer, &data).unwrap();
+        });
+
+        let mut received = Vec::new();
+        Read::read_to_end(&mut reader, &mut received).unwrap();
+        producer.join().unwrap();
+
+        assert_eq!(received, (0..=255).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn write_to_dropped_reader() {
+        let (mut writer, reader) = byte_ring(4);
+        drop(reader);
+        let err = Write::write(&mut writer, &[1]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn mirrored_slices_are_contiguous() {
+        let (mut writer, mut reader) = mirrored_byte_ring(1).unwrap();
+        let capacity = writer.capacity();
+        assert!(writer.is_mirrored());
+
+        let fill = vec![0; capacity - 2];
+        assert_eq!(writer.write(&fill), capacity - 2);
+        reader.consume(capacity - 2);
+
+        assert_eq!(writer.write_slice().len(), capacity);
+        assert_eq!(writer.write(&[1, 2, 3, 4]), 4);
+        assert_eq!(reader.read_slice(), &[1, 2, 3, 4]);
+
+        let mut out = [0; 4];
+        assert_eq!(reader.read(&mut out), 4);
+        assert_eq!(out, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn emulated_mirror() {
+        let (mut writer, mut reader) = emulated_mirrored_byte_ring(8);
+        assert!(writer.is_mirrored());
+        assert_eq!(writer.capacity(), 8);
+
+        for round in 0..5u8 {
+            let data = [round; 6];
+            assert_eq!(writer.write_slice().len(), 8);
+            assert_eq!(writer.write(&data), 6);
+            assert_eq!(reader.read_slice(), &data);
+            reader.consume(6);
+        }
+
+        // Written through the slice straddling the end.
+        writer.write_slice()[..4].copy_from_slice(&[1, 2, 3, 4]);
+        writer.commit(4);
+        assert_eq!(reader.read_slice(), &[1, 2, 3, 4]);
+        let (a, b) = reader.chunks();
+        assert_eq!((a, b.len()), (&[1, 2, 3, 4][..], 0));
+    }
+
+    #[test]
+    fn positions_wrap() {
+        for (mut writer, mut reader) in [byte_ring(5), emulated_mirrored_byte_ring(5)] {
+            let mut expected = 0u8;
+            let mut next = 0u8;
+            // Runs the positions around their period several times, with
+            // chunk sizes that don't divide the capacity.
+            for round in 0..40 {
+                let chunk: Vec<u8> = (0..1 + round % 5).map(|i| next.wrapping_add(i)).collect();
+                let written = writer.write(&chunk);
+                next = next.wrapping_add(written as u8);
+                assert!(writer.available_write() + reader.available_read() == 5);
+
+                let mut out = [0; 3];
+                let read = reader.read(&mut out);
+                for &byte in &out[..read] {
+                    assert_eq!(byte, expected);
+                    expected = expected.wrapping_add(1);
+                }
+            }
+            assert!(expected > 30);
+        }
+    }
+
+    #[test]
+    fn starts_at_period_end() {
+        let (mut writer, mut reader) = byte_ring(6);
+        // Two bytes before the positions wrap back to 0.
+        writer.ring.read.store(10, Ordering::Relaxed);
+        writer.ring.write.store(10, Ordering::Relaxed);
+
+        assert_eq!(writer.write(&[1, 2, 3, 4, 5, 6]), 6);
+        assert_eq!(writer.ring.write.load(Ordering::Relaxed), 4);
+        assert_eq!(reader.available_read(), 6);
+        assert_eq!(reader.chunks(), (&[1, 2][..], &[3, 4, 5, 6][..]));
+
+        let mut out = [0; 6];
+        assert_eq!(reader.read(&mut out), 6);
+        assert_eq!(out, [1, 2, 3, 4, 5, 6]);
+        assert_eq!(writer.available_write(), 6);
+    }
+}
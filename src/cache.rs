@@ -0,0 +1,46 @@
+// Cache maintenance for queues in memory that isn't kept coherent by the
+// hardware: RAM shared between the cores of an AMP system where at least
+// one side caches it, or a DMA buffer. On such memory a store can sit in
+// the writer's cache while the reader keeps reading a stale line of its
+// own, no matter which atomic orderings are used.
+//
+// `StaticSpscQueue` takes an implementation as a type parameter and calls
+// it around every publication: the side that wrote a slot or its counter
+// cleans those lines, the side about to read them invalidates its copies
+// first. Each side's counters sit on their own 64-byte lines and a side
+// never writes the other's, so cleaning whole lines doesn't clobber
+// anything. The slots should be aligned to the line size of the target
+// too, which the queue's own alignment takes care of up to 64 bytes.
+//
+// Both functions get the exact byte range; rounding it out to whole lines
+// is up to the implementation, as are the barriers the architecture needs
+// around the maintenance operations (`dsb` on Arm).
+pub trait CacheMaintenance {
+    // Writes the lines covering `len` bytes at `ptr` back to memory.
+    fn clean(ptr: *const u8, len: usize);
+
+    // Drops the local copies of the lines covering `len` bytes at `ptr`,
+    // so the next read fetches them from memory.
+    fn invalidate(ptr: *const u8, len: usize);
+}
+
+// Hardware-coherent memory, the default: the hooks do nothing and are
+// compiled out.
+pub struct Coherent;
+
+impl CacheMaintenance for Coherent {
+    #[inline(always)]
+    fn clean(_ptr: *const u8, _len: usize) {}
+
+    #[inline(always)]
+    fn invalidate(_ptr: *const u8, _len: usize) {}
+}
+
+// The hooks over the bytes of a single value, a slot say.
+pub(crate) fn clean<M: CacheMaintenance, T>(value: *const T) {
+    M::clean(value as *const u8, core::mem::size_of::<T>());
+}
+
+pub(crate) fn invalidate<M: CacheMaintenance, T>(value: *const T) {
+    M::invalidate(value as *const u8, core::mem::size_of::<T>());
+}
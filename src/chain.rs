@@ -0,0 +1,188 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::spsc::{self, TrySendError};
+
+// Backpressure for pipelines of channels (producer -> worker -> ... ->
+// consumer). All links share one credit counter, initialised to the
+// smallest capacity in the chain: the head spends a credit per item sent
+// and the tail returns it when the item leaves the chain. Since no more
+// items than the smallest queue holds are ever in flight, a middle stage
+// can always forward what it receives, and a slow consumer shows up as
+// `try_send` failing at the head instead of data silently dropped halfway.
+//
+// Stages are expected to map items one to one; a stage that discards an
+// item must call `discard` to return its credit. Build the whole chain
+// before sending anything.
+struct Credits {
+    available: AtomicUsize,
+    limit: AtomicUsize,
+}
+
+pub struct ChainSender<T> {
+    sender: spsc::Sender<T>,
+    credits: Arc<Credits>,
+}
+
+pub struct ChainStage<T, U> {
+    input: spsc::Receiver<T>,
+    output: spsc::Sender<U>,
+    credits: Arc<Credits>,
+}
+
+pub struct ChainReceiver<T> {
+    receiver: spsc::Receiver<T>,
+    credits: Arc<Credits>,
+}
+
+impl Credits {
+    fn acquire(&self) -> bool {
+        self.available
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |n| n.checked_sub(1))
+            .is_ok()
+    }
+
+    fn release(&self) {
+        self.available.fetch_add(1, Ordering::Release);
+    }
+}
+
+impl<T> ChainSender<T> {
+    // Fails if the chain as a whole is full, even if the first queue has
+    // room.
+    pub fn try_send(&mut self, value: T) -> Result<(), T> {
+        if !self.credits.acquire() {
+            return Err(value);
+        }
+
+        self.sender.try_send(value).map_err(|e| {
+            self.credits.release();
+            e.into_inner()
+        })
+    }
+
+    pub fn credits(&self) -> usize {
+        self.credits.available.load(Ordering::Relaxed)
+    }
+}
+
+impl<T, U> ChainStage<T, U> {
+    pub fn try_recv(&mut self) -> Option<T> {
+        self.input.try_recv().ok()
+    }
+
+    pub fn try_send(&mut self, value: U) -> Result<(), U> {
+        self.output
+            .try_send(value)
+            .map_err(TrySendError::into_inner)
+    }
+
+    // Returns the credit of an item received but not forwarded.
+    pub fn discard(&self) {
+        self.credits.release();
+    }
+
+    // Moves everything queued through `f`. Returns the number of items
+    // forwarded.
+    pub fn forward(&mut self, mut f: impl FnMut(T) -> U) -> usize {
+        let mut forwarded = 0;
+        while let Ok(value) = self.input.try_recv() {
+            // Credits guarantee room downstream.
+            if self.output.try_send(f(value)).is_err() {
+                self.credits.release();
+            } else {
+                forwarded += 1;
+            }
+        }
+        forwarded
+    }
+}
+
+impl<T> ChainReceiver<T> {
+    pub fn try_recv(&mut self) -> Option<T> {
+        let value = self.receiver.try_recv().ok()?;
+        self.credits.release();
+        Some(value)
+    }
+
+    // Turns this end into a stage feeding a new queue of `capacity` items.
+    pub fn extend<U>(self, capacity: usize) -> (ChainStage<T, U>, ChainReceiver<U>) {
+        let limit = self.credits.limit.load(Ordering::Relaxed);
+        if capacity < limit {
+            self.credits.limit.store(capacity, Ordering::Relaxed);
+            let _ =
+                self.credits
+                    .available
+                    .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| {
+                        Some(n.saturating_sub(limit - capacity))
+                    });
+        }
+
+        let (output, receiver) = spsc::channel(capacity);
+        let stage = ChainStage {
+            input: self.receiver,
+            output,
+            credits: self.credits.clone(),
+        };
+        let tail = ChainReceiver {
+            receiver,
+            credits: self.credits,
+        };
+
+        (stage, tail)
+    }
+}
+
+pub fn chain<T>(capacity: usize) -> (ChainSender<T>, ChainReceiver<T>) {
+    let (sender, receiver) = spsc::channel(capacity);
+    let credits = Arc::new(Credits {
+        available: AtomicUsize::new(capacity),
+        limit: AtomicUsize::new(capacity),
+    });
+
+    (
+        ChainSender {
+            sender,
+            credits: credits.clone(),
+        },
+        ChainReceiver { receiver, credits },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn head_stalls_instead_of_middle_dropping() {
+        let (mut head, tail) = chain::<u32>(8);
+        let (mut worker, mut tail) = tail.extend::<u64>(2);
+        assert_eq!(head.credits(), 2);
+
+        head.try_send(1).unwrap();
+        head.try_send(2).unwrap();
+        assert_eq!(head.try_send(3), Err(3));
+
+        assert_eq!(worker.forward(|v| v as u64 * 10), 2);
+        assert_eq!(head.try_send(3), Err(3));
+
+        assert_eq!(tail.try_recv(), Some(10));
+        head.try_send(3).unwrap();
+        assert_eq!(worker.forward(|v| v as u64 * 10), 1);
+        assert_eq!(tail.try_recv(), Some(20));
+        assert_eq!(tail.try_recv(), Some(30));
+        assert_eq!(head.credits(), 2);
+    }
+
+    #[test]
+    fn discard_returns_credit() {
+        let (mut head, tail) = chain::<u32>(1);
+        let (mut worker, _tail) = tail.extend::<u32>(4);
+
+        head.try_send(1).unwrap();
+        assert_eq!(head.try_send(2), Err(2));
+        assert_eq!(worker.try_recv(), Some(1));
+        worker.discard();
+        head.try_send(2).unwrap();
+    }
+}
@@ -0,0 +1,248 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::spsc::{self, TrySendError};
+
+// Plug-in point for audio codecs. Codecs only ever run on a worker thread
+// spawned by `spawn_encoder`/`spawn_decoder`, which sit between the RT
+// thread's sample rings and whatever carries the encoded packets.
+pub trait Encoder: Send {
+    // Samples consumed per call to `encode`.
+    fn frame_len(&self) -> usize;
+
+    // Encodes exactly `frame_len` samples into one packet, appended to
+    // `out`.
+    fn encode(&mut self, samples: &[f32], out: &mut Vec<u8>) -> io::Result<()>;
+}
+
+pub trait Decoder: Send {
+    // Decodes one packet, appending the samples to `out`.
+    fn decode(&mut self, packet: &[u8], out: &mut Vec<f32>) -> io::Result<()>;
+}
+
+const IDLE_SLEEP: Duration = Duration::from_millis(1);
+
+pub struct CodecWorker {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl CodecWorker {
+    pub fn is_finished(&self) -> bool {
+        self.thread.as_ref().is_none_or(|t| t.is_finished())
+    }
+
+    // Stops the worker and returns the codec error that ended it, if any.
+    pub fn stop(mut self) -> io::Result<()> {
+        self.join()
+    }
+
+    fn join(&mut self) -> io::Result<()> {
+        self.stop.store(true, Ordering::Relaxed);
+        match self.thread.take() {
+            Some(thread) => thread
+                .join()
+                .unwrap_or_else(|_| Err(io::Error::other("codec panicked"))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for CodecWorker {
+    fn drop(&mut self) {
+        let _ = self.join();
+    }
+}
+
+// Pulls samples from `input`, encodes them a frame at a time and pushes the
+// packets to `output`. A full output queue holds the worker back rather
+// than dropping packets.
+pub fn spawn_encoder<E: Encoder + 'static>(
+    mut encoder: E,
+    mut input: spsc::Receiver<f32>,
+    mut output: spsc::Sender<Vec<u8>>,
+) -> io::Result<CodecWorker> {
+    spawn("encoder", move |stop| {
+        let frame_len = encoder.frame_len();
+        let mut frame = Vec::with_capacity(frame_len);
+
+        while !stop.load(Ordering::Relaxed) {
+            while frame.len() < frame_len {
+                match input.try_recv() {
+                    Ok(sample) => frame.push(sample),
+                    Err(_) => break,
+                }
+            }
+
+            if frame.len() < frame_len {
+                thread::sleep(IDLE_SLEEP);
+                continue;
+            }
+
+            let mut packet = Vec::new();
+            encoder.encode(&frame, &mut packet)?;
+            frame.clear();
+
+            if !push(&mut output, packet, stop) {
+                break;
+            }
+        }
+
+        Ok(())
+    })
+}
+
+// Pulls packets from `input`, decodes them and pushes the samples to
+// `output`.
+pub fn spawn_decoder<D: Decoder + 'static>(
+    mut decoder: D,
+    mut input: spsc::Receiver<Vec<u8>>,
+    mut output: spsc::Sender<f32>,
+) -> io::Result<CodecWorker> {
+    spawn("decoder", move |stop| {
+        let mut samples = Vec::new();
+
+        while !stop.load(Ordering::Relaxed) {
+            let packet = match input.try_recv() {
+                Ok(packet) => packet,
+                Err(_) => {
+                    thread::sleep(IDLE_SLEEP);
+                    continue;
+                }
+            };
+
+            samples.clear();
+            decoder.decode(&packet, &mut samples)?;
+
+            for &sample in &samples {
+                if !push(&mut output, sample, stop) {
+                    return Ok(());
+                }
+            }
+        }
+
+        Ok(())
+    })
+}
+
+fn spawn(
+    name: &str,
+    body: impl FnOnce(&AtomicBool) -> io::Result<()> + Send + 'static,
+) -> io::Result<CodecWorker> {
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread = thread::Builder::new()
+        .name(format!("rt_utils {}", name))
+        .spawn({
+            let stop = stop.clone();
+            move || body(&stop)
+        })?;
+
+    Ok(CodecWorker {
+        stop,
+        thread: Some(thread),
+    })
+}
+
+// Returns false if the worker was stopped while waiting for room, or the
+// receiving end is gone.
+fn push<T>(output: &mut spsc::Sender<T>, mut value: T, stop: &AtomicBool) -> bool {
+    loop {
+        value = match output.try_send(value) {
+            Ok(()) => return true,
+            Err(TrySendError::Full(value)) => value,
+            Err(TrySendError::Disconnected(_)) => return false,
+        };
+        if stop.load(Ordering::Relaxed) {
+            return false;
+        }
+        thread::sleep(IDLE_SLEEP);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::time::Instant;
+
+    // 16-bit PCM, enough to exercise the plumbing.
+    struct Pcm16 {
+        frame_len: usize,
+    }
+
+    impl Encoder for Pcm16 {
+        fn frame_len(&self) -> usize {
+            self.frame_len
+        }
+
+        fn encode(&mut self, samples: &[f32], out: &mut Vec<u8>) -> io::Result<()> {
+            for &s in samples {
+                out.extend_from_slice(&((s * 32767.0) as i16).to_le_bytes());
+            }
+            Ok(())
+        }
+    }
+
+    impl Decoder for Pcm16 {
+        fn decode(&mut self, packet: &[u8], out: &mut Vec<f32>) -> io::Result<()> {
+            if !packet.len().is_multiple_of(2) {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "odd packet"));
+            }
+            for pair in packet.chunks(2) {
+                out.push(i16::from_le_bytes([pair[0], pair[1]]) as f32 / 32767.0);
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn encode_decode_pipeline() {
+        let (mut rt_out, samples_in) = spsc::channel(64);
+        let (packets_out, packets_in) = spsc::channel(4);
+        let (samples_out, mut rt_in) = spsc::channel(64);
+
+        let encoder = spawn_encoder(Pcm16 { frame_len: 8 }, samples_in, packets_out).unwrap();
+        let decoder = spawn_decoder(Pcm16 { frame_len: 8 }, packets_in, samples_out).unwrap();
+
+        for i in 0..20 {
+            rt_out.try_send(i as f32 / 32.0).unwrap();
+        }
+
+        let mut received = Vec::new();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while received.len() < 16 && Instant::now() < deadline {
+            received.extend(rt_in.try_iter());
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        // The last partial frame stays with the encoder.
+        assert_eq!(received.len(), 16);
+        for (i, s) in received.iter().enumerate() {
+            assert!((s - i as f32 / 32.0).abs() < 1e-4);
+        }
+
+        assert!(encoder.stop().is_ok());
+        assert!(decoder.stop().is_ok());
+    }
+
+    #[test]
+    fn codec_error_stops_worker() {
+        let (mut packets_out, packets_in) = spsc::channel(4);
+        let (samples_out, _rt_in) = spsc::channel(64);
+        let decoder = spawn_decoder(Pcm16 { frame_len: 8 }, packets_in, samples_out).unwrap();
+
+        packets_out.try_send(vec![1, 2, 3]).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !decoder.is_finished() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        assert_eq!(
+            decoder.stop().unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
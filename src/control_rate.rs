@@ -0,0 +1,141 @@
+use crate::interpolate::Interpolate;
+use crate::triple_buffer::{self, Reader, Writer};
+
+// Turns the latest control value published through a triple buffer into
+// per-block (or per-sample) values on the RT thread, optionally slewing
+// linearly towards each new value over a fixed number of samples.
+pub struct ControlRate<T> {
+    reader: Reader<T>,
+    current: T,
+    target: T,
+    slew_samples: usize,
+    remaining: usize,
+}
+
+impl<T: Interpolate + PartialEq> ControlRate<T> {
+    pub fn new(mut reader: Reader<T>, slew_samples: usize) -> Self {
+        let initial = *reader.read();
+
+        ControlRate {
+            reader,
+            current: initial,
+            target: initial,
+            slew_samples,
+            remaining: 0,
+        }
+    }
+
+    pub fn current(&self) -> T {
+        self.current
+    }
+
+    pub fn target(&self) -> T {
+        self.target
+    }
+
+    pub fn is_slewing(&self) -> bool {
+        self.remaining > 0
+    }
+
+    // Picks up the latest value and advances the slew by `block_len` samples,
+    // returning the value reached at the end of the block.
+    pub fn next(&mut self, block_len: usize) -> T {
+        self.poll();
+        self.advance(block_len);
+        self.current
+    }
+
+    // Picks up the latest value and writes one value per sample into `out`.
+    pub fn next_block(&mut self, out: &mut [T]) {
+        self.poll();
+
+        for sample in out.iter_mut() {
+            self.advance(1);
+            *sample = self.current;
+        }
+    }
+
+    fn poll(&mut self) {
+        let latest = *self.reader.read();
+        if latest != self.target {
+            self.target = latest;
+            self.remaining = self.slew_samples;
+            if self.remaining == 0 {
+                self.current = latest;
+            }
+        }
+    }
+
+    fn advance(&mut self, samples: usize) {
+        if self.remaining == 0 {
+            return;
+        }
+
+        if samples >= self.remaining {
+            self.current = self.target;
+            self.remaining = 0;
+        } else {
+            let t = samples as f32 / self.remaining as f32;
+            self.current = self.current.interpolate(self.target, t);
+            self.remaining -= samples;
+        }
+    }
+}
+
+pub fn control_rate<T: Interpolate + PartialEq>(
+    initial: T,
+    slew_samples: usize,
+) -> (Writer<T>, ControlRate<T>) {
+    let (writer, reader) = triple_buffer::triple_buffer(initial);
+    (writer, ControlRate::new(reader, slew_samples))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn without_slew() {
+        let (mut writer, mut control) = control_rate(1.0f32, 0);
+        assert_eq!(control.next(64), 1.0);
+
+        writer.write(2.0);
+        assert_eq!(control.next(64), 2.0);
+        assert!(!control.is_slewing());
+    }
+
+    #[test]
+    fn slew_per_block() {
+        let (mut writer, mut control) = control_rate(0.0f32, 100);
+        writer.write(1.0);
+
+        assert_eq!(control.next(25), 0.25);
+        assert_eq!(control.next(25), 0.5);
+        assert!(control.is_slewing());
+        assert_eq!(control.next(50), 1.0);
+        assert!(!control.is_slewing());
+        assert_eq!(control.next(50), 1.0);
+    }
+
+    #[test]
+    fn slew_per_sample() {
+        let (mut writer, mut control) = control_rate(0.0f32, 4);
+        writer.write(1.0);
+
+        let mut block = [0.0; 6];
+        control.next_block(&mut block);
+        assert_eq!(block, [0.25, 0.5, 0.75, 1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn retarget_mid_slew() {
+        let (mut writer, mut control) = control_rate(0.0f32, 4);
+        writer.write(1.0);
+        assert_eq!(control.next(2), 0.5);
+
+        writer.write(0.5);
+        assert_eq!(control.target(), 1.0);
+        assert_eq!(control.next(2), 0.5);
+        assert_eq!(control.target(), 0.5);
+    }
+}
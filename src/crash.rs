@@ -0,0 +1,613 @@
+use std::fmt::{self, Write};
+use std::fs::OpenOptions;
+use std::io;
+use std::path::Path;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::frame::FramePosition;
+use crate::rtlog::{self, LogDrain};
+use crate::seqlock::SeqLock;
+use crate::spsc::CrashView;
+use crate::stats::{Counter, Gauge};
+use crate::trace::{self, Collector};
+use crate::triple_buffer::{self, Latest};
+
+// Writes a text snapshot of registered state to a file when the process
+// crashes: on SIGSEGV, SIGBUS, SIGILL, SIGFPE and SIGABRT on unix, and on an
+// unhandled SEH exception on Windows. Other platforms don't have the module.
+//
+// Everything that could allocate or lock happens in `install_crash_dumper`:
+// the dump file is created and opened up front, and the handler list is
+// leaked into a static. The handler itself only loads atomics, formats into
+// a stack buffer and writes to the file, once per process.
+//
+// Whatever was installed before is kept and gets the crash afterwards. On
+// unix the previous signal action is restored and the signal delivered
+// again, so the process still dies with the original signal (and core
+// dumps as before) unless an earlier handler decides otherwise. On Windows
+// the previous unhandled exception filter is called, if there was one.
+//
+// `CrashSource` implementations run inside the handler and must stay
+// async-signal-safe: atomics and formatting of plain values only.
+pub trait CrashSource: Send + Sync {
+    fn dump(&self, out: &mut CrashWriter);
+}
+
+pub struct CrashHandler {
+    name: &'static str,
+    source: Arc<dyn CrashSource>,
+}
+
+impl CrashHandler {
+    pub fn new(name: &'static str, source: Arc<dyn CrashSource>) -> Self {
+        CrashHandler { name, source }
+    }
+}
+
+#[cfg(unix)]
+type RawFile = std::os::unix::io::RawFd;
+#[cfg(windows)]
+type RawFile = std::os::windows::io::RawHandle;
+
+#[cfg(unix)]
+const SIGNALS: [libc::c_int; 5] = [
+    libc::SIGSEGV,
+    libc::SIGBUS,
+    libc::SIGILL,
+    libc::SIGFPE,
+    libc::SIGABRT,
+];
+
+#[cfg(unix)]
+type Previous = [libc::sigaction; SIGNALS.len()];
+#[cfg(windows)]
+type Previous = win::Filter;
+
+struct Installed {
+    file: RawFile,
+    handlers: Vec<CrashHandler>,
+    previous: Previous,
+}
+
+static INSTALLED: AtomicPtr<Installed> = AtomicPtr::new(ptr::null_mut());
+static DUMPED: AtomicBool = AtomicBool::new(false);
+
+// Creates `path` and installs the handlers. Installing again replaces the
+// file and handler list but still chains to what was there before the
+// first install; the previous list is leaked, since a handler may still be
+// reading it.
+pub fn install_crash_dumper<P: AsRef<Path>>(
+    path: P,
+    handlers: Vec<CrashHandler>,
+) -> io::Result<()> {
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)?;
+    install(into_raw(file), handlers)
+}
+
+#[cfg(unix)]
+fn into_raw(file: std::fs::File) -> RawFile {
+    std::os::unix::io::IntoRawFd::into_raw_fd(file)
+}
+
+#[cfg(windows)]
+fn into_raw(file: std::fs::File) -> RawFile {
+    std::os::windows::io::IntoRawHandle::into_raw_handle(file)
+}
+
+#[cfg(unix)]
+fn install(file: RawFile, handlers: Vec<CrashHandler>) -> io::Result<()> {
+    let handler = handle_signal as *const () as libc::sighandler_t;
+    let old = unsafe { INSTALLED.load(Ordering::Acquire).as_ref() };
+
+    // The handler can run as soon as the first action is set, so the
+    // previous actions are collected before anything is installed.
+    let mut previous: Previous = unsafe { std::mem::zeroed() };
+    for (i, &signal) in SIGNALS.iter().enumerate() {
+        if unsafe { libc::sigaction(signal, ptr::null(), &mut previous[i]) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if let Some(old) = old.filter(|_| previous[i].sa_sigaction == handler) {
+            previous[i] = old.previous[i];
+        }
+    }
+
+    let installed = Box::into_raw(Box::new(Installed {
+        file,
+        handlers,
+        previous,
+    }));
+    INSTALLED.store(installed, Ordering::Release);
+
+    for &signal in &SIGNALS {
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handler;
+            action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(signal, &action, ptr::null_mut()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+extern "C" fn handle_signal(signal: libc::c_int, info: *mut libc::siginfo_t, _: *mut libc::c_void) {
+    let installed = unsafe { INSTALLED.load(Ordering::Acquire).as_ref() };
+    let index = SIGNALS.iter().position(|&s| s == signal);
+    let (installed, index) = match (installed, index) {
+        (Some(installed), Some(index)) => (installed, index),
+        _ => return,
+    };
+
+    write_dump(installed, format_args!("signal {}", signal));
+
+    unsafe {
+        libc::sigaction(signal, &installed.previous[index], ptr::null_mut());
+
+        // A fault raised by the hardware happens again when the handler
+        // returns, this time going to the previous action. One sent with
+        // kill or raise (si_code <= 0) has to be sent again; it stays
+        // blocked until the handler returns.
+        if (*info).si_code <= 0 {
+            libc::raise(signal);
+        }
+    }
+}
+
+#[cfg(windows)]
+fn install(file: RawFile, handlers: Vec<CrashHandler>) -> io::Result<()> {
+    // A crash between setting the filter and storing the handler list isn't
+    // dumped or chained; it just goes on to the default handling.
+    let previous = match unsafe { INSTALLED.load(Ordering::Acquire).as_ref() } {
+        Some(old) => old.previous,
+        None => unsafe { win::SetUnhandledExceptionFilter(Some(handle_exception)) },
+    };
+
+    let installed = Box::into_raw(Box::new(Installed {
+        file,
+        handlers,
+        previous,
+    }));
+    INSTALLED.store(installed, Ordering::Release);
+    Ok(())
+}
+
+#[cfg(windows)]
+unsafe extern "system" fn handle_exception(pointers: *mut win::ExceptionPointers) -> i32 {
+    let installed = match INSTALLED.load(Ordering::Acquire).as_ref() {
+        Some(installed) => installed,
+        None => return win::EXCEPTION_CONTINUE_SEARCH,
+    };
+
+    let code = (*(*pointers).exception_record).exception_code;
+    write_dump(installed, format_args!("exception {:#010x}", code));
+
+    match installed.previous {
+        Some(previous) => previous(pointers),
+        None => win::EXCEPTION_CONTINUE_SEARCH,
+    }
+}
+
+// Only the first crash is written; another thread crashing meanwhile, or a
+// previous handler that crashes again, would interleave with it.
+fn write_dump(installed: &Installed, cause: fmt::Arguments) {
+    if DUMPED.swap(true, Ordering::AcqRel) {
+        return;
+    }
+
+    let mut out = CrashWriter::new(installed.file);
+    let _ = writeln!(out, "rt_utils crash dump\n{}", cause);
+    for handler in &installed.handlers {
+        let _ = writeln!(out, "\n[{}]", handler.name);
+        handler.source.dump(&mut out);
+    }
+    out.flush();
+}
+
+// Buffered writer on a raw file, usable from a signal handler.
+pub struct CrashWriter {
+    file: RawFile,
+    buffer: [u8; 512],
+    len: usize,
+}
+
+impl CrashWriter {
+    fn new(file: RawFile) -> Self {
+        CrashWriter {
+            file,
+            buffer: [0; 512],
+            len: 0,
+        }
+    }
+
+    fn flush(&mut self) {
+        let mut written = 0;
+        while written < self.len {
+            let result = write_raw(self.file, &self.buffer[written..self.len]);
+            if result == 0 {
+                break;
+            }
+            written += result;
+        }
+        self.len = 0;
+    }
+}
+
+// Bytes written, 0 on error.
+#[cfg(unix)]
+fn write_raw(file: RawFile, bytes: &[u8]) -> usize {
+    let result = unsafe { libc::write(file, bytes.as_ptr() as *const libc::c_void, bytes.len()) };
+    result.max(0) as usize
+}
+
+#[cfg(windows)]
+fn write_raw(file: RawFile, bytes: &[u8]) -> usize {
+    let len = bytes.len().min(u32::MAX as usize) as u32;
+    let mut written = 0;
+    let ok = unsafe { win::WriteFile(file, bytes.as_ptr(), len, &mut written, ptr::null_mut()) };
+    if ok == 0 {
+        0
+    } else {
+        written as usize
+    }
+}
+
+impl fmt::Write for CrashWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for chunk in s.as_bytes().chunks(self.buffer.len()) {
+            if self.len + chunk.len() > self.buffer.len() {
+                self.flush();
+            }
+            self.buffer[self.len..self.len + chunk.len()].copy_from_slice(chunk);
+            self.len += chunk.len();
+        }
+        Ok(())
+    }
+}
+
+impl CrashSource for Counter {
+    fn dump(&self, out: &mut CrashWriter) {
+        let _ = writeln!(out, "{}", self.get());
+    }
+}
+
+impl CrashSource for Gauge {
+    fn dump(&self, out: &mut CrashWriter) {
+        let _ = writeln!(out, "{}", self.get());
+    }
+}
+
+impl CrashSource for FramePosition {
+    fn dump(&self, out: &mut CrashWriter) {
+        let _ = writeln!(out, "{}", self.get());
+    }
+}
+
+// The crashing thread may have been in the middle of a write, so only a
+// bounded number of read attempts is made.
+impl<T: Copy + Send + fmt::Debug> CrashSource for SeqLock<T> {
+    fn dump(&self, out: &mut CrashWriter) {
+        for _ in 0..100 {
+            if let Some(value) = self.try_read() {
+                let _ = writeln!(out, "{:?}", value);
+                return;
+            }
+        }
+        let _ = writeln!(out, "<write in progress>");
+    }
+}
+
+// The records queued in a log ring, oldest first, with the same fields as
+// `LogDrain::dump_to`: a ring line, then level, age and message per record.
+pub struct LogSource {
+    view: CrashView<rtlog::Record>,
+    dropped: Arc<Counter>,
+}
+
+impl LogSource {
+    pub fn new(drain: &LogDrain) -> Self {
+        let (view, dropped) = drain.crash_view();
+        LogSource { view, dropped }
+    }
+}
+
+impl CrashSource for LogSource {
+    fn dump(&self, out: &mut CrashWriter) {
+        let ring = match self.view.ring() {
+            Some(ring) => ring,
+            None => {
+                let _ = writeln!(out, "<closed>");
+                return;
+            }
+        };
+
+        let now = Instant::now();
+        write_ring(out, &ring, self.dropped.get());
+        ring.each(|record| {
+            let _ = writeln!(
+                out,
+                "{:?} {:?} {}",
+                record.level,
+                now.saturating_duration_since(record.timestamp),
+                record.message
+            );
+        });
+    }
+}
+
+// The events queued in every ring registered with a trace collector when
+// the source was made, as in `Collector::dump_to`: a ring line per thread,
+// then kind, depth, timestamp and name per event. The source keeps the
+// rings allocated, also those of threads that have exited since.
+pub struct TraceSource {
+    views: Vec<(u32, String, CrashView<trace::Event>)>,
+}
+
+impl TraceSource {
+    pub fn new(collector: &Collector) -> Self {
+        TraceSource {
+            views: collector.crash_views(),
+        }
+    }
+}
+
+impl CrashSource for TraceSource {
+    fn dump(&self, out: &mut CrashWriter) {
+        for (thread, name, view) in &self.views {
+            let ring = match view.ring() {
+                Some(ring) => ring,
+                None => continue,
+            };
+
+            let _ = write!(out, "thread {} {} ", thread, name);
+            write_ring(out, &ring, 0);
+            ring.each(|event| {
+                let _ = writeln!(
+                    out,
+                    "{:?} {} {} {}",
+                    event.kind, event.depth, event.timestamp_ns, event.name
+                );
+            });
+        }
+    }
+}
+
+fn write_ring<T>(out: &mut CrashWriter, ring: &crate::spsc::CrashRing<T>, dropped: u64) {
+    let _ = writeln!(
+        out,
+        "capacity {} read {} write {} dropped {}",
+        ring.capacity, ring.read_index, ring.write_index, dropped
+    );
+}
+
+// The value last published to a triple buffer, read without swapping. It's
+// copied straight out of the buffer, so it can be torn if the reader swaps
+// it out and the writer reuses it while this runs.
+pub struct TripleBufferSource<T> {
+    latest: Latest<T>,
+}
+
+impl<T> TripleBufferSource<T> {
+    pub fn new(reader: &triple_buffer::Reader<T>) -> Self {
+        TripleBufferSource {
+            latest: reader.latest(),
+        }
+    }
+}
+
+impl<T: Copy + Send + fmt::Debug> CrashSource for TripleBufferSource<T> {
+    fn dump(&self, out: &mut CrashWriter) {
+        let _ = writeln!(out, "{:?}", self.latest.get());
+    }
+}
+
+#[cfg(windows)]
+mod win {
+    use std::ffi::c_void;
+
+    pub const EXCEPTION_CONTINUE_SEARCH: i32 = 0;
+
+    #[repr(C)]
+    pub struct ExceptionRecord {
+        pub exception_code: u32,
+        pub exception_flags: u32,
+        pub exception_record: *mut ExceptionRecord,
+        pub exception_address: *mut c_void,
+        pub number_parameters: u32,
+        pub exception_information: [usize; 15],
+    }
+
+    #[repr(C)]
+    pub struct ExceptionPointers {
+        pub exception_record: *mut ExceptionRecord,
+        pub context_record: *mut c_void,
+    }
+
+    pub type Filter = Option<unsafe extern "system" fn(*mut ExceptionPointers) -> i32>;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        pub fn SetUnhandledExceptionFilter(filter: Filter) -> Filter;
+        pub fn WriteFile(
+            file: *mut c_void,
+            buffer: *const u8,
+            len: u32,
+            written: *mut u32,
+            overlapped: *mut c_void,
+        ) -> i32;
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+
+    use std::sync::Mutex;
+
+    use crate::rtlog::Level;
+    use crate::shm::temp_path;
+
+    // The handlers are process-wide.
+    static LOCK: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn writer_buffers_across_flushes() {
+        let path = temp_path("crash-writer");
+        let file = std::fs::File::create(&path).unwrap();
+        let fd = std::os::unix::io::AsRawFd::as_raw_fd(&file);
+
+        let mut out = CrashWriter::new(fd);
+        let long = "x".repeat(1500);
+        write!(out, "{}|{}", long, 42).unwrap();
+        out.flush();
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            format!("{}|42", long)
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    // Runs `setup`, then `child` in a forked process, and returns the
+    // child's wait status. This process's actions are restored afterwards.
+    fn crash_child(setup: impl FnOnce(), child: impl FnOnce()) -> libc::c_int {
+        let mut saved: Previous = unsafe { std::mem::zeroed() };
+        for (i, &signal) in SIGNALS.iter().enumerate() {
+            unsafe { libc::sigaction(signal, ptr::null(), &mut saved[i]) };
+        }
+
+        setup();
+
+        let pid = unsafe { libc::fork() };
+        if pid == 0 {
+            child();
+            unsafe { libc::_exit(0) };
+        }
+
+        let mut status = 0;
+        unsafe { libc::waitpid(pid, &mut status, 0) };
+
+        for (i, &signal) in SIGNALS.iter().enumerate() {
+            unsafe { libc::sigaction(signal, &saved[i], ptr::null_mut()) };
+        }
+        status
+    }
+
+    #[test]
+    fn dump_on_crash() {
+        let _lock = LOCK.lock().unwrap();
+        let path = temp_path("crash");
+        let xruns = Arc::new(Counter::new());
+        xruns.add(3);
+        let position = Arc::new(FramePosition::new(4800));
+        let state = Arc::new(SeqLock::new((1u8, 0.5f32)));
+
+        let (mut params, mut reader) = triple_buffer::triple_buffer((0u8, 0.0f32));
+        params.write((2, 0.25));
+        // The reader holding the latest value doesn't hide it from the dump.
+        assert_eq!(*reader.read(), (2, 0.25));
+
+        let (mut logger, drain) = rtlog::logger(4);
+        logger.log_str(Level::Warn, "xrun");
+
+        let collector = Collector::new();
+        let thread = collector.register_current_thread("audio", 4);
+        {
+            crate::rt_scope!("process");
+        }
+
+        // The child only calls raise() before the handler takes over.
+        let handlers = vec![
+            CrashHandler::new("xruns", xruns),
+            CrashHandler::new("position", position),
+            CrashHandler::new("state", state),
+            CrashHandler::new("params", Arc::new(TripleBufferSource::new(&reader))),
+            CrashHandler::new("log", Arc::new(LogSource::new(&drain))),
+            CrashHandler::new("trace", Arc::new(TraceSource::new(&collector))),
+        ];
+        let status = crash_child(
+            || install_crash_dumper(&path, handlers).unwrap(),
+            || unsafe {
+                libc::raise(libc::SIGABRT);
+            },
+        );
+        assert!(libc::WIFSIGNALED(status));
+        assert_eq!(libc::WTERMSIG(status), libc::SIGABRT);
+
+        let dump = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        // Ages and timestamps vary, so the last lines are only checked up
+        // to them.
+        let (fixed, rest) = dump.split_at(dump.find("Warn ").unwrap());
+        assert_eq!(
+            fixed,
+            format!(
+                "rt_utils crash dump\nsignal {}\n\n[xruns]\n3\n\n[position]\n4800\n\n\
+                 [state]\n(1, 0.5)\n\n[params]\n(2, 0.25)\n\n\
+                 [log]\ncapacity 4 read 0 write 1 dropped 0\n",
+                libc::SIGABRT
+            )
+        );
+
+        let lines: Vec<_> = rest.lines().collect();
+        assert_eq!(lines.len(), 6);
+        assert!(lines[0].starts_with("Warn ") && lines[0].ends_with(" xrun"));
+        assert_eq!(lines[1..3], ["", "[trace]"]);
+        assert_eq!(
+            lines[3],
+            format!(
+                "thread {} audio capacity 4 read 0 write 2 dropped 0",
+                thread
+            )
+        );
+        assert!(lines[4].starts_with("Enter 0 ") && lines[4].ends_with(" process"));
+        assert!(lines[5].starts_with("Exit 0 ") && lines[5].ends_with(" process"));
+    }
+
+    extern "C" fn exit_42(_: libc::c_int) {
+        unsafe { libc::_exit(42) };
+    }
+
+    #[test]
+    fn chains_previous_action() {
+        let _lock = LOCK.lock().unwrap();
+        let path = temp_path("crash-chain");
+        let xruns = Arc::new(Counter::new());
+
+        let status = crash_child(
+            || {
+                unsafe { libc::signal(libc::SIGFPE, exit_42 as *const () as libc::sighandler_t) };
+                install_crash_dumper(&path, Vec::new()).unwrap();
+                // Installing again still chains to the action from before
+                // the first install, not to the dumper itself.
+                install_crash_dumper(&path, vec![CrashHandler::new("xruns", xruns)]).unwrap();
+            },
+            || unsafe {
+                libc::raise(libc::SIGFPE);
+            },
+        );
+        assert!(libc::WIFEXITED(status));
+        assert_eq!(libc::WEXITSTATUS(status), 42);
+
+        let dump = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(
+            dump,
+            format!(
+                "rt_utils crash dump\nsignal {}\n\n[xruns]\n0\n",
+                libc::SIGFPE
+            )
+        );
+    }
+}
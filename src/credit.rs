@@ -0,0 +1,117 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::spsc;
+
+// SPSC channel with explicit, consumer-granted credits. Every send spends a
+// credit and fails once they run out, even if the queue has free slots; the
+// consumer grants more as it gets through work (say one per processed
+// block). This bounds how far ahead the producer can get in terms the
+// consumer chooses, i.e. end-to-end latency rather than queue occupancy.
+pub struct CreditSender<T> {
+    sender: spsc::Sender<T>,
+    credits: Arc<AtomicUsize>,
+}
+
+pub struct CreditReceiver<T> {
+    receiver: spsc::Receiver<T>,
+    credits: Arc<AtomicUsize>,
+}
+
+impl<T> CreditSender<T> {
+    pub fn try_send(&mut self, value: T) -> Result<(), T> {
+        if self
+            .credits
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |n| n.checked_sub(1))
+            .is_err()
+        {
+            return Err(value);
+        }
+
+        // A full queue hands the credit back.
+        self.sender.try_send(value).map_err(|e| {
+            self.credits.fetch_add(1, Ordering::Release);
+            e.into_inner()
+        })
+    }
+
+    pub fn credits(&self) -> usize {
+        self.credits.load(Ordering::Relaxed)
+    }
+
+    pub fn is_receiver_active(&self) -> bool {
+        self.sender.is_receiver_active()
+    }
+}
+
+impl<T> CreditReceiver<T> {
+    pub fn try_recv(&mut self) -> Option<T> {
+        self.receiver.try_recv().ok()
+    }
+
+    pub fn grant(&self, credits: usize) {
+        self.credits.fetch_add(credits, Ordering::Release);
+    }
+
+    // Credits granted but not yet spent by the sender.
+    pub fn outstanding(&self) -> usize {
+        self.credits.load(Ordering::Relaxed)
+    }
+
+    pub fn size(&self) -> usize {
+        self.receiver.size()
+    }
+
+    pub fn is_sender_active(&self) -> bool {
+        self.receiver.is_sender_active()
+    }
+}
+
+pub fn credit_channel<T>(
+    size: usize,
+    initial_credits: usize,
+) -> (CreditSender<T>, CreditReceiver<T>) {
+    let (sender, receiver) = spsc::channel(size);
+    let credits = Arc::new(AtomicUsize::new(initial_credits));
+
+    (
+        CreditSender {
+            sender,
+            credits: credits.clone(),
+        },
+        CreditReceiver { receiver, credits },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn credits_bound_sends() {
+        let (mut send, mut recv) = credit_channel(8, 2);
+        send.try_send(1).unwrap();
+        send.try_send(2).unwrap();
+        assert_eq!(send.try_send(3), Err(3));
+        assert_eq!(recv.size(), 2);
+
+        assert_eq!(recv.try_recv(), Some(1));
+        assert_eq!(send.try_send(3), Err(3));
+
+        recv.grant(1);
+        assert_eq!(recv.outstanding(), 1);
+        send.try_send(3).unwrap();
+        assert_eq!(send.credits(), 0);
+    }
+
+    #[test]
+    fn full_queue_keeps_credit() {
+        let (mut send, mut recv) = credit_channel(1, 5);
+        send.try_send(1).unwrap();
+        assert_eq!(send.try_send(2), Err(2));
+        assert_eq!(send.credits(), 4);
+
+        recv.try_recv();
+        send.try_send(2).unwrap();
+    }
+}
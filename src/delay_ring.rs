@@ -0,0 +1,208 @@
+use std::sync::atomic::{fence, AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+
+// Delay line with reads at an offset behind the write head, for limiter
+// lookahead and latency compensation. The writer owns the head; `DelayTap`s
+// read the same history from other threads without copying it.
+//
+// `read(delay, out)` fills `out` with the samples that ended `delay` samples
+// before the write head; `delay + out.len()` may not exceed the capacity.
+// Positions before the first written sample read as silence.
+//
+// Storage follows `multi_ring`: f32 bits in relaxed atomics, with
+// `write_begin`/`write_end` letting a tap detect that the writer lapped it
+// mid-read.
+struct Shared {
+    samples: Box<[AtomicU32]>,
+    write_begin: AtomicU64,
+    write_end: AtomicU64,
+}
+
+pub struct DelayRing {
+    shared: Arc<Shared>,
+}
+
+pub struct DelayTap {
+    shared: Arc<Shared>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Overrun;
+
+impl DelayRing {
+    pub fn write(&self, samples: &[f32]) {
+        let shared = &*self.shared;
+        let start = shared.write_end.load(Ordering::Relaxed);
+        let end = start + samples.len() as u64;
+
+        shared.write_begin.store(end, Ordering::Relaxed);
+        fence(Ordering::Release);
+
+        for (i, sample) in samples.iter().enumerate() {
+            shared.samples[shared.slot(start + i as u64)]
+                .store(sample.to_bits(), Ordering::Relaxed);
+        }
+
+        shared.write_end.store(end, Ordering::Release);
+    }
+
+    // The writer can't race itself, so reads from this side always succeed.
+    pub fn read(&self, delay: usize, out: &mut [f32]) {
+        let end = self.position();
+        self.shared.copy(end, delay, out);
+    }
+
+    pub fn position(&self) -> u64 {
+        self.shared.write_end.load(Ordering::Relaxed)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.samples.len()
+    }
+
+    pub fn tap(&self) -> DelayTap {
+        DelayTap {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl DelayTap {
+    pub fn read(&self, delay: usize, out: &mut [f32]) -> Result<(), Overrun> {
+        let end = self.position();
+        self.read_from(end, delay, out)
+    }
+
+    // Reads relative to a head position observed earlier, e.g. one
+    // published alongside a block by the writer, so several taps can agree
+    // on the same window.
+    pub fn read_at(&self, position: u64, out: &mut [f32]) -> Result<(), Overrun> {
+        let end = position + out.len() as u64;
+        assert!(end <= self.position(), "Can not read past the write head");
+        self.read_from(end, 0, out)
+    }
+
+    pub fn position(&self) -> u64 {
+        self.shared.write_end.load(Ordering::Acquire)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.samples.len()
+    }
+
+    fn read_from(&self, end: u64, delay: usize, out: &mut [f32]) -> Result<(), Overrun> {
+        self.shared.copy(end, delay, out);
+
+        fence(Ordering::Acquire);
+        let write_begin = self.shared.write_begin.load(Ordering::Relaxed);
+        let oldest = end.saturating_sub((delay + out.len()) as u64);
+        if write_begin - oldest > self.capacity() as u64 {
+            return Err(Overrun);
+        }
+
+        Ok(())
+    }
+}
+
+impl Shared {
+    fn slot(&self, position: u64) -> usize {
+        (position % self.samples.len() as u64) as usize
+    }
+
+    fn copy(&self, end: u64, delay: usize, out: &mut [f32]) {
+        assert!(
+            delay + out.len() <= self.samples.len(),
+            "Delay exceeds ring capacity"
+        );
+
+        let start = end as i64 - (delay + out.len()) as i64;
+        for (i, sample) in out.iter_mut().enumerate() {
+            let position = start + i as i64;
+            *sample = if position < 0 {
+                0.0
+            } else {
+                f32::from_bits(self.samples[self.slot(position as u64)].load(Ordering::Relaxed))
+            };
+        }
+    }
+}
+
+pub fn delay_ring(capacity: usize) -> DelayRing {
+    assert!(capacity > 0, "Can not create delay ring with zero capacity");
+
+    DelayRing {
+        shared: Arc::new(Shared {
+            samples: (0..capacity).map(|_| AtomicU32::new(0)).collect(),
+            write_begin: AtomicU64::new(0),
+            write_end: AtomicU64::new(0),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn delayed_reads() {
+        let ring = delay_ring(8);
+        ring.write(&[1.0, 2.0, 3.0, 4.0, 5.0]);
+
+        let mut out = [0.0; 2];
+        ring.read(0, &mut out);
+        assert_eq!(out, [4.0, 5.0]);
+        ring.read(2, &mut out);
+        assert_eq!(out, [2.0, 3.0]);
+    }
+
+    #[test]
+    fn underfill_reads_silence() {
+        let ring = delay_ring(8);
+        ring.write(&[1.0, 2.0]);
+
+        let mut out = [9.0; 4];
+        ring.read(1, &mut out);
+        assert_eq!(out, [0.0, 0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn wraparound() {
+        let ring = delay_ring(4);
+        let mut out = [0.0; 3];
+
+        for i in 0..10 {
+            ring.write(&[i as f32]);
+            if i >= 3 {
+                ring.read(1, &mut out);
+                assert_eq!(out, [i as f32 - 3.0, i as f32 - 2.0, i as f32 - 1.0]);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn delay_beyond_capacity() {
+        let ring = delay_ring(4);
+        ring.read(3, &mut [0.0; 2]);
+    }
+
+    #[test]
+    fn taps() {
+        let ring = delay_ring(4);
+        let tap = ring.tap();
+        ring.write(&[1.0, 2.0, 3.0]);
+
+        let mut out = [0.0; 2];
+        assert_eq!(tap.read(1, &mut out), Ok(()));
+        assert_eq!(out, [1.0, 2.0]);
+
+        let position = tap.position() - 2;
+        assert_eq!(tap.read_at(position, &mut out), Ok(()));
+        assert_eq!(out, [2.0, 3.0]);
+
+        ring.write(&[4.0, 5.0, 6.0]);
+        assert_eq!(tap.read_at(position, &mut out), Err(Overrun));
+        assert_eq!(tap.read_at(position + 2, &mut out), Ok(()));
+        assert_eq!(out, [4.0, 5.0]);
+    }
+}
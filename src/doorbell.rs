@@ -0,0 +1,253 @@
+#[cfg(feature = "std")]
+use std::thread::{self, Thread};
+
+use crate::cache::CacheMaintenance;
+use crate::spsc::{self, Counter, TryRecvError, TrySendError};
+use crate::static_spsc::{Consumer, Producer};
+#[cfg(feature = "std")]
+use crate::wait::WaitStrategy;
+
+// Notification hook for channel ends whose peer can't be reached by a
+// thread wake: the other core of an asymmetric system (a Cortex-A host and
+// a Cortex-M audio coprocessor sharing memory, say), where the driver
+// raises an IPI or writes a mailbox register. Queues in memory both cores
+// can see would be a `StaticSpscQueue` placed there.
+//
+// `ring` runs on the notifying end's thread right after it made progress,
+// possibly RT code, so it must not block. The peer has to cope with
+// coalesced and spurious rings: it drains until `Empty` on every ring.
+pub trait Doorbell {
+    fn ring(&self);
+}
+
+// A closure, for drivers that are a single register write.
+impl<F: Fn()> Doorbell for F {
+    fn ring(&self) {
+        self()
+    }
+}
+
+// Unparks a thread, for a peer on the same machine waiting with
+// `WaitStrategy::Park`, which then wakes right away instead of at the end
+// of its backoff.
+#[cfg(feature = "std")]
+#[derive(Clone)]
+pub struct ThreadDoorbell(Thread);
+
+#[cfg(feature = "std")]
+impl ThreadDoorbell {
+    pub fn new(thread: Thread) -> Self {
+        ThreadDoorbell(thread)
+    }
+
+    pub fn current() -> Self {
+        ThreadDoorbell(thread::current())
+    }
+}
+
+#[cfg(feature = "std")]
+impl Doorbell for ThreadDoorbell {
+    fn ring(&self) {
+        self.0.unpark();
+    }
+}
+
+// A channel end that rings `doorbell` whenever it made progress the peer may
+// be waiting for: a sending end after queueing values, a receiving end
+// after freeing slots.
+pub struct DoorbellEnd<E, D> {
+    endpoint: E,
+    doorbell: D,
+}
+
+impl<E, D: Doorbell> DoorbellEnd<E, D> {
+    pub fn new(endpoint: E, doorbell: D) -> Self {
+        DoorbellEnd { endpoint, doorbell }
+    }
+
+    pub fn into_inner(self) -> (E, D) {
+        (self.endpoint, self.doorbell)
+    }
+
+    fn ring_if(&self, progress: bool) {
+        if progress {
+            self.doorbell.ring();
+        }
+    }
+}
+
+impl<T, D: Doorbell> DoorbellEnd<spsc::Sender<T>, D> {
+    pub fn try_send(&mut self, value: T) -> Result<(), TrySendError<T>> {
+        self.endpoint.try_send(value)?;
+        self.doorbell.ring();
+        Ok(())
+    }
+
+    pub fn write_slice(&mut self, data: &[T]) -> usize
+    where
+        T: Copy,
+    {
+        let written = self.endpoint.write_slice(data);
+        self.ring_if(written > 0);
+        written
+    }
+
+    pub fn size(&self) -> usize {
+        self.endpoint.size()
+    }
+
+    pub fn is_receiver_active(&self) -> bool {
+        self.endpoint.is_receiver_active()
+    }
+}
+
+impl<T, D: Doorbell> DoorbellEnd<spsc::Receiver<T>, D> {
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let value = self.endpoint.try_recv()?;
+        self.doorbell.ring();
+        Ok(value)
+    }
+
+    pub fn read_slice(&mut self, out: &mut [T]) -> usize
+    where
+        T: Copy,
+    {
+        let read = self.endpoint.read_slice(out);
+        self.ring_if(read > 0);
+        read
+    }
+
+    // For non-RT threads, see `spsc::Receiver::recv_blocking`.
+    #[cfg(feature = "std")]
+    pub fn recv_blocking(&mut self, strategy: WaitStrategy) -> Option<T> {
+        let value = self.endpoint.recv_blocking(strategy)?;
+        self.doorbell.ring();
+        Some(value)
+    }
+
+    pub fn size(&self) -> usize {
+        self.endpoint.size()
+    }
+
+    pub fn is_sender_active(&self) -> bool {
+        self.endpoint.is_sender_active()
+    }
+}
+
+impl<T, const N: usize, C: Counter, M: CacheMaintenance, D: Doorbell>
+    DoorbellEnd<Producer<'_, T, N, C, M>, D>
+{
+    pub fn try_send(&mut self, value: T) -> Result<(), T> {
+        self.endpoint.try_send(value)?;
+        self.doorbell.ring();
+        Ok(())
+    }
+
+    pub fn size(&self) -> usize {
+        self.endpoint.size()
+    }
+}
+
+impl<T, const N: usize, C: Counter, M: CacheMaintenance, D: Doorbell>
+    DoorbellEnd<Consumer<'_, T, N, C, M>, D>
+{
+    pub fn try_recv(&mut self) -> Option<T> {
+        let value = self.endpoint.try_recv()?;
+        self.doorbell.ring();
+        Some(value)
+    }
+
+    pub fn size(&self) -> usize {
+        self.endpoint.size()
+    }
+}
+
+// An spsc channel with a doorbell on each end: `to_receiver` is rung by the
+// sender after sending, `to_sender` by the receiver after receiving. Pass
+// `|| {}` for a direction nobody waits on.
+pub fn channel<T, S: Doorbell, R: Doorbell>(
+    size: usize,
+    to_receiver: S,
+    to_sender: R,
+) -> (
+    DoorbellEnd<spsc::Sender<T>, S>,
+    DoorbellEnd<spsc::Receiver<T>, R>,
+) {
+    let (sender, receiver) = spsc::channel(size);
+    (
+        DoorbellEnd::new(sender, to_receiver),
+        DoorbellEnd::new(receiver, to_sender),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::cell::Cell;
+    #[cfg(feature = "std")]
+    use std::time::Duration;
+
+    use crate::static_spsc::StaticSpscQueue;
+
+    #[test]
+    fn rings_on_progress() {
+        let sent = Cell::new(0);
+        let received = Cell::new(0);
+        let (mut send, mut recv) = channel(
+            2,
+            || sent.set(sent.get() + 1),
+            || received.set(received.get() + 1),
+        );
+
+        send.try_send(1).unwrap();
+        assert_eq!(send.write_slice(&[2, 3]), 1);
+        assert!(send.try_send(4).is_err());
+        assert_eq!(sent.get(), 2);
+
+        assert_eq!(recv.try_recv(), Ok(1));
+        let mut out = [0; 4];
+        assert_eq!(recv.read_slice(&mut out), 1);
+        assert_eq!(recv.read_slice(&mut out), 0);
+        assert_eq!(recv.try_recv(), Err(TryRecvError::Empty));
+        assert_eq!(received.get(), 2);
+    }
+
+    #[test]
+    fn static_queue() {
+        let rings = Cell::new(0);
+        let mut queue = StaticSpscQueue::<u32, 4>::new();
+        let (producer, consumer) = queue.split();
+        let mut producer = DoorbellEnd::new(producer, || rings.set(rings.get() + 1));
+        let mut consumer = DoorbellEnd::new(consumer, || {});
+
+        producer.try_send(7).unwrap();
+        assert_eq!(rings.get(), 1);
+        assert_eq!(consumer.try_recv(), Some(7));
+        assert_eq!(consumer.try_recv(), None);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn wakes_parked_thread() {
+        let (send, recv) = spsc::channel::<u32>(4);
+        let receiver = thread::spawn(move || {
+            let mut recv = DoorbellEnd::new(recv, || {});
+            // Would take a second per value without the doorbell.
+            let strategy = WaitStrategy::Park {
+                min: Duration::from_secs(1),
+                max: Duration::from_secs(1),
+            };
+            (0..3)
+                .map(|_| recv.recv_blocking(strategy).unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        let mut send = DoorbellEnd::new(send, ThreadDoorbell::new(receiver.thread().clone()));
+        for i in 0..3 {
+            thread::sleep(Duration::from_millis(5));
+            send.try_send(i).unwrap();
+        }
+        assert_eq!(receiver.join().unwrap(), [0, 1, 2]);
+    }
+}
@@ -0,0 +1,247 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::rtlog::Level;
+use crate::trace::EventKind;
+
+// Post-mortem dumps of the rtlog and trace rings. `LogDrain::dump_to` and
+// `Collector::dump_to` write the records still queued in their rings,
+// without consuming them, along with each ring's index state; `read_dump`
+// parses the file back, typically in a separate tool after a crash.
+//
+// Layout, all integers little endian:
+//
+//   magic "RTDUMP01", kind u8, ring count u32, then per ring:
+//   id u32, name (u16 length + UTF-8), capacity u64, read index u64,
+//   write index u64, dropped u64, record count u32, records.
+//
+//   log record: level u8, age in ns at dump time u64, message (u16 + UTF-8)
+//   trace record: kind u8, depth u16, timestamp ns u64, name (u16 + UTF-8)
+
+const MAGIC: &[u8; 8] = b"RTDUMP01";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DumpKind {
+    Log,
+    Trace,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dump {
+    pub kind: DumpKind,
+    pub rings: Vec<DumpedRing>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DumpedRing {
+    pub id: u32,
+    pub name: String,
+    pub capacity: u64,
+    pub read_index: u64,
+    pub write_index: u64,
+    pub dropped: u64,
+    pub entries: Vec<DumpEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DumpEntry {
+    Log {
+        level: Level,
+        age: Duration,
+        message: String,
+    },
+    Trace {
+        kind: EventKind,
+        depth: u16,
+        timestamp_ns: u64,
+        name: String,
+    },
+}
+
+// Index state of a ring at dump time, as reported by the receiver.
+pub(crate) struct RingState {
+    pub id: u32,
+    pub name: String,
+    pub capacity: usize,
+    pub read_index: usize,
+    pub write_index: usize,
+    pub dropped: u64,
+}
+
+pub(crate) struct DumpWriter {
+    out: BufWriter<File>,
+}
+
+impl DumpWriter {
+    pub fn create<P: AsRef<Path>>(path: P, kind: DumpKind, rings: u32) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(MAGIC)?;
+        out.write_all(&[match kind {
+            DumpKind::Log => 1,
+            DumpKind::Trace => 2,
+        }])?;
+        out.write_all(&rings.to_le_bytes())?;
+
+        Ok(DumpWriter { out })
+    }
+
+    pub fn ring(&mut self, state: &RingState, records: u32) -> io::Result<()> {
+        self.out.write_all(&state.id.to_le_bytes())?;
+        self.string(&state.name)?;
+        for value in &[
+            state.capacity as u64,
+            state.read_index as u64,
+            state.write_index as u64,
+            state.dropped,
+        ] {
+            self.out.write_all(&value.to_le_bytes())?;
+        }
+        self.out.write_all(&records.to_le_bytes())
+    }
+
+    pub fn log_record(&mut self, level: Level, age: Duration, message: &str) -> io::Result<()> {
+        self.out.write_all(&[level as u8])?;
+        self.out.write_all(&(age.as_nanos() as u64).to_le_bytes())?;
+        self.string(message)
+    }
+
+    pub fn trace_record(
+        &mut self,
+        kind: EventKind,
+        depth: u16,
+        timestamp_ns: u64,
+        name: &str,
+    ) -> io::Result<()> {
+        self.out.write_all(&[kind as u8])?;
+        self.out.write_all(&depth.to_le_bytes())?;
+        self.out.write_all(&timestamp_ns.to_le_bytes())?;
+        self.string(name)
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    fn string(&mut self, s: &str) -> io::Result<()> {
+        let len = s.len().min(u16::MAX as usize);
+        self.out.write_all(&(len as u16).to_le_bytes())?;
+        self.out.write_all(&s.as_bytes()[..len])
+    }
+}
+
+pub fn read_dump<P: AsRef<Path>>(path: P) -> io::Result<Dump> {
+    let mut input = BufReader::new(File::open(path)?);
+
+    let mut magic = [0; 8];
+    input.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid("not an rt_utils dump"));
+    }
+
+    let kind = match read_u8(&mut input)? {
+        1 => DumpKind::Log,
+        2 => DumpKind::Trace,
+        _ => return Err(invalid("unknown dump kind")),
+    };
+
+    let ring_count = read_u32(&mut input)?;
+    let mut rings = Vec::new();
+    for _ in 0..ring_count {
+        let id = read_u32(&mut input)?;
+        let name = read_string(&mut input)?;
+        let capacity = read_u64(&mut input)?;
+        let read_index = read_u64(&mut input)?;
+        let write_index = read_u64(&mut input)?;
+        let dropped = read_u64(&mut input)?;
+
+        let count = read_u32(&mut input)?;
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            entries.push(match kind {
+                DumpKind::Log => read_log_entry(&mut input)?,
+                DumpKind::Trace => read_trace_entry(&mut input)?,
+            });
+        }
+
+        rings.push(DumpedRing {
+            id,
+            name,
+            capacity,
+            read_index,
+            write_index,
+            dropped,
+            entries,
+        });
+    }
+
+    Ok(Dump { kind, rings })
+}
+
+fn read_log_entry(input: &mut impl Read) -> io::Result<DumpEntry> {
+    let level = match read_u8(input)? {
+        0 => Level::Error,
+        1 => Level::Warn,
+        2 => Level::Info,
+        3 => Level::Debug,
+        _ => return Err(invalid("unknown log level")),
+    };
+    let age = Duration::from_nanos(read_u64(input)?);
+    let message = read_string(input)?;
+
+    Ok(DumpEntry::Log {
+        level,
+        age,
+        message,
+    })
+}
+
+fn read_trace_entry(input: &mut impl Read) -> io::Result<DumpEntry> {
+    let kind = match read_u8(input)? {
+        0 => EventKind::Enter,
+        1 => EventKind::Exit,
+        _ => return Err(invalid("unknown trace event kind")),
+    };
+    let mut depth = [0; 2];
+    input.read_exact(&mut depth)?;
+    let timestamp_ns = read_u64(input)?;
+    let name = read_string(input)?;
+
+    Ok(DumpEntry::Trace {
+        kind,
+        depth: u16::from_le_bytes(depth),
+        timestamp_ns,
+        name,
+    })
+}
+
+fn read_u8(input: &mut impl Read) -> io::Result<u8> {
+    let mut byte = [0];
+    input.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn read_u32(input: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(input: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_string(input: &mut impl Read) -> io::Result<String> {
+    let mut len = [0; 2];
+    input.read_exact(&mut len)?;
+    let mut bytes = vec![0; u16::from_le_bytes(len) as usize];
+    input.read_exact(&mut bytes)?;
+    String::from_utf8(bytes).map_err(|_| invalid("invalid UTF-8 in dump"))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,161 @@
+use std::time::Instant;
+
+use crate::spsc;
+
+// Channel whose messages carry a priority and an optional deadline, with a
+// per-channel policy for what the consumer throws away while draining:
+// messages past their deadline, and, when the queue was congested at the
+// start of the drain, messages below a priority threshold. The producer
+// side stays a plain wait-free `try_send`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EvictionPolicy {
+    // Share of the capacity (0.0 - 1.0) at which the queue counts as
+    // congested.
+    pub congested_at: f32,
+    // While congested, messages with a lower priority are evicted.
+    pub min_priority: u8,
+    pub evict_expired: bool,
+}
+
+impl Default for EvictionPolicy {
+    fn default() -> Self {
+        EvictionPolicy {
+            congested_at: 0.75,
+            min_priority: 1,
+            evict_expired: true,
+        }
+    }
+}
+
+struct Envelope<T> {
+    value: T,
+    priority: u8,
+    deadline: Option<Instant>,
+}
+
+pub struct PrioritySender<T> {
+    sender: spsc::Sender<Envelope<T>>,
+}
+
+pub struct PriorityReceiver<T> {
+    receiver: spsc::Receiver<Envelope<T>>,
+    policy: EvictionPolicy,
+    capacity: usize,
+    expired: u64,
+    low_priority: u64,
+}
+
+impl<T> PrioritySender<T> {
+    pub fn try_send(&mut self, value: T, priority: u8, deadline: Option<Instant>) -> Result<(), T> {
+        self.sender
+            .try_send(Envelope {
+                value,
+                priority,
+                deadline,
+            })
+            .map_err(|e| e.into_inner().value)
+    }
+}
+
+impl<T> PriorityReceiver<T> {
+    // Hands every message queued at the start of the call to `f`, except the
+    // ones the policy evicts. Returns the number delivered.
+    pub fn drain(&mut self, now: Instant, mut f: impl FnMut(T)) -> usize {
+        let queued = self.receiver.size();
+        let congested = queued as f32 >= self.policy.congested_at * self.capacity as f32;
+
+        let mut delivered = 0;
+        for _ in 0..queued {
+            let envelope = match self.receiver.try_recv() {
+                Ok(envelope) => envelope,
+                Err(_) => break,
+            };
+
+            if self.policy.evict_expired && envelope.deadline.is_some_and(|d| d < now) {
+                self.expired += 1;
+            } else if congested && envelope.priority < self.policy.min_priority {
+                self.low_priority += 1;
+            } else {
+                f(envelope.value);
+                delivered += 1;
+            }
+        }
+        delivered
+    }
+
+    pub fn policy(&self) -> &EvictionPolicy {
+        &self.policy
+    }
+
+    // Messages evicted for missing their deadline and for low priority.
+    pub fn evicted(&self) -> (u64, u64) {
+        (self.expired, self.low_priority)
+    }
+}
+
+pub fn priority_channel<T>(
+    size: usize,
+    policy: EvictionPolicy,
+) -> (PrioritySender<T>, PriorityReceiver<T>) {
+    let (sender, receiver) = spsc::channel(size);
+
+    (
+        PrioritySender { sender },
+        PriorityReceiver {
+            receiver,
+            policy,
+            capacity: size,
+            expired: 0,
+            low_priority: 0,
+        },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::time::Duration;
+
+    fn drain_all(receiver: &mut PriorityReceiver<u32>, now: Instant) -> Vec<u32> {
+        let mut out = Vec::new();
+        receiver.drain(now, |v| out.push(v));
+        out
+    }
+
+    #[test]
+    fn evicts_expired() {
+        let (mut send, mut recv) = priority_channel(8, EvictionPolicy::default());
+        let now = Instant::now();
+
+        send.try_send(1, 0, Some(now - Duration::from_millis(1)))
+            .unwrap();
+        send.try_send(2, 0, Some(now + Duration::from_millis(1)))
+            .unwrap();
+        send.try_send(3, 0, None).unwrap();
+
+        assert_eq!(drain_all(&mut recv, now), [2, 3]);
+        assert_eq!(recv.evicted(), (1, 0));
+    }
+
+    #[test]
+    fn evicts_low_priority_when_congested() {
+        let policy = EvictionPolicy {
+            congested_at: 0.5,
+            min_priority: 5,
+            evict_expired: false,
+        };
+        let (mut send, mut recv) = priority_channel(4, policy);
+        let now = Instant::now();
+
+        send.try_send(1, 0, None).unwrap();
+        assert_eq!(drain_all(&mut recv, now), [1]);
+
+        send.try_send(1, 0, None).unwrap();
+        send.try_send(2, 9, None).unwrap();
+        send.try_send(3, 4, Some(now - Duration::from_secs(1)))
+            .unwrap();
+        assert_eq!(drain_all(&mut recv, now), [2]);
+        assert_eq!(recv.evicted(), (0, 2));
+    }
+}
@@ -0,0 +1,119 @@
+use std::time::Duration;
+
+use crate::triple_buffer::{triple_buffer, Reader, Writer};
+
+// Latest-value mailbox from a non-RT producer (a motion planner, say) to an
+// RT consumer (the motor control loop) that falls back to a safe value when
+// the producer stops updating. The producer publishes new values, or beats
+// with the current one when it has nothing new; if no commit arrives for
+// `max_age`, `Monitored::get` returns the fallback until one does.
+pub struct MonitoredWriter<T> {
+    writer: Writer<T>,
+}
+
+pub struct Monitored<T> {
+    reader: Reader<T>,
+    fallback: T,
+    max_age: Duration,
+    failsafe: bool,
+    trips: u64,
+}
+
+impl<T> MonitoredWriter<T> {
+    pub fn publish(&mut self, value: T) {
+        self.writer.write(value);
+    }
+
+    // Recommits the last published value, to keep the consumer out of
+    // failsafe while nothing changes.
+    pub fn heartbeat(&mut self)
+    where
+        T: Clone,
+    {
+        let value = self.writer.last_written().clone();
+        self.writer.write(value);
+    }
+
+    pub fn last_published(&self) -> &T {
+        self.writer.last_written()
+    }
+}
+
+impl<T> Monitored<T> {
+    // The latest value, or the fallback if it's older than `max_age`.
+    pub fn get(&mut self) -> &T {
+        match self.reader.read_fresh(self.max_age) {
+            Ok(value) => {
+                self.failsafe = false;
+                value
+            }
+            Err(_) => {
+                if !self.failsafe {
+                    self.failsafe = true;
+                    self.trips += 1;
+                }
+                &self.fallback
+            }
+        }
+    }
+
+    // Whether the last `get` returned the fallback.
+    pub fn is_failsafe(&self) -> bool {
+        self.failsafe
+    }
+
+    // How many times `get` has switched to the fallback.
+    pub fn trips(&self) -> u64 {
+        self.trips
+    }
+
+    pub fn max_age(&self) -> Duration {
+        self.max_age
+    }
+}
+
+pub fn monitored<T: Clone>(
+    initial: T,
+    fallback: T,
+    max_age: Duration,
+) -> (MonitoredWriter<T>, Monitored<T>) {
+    let (writer, reader) = triple_buffer(initial);
+
+    (
+        MonitoredWriter { writer },
+        Monitored {
+            reader,
+            fallback,
+            max_age,
+            failsafe: false,
+            trips: 0,
+        },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::thread;
+
+    #[test]
+    fn falls_back_and_recovers() {
+        let (mut writer, mut monitored) = monitored(1.0, 0.0, Duration::from_millis(50));
+        assert_eq!(*monitored.get(), 1.0);
+
+        writer.publish(2.0);
+        assert_eq!(*monitored.get(), 2.0);
+
+        thread::sleep(Duration::from_millis(60));
+        assert_eq!(*monitored.get(), 0.0);
+        assert_eq!(*monitored.get(), 0.0);
+        assert!(monitored.is_failsafe());
+        assert_eq!(monitored.trips(), 1);
+
+        writer.heartbeat();
+        assert_eq!(*monitored.get(), 2.0);
+        assert!(!monitored.is_failsafe());
+        assert_eq!(writer.last_published(), &2.0);
+    }
+}
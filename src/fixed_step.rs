@@ -0,0 +1,120 @@
+use crate::interpolate::Interpolate;
+use crate::triple_buffer::{self, Reader, Writer};
+
+// Fixed-timestep simulation handing its state to a render thread running at
+// its own rate. The simulation publishes each tick's state; the last two
+// states and their ticks go through a triple buffer together, so the render
+// thread always has a matching pair to blend between and never interpolates
+// from a state it happened to see towards one it skipped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Steps<T> {
+    pub previous: T,
+    pub current: T,
+    pub previous_tick: u64,
+    pub tick: u64,
+}
+
+pub struct StepWriter<T> {
+    writer: Writer<Steps<T>>,
+    steps: Steps<T>,
+}
+
+pub struct StepReader<T> {
+    reader: Reader<Steps<T>>,
+}
+
+impl<T: Interpolate> Steps<T> {
+    // 0.0 is `previous`, 1.0 is `current`.
+    pub fn at_alpha(&self, alpha: f32) -> T {
+        self.previous
+            .interpolate(self.current, alpha.clamp(0.0, 1.0))
+    }
+
+    // The state at a fractional tick, clamped to the two stored ticks.
+    pub fn at_tick(&self, tick: f64) -> T {
+        let span = self.tick.wrapping_sub(self.previous_tick);
+        if span == 0 {
+            return self.current;
+        }
+        let alpha = (tick - self.previous_tick as f64) / span as f64;
+        self.at_alpha(alpha as f32)
+    }
+}
+
+impl<T: Interpolate> StepWriter<T> {
+    // Publishes the state after simulating `tick`; the previously published
+    // state becomes the one interpolated from.
+    pub fn publish(&mut self, tick: u64, state: T) {
+        self.steps.previous = self.steps.current;
+        self.steps.previous_tick = self.steps.tick;
+        self.steps.current = state;
+        self.steps.tick = tick;
+        self.writer.write(self.steps);
+    }
+
+    pub fn steps(&self) -> &Steps<T> {
+        &self.steps
+    }
+}
+
+impl<T: Interpolate> StepReader<T> {
+    // The state `alpha` of the way from the previous to the latest tick,
+    // where the render loop's alpha is its leftover accumulator time over the
+    // step length.
+    pub fn view(&mut self, alpha: f32) -> T {
+        self.reader.read().at_alpha(alpha)
+    }
+
+    pub fn view_at(&mut self, tick: f64) -> T {
+        self.reader.read().at_tick(tick)
+    }
+
+    pub fn latest(&mut self) -> &Steps<T> {
+        self.reader.read()
+    }
+}
+
+pub fn fixed_step<T: Interpolate>(initial: T, tick: u64) -> (StepWriter<T>, StepReader<T>) {
+    let steps = Steps {
+        previous: initial,
+        current: initial,
+        previous_tick: tick,
+        tick,
+    };
+    let (writer, reader) = triple_buffer::triple_buffer(steps);
+
+    (StepWriter { writer, steps }, StepReader { reader })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn interpolates_between_last_two() {
+        let (mut writer, mut reader) = fixed_step([0.0f32, 0.0], 0);
+        assert_eq!(reader.view(0.5), [0.0, 0.0]);
+
+        writer.publish(1, [10.0, 20.0]);
+        assert_eq!(reader.view(0.5), [5.0, 10.0]);
+        assert_eq!(reader.view(2.0), [10.0, 20.0]);
+
+        writer.publish(2, [20.0, 20.0]);
+        writer.publish(3, [30.0, 20.0]);
+        let latest = *reader.latest();
+        assert_eq!((latest.previous_tick, latest.tick), (2, 3));
+        assert_eq!(reader.view(0.0), [20.0, 20.0]);
+    }
+
+    #[test]
+    fn by_tick() {
+        let (mut writer, mut reader) = fixed_step(0.0f64, 10);
+        assert_eq!(reader.view_at(12.0), 0.0);
+
+        writer.publish(12, 4.0);
+        assert_eq!(reader.view_at(11.0), 2.0);
+        assert_eq!(reader.view_at(11.5), 3.0);
+        assert_eq!(reader.view_at(9.0), 0.0);
+        assert_eq!(writer.steps().tick, 12);
+    }
+}
@@ -0,0 +1,266 @@
+use std::collections::VecDeque;
+use std::io;
+use std::time::{Duration, Instant};
+
+use crate::rtlog::{self, LogDrain, Logger};
+use crate::stats::{Registry, Sample};
+use crate::trace::{Collector, Scope};
+
+// Black box for an engine: keeps the last `window` of log records, completed
+// trace scopes and metric samples, so that when something goes wrong (an
+// xrun, a user filing a bug) the lead-up can be frozen and exported.
+//
+// The RT side only sees the `Logger` and `rt_scope!`; everything else runs
+// in `pump`, called periodically from a control thread. Each history is a
+// ring preallocated to a fixed number of entries in addition to the time
+// window, so a burst can't grow memory.
+//
+// Scopes whose enter and exit events land in different pumps are lost.
+pub struct FlightRecorder {
+    window: Duration,
+    logs: LogDrain,
+    collector: Option<&'static Collector>,
+    registry: Option<&'static Registry>,
+    stats_interval: Duration,
+    last_stats: Option<Instant>,
+    log_history: VecDeque<rtlog::Record>,
+    scope_history: VecDeque<Scope>,
+    stats_history: VecDeque<(Instant, Vec<Sample>)>,
+    capacity: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct FlightRecord {
+    pub frozen_at: Instant,
+    pub logs: Vec<rtlog::Record>,
+    pub scopes: Vec<Scope>,
+    pub stats: Vec<(Instant, Vec<Sample>)>,
+}
+
+impl FlightRecorder {
+    // Returns the recorder and the logger to hand to the RT thread. At most
+    // `capacity` entries of each kind are kept.
+    pub fn new(window: Duration, capacity: usize) -> (Self, Logger) {
+        let (logger, logs) = rtlog::logger(capacity);
+
+        let recorder = FlightRecorder {
+            window,
+            logs,
+            collector: None,
+            registry: None,
+            stats_interval: Duration::from_secs(1),
+            last_stats: None,
+            log_history: VecDeque::with_capacity(capacity),
+            scope_history: VecDeque::with_capacity(capacity),
+            stats_history: VecDeque::with_capacity(capacity),
+            capacity,
+        };
+
+        (recorder, logger)
+    }
+
+    pub fn with_trace(mut self, collector: &'static Collector) -> Self {
+        self.collector = Some(collector);
+        self
+    }
+
+    pub fn with_stats(mut self, registry: &'static Registry, interval: Duration) -> Self {
+        self.registry = Some(registry);
+        self.stats_interval = interval;
+        self
+    }
+
+    pub fn pump(&mut self) {
+        self.pump_at(Instant::now());
+    }
+
+    pub fn pump_at(&mut self, now: Instant) {
+        while let Some(record) = self.logs.try_recv() {
+            push_bounded(&mut self.log_history, record, self.capacity);
+        }
+        while self
+            .log_history
+            .front()
+            .is_some_and(|r| now.saturating_duration_since(r.timestamp) > self.window)
+        {
+            self.log_history.pop_front();
+        }
+
+        if let Some(collector) = self.collector {
+            for scope in collector.drain().scopes() {
+                push_bounded(&mut self.scope_history, scope, self.capacity);
+            }
+
+            let newest_end = self
+                .scope_history
+                .iter()
+                .map(|s| s.start_ns + s.duration_ns)
+                .max()
+                .unwrap_or(0);
+            let cutoff = newest_end.saturating_sub(self.window.as_nanos() as u64);
+            self.scope_history
+                .retain(|s| s.start_ns + s.duration_ns >= cutoff);
+        }
+
+        if let Some(registry) = self.registry {
+            let due = self
+                .last_stats
+                .is_none_or(|last| now.saturating_duration_since(last) >= self.stats_interval);
+            if due {
+                self.last_stats = Some(now);
+                push_bounded(
+                    &mut self.stats_history,
+                    (now, registry.snapshot()),
+                    self.capacity,
+                );
+            }
+            while self
+                .stats_history
+                .front()
+                .is_some_and(|(t, _)| now.saturating_duration_since(*t) > self.window)
+            {
+                self.stats_history.pop_front();
+            }
+        }
+    }
+
+    // Pumps once more and copies out the current history. Recording carries
+    // on afterwards.
+    pub fn freeze(&mut self) -> FlightRecord {
+        let now = Instant::now();
+        self.pump_at(now);
+
+        FlightRecord {
+            frozen_at: now,
+            logs: self.log_history.iter().copied().collect(),
+            scopes: self.scope_history.iter().copied().collect(),
+            stats: self.stats_history.iter().cloned().collect(),
+        }
+    }
+
+    pub fn dropped_logs(&self) -> u64 {
+        self.logs.dropped()
+    }
+}
+
+fn push_bounded<T>(ring: &mut VecDeque<T>, value: T, capacity: usize) {
+    if ring.len() == capacity {
+        ring.pop_front();
+    }
+    ring.push_back(value);
+}
+
+impl FlightRecord {
+    // Plain text report, times relative to the freeze.
+    pub fn write_text<W: io::Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "== log ==")?;
+        for record in &self.logs {
+            let age = self.frozen_at.saturating_duration_since(record.timestamp);
+            writeln!(
+                out,
+                "-{:.3}s {:?} {}",
+                age.as_secs_f64(),
+                record.level,
+                record.message
+            )?;
+        }
+
+        writeln!(out, "== scopes ==")?;
+        for scope in &self.scopes {
+            writeln!(
+                out,
+                "thread {} {:indent$}{} {}us",
+                scope.thread,
+                "",
+                scope.name,
+                scope.duration_ns / 1000,
+                indent = scope.depth as usize * 2
+            )?;
+        }
+
+        writeln!(out, "== stats ==")?;
+        for (time, samples) in &self.stats {
+            let age = self.frozen_at.saturating_duration_since(*time);
+            for sample in samples {
+                writeln!(
+                    out,
+                    "-{:.3}s {} {:?}",
+                    age.as_secs_f64(),
+                    sample.name,
+                    sample.value
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::rtlog::Level;
+    use crate::stats::Value;
+
+    #[test]
+    fn keeps_window() {
+        let (mut recorder, mut logger) = FlightRecorder::new(Duration::from_secs(2), 16);
+        logger.log_str(Level::Warn, "xrun");
+        let now = Instant::now();
+
+        recorder.pump_at(now + Duration::from_secs(1));
+        assert_eq!(recorder.log_history.len(), 1);
+        recorder.pump_at(now + Duration::from_secs(3));
+        assert!(recorder.log_history.is_empty());
+    }
+
+    #[test]
+    fn capacity_bounds_history() {
+        let (mut recorder, mut logger) = FlightRecorder::new(Duration::from_secs(60), 3);
+        for i in 0..3 {
+            crate::rtlog!(logger, Level::Debug, "{}", i);
+        }
+        recorder.pump();
+        for i in 3..5 {
+            crate::rtlog!(logger, Level::Debug, "{}", i);
+        }
+
+        let record = recorder.freeze();
+        let messages: Vec<_> = record.logs.iter().map(|r| r.message.as_str()).collect();
+        assert_eq!(messages, ["2", "3", "4"]);
+    }
+
+    #[test]
+    fn scopes_and_stats() {
+        static COLLECTOR: Collector = Collector::new();
+        static REGISTRY: Registry = Registry::new();
+
+        COLLECTOR.register_current_thread("audio", 16);
+        let xruns = REGISTRY.counter("xruns", "Buffer underruns");
+
+        let (recorder, mut logger) = FlightRecorder::new(Duration::from_secs(60), 8);
+        let mut recorder = recorder
+            .with_trace(&COLLECTOR)
+            .with_stats(&REGISTRY, Duration::from_millis(0));
+
+        {
+            crate::rt_scope!("callback");
+            xruns.increment();
+            logger.log_str(Level::Error, "xrun");
+        }
+
+        let record = recorder.freeze();
+        assert_eq!(record.scopes.len(), 1);
+        assert_eq!(record.scopes[0].name, "callback");
+        assert_eq!(record.stats.len(), 1);
+        assert_eq!(record.stats[0].1[0].value, Value::Counter(1));
+
+        let mut text = Vec::new();
+        record.write_text(&mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.contains("Error xrun"));
+        assert!(text.contains("callback"));
+        assert!(text.contains("xruns Counter(1)"));
+    }
+}
@@ -0,0 +1,122 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// Stream position in frames, published by the RT thread (usually once per
+// block) and readable from anywhere. Can live in a static.
+pub struct FramePosition {
+    frames: AtomicU64,
+}
+
+impl FramePosition {
+    pub const fn new(frames: u64) -> Self {
+        FramePosition {
+            frames: AtomicU64::new(frames),
+        }
+    }
+
+    // Only the publishing thread may call this; it is a load and a store,
+    // not an atomic add.
+    pub fn advance(&self, frames: u64) -> u64 {
+        let position = self.frames.load(Ordering::Relaxed).wrapping_add(frames);
+        self.frames.store(position, Ordering::Release);
+        position
+    }
+
+    pub fn set(&self, frames: u64) {
+        self.frames.store(frames, Ordering::Release);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.frames.load(Ordering::Acquire)
+    }
+}
+
+impl Default for FramePosition {
+    fn default() -> Self {
+        FramePosition::new(0)
+    }
+}
+
+// Wraparound-safe comparisons, serial number style: positions compare
+// correctly as long as they are less than 2^63 frames apart, so counters
+// seeded from hardware clocks or restarted mid-range still order properly.
+pub fn frames_between(from: u64, to: u64) -> i64 {
+    to.wrapping_sub(from) as i64
+}
+
+pub fn is_before(a: u64, b: u64) -> bool {
+    frames_between(a, b) > 0
+}
+
+// Converts between frames and wall-clock time at a fixed sample rate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Timebase {
+    sample_rate: u32,
+}
+
+impl Timebase {
+    pub const fn new(sample_rate: u32) -> Self {
+        assert!(sample_rate > 0, "Sample rate must be positive");
+        Timebase { sample_rate }
+    }
+
+    pub const fn sample_rate(self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn to_duration(self, frames: u64) -> Duration {
+        let rate = u64::from(self.sample_rate);
+        let nanos = (frames % rate) * 1_000_000_000 / rate;
+        Duration::new(frames / rate, nanos as u32)
+    }
+
+    // Rounds down to whole frames.
+    pub fn to_frames(self, duration: Duration) -> u64 {
+        let rate = u64::from(self.sample_rate);
+        duration.as_secs() * rate + u64::from(duration.subsec_nanos()) * rate / 1_000_000_000
+    }
+
+    pub fn to_seconds(self, frames: i64) -> f64 {
+        frames as f64 / f64::from(self.sample_rate)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn publish() {
+        static POSITION: FramePosition = FramePosition::new(0);
+
+        assert_eq!(POSITION.advance(64), 64);
+        assert_eq!(POSITION.advance(64), 128);
+        assert_eq!(POSITION.get(), 128);
+        POSITION.set(10);
+        assert_eq!(POSITION.get(), 10);
+    }
+
+    #[test]
+    fn wraparound() {
+        let position = FramePosition::new(u64::MAX - 10);
+        let before = position.get();
+        let after = position.advance(20);
+
+        assert_eq!(after, 9);
+        assert_eq!(frames_between(before, after), 20);
+        assert_eq!(frames_between(after, before), -20);
+        assert!(is_before(before, after));
+        assert!(!is_before(after, before));
+        assert!(!is_before(after, after));
+    }
+
+    #[test]
+    fn timebase() {
+        let timebase = Timebase::new(48000);
+        assert_eq!(timebase.to_duration(48000), Duration::from_secs(1));
+        assert_eq!(timebase.to_duration(24), Duration::from_micros(500));
+        assert_eq!(timebase.to_frames(Duration::from_millis(1500)), 72000);
+        assert_eq!(timebase.to_frames(Duration::from_nanos(20_000)), 0);
+        assert_eq!(timebase.to_seconds(-24000), -0.5);
+    }
+}
@@ -0,0 +1,170 @@
+use crate::byte_ring::{self, ByteReader, ByteWriter};
+
+// Length-prefixed messages over the byte ring. Each frame is a 4 byte
+// little-endian length followed by the payload, and is published with a
+// single commit: either the whole frame is sent or nothing is, so the reader
+// never sees a header without its payload.
+const HEADER_LEN: usize = 4;
+
+pub struct FrameSender {
+    writer: ByteWriter,
+}
+
+pub struct FrameReceiver {
+    reader: ByteReader,
+}
+
+impl FrameSender {
+    pub fn new(writer: ByteWriter) -> Self {
+        assert!(
+            writer.capacity() > HEADER_LEN,
+            "Ring too small for a frame header"
+        );
+        FrameSender { writer }
+    }
+
+    // Returns false, sending nothing, if the frame doesn't fit right now.
+    pub fn send_frame(&mut self, payload: &[u8]) -> bool {
+        assert!(payload.len() <= u32::MAX as usize, "Frame too large");
+
+        let len = HEADER_LEN + payload.len();
+        if len > self.writer.available_write() {
+            return false;
+        }
+
+        let (a, b) = self.writer.chunks_mut();
+        write_at(a, b, 0, &(payload.len() as u32).to_le_bytes());
+        write_at(a, b, HEADER_LEN, payload);
+        self.writer.commit(len);
+        true
+    }
+
+    // Largest payload that could ever be sent through this ring.
+    pub fn max_frame_len(&self) -> usize {
+        self.writer.capacity() - HEADER_LEN
+    }
+
+    pub fn into_inner(self) -> ByteWriter {
+        self.writer
+    }
+}
+
+impl FrameReceiver {
+    pub fn new(reader: ByteReader) -> Self {
+        FrameReceiver { reader }
+    }
+
+    // Payload length of the next queued frame.
+    pub fn next_frame_len(&self) -> Option<usize> {
+        let (a, b) = self.reader.chunks();
+        if a.len() + b.len() < HEADER_LEN {
+            return None;
+        }
+
+        let mut header = [0; HEADER_LEN];
+        read_at(a, b, 0, &mut header);
+        Some(u32::from_le_bytes(header) as usize)
+    }
+
+    // Copies the next frame into `out` and returns its length. A frame that
+    // doesn't fit in `out` stays queued; check `next_frame_len` first when
+    // frames can be larger than the buffer.
+    pub fn recv_frame(&mut self, out: &mut [u8]) -> Option<usize> {
+        let len = self.next_frame_len()?;
+        if len > out.len() {
+            return None;
+        }
+
+        let (a, b) = self.reader.chunks();
+        read_at(a, b, HEADER_LEN, &mut out[..len]);
+        self.reader.consume(HEADER_LEN + len);
+        Some(len)
+    }
+
+    // Drops the next frame without reading it, for frames too large for
+    // any buffer at hand. Returns its payload length.
+    pub fn skip_frame(&mut self) -> Option<usize> {
+        let len = self.next_frame_len()?;
+        self.reader.consume(HEADER_LEN + len);
+        Some(len)
+    }
+
+    pub fn into_inner(self) -> ByteReader {
+        self.reader
+    }
+}
+
+pub fn frame_ring(capacity: usize) -> (FrameSender, FrameReceiver) {
+    let (writer, reader) = byte_ring::byte_ring(capacity);
+    (FrameSender::new(writer), FrameReceiver::new(reader))
+}
+
+// Copy into/out of a pair of ring regions, treated as one run of bytes.
+fn write_at(a: &mut [u8], b: &mut [u8], offset: usize, data: &[u8]) {
+    if offset >= a.len() {
+        let offset = offset - a.len();
+        b[offset..offset + data.len()].copy_from_slice(data);
+        return;
+    }
+
+    let n = data.len().min(a.len() - offset);
+    a[offset..offset + n].copy_from_slice(&data[..n]);
+    b[..data.len() - n].copy_from_slice(&data[n..]);
+}
+
+fn read_at(a: &[u8], b: &[u8], offset: usize, out: &mut [u8]) {
+    if offset >= a.len() {
+        let offset = offset - a.len();
+        out.copy_from_slice(&b[offset..offset + out.len()]);
+        return;
+    }
+
+    let n = out.len().min(a.len() - offset);
+    out[..n].copy_from_slice(&a[offset..offset + n]);
+    let rest = out.len() - n;
+    out[n..].copy_from_slice(&b[..rest]);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip_across_wrap() {
+        let (mut send, mut recv) = frame_ring(16);
+        let mut out = [0; 16];
+
+        assert!(send.send_frame(&[1, 2, 3, 4, 5, 6]));
+        assert_eq!(recv.recv_frame(&mut out), Some(6));
+
+        assert!(send.send_frame(b"hello"));
+        assert!(!send.send_frame(b"too long now"));
+        assert!(send.send_frame(&[]));
+
+        assert_eq!(recv.next_frame_len(), Some(5));
+        assert_eq!(recv.recv_frame(&mut out[..2]), None);
+        assert_eq!(recv.recv_frame(&mut out), Some(5));
+        assert_eq!(&out[..5], b"hello");
+        assert_eq!(recv.recv_frame(&mut out), Some(0));
+        assert_eq!(recv.recv_frame(&mut out), None);
+    }
+
+    #[test]
+    fn skip() {
+        let (mut send, mut recv) = frame_ring(32);
+        assert!(send.send_frame(b"skipped"));
+        assert!(send.send_frame(b"kept"));
+
+        assert_eq!(recv.skip_frame(), Some(7));
+        let mut out = [0; 4];
+        assert_eq!(recv.recv_frame(&mut out), Some(4));
+        assert_eq!(&out, b"kept");
+        assert_eq!(recv.skip_frame(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "too small")]
+    fn ring_too_small() {
+        frame_ring(4);
+    }
+}
@@ -0,0 +1,302 @@
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::role;
+use crate::spsc::Indices;
+
+// Queue for input events where high-rate kinds (pointer motion, scroll)
+// coalesce while discrete ones (presses, releases) are all kept in order.
+// An event with a coalesce key is merged into the newest queued event if
+// that one has the same key and hasn't been received yet; anything else is
+// queued as usual. Merging only ever touches the newest event, so a press
+// between two moves keeps them apart and positions stay in order relative
+// to presses.
+//
+// Each slot has a small state word so that the sender can rewrite the
+// newest slot in place while the receiver may be taking it: the receiver
+// claims a slot with a CAS after copying it, and the sender claims it for
+// rewriting with a CAS too, falling back to queueing the event if the
+// receiver won. Both sides are wait-free apart from the receiver retrying
+// a copy that raced with a rewrite.
+pub trait Coalesce: Copy {
+    // Events with equal keys coalesce; `None` means never.
+    fn coalesce_key(&self) -> Option<u32>;
+
+    // Combines a queued event with a newer one of the same key. Keeps the
+    // newer one by default; accumulate here for deltas like scroll.
+    fn merge(self, newer: Self) -> Self {
+        newer
+    }
+}
+
+pub struct InputSender<T> {
+    shared: Arc<Shared<T>>,
+    // Key and value of the newest queued event, if it can coalesce.
+    newest: Option<(u32, T)>,
+}
+
+pub struct InputReceiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+// The low bits of a slot state, the rest counts rewrites so a receiver can
+// tell that its copy is stale.
+const READY: usize = 0;
+const WRITING: usize = 1;
+const TAKEN: usize = 2;
+const FLAGS: usize = 0b11;
+const GENERATION: usize = 0b100;
+
+struct Slot<T> {
+    state: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+struct Shared<T> {
+    indices: Indices,
+    slots: Box<[Slot<T>]>,
+    capacity: usize,
+}
+
+unsafe impl<T: Send> Sync for Shared<T> {}
+unsafe impl<T: Send> Send for Shared<T> {}
+
+impl<T: Coalesce> InputSender<T> {
+    // Gives the event back if it can't be coalesced and the queue is full.
+    pub fn send(&mut self, event: T) -> Result<(), T> {
+        let key = event.coalesce_key();
+        if let (Some(key), Some((newest_key, newest))) = (key, self.newest) {
+            if key == newest_key {
+                let merged = newest.merge(event);
+                if self.rewrite_newest(merged) {
+                    self.newest = Some((key, merged));
+                    return Ok(());
+                }
+            }
+        }
+
+        let shared = &*self.shared;
+        if shared.indices.writable(shared.capacity, 1) == 0 {
+            return Err(event);
+        }
+
+        // The receiver is done with this slot and won't look at it again
+        // until the write counter moves past it.
+        let slot = shared.slot(shared.indices.write_index());
+        unsafe { ptr::write(slot.value.get(), MaybeUninit::new(event)) };
+        let state = slot.state.load(Ordering::Relaxed);
+        slot.state
+            .store((state & !FLAGS) + GENERATION, Ordering::Relaxed);
+        shared.indices.commit_write(1);
+
+        self.newest = key.map(|key| (key, event));
+        Ok(())
+    }
+
+    pub fn size(&self) -> usize {
+        self.shared.indices.available_write(self.shared.capacity)
+    }
+
+    fn rewrite_newest(&self, value: T) -> bool {
+        let shared = &*self.shared;
+        let slot = shared.slot(shared.indices.write_index().wrapping_sub(1));
+
+        let state = slot.state.load(Ordering::Relaxed);
+        if state & FLAGS != READY
+            || slot
+                .state
+                .compare_exchange(state, state | WRITING, Ordering::Acquire, Ordering::Relaxed)
+                .is_err()
+        {
+            return false;
+        }
+
+        unsafe { ptr::write_volatile(slot.value.get(), MaybeUninit::new(value)) };
+        slot.state.store(state + GENERATION, Ordering::Release);
+        true
+    }
+}
+
+impl<T: Coalesce> InputReceiver<T> {
+    pub fn try_recv(&mut self) -> Option<T> {
+        let shared = &*self.shared;
+        loop {
+            if shared.indices.readable(1) == 0 {
+                return None;
+            }
+
+            let slot = shared.slot(shared.indices.read_index());
+            let state = slot.state.load(Ordering::Acquire);
+            if state & FLAGS == WRITING {
+                // Being replaced by a newer event right now.
+                return None;
+            }
+
+            let value = unsafe { ptr::read_volatile(slot.value.get()) };
+            if slot
+                .state
+                .compare_exchange(state, state | TAKEN, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+            {
+                shared.indices.commit_read(1);
+                return Some(unsafe { value.assume_init() });
+            }
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.shared.indices.available_read()
+    }
+}
+
+impl<T> Shared<T> {
+    fn slot(&self, index: usize) -> &Slot<T> {
+        &self.slots[index & (self.slots.len() - 1)]
+    }
+}
+
+pub fn input_queue<T: Coalesce>(capacity: usize) -> (InputSender<T>, InputReceiver<T>) {
+    role::assert_not_rt("input_queue");
+    assert!(capacity > 0, "Can not create channel with zero size");
+
+    let slots = (0..capacity.next_power_of_two())
+        .map(|_| Slot {
+            state: AtomicUsize::new(TAKEN),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        })
+        .collect();
+    let shared = Arc::new(Shared {
+        indices: Indices::new(),
+        slots,
+        capacity,
+    });
+
+    (
+        InputSender {
+            shared: shared.clone(),
+            newest: None,
+        },
+        InputReceiver { shared },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    enum Event {
+        Move(i32, i32),
+        Scroll(i32),
+        Press(u8),
+    }
+
+    impl Coalesce for Event {
+        fn coalesce_key(&self) -> Option<u32> {
+            match self {
+                Event::Move(..) => Some(0),
+                Event::Scroll(..) => Some(1),
+                Event::Press(..) => None,
+            }
+        }
+
+        fn merge(self, newer: Self) -> Self {
+            match (self, newer) {
+                (Event::Scroll(a), Event::Scroll(b)) => Event::Scroll(a + b),
+                (_, newer) => newer,
+            }
+        }
+    }
+
+    #[test]
+    fn coalesces_runs() {
+        let (mut send, mut recv) = input_queue(8);
+        for event in [
+            Event::Move(1, 1),
+            Event::Move(2, 2),
+            Event::Press(1),
+            Event::Move(3, 3),
+            Event::Scroll(1),
+            Event::Scroll(2),
+            Event::Press(1),
+            Event::Press(1),
+        ] {
+            send.send(event).unwrap();
+        }
+        assert_eq!(recv.size(), 6);
+
+        let received: Vec<_> = std::iter::from_fn(|| recv.try_recv()).collect();
+        assert_eq!(
+            received,
+            [
+                Event::Move(2, 2),
+                Event::Press(1),
+                Event::Move(3, 3),
+                Event::Scroll(3),
+                Event::Press(1),
+                Event::Press(1),
+            ]
+        );
+    }
+
+    #[test]
+    fn no_coalescing_once_received() {
+        let (mut send, mut recv) = input_queue(2);
+        send.send(Event::Move(1, 1)).unwrap();
+        assert_eq!(recv.try_recv(), Some(Event::Move(1, 1)));
+        send.send(Event::Move(2, 2)).unwrap();
+        send.send(Event::Move(3, 3)).unwrap();
+        assert_eq!(recv.try_recv(), Some(Event::Move(3, 3)));
+        assert_eq!(recv.try_recv(), None);
+    }
+
+    #[test]
+    fn full() {
+        let (mut send, _recv) = input_queue(2);
+        send.send(Event::Press(1)).unwrap();
+        send.send(Event::Move(0, 0)).unwrap();
+        assert_eq!(send.send(Event::Press(2)), Err(Event::Press(2)));
+
+        // Still room to coalesce.
+        send.send(Event::Move(1, 1)).unwrap();
+    }
+
+    #[test]
+    fn concurrent() {
+        let (mut send, mut recv) = input_queue(4);
+
+        let producer = std::thread::spawn(move || {
+            for i in 0..10_000 {
+                let event = if i % 10 == 0 {
+                    Event::Press((i / 10 % 256) as u8)
+                } else {
+                    Event::Move(i, i)
+                };
+                while send.send(event).is_err() {
+                    std::thread::yield_now();
+                }
+            }
+        });
+
+        let mut presses = 0;
+        let mut last_move = -1;
+        while presses < 1000 || last_move < 9999 {
+            match recv.try_recv() {
+                Some(Event::Press(n)) => {
+                    assert_eq!(n, (presses % 256) as u8);
+                    presses += 1;
+                }
+                Some(Event::Move(x, _)) => {
+                    assert!(x > last_move);
+                    last_move = x;
+                }
+                Some(Event::Scroll(_)) => unreachable!(),
+                None => std::thread::yield_now(),
+            }
+        }
+        producer.join().unwrap();
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::role;
+
+// Small, copyable identity for a name. Symbols are what RT code and message
+// payloads carry; only the control thread owns the strings behind them.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    pub const fn index(self) -> u32 {
+        self.0
+    }
+
+    pub const fn from_index(index: u32) -> Self {
+        Symbol(index)
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Symbol({})", self.0)
+    }
+}
+
+// Control-side symbol table. Interning allocates; never call `intern` from
+// the RT thread.
+#[derive(Default)]
+pub struct Interner {
+    names: Vec<Box<str>>,
+    symbols: HashMap<Box<str>, Symbol>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Interner::default()
+    }
+
+    pub fn intern(&mut self, name: &str) -> Symbol {
+        role::assert_not_rt("Interner::intern");
+
+        if let Some(&symbol) = self.symbols.get(name) {
+            return symbol;
+        }
+
+        assert!(
+            self.names.len() < u32::MAX as usize,
+            "Interner symbol space exhausted"
+        );
+
+        let symbol = Symbol(self.names.len() as u32);
+        self.names.push(name.into());
+        self.symbols.insert(name.into(), symbol);
+
+        symbol
+    }
+
+    pub fn get(&self, name: &str) -> Option<Symbol> {
+        self.symbols.get(name).copied()
+    }
+
+    pub fn resolve(&self, symbol: Symbol) -> Option<&str> {
+        self.names.get(symbol.0 as usize).map(|name| &**name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Symbol, &str)> {
+        self.names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (Symbol(i as u32), &**name))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn intern_is_idempotent() {
+        let mut interner = Interner::new();
+        let mixer = interner.intern("mixer");
+        let reverb = interner.intern("reverb");
+
+        assert_ne!(mixer, reverb);
+        assert_eq!(interner.intern("mixer"), mixer);
+        assert_eq!(interner.len(), 2);
+    }
+
+    #[test]
+    fn resolve() {
+        let mut interner = Interner::new();
+        let gain = interner.intern("gain");
+
+        assert_eq!(interner.resolve(gain), Some("gain"));
+        assert_eq!(interner.get("gain"), Some(gain));
+        assert_eq!(interner.get("pan"), None);
+        assert_eq!(interner.resolve(Symbol::from_index(7)), None);
+    }
+
+    #[test]
+    fn iter_in_interning_order() {
+        let mut interner = Interner::new();
+        interner.intern("a");
+        interner.intern("b");
+
+        let names: Vec<_> = interner.iter().map(|(_, name)| name).collect();
+        assert_eq!(names, vec!["a", "b"]);
+    }
+}
@@ -0,0 +1,42 @@
+pub trait Interpolate: Copy {
+    // Linear interpolation: `t == 0.0` yields `self`, `t == 1.0` yields `to`.
+    fn interpolate(self, to: Self, t: f32) -> Self;
+}
+
+impl Interpolate for f32 {
+    fn interpolate(self, to: Self, t: f32) -> Self {
+        self + (to - self) * t
+    }
+}
+
+impl Interpolate for f64 {
+    fn interpolate(self, to: Self, t: f32) -> Self {
+        self + (to - self) * t as f64
+    }
+}
+
+impl<T: Interpolate, const N: usize> Interpolate for [T; N] {
+    fn interpolate(mut self, to: Self, t: f32) -> Self {
+        for (a, b) in self.iter_mut().zip(to.iter()) {
+            *a = a.interpolate(*b, t);
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scalars() {
+        assert_eq!(1.0f32.interpolate(3.0, 0.5), 2.0);
+        assert_eq!(1.0f64.interpolate(3.0, 0.0), 1.0);
+        assert_eq!(1.0f64.interpolate(3.0, 1.0), 3.0);
+    }
+
+    #[test]
+    fn arrays() {
+        assert_eq!([0.0f32, 10.0].interpolate([1.0, 20.0], 0.5), [0.5, 15.0]);
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::VecDeque;
+
+use crate::spsc::{self, TrySendError};
+
+// Undo/redo for commands sent to the RT thread. Every command goes out
+// through the journal together with its inverse (typically captured from the
+// control thread's copy of the state it is about to change), and undo and
+// redo are replayed through the same sender, so the RT thread sees them as
+// ordinary commands.
+//
+// If the channel is full, the command is handed back and the history is
+// left untouched.
+pub struct Journal<T> {
+    sender: spsc::Sender<T>,
+    undo: VecDeque<Entry<T>>,
+    redo: Vec<Entry<T>>,
+    limit: usize,
+}
+
+struct Entry<T> {
+    command: T,
+    inverse: T,
+}
+
+impl<T: Clone> Journal<T> {
+    pub fn new(sender: spsc::Sender<T>) -> Self {
+        Journal {
+            sender,
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            limit: usize::MAX,
+        }
+    }
+
+    // Maximum number of undo steps kept; the oldest are forgotten first.
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    pub fn send(&mut self, command: T, inverse: T) -> Result<(), T> {
+        self.sender
+            .try_send(command.clone())
+            .map_err(TrySendError::into_inner)?;
+
+        self.redo.clear();
+        self.undo.push_back(Entry { command, inverse });
+        if self.undo.len() > self.limit {
+            self.undo.pop_front();
+        }
+
+        Ok(())
+    }
+
+    // Sends the inverse of the latest command. Returns false if there was
+    // nothing to undo.
+    pub fn undo(&mut self) -> Result<bool, T> {
+        let entry = match self.undo.pop_back() {
+            Some(entry) => entry,
+            None => return Ok(false),
+        };
+
+        if let Err(e) = self.sender.try_send(entry.inverse.clone()) {
+            self.undo.push_back(entry);
+            return Err(e.into_inner());
+        }
+
+        self.redo.push(entry);
+        Ok(true)
+    }
+
+    pub fn redo(&mut self) -> Result<bool, T> {
+        let entry = match self.redo.pop() {
+            Some(entry) => entry,
+            None => return Ok(false),
+        };
+
+        if let Err(e) = self.sender.try_send(entry.command.clone()) {
+            self.redo.push(entry);
+            return Err(e.into_inner());
+        }
+
+        self.undo.push_back(entry);
+        Ok(true)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    pub fn sender(&self) -> &spsc::Sender<T> {
+        &self.sender
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Clone, Debug, PartialEq)]
+    enum Command {
+        SetGain(f32),
+    }
+
+    #[test]
+    fn undo_redo() {
+        let (sender, mut receiver) = spsc::channel(8);
+        let mut journal = Journal::new(sender);
+        assert_eq!(journal.undo(), Ok(false));
+
+        journal
+            .send(Command::SetGain(0.5), Command::SetGain(1.0))
+            .unwrap();
+        journal
+            .send(Command::SetGain(0.25), Command::SetGain(0.5))
+            .unwrap();
+
+        assert_eq!(journal.undo(), Ok(true));
+        assert_eq!(journal.undo(), Ok(true));
+        assert!(!journal.can_undo());
+        assert_eq!(journal.redo(), Ok(true));
+        assert!(journal.can_redo());
+
+        let received: Vec<_> = receiver.try_iter().collect();
+        assert_eq!(
+            received,
+            vec![
+                Command::SetGain(0.5),
+                Command::SetGain(0.25),
+                Command::SetGain(0.5),
+                Command::SetGain(1.0),
+                Command::SetGain(0.5),
+            ]
+        );
+
+        journal
+            .send(Command::SetGain(0.0), Command::SetGain(0.5))
+            .unwrap();
+        assert!(!journal.can_redo());
+    }
+
+    #[test]
+    fn full_channel_keeps_history() {
+        let (sender, mut receiver) = spsc::channel(1);
+        let mut journal = Journal::new(sender);
+
+        journal.send(1, -1).unwrap();
+        assert_eq!(journal.send(2, -2), Err(2));
+        assert_eq!(journal.undo(), Err(-1));
+        assert!(journal.can_undo());
+
+        assert_eq!(receiver.try_recv(), Ok(1));
+        assert_eq!(journal.undo(), Ok(true));
+        assert_eq!(receiver.try_recv(), Ok(-1));
+    }
+
+    #[test]
+    fn limit() {
+        let (sender, _receiver) = spsc::channel(8);
+        let mut journal = Journal::new(sender).with_limit(2);
+        for i in 0..4 {
+            journal.send(i, -i).unwrap();
+        }
+
+        assert_eq!(journal.undo(), Ok(true));
+        assert_eq!(journal.undo(), Ok(true));
+        assert_eq!(journal.undo(), Ok(false));
+    }
+}
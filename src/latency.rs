@@ -0,0 +1,105 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::spsc::{self, TryRecvError, TrySendError};
+use crate::stats::RtHistogram;
+
+// Latencies are recorded in microseconds; 96 log-linear buckets cover up to
+// roughly thirty seconds before clamping.
+pub const LATENCY_BUCKETS: usize = 96;
+
+pub type LatencyHistogram = RtHistogram<LATENCY_BUCKETS>;
+
+struct Stamped<T> {
+    sent_at: Instant,
+    value: T,
+}
+
+pub struct Sender<T> {
+    inner: spsc::Sender<Stamped<T>>,
+}
+
+pub struct Receiver<T> {
+    inner: spsc::Receiver<Stamped<T>>,
+    histogram: Arc<LatencyHistogram>,
+}
+
+impl<T> Sender<T> {
+    pub fn try_send(&mut self, value: T) -> Result<(), TrySendError<T>> {
+        let stamped = Stamped {
+            sent_at: Instant::now(),
+            value,
+        };
+
+        self.inner.try_send(stamped).map_err(|e| match e {
+            TrySendError::Full(s) => TrySendError::Full(s.value),
+            TrySendError::Disconnected(s) => TrySendError::Disconnected(s.value),
+        })
+    }
+
+    pub fn size(&self) -> usize {
+        self.inner.size()
+    }
+
+    pub fn is_receiver_active(&self) -> bool {
+        self.inner.is_receiver_active()
+    }
+}
+
+impl<T> Receiver<T> {
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let stamped = self.inner.try_recv()?;
+        let latency = Instant::now().saturating_duration_since(stamped.sent_at);
+        self.histogram.record(latency.as_micros() as u64);
+        Ok(stamped.value)
+    }
+
+    pub fn size(&self) -> usize {
+        self.inner.size()
+    }
+
+    pub fn is_sender_active(&self) -> bool {
+        self.inner.is_sender_active()
+    }
+
+    pub fn histogram(&self) -> &Arc<LatencyHistogram> {
+        &self.histogram
+    }
+}
+
+pub fn channel<T>(size: usize) -> (Sender<T>, Receiver<T>) {
+    let (sender, receiver) = spsc::channel(size);
+
+    (
+        Sender { inner: sender },
+        Receiver {
+            inner: receiver,
+            histogram: Arc::new(LatencyHistogram::new()),
+        },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn records_on_recv() {
+        let (mut send, mut recv) = channel(4);
+        assert!(send.try_send(1).is_ok());
+        assert!(send.try_send(2).is_ok());
+        assert_eq!(recv.histogram().snapshot().count(), 0);
+
+        assert_eq!(recv.try_recv(), Ok(1));
+        assert_eq!(recv.try_recv(), Ok(2));
+        assert_eq!(recv.try_recv(), Err(TryRecvError::Empty));
+        assert_eq!(recv.histogram().snapshot().count(), 2);
+    }
+
+    #[test]
+    fn full_returns_value() {
+        let (mut send, _recv) = channel(1);
+        assert!(send.try_send(1).is_ok());
+        assert_eq!(send.try_send(2), Err(TrySendError::Full(2)));
+    }
+}
@@ -0,0 +1,114 @@
+// Compile-time fingerprints of type layouts, for catching layout drift
+// between separately built binaries that share memory. `layout_hash` covers
+// size and alignment; `stable_layout!` also folds in each field's name,
+// offset, type name and layout, which catches reordered or retyped fields
+// of the same overall size.
+pub trait StableLayout {
+    const LAYOUT_HASH: u64;
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+pub const fn hash_bytes(mut hash: u64, bytes: &[u8]) -> u64 {
+    let mut i = 0;
+    while i < bytes.len() {
+        hash ^= bytes[i] as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+        i += 1;
+    }
+    hash
+}
+
+pub const fn hash_u64(hash: u64, value: u64) -> u64 {
+    hash_bytes(hash, &value.to_le_bytes())
+}
+
+pub const fn layout_hash<T>() -> u64 {
+    let hash = hash_u64(FNV_OFFSET, core::mem::size_of::<T>() as u64);
+    hash_u64(hash, core::mem::align_of::<T>() as u64)
+}
+
+// Implements `StableLayout` for a struct from its field list:
+//
+//     stable_layout!(Voice { note: u8, gain: f32 });
+//
+// Fields left out of the list aren't part of the digest beyond their effect
+// on the overall size. Each listed type is checked against the field's
+// actual type at compile time. The type is hashed as written, so spelling
+// the same type differently (`f32` vs `core::primitive::f32`) changes the
+// digest.
+#[macro_export]
+macro_rules! stable_layout {
+    ($ty:ty { $($field:ident : $field_ty:ty),* $(,)? }) => {
+        impl $crate::layout::StableLayout for $ty {
+            const LAYOUT_HASH: u64 = {
+                #[allow(unused_mut)]
+                let mut hash = $crate::layout::layout_hash::<$ty>();
+                $(
+                    let _: fn(&$ty) -> &$field_ty = |value| &value.$field;
+                    hash = $crate::layout::hash_bytes(hash, stringify!($field).as_bytes());
+                    hash = $crate::layout::hash_u64(hash, ::core::mem::offset_of!($ty, $field) as u64);
+                    hash = $crate::layout::hash_bytes(hash, stringify!($field_ty).as_bytes());
+                    hash = $crate::layout::hash_u64(hash, $crate::layout::layout_hash::<$field_ty>());
+                )*
+                hash
+            };
+        }
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[allow(dead_code)]
+    #[repr(C)]
+    struct V1 {
+        note: u8,
+        gain: f32,
+    }
+
+    #[allow(dead_code)]
+    #[repr(C)]
+    struct V2 {
+        gain: f32,
+        note: u8,
+    }
+
+    stable_layout!(V1 {
+        note: u8,
+        gain: f32
+    });
+    stable_layout!(V2 {
+        gain: f32,
+        note: u8
+    });
+
+    #[test]
+    fn field_order_changes_hash() {
+        assert_eq!(layout_hash::<V1>(), layout_hash::<V2>());
+        assert_ne!(V1::LAYOUT_HASH, V2::LAYOUT_HASH);
+        assert_ne!(layout_hash::<u32>(), layout_hash::<u64>());
+    }
+
+    #[allow(dead_code)]
+    #[repr(C)]
+    struct Int {
+        value: i32,
+    }
+
+    #[allow(dead_code)]
+    #[repr(C)]
+    struct Float {
+        value: f32,
+    }
+
+    stable_layout!(Int { value: i32 });
+    stable_layout!(Float { value: f32 });
+
+    #[test]
+    fn field_type_changes_hash() {
+        assert_ne!(Int::LAYOUT_HASH, Float::LAYOUT_HASH);
+    }
+}
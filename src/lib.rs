@@ -1,4 +1,133 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 #![warn(clippy::all)]
 
+// Without the default `std` feature only the core primitives are built
+// (`cache`, `doorbell`, `mpmc`, `mpsc`, `spsc`, `static_spsc`,
+// `triple_buffer` and what they depend on), on top of `core` and `alloc`.
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod arc_spsc;
+#[cfg(feature = "async")]
+pub mod async_byte_ring;
+#[cfg(feature = "async")]
+pub mod async_spsc;
+#[cfg(feature = "std")]
+pub mod audio;
+#[cfg(feature = "std")]
+pub mod broadcast;
+#[cfg(feature = "std")]
+pub mod byte_ring;
+pub mod cache;
+#[cfg(feature = "std")]
+pub mod chain;
+#[cfg(feature = "std")]
+pub mod codec;
+#[cfg(feature = "std")]
+pub mod control_rate;
+#[cfg(all(feature = "std", any(unix, windows)))]
+pub mod crash;
+#[cfg(feature = "std")]
+pub mod credit;
+#[cfg(feature = "std")]
+pub mod delay_ring;
+pub mod doorbell;
+#[cfg(feature = "std")]
+pub mod dump;
+#[cfg(feature = "std")]
+pub mod eviction;
+#[cfg(feature = "std")]
+pub mod failsafe;
+#[cfg(feature = "std")]
+pub mod fixed_step;
+#[cfg(feature = "std")]
+pub mod flight_recorder;
+#[cfg(feature = "std")]
+pub mod frame;
+#[cfg(feature = "std")]
+pub mod framed;
+#[cfg(feature = "std")]
+pub mod input;
+#[cfg(feature = "std")]
+pub mod intern;
+#[cfg(feature = "std")]
+pub mod interpolate;
+#[cfg(feature = "std")]
+pub mod journal;
+#[cfg(feature = "std")]
+pub mod latency;
+pub mod layout;
+#[cfg(feature = "std")]
+pub mod meter;
+#[cfg(feature = "std")]
+pub mod morph;
+pub mod mpmc;
+pub mod mpsc;
+#[cfg(feature = "std")]
+pub mod multi_ring;
+#[cfg(feature = "net-audio")]
+pub mod net_audio;
+#[cfg(feature = "std")]
+pub mod once;
+#[cfg(feature = "std")]
+pub mod oneshot;
+#[cfg(feature = "std")]
+pub mod panic_guard;
+#[cfg(feature = "std")]
+pub mod param_bank;
+#[cfg(feature = "serde")]
+pub mod pending;
+#[cfg(feature = "std")]
+pub mod pi_detect;
+pub mod poison;
+#[cfg(feature = "std")]
+pub mod poll;
+#[cfg(feature = "std")]
+pub mod rebuffer;
+#[cfg(feature = "std")]
+pub mod reconfigure;
+pub mod role;
+#[cfg(feature = "std")]
+pub mod rtlog;
+#[cfg(feature = "std")]
+pub mod scatter;
+pub mod seqlock;
+#[cfg(feature = "std")]
+pub mod sequence;
+#[cfg(all(feature = "std", unix))]
+pub mod shm;
+#[cfg(all(feature = "std", unix))]
+pub mod shm_ring;
+#[cfg(feature = "sim")]
+pub mod sim;
+#[cfg(feature = "std")]
+pub mod snapshot_pool;
+#[cfg(all(feature = "std", unix))]
+pub mod spill;
 pub mod spsc;
+pub mod static_spsc;
+#[cfg(feature = "std")]
+pub mod stats;
+#[cfg(feature = "std")]
+pub mod supervisor;
+#[cfg(feature = "std")]
+pub mod synchronizer;
+#[cfg(feature = "std")]
+pub mod tempo;
+#[cfg(feature = "std")]
+pub mod text;
+#[cfg(feature = "std")]
+pub mod trace;
+#[cfg(feature = "std")]
+pub mod transport;
 pub mod triple_buffer;
+#[cfg(feature = "serde")]
+pub mod typed;
+#[cfg(feature = "verify")]
+pub mod verify;
+#[cfg(feature = "std")]
+pub mod wait;
+#[cfg(feature = "std")]
+pub mod watchdog;
+#[cfg(feature = "std")]
+pub mod wiring;
@@ -0,0 +1,169 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+// Scalar reducers for metering: the RT thread feeds samples (typically one
+// per block), any other thread reads the current value. Both types assume a
+// single updating thread; reads are relaxed and may lag by one update.
+
+pub struct Ewma {
+    alpha: f32,
+    value: AtomicU32,
+}
+
+impl Ewma {
+    // `alpha` is the weight given to each new sample, in (0, 1].
+    pub const fn new(alpha: f32, initial: f32) -> Self {
+        assert!(alpha > 0.0 && alpha <= 1.0, "EWMA alpha must be in (0, 1]");
+
+        Ewma {
+            alpha,
+            value: AtomicU32::new(initial.to_bits()),
+        }
+    }
+
+    // Alpha giving a time constant of `samples` updates: after that many
+    // updates a step input has reached ~63% of its final value.
+    pub fn with_time_constant(samples: f32, initial: f32) -> Self {
+        Ewma::new(1.0 - (-1.0 / samples.max(1.0)).exp(), initial)
+    }
+
+    pub fn update(&self, sample: f32) {
+        let current = f32::from_bits(self.value.load(Ordering::Relaxed));
+        let next = current + self.alpha * (sample - current);
+        self.value.store(next.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.value.load(Ordering::Relaxed))
+    }
+
+    pub fn reset(&self, value: f32) {
+        self.value.store(value.to_bits(), Ordering::Relaxed);
+    }
+}
+
+// Holds the largest sample for `hold` updates, then decays geometrically by
+// `decay` per update until a new, larger sample arrives.
+pub struct PeakHold {
+    hold: u32,
+    decay: f32,
+    value: AtomicU32,
+    hold_remaining: AtomicU32,
+}
+
+impl PeakHold {
+    pub const fn new(hold: u32, decay: f32) -> Self {
+        assert!(decay >= 0.0 && decay <= 1.0, "Peak decay must be in [0, 1]");
+
+        PeakHold {
+            hold,
+            decay,
+            value: AtomicU32::new(0.0f32.to_bits()),
+            hold_remaining: AtomicU32::new(0),
+        }
+    }
+
+    pub fn update(&self, sample: f32) {
+        let peak = f32::from_bits(self.value.load(Ordering::Relaxed));
+
+        if sample >= peak {
+            self.value.store(sample.to_bits(), Ordering::Relaxed);
+            self.hold_remaining.store(self.hold, Ordering::Relaxed);
+            return;
+        }
+
+        let remaining = self.hold_remaining.load(Ordering::Relaxed);
+        if remaining > 0 {
+            self.hold_remaining.store(remaining - 1, Ordering::Relaxed);
+        } else {
+            let decayed = (peak * self.decay).max(sample);
+            self.value.store(decayed.to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.value.load(Ordering::Relaxed))
+    }
+
+    pub fn reset(&self) {
+        self.value.store(0.0f32.to_bits(), Ordering::Relaxed);
+        self.hold_remaining.store(0, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn statics() {
+        static LEVEL: Ewma = Ewma::new(0.5, 0.0);
+        static PEAK: PeakHold = PeakHold::new(0, 0.5);
+
+        LEVEL.update(1.0);
+        PEAK.update(1.0);
+        assert_eq!(LEVEL.get(), 0.5);
+        assert_eq!(PEAK.get(), 1.0);
+    }
+
+    #[test]
+    fn ewma_converges() {
+        let ewma = Ewma::new(0.5, 0.0);
+        ewma.update(1.0);
+        assert_eq!(ewma.get(), 0.5);
+        ewma.update(1.0);
+        assert_eq!(ewma.get(), 0.75);
+
+        for _ in 0..50 {
+            ewma.update(1.0);
+        }
+        assert!((ewma.get() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn ewma_time_constant() {
+        let ewma = Ewma::with_time_constant(10.0, 0.0);
+        for _ in 0..10 {
+            ewma.update(1.0);
+        }
+        assert!((ewma.get() - 0.632).abs() < 0.01, "{}", ewma.get());
+    }
+
+    #[test]
+    #[should_panic]
+    fn ewma_invalid_alpha() {
+        Ewma::new(0.0, 0.0);
+    }
+
+    #[test]
+    fn peak_hold() {
+        let peak = PeakHold::new(2, 0.5);
+        peak.update(0.8);
+        assert_eq!(peak.get(), 0.8);
+
+        peak.update(0.1);
+        peak.update(0.1);
+        assert_eq!(peak.get(), 0.8);
+
+        peak.update(0.1);
+        assert_eq!(peak.get(), 0.4);
+        peak.update(0.1);
+        assert_eq!(peak.get(), 0.2);
+        peak.update(0.1);
+        assert_eq!(peak.get(), 0.1);
+    }
+
+    #[test]
+    fn peak_hold_new_peak_restarts_hold() {
+        let peak = PeakHold::new(1, 0.5);
+        peak.update(0.5);
+        peak.update(0.0);
+        peak.update(0.6);
+        peak.update(0.0);
+        assert_eq!(peak.get(), 0.6);
+        peak.update(0.0);
+        assert_eq!(peak.get(), 0.3);
+
+        peak.reset();
+        assert_eq!(peak.get(), 0.0);
+    }
+}
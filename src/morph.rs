@@ -0,0 +1,134 @@
+use crate::interpolate::Interpolate;
+use crate::triple_buffer::{self, Reader, Writer};
+
+// Preset morphing: two scenes and the position between them are published
+// together through a triple buffer, so the RT side never sees a new scene
+// paired with a stale position. The RT side interpolates once per block and
+// reports the values at both block edges, so a position or scene change can
+// be ramped across the block instead of jumping.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MorphState<T> {
+    pub a: T,
+    pub b: T,
+    // 0.0 is all `a`, 1.0 all `b`.
+    pub position: f32,
+}
+
+pub struct MorphWriter<T> {
+    writer: Writer<MorphState<T>>,
+    state: MorphState<T>,
+}
+
+pub struct MorphReader<T> {
+    reader: Reader<MorphState<T>>,
+    last: T,
+}
+
+impl<T: Interpolate> MorphState<T> {
+    pub fn value(&self) -> T {
+        self.a.interpolate(self.b, self.position)
+    }
+}
+
+impl<T: Interpolate> MorphWriter<T> {
+    pub fn set_scenes(&mut self, a: T, b: T) {
+        self.state.a = a;
+        self.state.b = b;
+    }
+
+    pub fn set_position(&mut self, position: f32) {
+        self.state.position = position.clamp(0.0, 1.0);
+    }
+
+    pub fn state(&self) -> &MorphState<T> {
+        &self.state
+    }
+
+    pub fn publish(&mut self) {
+        self.writer.write(self.state);
+    }
+}
+
+impl<T: Interpolate> MorphReader<T> {
+    // Values at the start and end of the next block. The start value is the
+    // end value of the previous block.
+    pub fn next_block(&mut self) -> (T, T) {
+        let start = self.last;
+        self.last = self.reader.read().value();
+        (start, self.last)
+    }
+
+    // Fills `out` with a linear ramp across the next block.
+    pub fn ramp(&mut self, out: &mut [T]) {
+        let (start, end) = self.next_block();
+        let len = out.len() as f32;
+        for (i, value) in out.iter_mut().enumerate() {
+            *value = start.interpolate(end, (i + 1) as f32 / len);
+        }
+    }
+
+    pub fn current(&self) -> T {
+        self.last
+    }
+}
+
+pub fn morph<T: Interpolate>(a: T, b: T, position: f32) -> (MorphWriter<T>, MorphReader<T>) {
+    let state = MorphState {
+        a,
+        b,
+        position: position.clamp(0.0, 1.0),
+    };
+    let (writer, reader) = triple_buffer::triple_buffer(state);
+
+    (
+        MorphWriter { writer, state },
+        MorphReader {
+            reader,
+            last: state.value(),
+        },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn morph_position() {
+        let (mut writer, mut reader) = morph([0.0f32, 100.0], [1.0, 200.0], 0.0);
+        assert_eq!(reader.next_block(), ([0.0, 100.0], [0.0, 100.0]));
+
+        writer.set_position(0.5);
+        assert_eq!(reader.next_block(), ([0.0, 100.0], [0.0, 100.0]));
+
+        writer.publish();
+        assert_eq!(reader.next_block(), ([0.0, 100.0], [0.5, 150.0]));
+        assert_eq!(reader.current(), [0.5, 150.0]);
+
+        writer.set_position(2.0);
+        writer.publish();
+        assert_eq!(reader.next_block().1, [1.0, 200.0]);
+    }
+
+    #[test]
+    fn scenes_and_position_together() {
+        let (mut writer, mut reader) = morph(0.0f32, 1.0, 1.0);
+        writer.set_scenes(10.0, 20.0);
+        writer.set_position(0.0);
+        writer.publish();
+        assert_eq!(reader.next_block(), (1.0, 10.0));
+    }
+
+    #[test]
+    fn ramp() {
+        let (mut writer, mut reader) = morph(0.0f32, 4.0, 0.0);
+        writer.set_position(1.0);
+        writer.publish();
+
+        let mut out = [0.0; 4];
+        reader.ramp(&mut out);
+        assert_eq!(out, [1.0, 2.0, 3.0, 4.0]);
+        reader.ramp(&mut out);
+        assert_eq!(out, [4.0; 4]);
+    }
+}
@@ -0,0 +1,433 @@
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::cell::UnsafeCell;
+use core::mem::{self, MaybeUninit};
+#[cfg(target_has_atomic = "64")]
+use core::sync::atomic::AtomicU64;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::role;
+use crate::spsc::{TryRecvError, TrySendError};
+
+const CACHELINE_SIZE: usize = 64;
+
+// Bounded queue for any number of producers and consumers, after Vyukov's
+// bounded MPMC queue. Every slot carries a sequence number telling whose
+// turn it is: both ends claim their next position with a CAS and then hand
+// the slot over through its sequence number. Neither side locks or
+// allocates after construction; an operation only retries when another
+// thread on the same side won the position.
+//
+// A thread stalled between claiming a slot and handing it over holds up the
+// other side at that slot: until it's done, consumers see `Empty` there (or
+// producers `Full`), even if later slots are ready.
+//
+// The capacity is rounded up to a power of two.
+//
+// Positions count every operation since creation, so a position is the
+// pair (cycle, index) packed into one word: the index of the slot in the
+// low bits and the number of times the queue wrapped around above them.
+// They're 64-bit wherever the target has 64-bit atomics, 32-bit targets
+// included. A thread stalled between loading a position and its CAS would
+// then have to miss 2^64 operations for the CAS to wrongly succeed (ABA),
+// where 2^32 is within reach of a busy queue in a few hours.
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+struct Shared<T> {
+    queue: ArrayQueue<T>,
+    senders: AtomicUsize,
+    receivers: AtomicUsize,
+}
+
+#[cfg(target_has_atomic = "64")]
+type Position = u64;
+#[cfg(target_has_atomic = "64")]
+type AtomicPosition = AtomicU64;
+#[cfg(not(target_has_atomic = "64"))]
+type Position = usize;
+#[cfg(not(target_has_atomic = "64"))]
+type AtomicPosition = AtomicUsize;
+
+// Signed distance between two positions. They're never more than a lap
+// apart, so truncating to `isize` keeps the sign.
+fn lag(a: Position, b: Position) -> isize {
+    a.wrapping_sub(b) as isize
+}
+
+struct Slot<T> {
+    sequence: AtomicPosition,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+const PADDING1_SIZE: usize = CACHELINE_SIZE - mem::size_of::<Box<[u8]>>();
+const PADDING2_SIZE: usize = CACHELINE_SIZE - mem::size_of::<AtomicPosition>();
+
+// The slot array and its two positions, shared with `mpsc`, which only
+// differs in having a single consumer that doesn't need the CAS.
+#[repr(C)]
+pub(crate) struct ArrayQueue<T> {
+    slots: Box<[Slot<T>]>,          // size_of::<Box<[u8]>>()
+    _padding1: [u8; PADDING1_SIZE], // pad up to next cache line
+    tail: AtomicPosition,           // producers
+    _padding2: [u8; PADDING2_SIZE], // pad up to next cache line
+    head: AtomicPosition,           // consumers
+}
+
+unsafe impl<T: Send> Sync for ArrayQueue<T> {}
+unsafe impl<T: Send> Send for ArrayQueue<T> {}
+
+impl<T> ArrayQueue<T> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "Can not create channel with zero size");
+
+        let slots = (0..capacity.next_power_of_two())
+            .map(|i| Slot {
+                sequence: AtomicPosition::new(i as Position),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            })
+            .collect();
+        ArrayQueue {
+            slots,
+            _padding1: [0; PADDING1_SIZE],
+            tail: AtomicPosition::new(0),
+            _padding2: [0; PADDING2_SIZE],
+            head: AtomicPosition::new(0),
+        }
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    // Approximate while either side is active.
+    pub(crate) fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Relaxed);
+        let queued = self.tail.load(Ordering::Relaxed).wrapping_sub(head);
+        queued.min(self.capacity() as Position) as usize
+    }
+
+    // Times the producer and consumer positions have wrapped around the
+    // slots. Both wrap themselves after 2^64 operations (or 2^32 without
+    // 64-bit atomics).
+    #[allow(clippy::unnecessary_cast)] // `Position` may be `usize`
+    pub(crate) fn cycles(&self) -> (u64, u64) {
+        let shift = self.slots.len().trailing_zeros();
+        (
+            (self.tail.load(Ordering::Relaxed) >> shift) as u64,
+            (self.head.load(Ordering::Relaxed) >> shift) as u64,
+        )
+    }
+
+    fn slot(&self, position: Position) -> &Slot<T> {
+        &self.slots[position as usize & (self.slots.len() - 1)]
+    }
+
+    pub(crate) fn push(&self, value: T) -> Result<(), T> {
+        let mut position = self.tail.load(Ordering::Relaxed);
+        loop {
+            let slot = self.slot(position);
+            let lag = lag(slot.sequence.load(Ordering::Acquire), position);
+
+            if lag == 0 {
+                match self.tail.compare_exchange_weak(
+                    position,
+                    position.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        unsafe { (*slot.value.get()).write(value) };
+                        slot.sequence
+                            .store(position.wrapping_add(1), Ordering::Release);
+                        return Ok(());
+                    }
+                    Err(current) => position = current,
+                }
+            } else if lag < 0 {
+                // The slot still holds the value from one lap ago.
+                return Err(value);
+            } else {
+                position = self.tail.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub(crate) fn pop(&self) -> Option<T> {
+        let mut position = self.head.load(Ordering::Relaxed);
+        loop {
+            let slot = self.slot(position);
+            let lag = lag(
+                slot.sequence.load(Ordering::Acquire),
+                position.wrapping_add(1),
+            );
+
+            if lag == 0 {
+                match self.head.compare_exchange_weak(
+                    position,
+                    position.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => return Some(unsafe { self.take(slot, position) }),
+                    Err(current) => position = current,
+                }
+            } else if lag < 0 {
+                return None;
+            } else {
+                position = self.head.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    // `pop` for queues with a single consumer, which owns the head and
+    // never has to retry.
+    pub(crate) fn pop_single(&self) -> Option<T> {
+        let position = self.head.load(Ordering::Relaxed);
+        let slot = self.slot(position);
+
+        if slot.sequence.load(Ordering::Acquire) != position.wrapping_add(1) {
+            return None;
+        }
+
+        self.head.store(position.wrapping_add(1), Ordering::Relaxed);
+        Some(unsafe { self.take(slot, position) })
+    }
+
+    // Moves the value out of a slot claimed at `position` and hands the
+    // slot to the producer one lap ahead.
+    unsafe fn take(&self, slot: &Slot<T>, position: Position) -> T {
+        let value = (*slot.value.get()).assume_init_read();
+        slot.sequence.store(
+            position.wrapping_add(self.slots.len() as Position),
+            Ordering::Release,
+        );
+        value
+    }
+}
+
+impl<T> Drop for ArrayQueue<T> {
+    fn drop(&mut self) {
+        while self.pop_single().is_some() {}
+    }
+}
+
+impl<T> Sender<T> {
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        if !self.is_receiver_active() {
+            return Err(TrySendError::Disconnected(value));
+        }
+        self.shared.queue.push(value).map_err(TrySendError::Full)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.queue.capacity()
+    }
+
+    // Laps the send and receive positions have made around the queue, for
+    // diagnostics.
+    pub fn cycles(&self) -> (u64, u64) {
+        self.shared.queue.cycles()
+    }
+
+    pub fn is_receiver_active(&self) -> bool {
+        self.shared.receivers.load(Ordering::Relaxed) > 0
+    }
+}
+
+impl<T> Receiver<T> {
+    // Like `spsc::Receiver::try_recv`, only an empty queue with no senders
+    // left is `Disconnected`.
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let senders_active = self.is_sender_active();
+        match self.shared.queue.pop() {
+            Some(value) => Ok(value),
+            None if senders_active => Err(TryRecvError::Empty),
+            None => Err(TryRecvError::Disconnected),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.shared.queue.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.queue.capacity()
+    }
+
+    pub fn cycles(&self) -> (u64, u64) {
+        self.shared.queue.cycles()
+    }
+
+    pub fn is_sender_active(&self) -> bool {
+        self.shared.senders.load(Ordering::Acquire) > 0
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::Relaxed);
+        Sender {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        self.shared.receivers.fetch_add(1, Ordering::Relaxed);
+        Receiver {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.shared.senders.fetch_sub(1, Ordering::Release);
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.receivers.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    role::assert_not_rt("mpmc::channel");
+    let shared = Arc::new(Shared {
+        queue: ArrayQueue::new(capacity),
+        senders: AtomicUsize::new(1),
+        receivers: AtomicUsize::new(1),
+    });
+
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use memoffset::offset_of;
+
+    #[test]
+    fn verify_no_false_sharing() {
+        assert_eq!(offset_of!(ArrayQueue<u8>, tail), CACHELINE_SIZE);
+        assert_eq!(offset_of!(ArrayQueue<u8>, head), 2 * CACHELINE_SIZE);
+    }
+
+    #[test]
+    fn send_recv() {
+        let (send, recv) = channel(2);
+        let other = recv.clone();
+        send.try_send(1).unwrap();
+        send.try_send(2).unwrap();
+        assert_eq!(send.try_send(3), Err(TrySendError::Full(3)));
+
+        assert_eq!(other.try_recv(), Ok(1));
+        assert_eq!(recv.try_recv(), Ok(2));
+        assert_eq!(recv.try_recv(), Err(TryRecvError::Empty));
+
+        drop(send);
+        assert_eq!(other.try_recv(), Err(TryRecvError::Disconnected));
+        drop((recv, other));
+    }
+
+    #[test]
+    fn disconnected_receivers() {
+        let (send, recv) = channel(2);
+        let other = recv.clone();
+        drop(recv);
+        send.try_send(1).unwrap();
+        drop(other);
+        assert_eq!(send.try_send(2), Err(TrySendError::Disconnected(2)));
+    }
+
+    #[test]
+    fn many_to_many() {
+        const PRODUCERS: u64 = 4;
+        const PER_PRODUCER: u64 = 10_000;
+
+        let (send, recv) = channel::<u64>(16);
+        let producers: Vec<_> = (0..PRODUCERS)
+            .map(|p| {
+                let send = send.clone();
+                std::thread::spawn(move || {
+                    for i in 0..PER_PRODUCER {
+                        let mut value = p * PER_PRODUCER + i;
+                        while let Err(e) = send.try_send(value) {
+                            value = e.into_inner();
+                            std::thread::yield_now();
+                        }
+                    }
+                })
+            })
+            .collect();
+        let consumers: Vec<_> = (0..3)
+            .map(|_| {
+                let recv = recv.clone();
+                std::thread::spawn(move || {
+                    let mut received = Vec::new();
+                    loop {
+                        match recv.try_recv() {
+                            Ok(value) => received.push(value),
+                            Err(TryRecvError::Empty) => std::thread::yield_now(),
+                            Err(TryRecvError::Disconnected) => return received,
+                        }
+                    }
+                })
+            })
+            .collect();
+        drop((send, recv));
+
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        let mut all: Vec<u64> = consumers
+            .into_iter()
+            .flat_map(|c| c.join().unwrap())
+            .collect();
+        all.sort_unstable();
+        assert_eq!(all, (0..PRODUCERS * PER_PRODUCER).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn positions_wrap() {
+        let (send, recv) = channel(2);
+        let queue = &send.shared.queue;
+        let start = Position::MAX - 2;
+        queue.tail.store(start, Ordering::Relaxed);
+        queue.head.store(start, Ordering::Relaxed);
+        for i in 0..2 {
+            let position = start.wrapping_add(i);
+            queue
+                .slot(position)
+                .sequence
+                .store(position, Ordering::Relaxed);
+        }
+        let (sent, received) = send.cycles();
+        assert_eq!(sent, received);
+        assert!(sent > 1);
+
+        for i in 0..6 {
+            send.try_send(i).unwrap();
+            assert_eq!(recv.try_recv(), Ok(i));
+        }
+        assert_eq!(send.try_send(6), Ok(()));
+        assert_eq!(send.try_send(7), Ok(()));
+        assert_eq!(send.try_send(8), Err(TrySendError::Full(8)));
+        assert_eq!(recv.size(), 2);
+        assert_eq!(recv.cycles(), (2, 1));
+    }
+}
@@ -0,0 +1,234 @@
+use alloc::sync::Arc;
+use core::cell::Cell;
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::mpmc::ArrayQueue;
+use crate::role;
+use crate::spsc::{TryRecvError, TrySendError};
+
+// Bounded queue for several producers and one consumer. It's the `mpmc`
+// array queue, but with a single consumer that never competes for its
+// position: producers claim theirs with a CAS, so `try_send` is lock-free
+// and retries only when another producer won the position, while
+// `try_recv` is wait-free.
+//
+// A producer stalled between claiming a slot and publishing it holds up
+// the consumer: values queued behind it show up once it's done, and until
+// then `try_recv` reports `Empty`.
+//
+// The capacity is rounded up to a power of two.
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+// Not `Sync`: receiving relies on being the only consumer of the queue.
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+struct Shared<T> {
+    queue: ArrayQueue<T>,
+    senders: AtomicUsize,
+    receiver_alive: AtomicBool,
+}
+
+impl<T> Sender<T> {
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        if !self.is_receiver_active() {
+            return Err(TrySendError::Disconnected(value));
+        }
+        self.shared.queue.push(value).map_err(TrySendError::Full)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.queue.capacity()
+    }
+
+    // Laps the send and receive positions have made around the queue, see
+    // `mpmc::Sender::cycles`.
+    pub fn cycles(&self) -> (u64, u64) {
+        self.shared.queue.cycles()
+    }
+
+    pub fn is_receiver_active(&self) -> bool {
+        self.shared.receiver_alive.load(Ordering::Relaxed)
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::Relaxed);
+        Sender {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.shared.senders.fetch_sub(1, Ordering::Release);
+    }
+}
+
+impl<T> Receiver<T> {
+    // Like `spsc::Receiver::try_recv`, only an empty queue with no senders
+    // left is `Disconnected`.
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let senders_active = self.is_sender_active();
+        match self.shared.queue.pop_single() {
+            Some(value) => Ok(value),
+            None if senders_active => Err(TryRecvError::Empty),
+            None => Err(TryRecvError::Disconnected),
+        }
+    }
+
+    // Approximate while producers are active.
+    pub fn size(&self) -> usize {
+        self.shared.queue.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.queue.capacity()
+    }
+
+    pub fn cycles(&self) -> (u64, u64) {
+        self.shared.queue.cycles()
+    }
+
+    pub fn is_sender_active(&self) -> bool {
+        self.shared.senders.load(Ordering::Acquire) > 0
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.receiver_alive.store(false, Ordering::Relaxed);
+    }
+}
+
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    role::assert_not_rt("mpsc::channel");
+    let shared = Arc::new(Shared {
+        queue: ArrayQueue::new(capacity),
+        senders: AtomicUsize::new(1),
+        receiver_alive: AtomicBool::new(true),
+    });
+
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver {
+            shared,
+            _not_sync: PhantomData,
+        },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn send_recv() {
+        let (send, mut recv) = channel(3);
+        assert_eq!(send.capacity(), 4);
+        assert_eq!(recv.try_recv(), Err(TryRecvError::Empty));
+
+        for i in 0..4 {
+            send.try_send(i).unwrap();
+        }
+        assert_eq!(send.try_send(4), Err(TrySendError::Full(4)));
+        assert_eq!(recv.size(), 4);
+
+        assert_eq!(recv.try_recv(), Ok(0));
+        send.clone().try_send(4).unwrap();
+        let received: Vec<_> = (0..4).map(|_| recv.try_recv().unwrap()).collect();
+        assert_eq!(received, [1, 2, 3, 4]);
+        assert_eq!(recv.cycles(), (1, 1));
+    }
+
+    #[test]
+    fn disconnected() {
+        let (send, mut recv) = channel(4);
+        let other = send.clone();
+        send.try_send(1).unwrap();
+        drop(send);
+        assert!(recv.is_sender_active());
+        drop(other);
+
+        assert_eq!(recv.try_recv(), Ok(1));
+        assert_eq!(recv.try_recv(), Err(TryRecvError::Disconnected));
+
+        let (send, recv) = channel(4);
+        drop(recv);
+        assert_eq!(send.try_send(1), Err(TrySendError::Disconnected(1)));
+    }
+
+    #[test]
+    fn drops_queued() {
+        use std::sync::Arc;
+
+        let value = Arc::new(());
+        let (send, recv) = channel(4);
+        send.try_send(value.clone()).unwrap();
+        send.try_send(value.clone()).unwrap();
+        drop((send, recv));
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn many_producers() {
+        const PRODUCERS: u64 = 4;
+        const PER_PRODUCER: u64 = 10_000;
+
+        let (send, mut recv) = channel::<(u64, u64)>(16);
+        let producers: Vec<_> = (0..PRODUCERS)
+            .map(|p| {
+                let send = send.clone();
+                std::thread::spawn(move || {
+                    for i in 0..PER_PRODUCER {
+                        while send.try_send((p, i)).is_err() {
+                            std::thread::yield_now();
+                        }
+                    }
+                })
+            })
+            .collect();
+        drop(send);
+
+        // Each producer's values arrive in order.
+        let mut next = [0; PRODUCERS as usize];
+        loop {
+            match recv.try_recv() {
+                Ok((p, i)) => {
+                    assert_eq!(next[p as usize], i);
+                    next[p as usize] += 1;
+                }
+                Err(TryRecvError::Empty) => std::thread::yield_now(),
+                Err(TryRecvError::Disconnected) => break,
+            }
+        }
+
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        assert_eq!(next, [PER_PRODUCER; PRODUCERS as usize]);
+    }
+
+    // Only compiles while the call below is unambiguous, i.e. while
+    // `Receiver` isn't `Sync`.
+    trait AmbiguousIfSync<A> {
+        fn check() {}
+    }
+
+    impl<T: ?Sized> AmbiguousIfSync<()> for T {}
+    impl<T: ?Sized + Sync> AmbiguousIfSync<u8> for T {}
+
+    #[test]
+    fn receiver_not_sync() {
+        <Receiver<String> as AmbiguousIfSync<_>>::check();
+    }
+}
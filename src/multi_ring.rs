@@ -0,0 +1,262 @@
+use std::sync::atomic::{fence, AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+
+// Multichannel audio ring written once by a single producer and read by any
+// number of consumers, each at its own position (main output, a meter, a
+// sidechain analyzer, ...). The producer never waits for consumers: a
+// consumer that falls more than `frames` behind loses data, which it is told
+// about through `Lagged` on its next read.
+//
+// Samples are stored as f32 bits in relaxed atomics. The producer bumps
+// `write_begin` before touching any slot and `write_end` after, so a reader
+// can tell afterwards whether the frames it copied were overwritten while it
+// was reading.
+struct Shared {
+    samples: Box<[AtomicU32]>,
+    channels: usize,
+    frames: usize,
+    write_begin: AtomicU64,
+    write_end: AtomicU64,
+}
+
+pub struct MultiRingWriter {
+    shared: Arc<Shared>,
+}
+
+pub struct MultiRingReader {
+    shared: Arc<Shared>,
+    read_position: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Lagged {
+    pub skipped: u64,
+}
+
+impl MultiRingWriter {
+    // Writes interleaved frames, overwriting the oldest ones.
+    pub fn write(&self, interleaved: &[f32]) {
+        let shared = &*self.shared;
+        assert_eq!(
+            interleaved.len() % shared.channels,
+            0,
+            "Partial frame written"
+        );
+
+        let start = shared.write_end.load(Ordering::Relaxed);
+        let count = (interleaved.len() / shared.channels) as u64;
+
+        shared.write_begin.store(start + count, Ordering::Relaxed);
+        fence(Ordering::Release);
+
+        for (i, frame) in interleaved.chunks(shared.channels).enumerate() {
+            let slot = shared.slot(start + i as u64);
+            for (cell, sample) in shared.samples[slot..slot + shared.channels]
+                .iter()
+                .zip(frame)
+            {
+                cell.store(sample.to_bits(), Ordering::Relaxed);
+            }
+        }
+
+        shared.write_end.store(start + count, Ordering::Release);
+    }
+
+    pub fn position(&self) -> u64 {
+        self.shared.write_end.load(Ordering::Relaxed)
+    }
+
+    pub fn channels(&self) -> usize {
+        self.shared.channels
+    }
+
+    // A new consumer, starting at the current write position.
+    pub fn reader(&self) -> MultiRingReader {
+        MultiRingReader {
+            shared: self.shared.clone(),
+            read_position: self.position(),
+        }
+    }
+}
+
+impl MultiRingReader {
+    // Copies up to `out.len() / channels` frames into `out` and returns the
+    // number of frames read. If the producer got more than a ring's worth
+    // ahead, the reader jumps to the write position and reports how many
+    // frames were skipped instead.
+    pub fn read(&mut self, out: &mut [f32]) -> Result<usize, Lagged> {
+        let shared = &*self.shared;
+        let write_end = shared.write_end.load(Ordering::Acquire);
+        check_lag(shared, &mut self.read_position, write_end)?;
+
+        let available = (write_end - self.read_position) as usize;
+        let count = available.min(out.len() / shared.channels);
+
+        for (i, frame) in out.chunks_mut(shared.channels).take(count).enumerate() {
+            let slot = shared.slot(self.read_position + i as u64);
+            for (sample, cell) in frame.iter_mut().zip(&shared.samples[slot..]) {
+                *sample = f32::from_bits(cell.load(Ordering::Relaxed));
+            }
+        }
+
+        fence(Ordering::Acquire);
+        check_lag(
+            shared,
+            &mut self.read_position,
+            shared.write_begin.load(Ordering::Relaxed),
+        )?;
+
+        self.read_position += count as u64;
+        Ok(count)
+    }
+
+    pub fn position(&self) -> u64 {
+        self.read_position
+    }
+
+    // Frames written but not yet read by this consumer.
+    pub fn lag(&self) -> u64 {
+        self.shared.write_end.load(Ordering::Acquire) - self.read_position
+    }
+
+    pub fn channels(&self) -> usize {
+        self.shared.channels
+    }
+}
+
+fn check_lag(shared: &Shared, read_position: &mut u64, write_position: u64) -> Result<(), Lagged> {
+    if write_position - *read_position <= shared.frames as u64 {
+        return Ok(());
+    }
+
+    let write_end = shared.write_end.load(Ordering::Acquire);
+    let skipped = write_end - *read_position;
+    *read_position = write_end;
+    Err(Lagged { skipped })
+}
+
+impl Clone for MultiRingReader {
+    fn clone(&self) -> Self {
+        MultiRingReader {
+            shared: self.shared.clone(),
+            read_position: self.read_position,
+        }
+    }
+}
+
+impl Shared {
+    fn slot(&self, position: u64) -> usize {
+        (position % self.frames as u64) as usize * self.channels
+    }
+}
+
+pub fn multi_ring(channels: usize, frames: usize) -> MultiRingWriter {
+    assert!(channels > 0, "Ring needs at least one channel");
+    assert!(frames > 0, "Can not create ring with zero frames");
+
+    let samples = (0..channels * frames).map(|_| AtomicU32::new(0)).collect();
+    MultiRingWriter {
+        shared: Arc::new(Shared {
+            samples,
+            channels,
+            frames,
+            write_begin: AtomicU64::new(0),
+            write_end: AtomicU64::new(0),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::thread;
+
+    #[test]
+    fn independent_readers() {
+        let writer = multi_ring(2, 4);
+        let mut main = writer.reader();
+        let mut analyzer = writer.reader();
+
+        writer.write(&[1.0, -1.0, 2.0, -2.0, 3.0, -3.0]);
+
+        let mut out = [0.0; 4];
+        assert_eq!(main.read(&mut out), Ok(2));
+        assert_eq!(out, [1.0, -1.0, 2.0, -2.0]);
+        assert_eq!(main.lag(), 1);
+        assert_eq!(main.read(&mut out), Ok(1));
+        assert_eq!(out[..2], [3.0, -3.0]);
+        assert_eq!(main.read(&mut out), Ok(0));
+
+        let mut out = [0.0; 6];
+        assert_eq!(analyzer.read(&mut out), Ok(3));
+        assert_eq!(out, [1.0, -1.0, 2.0, -2.0, 3.0, -3.0]);
+    }
+
+    #[test]
+    fn wraparound() {
+        let writer = multi_ring(1, 3);
+        let mut reader = writer.reader();
+        let mut out = [0.0; 2];
+
+        for i in 0..10 {
+            writer.write(&[i as f32, i as f32 + 0.5]);
+            assert_eq!(reader.read(&mut out), Ok(2));
+            assert_eq!(out, [i as f32, i as f32 + 0.5]);
+        }
+    }
+
+    #[test]
+    fn lagging_reader() {
+        let writer = multi_ring(1, 4);
+        let mut slow = writer.reader();
+        let mut out = [0.0; 4];
+
+        writer.write(&[1.0, 2.0, 3.0]);
+        writer.write(&[4.0, 5.0, 6.0]);
+        assert_eq!(slow.read(&mut out), Err(Lagged { skipped: 6 }));
+        assert_eq!(slow.position(), 6);
+
+        writer.write(&[7.0]);
+        assert_eq!(slow.read(&mut out), Ok(1));
+        assert_eq!(out[0], 7.0);
+    }
+
+    #[test]
+    fn concurrent_readers_see_whole_frames() {
+        let writer = multi_ring(2, 64);
+        let readers: Vec<_> = (0..2).map(|_| writer.reader()).collect();
+
+        let threads: Vec<_> = readers
+            .into_iter()
+            .map(|mut reader| {
+                thread::spawn(move || {
+                    let mut out = [0.0; 16];
+                    let mut frames = 0;
+                    while frames < 1000 {
+                        match reader.read(&mut out) {
+                            Ok(n) => {
+                                for frame in out[..n * 2].chunks(2) {
+                                    assert_eq!(frame[0], -frame[1]);
+                                }
+                                frames += n;
+                            }
+                            Err(Lagged { skipped }) => frames += skipped as usize,
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for i in 0..1000 {
+            writer.write(&[i as f32, -(i as f32)]);
+            if i % 8 == 0 {
+                thread::yield_now();
+            }
+        }
+
+        for t in threads {
+            t.join().unwrap();
+        }
+    }
+}
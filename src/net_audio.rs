@@ -0,0 +1,426 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+
+use crate::spsc;
+
+// Minimal network audio transport over UDP, built on byte rings. Both ends
+// run on control threads and are driven by calling `pump` periodically:
+//
+// * `NetSender` takes bytes out of a ring filled by the RT thread and sends
+//   them as fixed-size packets carrying a sequence number and a timestamp
+//   (the stream offset of the first payload byte);
+// * `NetReceiver` reads packets into a `JitterBuffer`, which puts them back
+//   in order and fills the gaps left by lost packets with silence, and
+//   writes the reassembled stream into a ring for the RT thread on the far
+//   side.
+
+const MAGIC: [u8; 4] = *b"RTNA";
+pub const HEADER_LEN: usize = 22;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Packet {
+    pub sequence: u64,
+    pub timestamp: u64,
+    pub payload: Vec<u8>,
+}
+
+pub fn encode_packet(sequence: u64, timestamp: u64, payload: &[u8], out: &mut Vec<u8>) {
+    assert!(payload.len() <= u16::MAX as usize, "Payload too large");
+
+    out.clear();
+    out.extend_from_slice(&MAGIC);
+    out.extend_from_slice(&sequence.to_be_bytes());
+    out.extend_from_slice(&timestamp.to_be_bytes());
+    out.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    out.extend_from_slice(payload);
+}
+
+pub fn decode_packet(data: &[u8]) -> Option<Packet> {
+    if data.len() < HEADER_LEN || data[..4] != MAGIC {
+        return None;
+    }
+
+    let mut word = [0; 8];
+    word.copy_from_slice(&data[4..12]);
+    let sequence = u64::from_be_bytes(word);
+    word.copy_from_slice(&data[12..20]);
+    let timestamp = u64::from_be_bytes(word);
+    let len = u16::from_be_bytes([data[20], data[21]]) as usize;
+
+    let payload = data.get(HEADER_LEN..HEADER_LEN + len)?.to_vec();
+    Some(Packet {
+        sequence,
+        timestamp,
+        payload,
+    })
+}
+
+pub struct NetSender {
+    ring: spsc::Receiver<u8>,
+    socket: UdpSocket,
+    peer: SocketAddr,
+    payload_len: usize,
+    sequence: u64,
+    timestamp: u64,
+    payload: Vec<u8>,
+    packet: Vec<u8>,
+}
+
+impl NetSender {
+    pub fn new(
+        ring: spsc::Receiver<u8>,
+        socket: UdpSocket,
+        peer: SocketAddr,
+        payload_len: usize,
+    ) -> Self {
+        assert!(
+            payload_len > 0 && payload_len <= u16::MAX as usize,
+            "Invalid payload length"
+        );
+
+        NetSender {
+            ring,
+            socket,
+            peer,
+            payload_len,
+            sequence: 0,
+            timestamp: 0,
+            payload: Vec::with_capacity(payload_len),
+            packet: Vec::with_capacity(HEADER_LEN + payload_len),
+        }
+    }
+
+    // Sends every full packet's worth of queued bytes. Returns the number
+    // of packets sent.
+    pub fn pump(&mut self) -> io::Result<usize> {
+        let mut sent = 0;
+        while self.ring.size() >= self.payload_len {
+            self.send_packet(self.payload_len)?;
+            sent += 1;
+        }
+        Ok(sent)
+    }
+
+    // Sends whatever is queued, even less than a full packet.
+    pub fn flush(&mut self) -> io::Result<usize> {
+        let sent = self.pump()?;
+        let remaining = self.ring.size();
+        if remaining > 0 {
+            self.send_packet(remaining)?;
+            return Ok(sent + 1);
+        }
+        Ok(sent)
+    }
+
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    fn send_packet(&mut self, len: usize) -> io::Result<()> {
+        let ring = &mut self.ring;
+        self.payload.clear();
+        self.payload
+            .extend((0..len).filter_map(|_| ring.try_recv().ok()));
+
+        encode_packet(
+            self.sequence,
+            self.timestamp,
+            &self.payload,
+            &mut self.packet,
+        );
+        self.socket.send_to(&self.packet, self.peer)?;
+
+        self.sequence += 1;
+        self.timestamp += self.payload.len() as u64;
+        Ok(())
+    }
+}
+
+// Reorders packets and conceals losses. Packets are released in sequence
+// order; when more than `depth` packets are waiting behind a missing one,
+// the missing one is declared lost and replaced by silence covering its
+// timestamp range, up to `depth + 1` packets' worth; longer gaps resync
+// the stream instead. Packets arriving after their slot was released are
+// dropped, unless the sequence went back by more than `depth`, which is
+// taken as a sender restart.
+pub struct JitterBuffer {
+    depth: usize,
+    packets: BTreeMap<u64, Packet>,
+    next_sequence: u64,
+    next_timestamp: u64,
+    lost: u64,
+    late: u64,
+}
+
+impl JitterBuffer {
+    pub fn new(depth: usize) -> Self {
+        JitterBuffer {
+            depth,
+            packets: BTreeMap::new(),
+            next_sequence: 0,
+            next_timestamp: 0,
+            lost: 0,
+            late: 0,
+        }
+    }
+
+    pub fn insert(&mut self, packet: Packet) {
+        if packet.sequence + (self.depth as u64) < self.next_sequence {
+            // Whatever is still waiting belongs to the old stream.
+            self.lost += self.packets.len() as u64;
+            self.packets.clear();
+            self.next_sequence = packet.sequence;
+            self.next_timestamp = packet.timestamp;
+        } else if packet.sequence < self.next_sequence {
+            self.late += 1;
+            return;
+        }
+        self.packets.insert(packet.sequence, packet);
+    }
+
+    // Appends the bytes that are ready, in stream order, to `out`.
+    pub fn release(&mut self, out: &mut Vec<u8>) {
+        loop {
+            if let Some(packet) = self.packets.remove(&self.next_sequence) {
+                self.emit(&packet, out);
+                continue;
+            }
+
+            if self.packets.len() <= self.depth {
+                break;
+            }
+
+            let (&sequence, _) = self.packets.iter().next().unwrap();
+            let packet = self.packets.remove(&sequence).unwrap();
+            self.lost += sequence - self.next_sequence;
+            let gap = packet.timestamp.saturating_sub(self.next_timestamp);
+            let max_gap = (self.depth as u64 + 1) * packet.payload.len() as u64;
+            if gap <= max_gap {
+                out.extend((0..gap).map(|_| 0));
+            }
+            self.emit(&packet, out);
+        }
+    }
+
+    pub fn lost(&self) -> u64 {
+        self.lost
+    }
+
+    pub fn late(&self) -> u64 {
+        self.late
+    }
+
+    pub fn buffered(&self) -> usize {
+        self.packets.len()
+    }
+
+    fn emit(&mut self, packet: &Packet, out: &mut Vec<u8>) {
+        out.extend_from_slice(&packet.payload);
+        self.next_sequence = packet.sequence + 1;
+        self.next_timestamp = packet.timestamp + packet.payload.len() as u64;
+    }
+}
+
+pub struct NetReceiver {
+    socket: UdpSocket,
+    ring: spsc::Sender<u8>,
+    jitter: JitterBuffer,
+    datagram: Vec<u8>,
+    released: Vec<u8>,
+    overflowed: u64,
+}
+
+impl NetReceiver {
+    // Puts `socket` in non-blocking mode.
+    pub fn new(socket: UdpSocket, ring: spsc::Sender<u8>, jitter_depth: usize) -> io::Result<Self> {
+        socket.set_nonblocking(true)?;
+
+        Ok(NetReceiver {
+            socket,
+            ring,
+            jitter: JitterBuffer::new(jitter_depth),
+            datagram: vec![0; HEADER_LEN + u16::MAX as usize],
+            released: Vec::new(),
+            overflowed: 0,
+        })
+    }
+
+    // Reads all pending packets and writes whatever the jitter buffer
+    // releases into the ring. Bytes that don't fit in the ring are dropped
+    // and counted in `overflowed`. Returns the number of bytes written.
+    pub fn pump(&mut self) -> io::Result<usize> {
+        loop {
+            match self.socket.recv(&mut self.datagram) {
+                Ok(len) => {
+                    if let Some(packet) = decode_packet(&self.datagram[..len]) {
+                        self.jitter.insert(packet);
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        self.released.clear();
+        self.jitter.release(&mut self.released);
+
+        let mut written = 0;
+        for &byte in &self.released {
+            if self.ring.try_send(byte).is_err() {
+                self.overflowed += (self.released.len() - written) as u64;
+                break;
+            }
+            written += 1;
+        }
+        Ok(written)
+    }
+
+    pub fn jitter_buffer(&self) -> &JitterBuffer {
+        &self.jitter
+    }
+
+    pub fn overflowed(&self) -> u64 {
+        self.overflowed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    fn packet(sequence: u64, payload: &[u8]) -> Packet {
+        Packet {
+            sequence,
+            timestamp: sequence * payload.len() as u64,
+            payload: payload.to_vec(),
+        }
+    }
+
+    #[test]
+    fn packet_round_trip() {
+        let mut data = Vec::new();
+        encode_packet(7, 1024, &[1, 2, 3], &mut data);
+        assert_eq!(data.len(), HEADER_LEN + 3);
+        assert_eq!(
+            decode_packet(&data),
+            Some(packet(7, &[1, 2, 3])).map(|p| Packet {
+                timestamp: 1024,
+                ..p
+            })
+        );
+
+        assert_eq!(decode_packet(&data[..HEADER_LEN + 2]), None);
+        data[0] = b'X';
+        assert_eq!(decode_packet(&data), None);
+    }
+
+    #[test]
+    fn jitter_reorders() {
+        let mut jitter = JitterBuffer::new(2);
+        let mut out = Vec::new();
+
+        jitter.insert(packet(1, &[3, 4]));
+        jitter.release(&mut out);
+        assert!(out.is_empty());
+
+        jitter.insert(packet(0, &[1, 2]));
+        jitter.release(&mut out);
+        assert_eq!(out, [1, 2, 3, 4]);
+
+        jitter.insert(packet(0, &[1, 2]));
+        assert_eq!(jitter.late(), 1);
+    }
+
+    #[test]
+    fn jitter_conceals_loss() {
+        let mut jitter = JitterBuffer::new(1);
+        let mut out = Vec::new();
+
+        jitter.insert(packet(0, &[1, 1]));
+        jitter.insert(packet(2, &[3, 3]));
+        jitter.release(&mut out);
+        assert_eq!(out, [1, 1]);
+
+        jitter.insert(packet(3, &[4, 4]));
+        jitter.release(&mut out);
+        assert_eq!(out, [1, 1, 0, 0, 3, 3, 4, 4]);
+        assert_eq!(jitter.lost(), 1);
+    }
+
+    #[test]
+    fn jitter_bounds_concealment() {
+        let mut jitter = JitterBuffer::new(1);
+        let mut out = Vec::new();
+
+        jitter.insert(packet(0, &[1, 1]));
+        jitter.insert(Packet {
+            timestamp: u64::MAX / 2,
+            ..packet(2, &[3, 3])
+        });
+        jitter.insert(packet(3, &[4, 4]));
+        jitter.release(&mut out);
+        assert_eq!(out, [1, 1, 3, 3, 4, 4]);
+        assert_eq!(jitter.lost(), 1);
+    }
+
+    #[test]
+    fn jitter_resyncs_on_restart() {
+        let mut jitter = JitterBuffer::new(2);
+        let mut out = Vec::new();
+
+        for sequence in 100..103 {
+            jitter.insert(packet(sequence, &[1]));
+        }
+        jitter.insert(packet(104, &[1]));
+        jitter.insert(packet(105, &[1]));
+        jitter.insert(packet(106, &[1]));
+        jitter.release(&mut out);
+        assert_eq!(out, [1, 1, 1, 0, 1, 1, 1]);
+        out.clear();
+
+        // A straggler is still late...
+        jitter.insert(packet(105, &[9]));
+        assert_eq!(jitter.late(), 1);
+
+        // ...but a restarted sender is picked up again.
+        jitter.insert(packet(0, &[2]));
+        jitter.insert(packet(1, &[3]));
+        jitter.release(&mut out);
+        assert_eq!(out, [2, 3]);
+        assert_eq!(jitter.late(), 1);
+    }
+
+    #[test]
+    fn over_udp() {
+        let rx_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let tx_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let peer = rx_socket.local_addr().unwrap();
+
+        let (mut rt_out, net_in) = spsc::channel(256);
+        let (net_out, mut rt_in) = spsc::channel(256);
+        let mut sender = NetSender::new(net_in, tx_socket, peer, 16);
+        let mut receiver = NetReceiver::new(rx_socket, net_out, 4).unwrap();
+
+        for i in 0..40u8 {
+            rt_out.try_send(i).unwrap();
+        }
+        assert_eq!(sender.pump().unwrap(), 2);
+        assert_eq!(sender.flush().unwrap(), 1);
+        assert_eq!(sender.sequence(), 3);
+
+        let mut received = Vec::new();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while received.len() < 40 && Instant::now() < deadline {
+            receiver.pump().unwrap();
+            received.extend(rt_in.try_iter());
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        assert_eq!(received, (0..40).collect::<Vec<u8>>());
+        assert_eq!(receiver.jitter_buffer().lost(), 0);
+    }
+}
@@ -0,0 +1,148 @@
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+const UNINIT: u8 = 0;
+const INITIALIZING: u8 = 1;
+const READY: u8 = 2;
+
+// Write-once cell for values the RT thread needs but must never construct.
+//
+// Unlike `OnceLock`, there is no `get_or_init`: initialization only happens
+// through an explicit `init` on the control thread, and `get` is a single
+// Acquire load that returns `None` rather than waiting when the value isn't
+// ready yet.
+pub struct RtOnce<T> {
+    state: AtomicU8,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+unsafe impl<T: Send + Sync> Sync for RtOnce<T> {}
+unsafe impl<T: Send> Send for RtOnce<T> {}
+
+impl<T> RtOnce<T> {
+    pub const fn new() -> Self {
+        RtOnce {
+            state: AtomicU8::new(UNINIT),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    // Stores `value` unless the cell was already initialized (or is being
+    // initialized by another thread), in which case it is handed back.
+    pub fn init(&self, value: T) -> Result<(), T> {
+        if self
+            .state
+            .compare_exchange(UNINIT, INITIALIZING, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return Err(value);
+        }
+
+        unsafe { (*self.value.get()).as_mut_ptr().write(value) };
+        self.state.store(READY, Ordering::Release);
+
+        Ok(())
+    }
+
+    pub fn get(&self) -> Option<&T> {
+        if self.state.load(Ordering::Acquire) == READY {
+            Some(unsafe { &*(*self.value.get()).as_ptr() })
+        } else {
+            None
+        }
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        self.state.load(Ordering::Acquire) == READY
+    }
+
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        if *self.state.get_mut() == READY {
+            Some(unsafe { &mut *(*self.value.get()).as_mut_ptr() })
+        } else {
+            None
+        }
+    }
+
+    pub fn into_inner(mut self) -> Option<T> {
+        if *self.state.get_mut() == READY {
+            *self.state.get_mut() = UNINIT;
+            Some(unsafe { (*self.value.get()).as_ptr().read() })
+        } else {
+            None
+        }
+    }
+}
+
+impl<T> Default for RtOnce<T> {
+    fn default() -> Self {
+        RtOnce::new()
+    }
+}
+
+impl<T> Drop for RtOnce<T> {
+    fn drop(&mut self) {
+        if *self.state.get_mut() == READY {
+            unsafe { (*self.value.get()).as_mut_ptr().drop_in_place() };
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn uninitialized() {
+        let once = RtOnce::<i32>::new();
+        assert_eq!(once.get(), None);
+        assert!(!once.is_initialized());
+    }
+
+    #[test]
+    fn init_once() {
+        let once = RtOnce::new();
+        assert_eq!(once.init(1), Ok(()));
+        assert_eq!(once.init(2), Err(2));
+        assert_eq!(once.get(), Some(&1));
+    }
+
+    #[test]
+    fn static_table() {
+        static TABLE: RtOnce<Vec<f32>> = RtOnce::new();
+        TABLE.init((0..4).map(|i| i as f32).collect()).unwrap();
+        assert_eq!(TABLE.get().map(|t| t[3]), Some(3.0));
+    }
+
+    #[test]
+    fn visible_across_threads() {
+        let once = Arc::new(RtOnce::new());
+        let reader = once.clone();
+
+        once.init(String::from("ready")).unwrap();
+        let seen = thread::spawn(move || reader.get().cloned()).join().unwrap();
+        assert_eq!(seen.as_deref(), Some("ready"));
+    }
+
+    #[test]
+    fn drops_value() {
+        let value = Arc::new(());
+        {
+            let once = RtOnce::new();
+            once.init(value.clone()).unwrap();
+            assert_eq!(Arc::strong_count(&value), 2);
+        }
+        assert_eq!(Arc::strong_count(&value), 1);
+
+        let once = RtOnce::new();
+        once.init(value.clone()).unwrap();
+        let inner = once.into_inner().unwrap();
+        assert_eq!(Arc::strong_count(&value), 2);
+        drop(inner);
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+}
@@ -0,0 +1,182 @@
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+
+use crate::pi_detect;
+use crate::role;
+use crate::spsc::TryRecvError;
+use crate::wait::WaitStrategy;
+
+const EMPTY: u8 = 0;
+const SENT: u8 = 1;
+// Received, or one side dropped without the value getting through.
+const CLOSED: u8 = 2;
+
+// Channel for handing back a single value, typically from the RT thread
+// ("engine stopped, here's the final state"). `send` consumes the sender
+// and is a write plus one CAS; the receiver polls with `try_recv` or waits
+// with `recv_blocking`. A value the receiver never picked up is dropped with
+// the receiver, not on the sending thread.
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+struct Shared<T> {
+    state: AtomicU8,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+unsafe impl<T: Send> Sync for Shared<T> {}
+unsafe impl<T: Send> Send for Shared<T> {}
+
+impl<T> Sender<T> {
+    // Gives the value back if the receiver is gone.
+    pub fn send(self, value: T) -> Result<(), T> {
+        let shared = &*self.shared;
+        if shared.state.load(Ordering::Relaxed) != EMPTY {
+            return Err(value);
+        }
+
+        // Only the sender writes, and the receiver doesn't read before the
+        // state says so.
+        unsafe { (*shared.value.get()).write(value) };
+        match shared
+            .state
+            .compare_exchange(EMPTY, SENT, Ordering::Release, Ordering::Relaxed)
+        {
+            Ok(_) => Ok(()),
+            Err(_) => Err(unsafe { (*shared.value.get()).assume_init_read() }),
+        }
+    }
+
+    pub fn is_receiver_active(&self) -> bool {
+        self.shared.state.load(Ordering::Relaxed) == EMPTY
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        // Fails harmlessly after a send.
+        let _ =
+            self.shared
+                .state
+                .compare_exchange(EMPTY, CLOSED, Ordering::Release, Ordering::Relaxed);
+    }
+}
+
+impl<T> Receiver<T> {
+    // `Disconnected` once the value has been received, or if the sender was
+    // dropped without sending.
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let shared = &*self.shared;
+        match shared.state.load(Ordering::Acquire) {
+            EMPTY => Err(TryRecvError::Empty),
+            SENT => {
+                // The sender is done with the slot once it's `SENT`.
+                let value = unsafe { (*shared.value.get()).assume_init_read() };
+                shared.state.store(CLOSED, Ordering::Relaxed);
+                Ok(value)
+            }
+            _ => Err(TryRecvError::Disconnected),
+        }
+    }
+
+    pub fn recv_blocking(&mut self, strategy: WaitStrategy) -> Option<T> {
+        role::assert_not_rt("oneshot::Receiver::recv_blocking");
+        pi_detect::blocking("oneshot::recv_blocking");
+
+        let mut waiter = strategy.waiter();
+        loop {
+            match self.try_recv() {
+                Ok(value) => return Some(value),
+                Err(TryRecvError::Disconnected) => return None,
+                Err(TryRecvError::Empty) => {}
+            }
+
+            waiter.wait();
+        }
+    }
+
+    pub fn is_sender_active(&self) -> bool {
+        self.shared.state.load(Ordering::Relaxed) == EMPTY
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let shared = &*self.shared;
+        if shared.state.swap(CLOSED, Ordering::Acquire) == SENT {
+            unsafe { (*shared.value.get()).assume_init_drop() };
+        }
+    }
+}
+
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    role::assert_not_rt("oneshot::channel");
+    let shared = Arc::new(Shared {
+        state: AtomicU8::new(EMPTY),
+        value: UnsafeCell::new(MaybeUninit::uninit()),
+    });
+
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn send_recv() {
+        let (send, mut recv) = channel();
+        assert_eq!(recv.try_recv(), Err(TryRecvError::Empty));
+        assert!(send.is_receiver_active());
+
+        send.send(5).unwrap();
+        assert_eq!(recv.try_recv(), Ok(5));
+        assert_eq!(recv.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn disconnected() {
+        let (send, mut recv) = channel::<u32>();
+        drop(send);
+        assert!(!recv.is_sender_active());
+        assert_eq!(recv.try_recv(), Err(TryRecvError::Disconnected));
+
+        let (send, recv) = channel();
+        drop(recv);
+        assert!(!send.is_receiver_active());
+        assert_eq!(send.send(1), Err(1));
+    }
+
+    #[test]
+    fn drops_unreceived() {
+        let value = Arc::new(());
+        let (send, recv) = channel();
+        send.send(value.clone()).unwrap();
+        assert_eq!(Arc::strong_count(&value), 2);
+        drop(recv);
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn blocking() {
+        let (send, mut recv) = channel();
+        let sender = std::thread::spawn(move || send.send(42).unwrap());
+
+        let strategy = WaitStrategy::SpinThenYield { spins: 16 };
+        assert_eq!(recv.recv_blocking(strategy), Some(42));
+        assert_eq!(recv.recv_blocking(strategy), None);
+        sender.join().unwrap();
+    }
+}
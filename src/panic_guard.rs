@@ -0,0 +1,218 @@
+use std::any::Any;
+use std::cell::{Cell, UnsafeCell};
+use std::marker::PhantomData;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::poison::PoisonFlag;
+use crate::rtlog::{Level, Logger};
+use crate::text::FixedString;
+
+pub const PANIC_MESSAGE_LEN: usize = 128;
+
+struct State {
+    panicked: AtomicBool,
+    panic_count: AtomicU64,
+    // Written once by the RT thread before `panicked` is set, read-only after.
+    message: UnsafeCell<FixedString<PANIC_MESSAGE_LEN>>,
+}
+
+unsafe impl Sync for State {}
+unsafe impl Send for State {}
+
+// RT side. Owned by the one thread that runs the guarded callback.
+pub struct RtGuard {
+    state: Arc<State>,
+    log: Option<Logger>,
+    poisons: Vec<PoisonFlag>,
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+// Host/control side, for polling whether the callback has panicked.
+#[derive(Clone)]
+pub struct PanicMonitor {
+    state: Arc<State>,
+}
+
+// Runs `f`, catching any panic so it never unwinds into the caller (which is
+// typically an audio driver across an FFI boundary). Returns `None` if `f`
+// panicked. After the first panic the guard stays tripped; callers should
+// check `is_tripped` and output silence instead of re-entering broken state.
+//
+// Note that the process-wide panic hook still runs before the unwind is
+// caught; install a quiet hook if its stderr output is unacceptable.
+pub fn rt_guard<R, F: FnOnce() -> R>(guard: &mut RtGuard, f: F) -> Option<R> {
+    guard.run(f)
+}
+
+impl RtGuard {
+    pub fn with_log(mut self, log: Logger) -> Self {
+        self.log = Some(log);
+        self
+    }
+
+    // Poisons `flag` when a panic is caught, so control-side users of the
+    // attached channel or buffer see `Poisoned` instead of a silent consumer.
+    pub fn poisons(mut self, flag: PoisonFlag) -> Self {
+        self.poisons.push(flag);
+        self
+    }
+
+    pub fn run<R, F: FnOnce() -> R>(&mut self, f: F) -> Option<R> {
+        match panic::catch_unwind(AssertUnwindSafe(f)) {
+            Ok(value) => Some(value),
+            Err(payload) => {
+                self.on_panic(payload);
+                None
+            }
+        }
+    }
+
+    pub fn is_tripped(&self) -> bool {
+        self.state.panicked.load(Ordering::Relaxed)
+    }
+
+    fn on_panic(&mut self, payload: Box<dyn Any + Send>) {
+        let text = payload_text(&*payload);
+
+        self.state.panic_count.fetch_add(1, Ordering::Relaxed);
+
+        if !self.state.panicked.load(Ordering::Relaxed) {
+            unsafe {
+                let message = &mut *self.state.message.get();
+                message.push_str(text);
+            }
+            self.state.panicked.store(true, Ordering::Release);
+        }
+
+        for flag in &self.poisons {
+            flag.poison();
+        }
+
+        if let Some(log) = &mut self.log {
+            crate::rtlog!(log, Level::Error, "RT callback panicked: {}", text);
+        }
+
+        // The payload box was allocated by the panicking code; freeing it
+        // here is unavoidable without leaking it.
+        drop(payload);
+    }
+}
+
+impl PanicMonitor {
+    pub fn has_panicked(&self) -> bool {
+        self.state.panicked.load(Ordering::Acquire)
+    }
+
+    pub fn panic_count(&self) -> u64 {
+        self.state.panic_count.load(Ordering::Relaxed)
+    }
+
+    // The message of the first caught panic.
+    pub fn message(&self) -> Option<FixedString<PANIC_MESSAGE_LEN>> {
+        if self.has_panicked() {
+            Some(unsafe { *self.state.message.get() })
+        } else {
+            None
+        }
+    }
+}
+
+fn payload_text(payload: &(dyn Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&'static str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s
+    } else {
+        "<non-string panic payload>"
+    }
+}
+
+pub fn guard() -> (RtGuard, PanicMonitor) {
+    let state = Arc::new(State {
+        panicked: AtomicBool::new(false),
+        panic_count: AtomicU64::new(0),
+        message: UnsafeCell::new(FixedString::new()),
+    });
+
+    (
+        RtGuard {
+            state: state.clone(),
+            log: None,
+            poisons: Vec::new(),
+            _not_sync: PhantomData,
+        },
+        PanicMonitor { state },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::rtlog;
+
+    #[test]
+    fn passes_through_value() {
+        let (mut guard, monitor) = guard();
+        assert_eq!(rt_guard(&mut guard, || 42), Some(42));
+        assert!(!guard.is_tripped());
+        assert!(!monitor.has_panicked());
+        assert_eq!(monitor.message(), None);
+    }
+
+    #[test]
+    fn catches_panic() {
+        let (mut guard, monitor) = guard();
+        let result: Option<()> = rt_guard(&mut guard, || panic!("buffer index {}", 7));
+
+        assert_eq!(result, None);
+        assert!(guard.is_tripped());
+        assert!(monitor.has_panicked());
+        assert_eq!(monitor.message().unwrap(), "buffer index 7");
+    }
+
+    #[test]
+    fn keeps_first_message() {
+        let (mut guard, monitor) = guard();
+        rt_guard(&mut guard, || panic!("first"));
+        rt_guard(&mut guard, || panic!("second"));
+
+        assert_eq!(monitor.panic_count(), 2);
+        assert_eq!(monitor.message().unwrap(), "first");
+    }
+
+    #[test]
+    fn poisons_attached_endpoints() {
+        use crate::poison::Poisoned;
+        use crate::{spsc, triple_buffer};
+
+        let (to_rt, from_control) = spsc::channel::<i32>(4);
+        let (writer, reader) = triple_buffer::triple_buffer(0);
+        let (guard, _monitor) = guard();
+        let mut guard = guard
+            .poisons(from_control.poison_flag())
+            .poisons(writer.poison_flag());
+
+        rt_guard(&mut guard, || 1);
+        assert_eq!(to_rt.check_poisoned(), Ok(()));
+
+        rt_guard(&mut guard, || panic!("boom"));
+        assert_eq!(to_rt.check_poisoned(), Err(Poisoned));
+        assert_eq!(reader.check_poisoned(), Err(Poisoned));
+    }
+
+    #[test]
+    fn publishes_to_rtlog() {
+        let (log, mut drain) = rtlog::logger(4);
+        let (guard, _monitor) = guard();
+        let mut guard = guard.with_log(log);
+
+        rt_guard(&mut guard, || panic!("static message"));
+
+        let record = drain.try_recv().unwrap();
+        assert_eq!(record.level, Level::Error);
+        assert_eq!(record.message, "RT callback panicked: static message");
+    }
+}
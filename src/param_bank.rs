@@ -0,0 +1,308 @@
+use crate::triple_buffer::{self, Reader, Writer};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParamSnapshot<const N: usize> {
+    pub values: [f32; N],
+    pub generation: u64,
+}
+
+impl<const N: usize> ParamSnapshot<N> {
+    pub fn get(&self, index: usize) -> f32 {
+        self.values[index]
+    }
+}
+
+// Control side of a parameter bank. Setters only touch a local copy; call
+// `publish` to hand the whole bank to the RT side as one coherent snapshot.
+pub struct ParamWriter<const N: usize> {
+    writer: Writer<ParamSnapshot<N>>,
+    pending: [f32; N],
+    generation: u64,
+    dirty: bool,
+}
+
+// RT side of a parameter bank.
+pub struct ParamReader<const N: usize> {
+    reader: Reader<ParamSnapshot<N>>,
+}
+
+impl<const N: usize> ParamWriter<N> {
+    pub fn set(&mut self, index: usize, value: f32) {
+        self.pending[index] = value;
+        self.dirty = true;
+    }
+
+    pub fn set_all(&mut self, values: &[f32; N]) {
+        self.pending = *values;
+        self.dirty = true;
+    }
+
+    pub fn get(&self, index: usize) -> f32 {
+        self.pending[index]
+    }
+
+    pub fn values(&self) -> &[f32; N] {
+        &self.pending
+    }
+
+    // The snapshot most recently handed to the RT side.
+    pub fn published(&self) -> &ParamSnapshot<N> {
+        self.writer.last_written()
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    // Publishes pending changes. Returns false without publishing if nothing
+    // changed since the last call.
+    pub fn publish(&mut self) -> bool {
+        if !self.dirty {
+            return false;
+        }
+
+        self.generation += 1;
+
+        let mut slot = self.writer.get_mut();
+        slot.values = self.pending;
+        slot.generation = self.generation;
+        drop(slot);
+
+        self.dirty = false;
+        true
+    }
+}
+
+impl<const N: usize> ParamReader<N> {
+    pub fn read(&mut self) -> &ParamSnapshot<N> {
+        self.reader.read()
+    }
+
+    // From the snapshot the last `read` returned, so all the parameters a
+    // block looks up come from the same publish. Call `read` once at the
+    // start of the block.
+    pub fn get(&self, index: usize) -> f32 {
+        self.reader.last_read().values[index]
+    }
+}
+
+// RT-side helper producing per-sample ramps for flagged parameters. Call
+// `begin_block` once per block with the latest snapshot; flagged parameters
+// then ramp linearly from their previous block's value to the new one across
+// the block, unflagged parameters jump immediately.
+pub struct Ramper<const N: usize> {
+    ramped: [bool; N],
+    from: [f32; N],
+    to: [f32; N],
+    generation: u64,
+}
+
+impl<const N: usize> Ramper<N> {
+    pub const fn new(initial: &ParamSnapshot<N>) -> Self {
+        Ramper {
+            ramped: [false; N],
+            from: initial.values,
+            to: initial.values,
+            generation: initial.generation,
+        }
+    }
+
+    pub fn set_ramped(&mut self, index: usize, ramped: bool) {
+        self.ramped[index] = ramped;
+    }
+
+    pub fn begin_block(&mut self, snapshot: &ParamSnapshot<N>) {
+        self.from = self.to;
+
+        if snapshot.generation != self.generation {
+            self.to = snapshot.values;
+            self.generation = snapshot.generation;
+
+            for ((from, to), ramped) in self.from.iter_mut().zip(&self.to).zip(&self.ramped) {
+                if !ramped {
+                    *from = *to;
+                }
+            }
+        }
+    }
+
+    pub fn is_ramping(&self, index: usize) -> bool {
+        self.from[index] != self.to[index]
+    }
+
+    pub fn value(&self, index: usize) -> f32 {
+        self.to[index]
+    }
+
+    // Fills `out` with the per-sample values of parameter `index` for this
+    // block, ending exactly on the new value.
+    pub fn ramp(&self, index: usize, out: &mut [f32]) {
+        let from = self.from[index];
+        let to = self.to[index];
+
+        if from == to {
+            for sample in out.iter_mut() {
+                *sample = to;
+            }
+            return;
+        }
+
+        let step = (to - from) / out.len() as f32;
+        for (i, sample) in out.iter_mut().enumerate() {
+            *sample = from + step * (i + 1) as f32;
+        }
+
+        if let Some(last) = out.last_mut() {
+            *last = to;
+        }
+    }
+}
+
+pub fn param_bank<const N: usize>(initial: [f32; N]) -> (ParamWriter<N>, ParamReader<N>) {
+    let (writer, reader) = triple_buffer::triple_buffer(ParamSnapshot {
+        values: initial,
+        generation: 0,
+    });
+
+    (
+        ParamWriter {
+            writer,
+            pending: initial,
+            generation: 0,
+            dirty: false,
+        },
+        ParamReader { reader },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn initial() {
+        let (_writer, mut reader) = param_bank([0.5; 4]);
+        assert_eq!(reader.read().values, [0.5; 4]);
+        assert_eq!(reader.read().generation, 0);
+    }
+
+    #[test]
+    fn set_requires_publish() {
+        let (mut writer, mut reader) = param_bank([0.0; 4]);
+        writer.set(1, 1.0);
+        writer.set(3, 3.0);
+        assert_eq!(reader.get(1), 0.0);
+
+        assert!(writer.publish());
+        assert_eq!(reader.read().values, [0.0, 1.0, 0.0, 3.0]);
+        assert_eq!(reader.read().generation, 1);
+    }
+
+    #[test]
+    fn get_uses_last_read() {
+        let (mut writer, mut reader) = param_bank([0.0; 2]);
+        writer.set_all(&[1.0, 1.0]);
+        writer.publish();
+        reader.read();
+
+        writer.set_all(&[2.0, 2.0]);
+        writer.publish();
+        assert_eq!((reader.get(0), reader.get(1)), (1.0, 1.0));
+
+        reader.read();
+        assert_eq!((reader.get(0), reader.get(1)), (2.0, 2.0));
+    }
+
+    #[test]
+    fn publish_is_coherent_across_slots() {
+        let (mut writer, mut reader) = param_bank([0.0; 2]);
+        writer.set(0, 1.0);
+        writer.publish();
+        writer.set(1, 2.0);
+        writer.publish();
+        writer.set(0, 3.0);
+        writer.publish();
+
+        assert_eq!(reader.read().values, [3.0, 2.0]);
+        assert_eq!(reader.read().generation, 3);
+    }
+
+    #[test]
+    fn ramper_flagged_parameters() {
+        let (mut writer, mut reader) = param_bank([0.0; 2]);
+        let mut ramper = Ramper::new(reader.read());
+        ramper.set_ramped(0, true);
+
+        writer.set_all(&[1.0, 1.0]);
+        writer.publish();
+        ramper.begin_block(reader.read());
+
+        let mut block = [0.0; 4];
+        ramper.ramp(0, &mut block);
+        assert_eq!(block, [0.25, 0.5, 0.75, 1.0]);
+        assert!(ramper.is_ramping(0));
+
+        ramper.ramp(1, &mut block);
+        assert_eq!(block, [1.0; 4]);
+        assert!(!ramper.is_ramping(1));
+    }
+
+    #[test]
+    fn ramper_settles_after_one_block() {
+        let (mut writer, mut reader) = param_bank([0.0; 1]);
+        let mut ramper = Ramper::new(reader.read());
+        ramper.set_ramped(0, true);
+
+        writer.set(0, 2.0);
+        writer.publish();
+        ramper.begin_block(reader.read());
+        ramper.begin_block(reader.read());
+
+        let mut block = [0.0; 2];
+        ramper.ramp(0, &mut block);
+        assert_eq!(block, [2.0, 2.0]);
+        assert_eq!(ramper.value(0), 2.0);
+    }
+
+    #[test]
+    fn ramper_continues_from_last_target() {
+        let (mut writer, mut reader) = param_bank([0.0; 1]);
+        let mut ramper = Ramper::new(reader.read());
+        ramper.set_ramped(0, true);
+
+        writer.set(0, 1.0);
+        writer.publish();
+        ramper.begin_block(reader.read());
+
+        writer.set(0, 3.0);
+        writer.publish();
+        ramper.begin_block(reader.read());
+
+        let mut block = [0.0; 2];
+        ramper.ramp(0, &mut block);
+        assert_eq!(block, [2.0, 3.0]);
+    }
+
+    #[test]
+    fn published_excludes_pending() {
+        let (mut writer, _reader) = param_bank([0.0; 2]);
+        writer.set(0, 1.0);
+        writer.publish();
+        writer.set(1, 2.0);
+
+        assert_eq!(writer.published().values, [1.0, 0.0]);
+        assert_eq!(writer.values(), &[1.0, 2.0]);
+    }
+
+    #[test]
+    fn clean_publish_is_noop() {
+        let (mut writer, mut reader) = param_bank([0.0; 2]);
+        assert!(!writer.publish());
+
+        writer.set_all(&[1.0, 2.0]);
+        assert!(writer.is_dirty());
+        assert!(writer.publish());
+        assert!(!writer.publish());
+        assert_eq!(reader.read().generation, 1);
+    }
+}
@@ -0,0 +1,149 @@
+use serde::{Deserialize, Serialize};
+
+use crate::spsc;
+
+// Messages that were queued but never processed when the engine shut down,
+// collected per named channel so they can be written out with the rest of a
+// crash-recovery or session file and fed back in on the next start. The
+// serialization format is up to the caller.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PendingWork<T> {
+    channels: Vec<PendingChannel<T>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PendingChannel<T> {
+    pub name: String,
+    pub items: Vec<T>,
+}
+
+impl<T> PendingWork<T> {
+    pub fn new() -> Self {
+        PendingWork {
+            channels: Vec::new(),
+        }
+    }
+
+    // Moves everything queued in `receiver` into the bundle under `name`,
+    // after anything already collected for it. Returns the number of items
+    // drained.
+    pub fn drain(&mut self, name: &str, receiver: &mut spsc::Receiver<T>) -> usize {
+        let items = self.items_mut(name);
+        let before = items.len();
+        items.extend(receiver.try_iter());
+        items.len() - before
+    }
+
+    // Sends the items stored under `name`, oldest first, until the channel
+    // is full. Whatever didn't fit stays in the bundle. Returns the number
+    // of items sent.
+    pub fn reinject(&mut self, name: &str, sender: &mut spsc::Sender<T>) -> usize {
+        let index = match self.channels.iter().position(|c| c.name == name) {
+            Some(index) => index,
+            None => return 0,
+        };
+
+        let items = &mut self.channels[index].items;
+        let count = items.len().min(sender.size());
+        for item in items.drain(..count) {
+            // Can't fail, there is room for `count` items.
+            let _ = sender.try_send(item);
+        }
+
+        if items.is_empty() {
+            self.channels.remove(index);
+        }
+        count
+    }
+
+    pub fn take(&mut self, name: &str) -> Vec<T> {
+        match self.channels.iter().position(|c| c.name == name) {
+            Some(index) => self.channels.remove(index).items,
+            None => Vec::new(),
+        }
+    }
+
+    pub fn channels(&self) -> &[PendingChannel<T>] {
+        &self.channels
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.channels.iter().all(|c| c.items.is_empty())
+    }
+
+    fn items_mut(&mut self, name: &str) -> &mut Vec<T> {
+        let index = match self.channels.iter().position(|c| c.name == name) {
+            Some(index) => index,
+            None => {
+                self.channels.push(PendingChannel {
+                    name: name.to_owned(),
+                    items: Vec::new(),
+                });
+                self.channels.len() - 1
+            }
+        };
+        &mut self.channels[index].items
+    }
+}
+
+impl<T> Default for PendingWork<T> {
+    fn default() -> Self {
+        PendingWork::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    enum Automation {
+        Point { param: u32, value: f32 },
+    }
+
+    #[test]
+    fn drain_and_reinject() {
+        let (mut sender, mut receiver) = spsc::channel(4);
+        for param in 0..3 {
+            sender
+                .try_send(Automation::Point { param, value: 0.5 })
+                .unwrap();
+        }
+
+        let mut pending = PendingWork::new();
+        assert_eq!(pending.drain("automation", &mut receiver), 3);
+        assert_eq!(pending.drain("automation", &mut receiver), 0);
+
+        let json = serde_json::to_string(&pending).unwrap();
+        let mut restored: PendingWork<Automation> = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, pending);
+
+        let (mut sender, mut receiver) = spsc::channel(2);
+        assert_eq!(restored.reinject("automation", &mut sender), 2);
+        assert_eq!(restored.channels()[0].items.len(), 1);
+        assert_eq!(
+            receiver.try_recv(),
+            Ok(Automation::Point {
+                param: 0,
+                value: 0.5
+            })
+        );
+
+        receiver.try_recv().unwrap();
+        assert_eq!(restored.reinject("automation", &mut sender), 1);
+        assert!(restored.is_empty());
+        assert_eq!(restored.reinject("automation", &mut sender), 0);
+    }
+
+    #[test]
+    fn take() {
+        let (mut sender, mut receiver) = spsc::channel(4);
+        sender.try_send(1).unwrap();
+        sender.try_send(2).unwrap();
+
+        let mut pending = PendingWork::new();
+        pending.drain("midi", &mut receiver);
+        assert_eq!(pending.take("midi"), vec![1, 2]);
+        assert_eq!(pending.take("midi"), Vec::<i32>::new());
+    }
+}
@@ -0,0 +1,161 @@
+// Debug-only detection of priority inversion hazards, enabled with the
+// `pi-detector` feature. Without the feature every function here compiles to
+// nothing, so blocking primitives can call the hooks unconditionally.
+//
+// Two situations are reported through the installed rtlog logger:
+//
+// * a thread marked with `mark_rt_thread` entering a blocking primitive
+//   (the primitive calls `blocking` with a tag identifying the call site);
+// * a `hold` guard, wrapped around a control-side critical section that the
+//   RT thread may wait on, being held for longer than its budget.
+//
+// Reporting uses `try_lock` and never blocks; reports that lose the race, or
+// that don't fit in the log, are still counted in `violations`.
+
+#[cfg(feature = "pi-detector")]
+mod imp {
+    use std::cell::Cell;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant};
+
+    use crate::rtlog::{Level, Logger};
+
+    static LOGGER: Mutex<Option<Logger>> = Mutex::new(None);
+    static VIOLATIONS: AtomicU64 = AtomicU64::new(0);
+
+    thread_local! {
+        static IS_RT: Cell<bool> = const { Cell::new(false) };
+    }
+
+    pub fn install(logger: Logger) {
+        *LOGGER.lock().unwrap() = Some(logger);
+    }
+
+    pub fn mark_rt_thread() {
+        IS_RT.with(|rt| rt.set(true));
+    }
+
+    pub fn violations() -> u64 {
+        VIOLATIONS.load(Ordering::Relaxed)
+    }
+
+    pub fn blocking(tag: &'static str) {
+        if IS_RT.with(|rt| rt.get()) {
+            report(format_args!("RT thread entered blocking call [{}]", tag));
+        }
+    }
+
+    pub struct HoldGuard {
+        tag: &'static str,
+        budget: Duration,
+        start: Instant,
+    }
+
+    pub fn hold(tag: &'static str, budget: Duration) -> HoldGuard {
+        HoldGuard {
+            tag,
+            budget,
+            start: Instant::now(),
+        }
+    }
+
+    impl Drop for HoldGuard {
+        fn drop(&mut self) {
+            let held = self.start.elapsed();
+            if held > self.budget {
+                report(format_args!(
+                    "[{}] held for {}us, budget {}us",
+                    self.tag,
+                    held.as_micros(),
+                    self.budget.as_micros()
+                ));
+            }
+        }
+    }
+
+    fn report(args: std::fmt::Arguments) {
+        VIOLATIONS.fetch_add(1, Ordering::Relaxed);
+
+        if let Ok(mut logger) = LOGGER.try_lock() {
+            if let Some(logger) = logger.as_mut() {
+                logger.log(Level::Warn, args);
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "pi-detector"))]
+mod imp {
+    use std::time::Duration;
+
+    use crate::rtlog::Logger;
+
+    pub fn install(_logger: Logger) {}
+
+    pub fn mark_rt_thread() {}
+
+    pub fn violations() -> u64 {
+        0
+    }
+
+    #[inline(always)]
+    pub fn blocking(_tag: &'static str) {}
+
+    pub struct HoldGuard;
+
+    #[inline(always)]
+    pub fn hold(_tag: &'static str, _budget: Duration) -> HoldGuard {
+        HoldGuard
+    }
+}
+
+pub use self::imp::{blocking, hold, install, mark_rt_thread, violations, HoldGuard};
+
+#[cfg(all(test, feature = "pi-detector"))]
+mod test {
+    use super::*;
+
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::rtlog;
+
+    // The detector is process-global, so everything is exercised in a single
+    // test to keep the log and the violation count deterministic.
+    #[test]
+    fn reports() {
+        let (logger, mut drain) = rtlog::logger(8);
+        install(logger);
+        let before = violations();
+
+        blocking("not rt");
+        assert_eq!(violations(), before);
+
+        thread::spawn(|| {
+            mark_rt_thread();
+            blocking("spsc::recv_blocking");
+        })
+        .join()
+        .unwrap();
+
+        {
+            let _guard = hold("engine state", Duration::from_millis(1));
+            thread::sleep(Duration::from_millis(5));
+        }
+        {
+            let _guard = hold("engine state", Duration::from_secs(5));
+        }
+
+        assert_eq!(violations(), before + 2);
+
+        let first = drain.try_recv().unwrap();
+        assert_eq!(
+            first.message,
+            "RT thread entered blocking call [spsc::recv_blocking]"
+        );
+        let second = drain.try_recv().unwrap();
+        assert!(second.message.starts_with("[engine state] held for"));
+        assert!(drain.try_recv().is_none());
+    }
+}
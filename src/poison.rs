@@ -0,0 +1,76 @@
+use alloc::sync::Arc;
+use core::error;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+// Shared marker set when the thread on the other end of a primitive died
+// mid-operation (see `panic_guard`). Once poisoned, a flag stays poisoned.
+#[derive(Clone)]
+pub struct PoisonFlag {
+    poisoned: Arc<AtomicBool>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Poisoned;
+
+impl PoisonFlag {
+    pub fn new() -> Self {
+        PoisonFlag {
+            poisoned: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn poison(&self) {
+        self.poisoned.store(true, Ordering::Release);
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned.load(Ordering::Acquire)
+    }
+
+    pub fn check(&self) -> Result<(), Poisoned> {
+        if self.is_poisoned() {
+            Err(Poisoned)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Default for PoisonFlag {
+    fn default() -> Self {
+        PoisonFlag::new()
+    }
+}
+
+impl fmt::Debug for PoisonFlag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PoisonFlag")
+            .field("poisoned", &self.is_poisoned())
+            .finish()
+    }
+}
+
+impl fmt::Display for Poisoned {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("the peer thread panicked and the endpoint is poisoned")
+    }
+}
+
+impl error::Error for Poisoned {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn shared_between_clones() {
+        let flag = PoisonFlag::new();
+        let other = flag.clone();
+        assert_eq!(other.check(), Ok(()));
+
+        flag.poison();
+        assert!(other.is_poisoned());
+        assert_eq!(other.check(), Err(Poisoned));
+    }
+}
@@ -0,0 +1,133 @@
+use crate::pi_detect;
+use crate::role;
+use crate::wait::WaitStrategy;
+use crate::{mpmc, mpsc, spsc};
+
+// A receiving end that can tell whether a receive would return right away.
+pub trait Pollable {
+    // True if there's data queued or the senders are gone, so `try_recv`
+    // wouldn't report `Empty`.
+    fn is_ready(&self) -> bool;
+}
+
+impl<T> Pollable for spsc::Receiver<T> {
+    fn is_ready(&self) -> bool {
+        self.size() > 0 || !self.is_sender_active()
+    }
+}
+
+impl<T> Pollable for mpsc::Receiver<T> {
+    fn is_ready(&self) -> bool {
+        self.size() > 0 || !self.is_sender_active()
+    }
+}
+
+impl<T> Pollable for mpmc::Receiver<T> {
+    fn is_ready(&self) -> bool {
+        self.size() > 0 || !self.is_sender_active()
+    }
+}
+
+// Polls several receivers from one consumer thread. The receivers are
+// passed in on every call instead of being registered, since receiving
+// needs them mutably; a ready one is reported by its position in the slice.
+// `poll` checks them round-robin starting after the last one reported, so a
+// busy channel can't starve the others. Leave out receivers whose senders
+// are gone, or they'll keep reporting ready.
+#[derive(Default)]
+pub struct PollSet {
+    next: usize,
+}
+
+impl PollSet {
+    pub fn new() -> Self {
+        PollSet::default()
+    }
+
+    // The position of a ready receiver, if any.
+    pub fn poll(&mut self, receivers: &[&dyn Pollable]) -> Option<usize> {
+        let count = receivers.len();
+        for offset in 0..count {
+            let index = (self.next + offset) % count;
+            if receivers[index].is_ready() {
+                self.next = index + 1;
+                return Some(index);
+            }
+        }
+        None
+    }
+
+    // Waits until a receiver is ready. Returns `None` right away if there
+    // are no receivers.
+    pub fn wait(&mut self, receivers: &[&dyn Pollable], strategy: WaitStrategy) -> Option<usize> {
+        role::assert_not_rt("PollSet::wait");
+        pi_detect::blocking("PollSet::wait");
+
+        if receivers.is_empty() {
+            return None;
+        }
+
+        let mut waiter = strategy.waiter();
+        loop {
+            if let Some(index) = self.poll(receivers) {
+                return Some(index);
+            }
+
+            waiter.wait();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::spsc::TryRecvError;
+
+    #[test]
+    fn poll_round_robin() {
+        let (mut send_a, mut recv_a) = spsc::channel::<u32>(4);
+        let (send_b, recv_b) = mpsc::channel::<u32>(4);
+
+        let mut set = PollSet::new();
+        assert_eq!(set.poll(&[&recv_a, &recv_b]), None);
+
+        send_a.try_send(1).unwrap();
+        send_a.try_send(2).unwrap();
+        send_b.try_send(3).unwrap();
+        assert_eq!(set.poll(&[&recv_a, &recv_b]), Some(0));
+        assert_eq!(recv_a.try_recv(), Ok(1));
+        assert_eq!(set.poll(&[&recv_a, &recv_b]), Some(1));
+        assert_eq!(set.poll(&[&recv_a, &recv_b]), Some(0));
+        assert_eq!(set.poll(&[&recv_b]), Some(0));
+    }
+
+    #[test]
+    fn disconnected_is_ready() {
+        let (send, mut recv) = spsc::channel::<u32>(4);
+        let mut set = PollSet::new();
+        drop(send);
+        assert_eq!(set.poll(&[&recv]), Some(0));
+        assert_eq!(recv.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn wait() {
+        let (send_a, recv_a) = spsc::channel::<u32>(4);
+        let (mut send_b, mut recv_b) = spsc::channel::<u32>(4);
+
+        let sender = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            send_b.try_send(7).unwrap();
+            send_a
+        });
+
+        let mut set = PollSet::new();
+        let strategy = WaitStrategy::SpinThenYield { spins: 16 };
+        assert_eq!(set.wait(&[&recv_a, &recv_b], strategy), Some(1));
+        assert_eq!(recv_b.try_recv(), Ok(7));
+        drop(sender.join().unwrap());
+
+        assert_eq!(set.wait(&[], strategy), None);
+    }
+}
@@ -0,0 +1,146 @@
+use crate::spsc;
+
+// Converts between mismatched block sizes, e.g. a host calling back with
+// whatever frame count it likes feeding a processor that works on fixed
+// blocks. The input side accepts chunks of any length, the output side
+// hands out blocks of exactly `block_size` once enough samples are queued.
+// Both ends may live on different threads.
+pub struct RebufferInput<T> {
+    sender: spsc::Sender<T>,
+}
+
+pub struct RebufferOutput<T> {
+    receiver: spsc::Receiver<T>,
+    block_size: usize,
+}
+
+impl<T: Copy> RebufferInput<T> {
+    // Queues as much of `chunk` as fits and returns how many samples were
+    // taken.
+    pub fn push(&mut self, chunk: &[T]) -> usize {
+        let count = chunk.len().min(self.sender.size());
+        for &sample in &chunk[..count] {
+            // Can't fail: there is room for at least `count` samples and
+            // only this end writes.
+            let _ = self.sender.try_send(sample);
+        }
+        count
+    }
+
+    pub fn available(&self) -> usize {
+        self.sender.size()
+    }
+}
+
+impl<T: Copy + Default> RebufferOutput<T> {
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    pub fn queued(&self) -> usize {
+        self.receiver.size()
+    }
+
+    pub fn is_block_ready(&self) -> bool {
+        self.receiver.size() >= self.block_size
+    }
+
+    // Fills `block` with the next full block. Leaves it untouched and
+    // returns false if fewer than `block_size` samples are queued.
+    pub fn pop_block(&mut self, block: &mut [T]) -> bool {
+        assert_eq!(block.len(), self.block_size, "Block length mismatch");
+
+        if !self.is_block_ready() {
+            return false;
+        }
+
+        self.fill(block);
+        true
+    }
+
+    // Emits whatever is queued, at most one block, padding the rest of
+    // `block` with `T::default()`. Returns the number of real samples.
+    pub fn flush(&mut self, block: &mut [T]) -> usize {
+        assert_eq!(block.len(), self.block_size, "Block length mismatch");
+
+        let count = self.receiver.size().min(self.block_size);
+        self.fill(&mut block[..count]);
+        for sample in &mut block[count..] {
+            *sample = T::default();
+        }
+        count
+    }
+
+    fn fill(&mut self, out: &mut [T]) {
+        for sample in out {
+            *sample = self.receiver.try_recv().unwrap_or_default();
+        }
+    }
+}
+
+pub fn rebuffer<T>(block_size: usize, capacity: usize) -> (RebufferInput<T>, RebufferOutput<T>) {
+    assert!(block_size > 0, "Block size must be positive");
+    assert!(
+        capacity >= block_size,
+        "Capacity must hold at least one block"
+    );
+
+    let (sender, receiver) = spsc::channel(capacity);
+    (
+        RebufferInput { sender },
+        RebufferOutput {
+            receiver,
+            block_size,
+        },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn odd_chunks_to_blocks() {
+        let (mut input, mut output) = rebuffer::<i32>(4, 16);
+        let mut block = [0; 4];
+
+        assert_eq!(input.push(&[1, 2, 3]), 3);
+        assert!(!output.pop_block(&mut block));
+        assert_eq!(block, [0; 4]);
+
+        assert_eq!(input.push(&[4, 5]), 2);
+        assert!(output.pop_block(&mut block));
+        assert_eq!(block, [1, 2, 3, 4]);
+        assert!(!output.pop_block(&mut block));
+        assert_eq!(output.queued(), 1);
+
+        assert_eq!(input.push(&[6, 7, 8, 9, 10, 11, 12]), 7);
+        assert!(output.pop_block(&mut block));
+        assert_eq!(block, [5, 6, 7, 8]);
+        assert!(output.pop_block(&mut block));
+        assert_eq!(block, [9, 10, 11, 12]);
+    }
+
+    #[test]
+    fn push_when_full() {
+        let (mut input, mut output) = rebuffer::<i32>(2, 3);
+        assert_eq!(input.push(&[1, 2, 3, 4, 5]), 3);
+        assert_eq!(input.available(), 0);
+
+        let mut block = [0; 2];
+        assert!(output.pop_block(&mut block));
+        assert_eq!(input.push(&[4, 5, 6]), 2);
+    }
+
+    #[test]
+    fn flush_partial() {
+        let (mut input, mut output) = rebuffer::<f32>(4, 8);
+        let mut block = [9.0; 4];
+
+        input.push(&[1.0, 2.0]);
+        assert_eq!(output.flush(&mut block), 2);
+        assert_eq!(block, [1.0, 2.0, 0.0, 0.0]);
+        assert_eq!(output.flush(&mut block), 0);
+        assert_eq!(block, [0.0; 4]);
+    }
+}
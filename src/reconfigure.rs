@@ -0,0 +1,328 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::role;
+use crate::spsc;
+
+// Hand-off protocol for swapping preallocated resources (buffers sized for
+// a sample rate or block size, say) under a running RT thread:
+//
+// 1. the control thread prepares the new resources and `publish`es them;
+// 2. the RT thread calls `begin_block` at every block boundary, which adopts
+//    pending resources and sends the old ones back as acknowledgement;
+// 3. the control thread `poll`s (or `wait`s) for the old resources and
+//    frees them off the RT thread.
+//
+// Only one reconfiguration is in flight at a time: `publish` is refused
+// until the previous one has been reclaimed, which also guarantees that the
+// RT thread always has room to hand the old resources back.
+//
+// With `crossfade_blocks(k)`, the RT thread keeps the old resources for `k`
+// blocks after adopting new ones so both can run side by side while the
+// output fades across (see `crossfade`). The old resources are handed back
+// once the fade has finished.
+pub struct Reconfigure<R> {
+    to_rt: spsc::Sender<(u64, R)>,
+    from_rt: spsc::Receiver<(u64, R)>,
+    generation: u64,
+    phase: Phase,
+}
+
+pub struct RtResources<R> {
+    current: R,
+    generation: u64,
+    previous: Option<(u64, R)>,
+    fade_blocks: u32,
+    fade_position: u32,
+    from_ctrl: spsc::Receiver<(u64, R)>,
+    to_ctrl: spsc::Sender<(u64, R)>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    Idle,
+    // Published, not yet adopted by the RT thread or still crossfading.
+    Pending(u64),
+    // Adopted and any crossfade finished; the old resources are waiting to
+    // be reclaimed.
+    Adopted(u64),
+}
+
+impl<R> Reconfigure<R> {
+    // Returns the generation of the new resources, or gives them back if a
+    // reconfiguration is still in flight.
+    pub fn publish(&mut self, resources: R) -> Result<u64, R> {
+        self.poll_phase();
+        if self.phase != Phase::Idle {
+            return Err(resources);
+        }
+
+        let generation = self.generation + 1;
+        self.to_rt
+            .try_send((generation, resources))
+            .map_err(|e| e.into_inner().1)?;
+
+        self.generation = generation;
+        self.phase = Phase::Pending(generation);
+        Ok(generation)
+    }
+
+    pub fn phase(&mut self) -> Phase {
+        self.poll_phase();
+        self.phase
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    // Returns the resources the RT thread stopped using, once it has
+    // adopted the latest published ones. Dropping them here is the reclaim
+    // step.
+    pub fn poll(&mut self) -> Option<R> {
+        if let Phase::Pending(_) = self.phase {
+            self.poll_phase();
+        }
+
+        if let Phase::Adopted(_) = self.phase {
+            let (_, old) = self.from_rt.try_recv().ok()?;
+            self.phase = Phase::Idle;
+            return Some(old);
+        }
+
+        None
+    }
+
+    // Blocks until the RT thread has adopted the pending resources, and
+    // returns the old ones. Returns `None` on timeout or if nothing is
+    // pending.
+    pub fn wait(&mut self, timeout: Duration) -> Option<R> {
+        role::assert_not_rt("Reconfigure::wait");
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            if self.phase == Phase::Idle {
+                return None;
+            }
+            if let Some(old) = self.poll() {
+                return Some(old);
+            }
+            if Instant::now() >= deadline || !self.to_rt.is_receiver_active() {
+                return None;
+            }
+
+            thread::sleep(Duration::from_micros(100));
+        }
+    }
+
+    fn poll_phase(&mut self) {
+        if let Phase::Pending(generation) = self.phase {
+            if self.from_rt.size() > 0 {
+                self.phase = Phase::Adopted(generation);
+            }
+        }
+    }
+}
+
+impl<R> RtResources<R> {
+    pub fn crossfade_blocks(mut self, blocks: u32) -> Self {
+        self.fade_blocks = blocks;
+        self
+    }
+
+    // Call at the start of every block. Returns true if new resources were
+    // adopted, in which case anything derived from the old ones should be
+    // re-read.
+    pub fn begin_block(&mut self) -> bool {
+        if self.previous.is_some() {
+            self.fade_position += 1;
+            if self.fade_position >= self.fade_blocks {
+                self.release_previous();
+            }
+        }
+
+        // Nothing new can arrive while a fade is running, since the control
+        // side is still waiting for the previous resources.
+        let (generation, resources) = match self.from_ctrl.try_recv() {
+            Ok(next) => next,
+            Err(_) => return false,
+        };
+
+        let old = std::mem::replace(&mut self.current, resources);
+        let old_generation = std::mem::replace(&mut self.generation, generation);
+        self.previous = Some((old_generation, old));
+        self.fade_position = 0;
+
+        if self.fade_blocks == 0 {
+            self.release_previous();
+        }
+
+        true
+    }
+
+    // Fade coefficients at the start and end of the current block while a
+    // crossfade is running, going from 0.0 (all old) to 1.0 (all new).
+    pub fn crossfade(&self) -> Option<(f32, f32)> {
+        self.previous.as_ref()?;
+
+        let blocks = self.fade_blocks as f32;
+        Some((
+            self.fade_position as f32 / blocks,
+            (self.fade_position + 1) as f32 / blocks,
+        ))
+    }
+
+    // The current resources and, during a crossfade, the ones being faded
+    // out.
+    pub fn split_mut(&mut self) -> (&mut R, Option<&mut R>) {
+        (&mut self.current, self.previous.as_mut().map(|(_, r)| r))
+    }
+
+    pub fn get(&self) -> &R {
+        &self.current
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.current
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    fn release_previous(&mut self) {
+        // Can't fail while the control side is alive: it never publishes
+        // before reclaiming. If it is gone, the old resources are dropped
+        // here, which only happens during teardown.
+        if let Some(previous) = self.previous.take() {
+            let _ = self.to_ctrl.try_send(previous);
+        }
+    }
+}
+
+pub fn reconfigure<R>(initial: R) -> (Reconfigure<R>, RtResources<R>) {
+    let (to_rt, from_ctrl) = spsc::channel(1);
+    let (to_ctrl, from_rt) = spsc::channel(1);
+
+    let control = Reconfigure {
+        to_rt,
+        from_rt,
+        generation: 0,
+        phase: Phase::Idle,
+    };
+    let rt = RtResources {
+        current: initial,
+        generation: 0,
+        previous: None,
+        fade_blocks: 0,
+        fade_position: 0,
+        from_ctrl,
+        to_ctrl,
+    };
+
+    (control, rt)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    struct Engine {
+        sample_rate: u32,
+        scratch: Vec<f32>,
+    }
+
+    fn engine(sample_rate: u32, block_size: usize) -> Engine {
+        Engine {
+            sample_rate,
+            scratch: vec![0.0; block_size],
+        }
+    }
+
+    #[test]
+    fn state_machine() {
+        let (mut control, mut rt) = reconfigure(engine(44100, 64));
+        assert_eq!(control.phase(), Phase::Idle);
+        assert!(!rt.begin_block());
+
+        assert_eq!(control.publish(engine(48000, 128)).ok(), Some(1));
+        assert_eq!(control.phase(), Phase::Pending(1));
+        assert!(control.publish(engine(96000, 256)).is_err());
+        assert!(control.poll().is_none());
+
+        assert!(rt.begin_block());
+        assert_eq!(rt.get().sample_rate, 48000);
+        assert_eq!(rt.get().scratch.len(), 128);
+        assert_eq!(rt.generation(), 1);
+        assert!(!rt.begin_block());
+
+        assert_eq!(control.phase(), Phase::Adopted(1));
+        let old = control.poll().unwrap();
+        assert_eq!(old.sample_rate, 44100);
+        assert_eq!(control.phase(), Phase::Idle);
+
+        assert_eq!(control.publish(engine(96000, 256)).ok(), Some(2));
+    }
+
+    #[test]
+    fn crossfade() {
+        let (mut control, rt) = reconfigure(engine(44100, 64));
+        let mut rt = rt.crossfade_blocks(4);
+
+        rt.begin_block();
+        assert_eq!(rt.crossfade(), None);
+
+        control.publish(engine(48000, 64)).ok().unwrap();
+        let mut fades = Vec::new();
+        for _ in 0..4 {
+            assert!(rt.begin_block() == fades.is_empty());
+            fades.push(rt.crossfade().unwrap());
+
+            let (current, previous) = rt.split_mut();
+            assert_eq!(current.sample_rate, 48000);
+            assert_eq!(previous.unwrap().sample_rate, 44100);
+            assert_eq!(control.phase(), Phase::Pending(1));
+        }
+        assert_eq!(
+            fades,
+            vec![(0.0, 0.25), (0.25, 0.5), (0.5, 0.75), (0.75, 1.0)]
+        );
+
+        rt.begin_block();
+        assert_eq!(rt.crossfade(), None);
+        assert!(rt.split_mut().1.is_none());
+        assert_eq!(control.poll().unwrap().sample_rate, 44100);
+    }
+
+    #[test]
+    fn wait_for_rt_thread() {
+        let (mut control, mut rt) = reconfigure(engine(44100, 64));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let rt_thread = thread::spawn({
+            let stop = stop.clone();
+            move || {
+                while !stop.load(Ordering::Relaxed) {
+                    rt.begin_block();
+                    for s in rt.get_mut().scratch.iter_mut() {
+                        *s += 1.0;
+                    }
+                    thread::yield_now();
+                }
+                rt.get().sample_rate
+            }
+        });
+
+        for &rate in &[48000, 96000, 192000] {
+            control.publish(engine(rate, 32)).ok().unwrap();
+            assert!(control.wait(Duration::from_secs(5)).is_some());
+        }
+        assert!(control.wait(Duration::from_millis(1)).is_none());
+
+        stop.store(true, Ordering::Relaxed);
+        assert_eq!(rt_thread.join().unwrap(), 192000);
+    }
+}
@@ -0,0 +1,328 @@
+#[cfg(all(debug_assertions, feature = "std"))]
+use std::cell::Cell;
+#[cfg(feature = "std")]
+use std::thread;
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "std")]
+use crate::pi_detect;
+use crate::spsc::{self, TryRecvError, TrySendError};
+use crate::triple_buffer::{Reader, WriteGuard, Writer};
+
+// Endpoints tagged with the role of the thread that owns them. `RtEnd` only
+// exposes the wait-free operations of the wrapped endpoint, while `CtrlEnd`
+// adds blocking conveniences on top. Handing an RT thread a `CtrlEnd` is
+// still possible, but calling `send`/`recv` on an `RtEnd` is a compile error.
+
+#[cfg(feature = "std")]
+const POLL_INTERVAL: Duration = Duration::from_micros(100);
+
+// Runtime counterpart of the type-level split, for code paths the types
+// can't reach (trait objects, closures handed across). In debug builds,
+// blocking and allocating entry points in this crate call `assert_not_rt`,
+// which panics on a thread marked with `mark_current_thread_rt`. Release
+// builds compile the checks out, and so do builds without `std`, which
+// have no thread-locals to keep the mark in.
+#[cfg(all(debug_assertions, feature = "std"))]
+thread_local! {
+    static IS_RT: Cell<bool> = const { Cell::new(false) };
+}
+
+// Also marks the thread for the priority inversion detector.
+pub fn mark_current_thread_rt() {
+    #[cfg(all(debug_assertions, feature = "std"))]
+    IS_RT.with(|rt| rt.set(true));
+    #[cfg(feature = "std")]
+    pi_detect::mark_rt_thread();
+}
+
+pub fn is_current_thread_rt() -> bool {
+    #[cfg(all(debug_assertions, feature = "std"))]
+    return IS_RT.with(|rt| rt.get());
+    #[cfg(not(all(debug_assertions, feature = "std")))]
+    false
+}
+
+#[inline]
+pub fn assert_not_rt(what: &'static str) {
+    #[cfg(debug_assertions)]
+    assert!(!is_current_thread_rt(), "{} called from an RT thread", what);
+    #[cfg(not(debug_assertions))]
+    let _ = what;
+}
+
+pub struct RtEnd<E>(E);
+
+pub struct CtrlEnd<E>(E);
+
+impl<E> RtEnd<E> {
+    pub fn new(endpoint: E) -> Self {
+        RtEnd(endpoint)
+    }
+
+    pub fn into_inner(self) -> E {
+        self.0
+    }
+}
+
+impl<E> CtrlEnd<E> {
+    pub fn new(endpoint: E) -> Self {
+        CtrlEnd(endpoint)
+    }
+
+    pub fn into_inner(self) -> E {
+        self.0
+    }
+}
+
+impl<T> RtEnd<spsc::Sender<T>> {
+    pub fn try_send(&mut self, value: T) -> Result<(), TrySendError<T>> {
+        self.0.try_send(value)
+    }
+
+    pub fn size(&self) -> usize {
+        self.0.size()
+    }
+
+    pub fn is_receiver_active(&self) -> bool {
+        self.0.is_receiver_active()
+    }
+}
+
+impl<T> RtEnd<spsc::Receiver<T>> {
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        self.0.try_recv()
+    }
+
+    pub fn size(&self) -> usize {
+        self.0.size()
+    }
+
+    pub fn is_sender_active(&self) -> bool {
+        self.0.is_sender_active()
+    }
+}
+
+impl<T> RtEnd<Reader<T>> {
+    pub fn read(&mut self) -> &T {
+        self.0.read()
+    }
+}
+
+impl<T> RtEnd<Writer<T>> {
+    pub fn write(&mut self, value: T) {
+        self.0.write(value)
+    }
+
+    pub fn get_mut(&mut self) -> WriteGuard<'_, T> {
+        self.0.get_mut()
+    }
+}
+
+impl<T> CtrlEnd<spsc::Sender<T>> {
+    pub fn try_send(&mut self, value: T) -> Result<(), TrySendError<T>> {
+        self.0.try_send(value)
+    }
+
+    // Waits for space in the queue. Gives the value back if the receiver
+    // has been dropped.
+    #[cfg(feature = "std")]
+    pub fn send(&mut self, value: T) -> Result<(), T> {
+        self.send_until(value, None)
+    }
+
+    #[cfg(feature = "std")]
+    pub fn send_timeout(&mut self, value: T, timeout: Duration) -> Result<(), T> {
+        self.send_until(value, Some(Instant::now() + timeout))
+    }
+
+    pub fn size(&self) -> usize {
+        self.0.size()
+    }
+
+    pub fn is_receiver_active(&self) -> bool {
+        self.0.is_receiver_active()
+    }
+
+    #[cfg(feature = "std")]
+    fn send_until(&mut self, mut value: T, deadline: Option<Instant>) -> Result<(), T> {
+        assert_not_rt("CtrlEnd::send");
+        pi_detect::blocking("role::send");
+
+        loop {
+            value = match self.0.try_send(value) {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Full(value)) => value,
+                Err(TrySendError::Disconnected(value)) => return Err(value),
+            };
+
+            if deadline.is_some_and(|d| Instant::now() >= d) {
+                return Err(value);
+            }
+
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+impl<T> CtrlEnd<spsc::Receiver<T>> {
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        self.0.try_recv()
+    }
+
+    // Waits for a value. Returns `None` once the sender has been dropped and
+    // the queue is drained.
+    #[cfg(feature = "std")]
+    pub fn recv(&mut self) -> Option<T> {
+        self.recv_until(None)
+    }
+
+    #[cfg(feature = "std")]
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Option<T> {
+        self.recv_until(Some(Instant::now() + timeout))
+    }
+
+    pub fn size(&self) -> usize {
+        self.0.size()
+    }
+
+    pub fn is_sender_active(&self) -> bool {
+        self.0.is_sender_active()
+    }
+
+    #[cfg(feature = "std")]
+    fn recv_until(&mut self, deadline: Option<Instant>) -> Option<T> {
+        assert_not_rt("CtrlEnd::recv");
+        pi_detect::blocking("role::recv");
+
+        loop {
+            match self.0.try_recv() {
+                Ok(value) => return Some(value),
+                Err(TryRecvError::Disconnected) => return None,
+                Err(TryRecvError::Empty) => {}
+            }
+
+            if deadline.is_some_and(|d| Instant::now() >= d) {
+                return None;
+            }
+
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+impl<T> CtrlEnd<Reader<T>> {
+    pub fn read(&mut self) -> &T {
+        self.0.read()
+    }
+}
+
+impl<T> CtrlEnd<Writer<T>> {
+    pub fn write(&mut self, value: T) {
+        self.0.write(value)
+    }
+
+    pub fn get_mut(&mut self) -> WriteGuard<'_, T> {
+        self.0.get_mut()
+    }
+
+    pub fn last_written(&self) -> &T {
+        self.0.last_written()
+    }
+}
+
+pub fn ctrl_to_rt<T>(size: usize) -> (CtrlEnd<spsc::Sender<T>>, RtEnd<spsc::Receiver<T>>) {
+    let (sender, receiver) = spsc::channel(size);
+    (CtrlEnd(sender), RtEnd(receiver))
+}
+
+pub fn rt_to_ctrl<T>(size: usize) -> (RtEnd<spsc::Sender<T>>, CtrlEnd<spsc::Receiver<T>>) {
+    let (sender, receiver) = spsc::channel(size);
+    (RtEnd(sender), CtrlEnd(receiver))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::triple_buffer::triple_buffer;
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn round_trip() {
+        let (mut commands, mut rt_commands) = ctrl_to_rt(4);
+        let (mut rt_events, mut events) = rt_to_ctrl(4);
+
+        let rt = thread::spawn(move || {
+            let mut n = 0;
+            while n < 3 {
+                if let Ok(v) = rt_commands.try_recv() {
+                    rt_events.try_send(v * 2).unwrap();
+                    n += 1;
+                }
+            }
+        });
+
+        for v in 1..=3 {
+            commands.send(v).unwrap();
+        }
+        assert_eq!(events.recv(), Some(2));
+        assert_eq!(events.recv(), Some(4));
+        assert_eq!(events.recv(), Some(6));
+
+        rt.join().unwrap();
+        assert_eq!(events.recv(), None);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn timeouts() {
+        let (mut commands, mut rt_commands) = ctrl_to_rt(1);
+        commands.send(1).unwrap();
+        assert_eq!(commands.send_timeout(2, Duration::from_millis(5)), Err(2));
+
+        assert_eq!(rt_commands.try_recv(), Ok(1));
+        let (_rt_events, mut events) = rt_to_ctrl::<i32>(1);
+        assert_eq!(events.recv_timeout(Duration::from_millis(5)), None);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn send_to_dropped_receiver() {
+        let (mut commands, rt_commands) = ctrl_to_rt(1);
+        drop(rt_commands);
+        assert_eq!(commands.send(1), Err(1));
+    }
+
+    #[cfg(feature = "std")]
+    #[cfg(debug_assertions)]
+    #[test]
+    fn rt_thread_assertions() {
+        use std::panic;
+
+        thread::spawn(|| {
+            let (mut commands, _rt_commands) = ctrl_to_rt::<i32>(1);
+            assert!(!is_current_thread_rt());
+            commands.send(1).unwrap();
+
+            mark_current_thread_rt();
+            assert!(is_current_thread_rt());
+            assert!(panic::catch_unwind(panic::AssertUnwindSafe(|| commands.send(2))).is_err());
+            assert!(panic::catch_unwind(|| spsc::channel::<i32>(1)).is_err());
+        })
+        .join()
+        .unwrap();
+
+        assert!(!is_current_thread_rt());
+    }
+
+    #[test]
+    fn triple_buffer_roles() {
+        let (writer, reader) = triple_buffer(0);
+        let (mut writer, mut reader) = (CtrlEnd::new(writer), RtEnd::new(reader));
+        writer.write(5);
+        assert_eq!(writer.last_written(), &5);
+        assert_eq!(reader.read(), &5);
+    }
+}
@@ -0,0 +1,206 @@
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::dump::{DumpKind, DumpWriter, RingState};
+use crate::spsc;
+use crate::stats::Counter;
+use crate::text::FixedString;
+
+// Log messages longer than this are truncated.
+pub const MESSAGE_LEN: usize = 120;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Record {
+    pub level: Level,
+    pub timestamp: Instant,
+    pub message: FixedString<MESSAGE_LEN>,
+}
+
+// RT side of a log. Formatting happens into a fixed-size stack buffer and the
+// record is queued without allocating or locking; when the queue is full the
+// record is dropped and counted.
+pub struct Logger {
+    sender: spsc::Sender<Record>,
+    dropped: Arc<Counter>,
+}
+
+// Control side of a log.
+pub struct LogDrain {
+    receiver: spsc::Receiver<Record>,
+    dropped: Arc<Counter>,
+}
+
+#[macro_export]
+macro_rules! rtlog {
+    ($logger:expr, $level:expr, $($arg:tt)+) => {
+        $logger.log($level, format_args!($($arg)+))
+    };
+}
+
+impl Logger {
+    pub fn log(&mut self, level: Level, args: fmt::Arguments) -> bool {
+        let mut message = FixedString::new();
+        let _ = fmt::Write::write_fmt(&mut message, args);
+
+        self.log_record(Record {
+            level,
+            timestamp: Instant::now(),
+            message,
+        })
+    }
+
+    pub fn log_str(&mut self, level: Level, message: &str) -> bool {
+        let mut fixed = FixedString::new();
+        fixed.push_str(message);
+
+        self.log_record(Record {
+            level,
+            timestamp: Instant::now(),
+            message: fixed,
+        })
+    }
+
+    fn log_record(&mut self, record: Record) -> bool {
+        if self.sender.try_send(record).is_err() {
+            self.dropped.increment();
+            return false;
+        }
+        true
+    }
+}
+
+impl LogDrain {
+    pub fn try_recv(&mut self) -> Option<Record> {
+        self.receiver.try_recv().ok()
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped.get()
+    }
+
+    pub(crate) fn crash_view(&self) -> (spsc::CrashView<Record>, Arc<Counter>) {
+        (self.receiver.crash_view(), self.dropped.clone())
+    }
+
+    // Writes the queued records to `path` without consuming them; see
+    // `dump::read_dump`. Timestamps are stored as ages relative to now.
+    pub fn dump_to<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let now = Instant::now();
+        let (capacity, read_index, write_index) = self.receiver.ring_state();
+        let mut records = Vec::new();
+        self.receiver.peek_each(|r| records.push(*r));
+
+        let mut dump = DumpWriter::create(path, DumpKind::Log, 1)?;
+        dump.ring(
+            &RingState {
+                id: 0,
+                name: String::new(),
+                capacity,
+                read_index,
+                write_index,
+                dropped: self.dropped(),
+            },
+            records.len() as u32,
+        )?;
+        for record in &records {
+            dump.log_record(
+                record.level,
+                now.saturating_duration_since(record.timestamp),
+                &record.message,
+            )?;
+        }
+        dump.finish()
+    }
+}
+
+pub fn logger(capacity: usize) -> (Logger, LogDrain) {
+    let (sender, receiver) = spsc::channel(capacity);
+    let dropped = Arc::new(Counter::new());
+
+    (
+        Logger {
+            sender,
+            dropped: dropped.clone(),
+        },
+        LogDrain { receiver, dropped },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn log_and_drain() {
+        let (mut log, mut drain) = logger(4);
+        assert!(rtlog!(log, Level::Warn, "xrun after {} frames", 512));
+        assert!(log.log_str(Level::Info, "started"));
+
+        let record = drain.try_recv().unwrap();
+        assert_eq!(record.level, Level::Warn);
+        assert_eq!(record.message, "xrun after 512 frames");
+
+        let record = drain.try_recv().unwrap();
+        assert_eq!(record.level, Level::Info);
+        assert!(drain.try_recv().is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dump() {
+        use crate::dump::{read_dump, DumpEntry};
+        use crate::shm::temp_path;
+
+        let (mut log, mut drain) = logger(2);
+        log.log_str(Level::Error, "first");
+        log.log_str(Level::Debug, "second");
+        log.log_str(Level::Info, "dropped");
+
+        let path = temp_path("rtlog-dump");
+        drain.dump_to(&path).unwrap();
+        let dump = read_dump(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(dump.kind, DumpKind::Log);
+        let ring = &dump.rings[0];
+        assert_eq!(
+            (ring.capacity, ring.read_index, ring.write_index),
+            (2, 0, 2)
+        );
+        assert_eq!(ring.dropped, 1);
+        let messages: Vec<_> = ring
+            .entries
+            .iter()
+            .map(|e| match e {
+                DumpEntry::Log { level, message, .. } => (*level, message.as_str()),
+                _ => panic!("unexpected entry"),
+            })
+            .collect();
+        assert_eq!(
+            messages,
+            [(Level::Error, "first"), (Level::Debug, "second")]
+        );
+
+        // Dumping doesn't consume.
+        assert_eq!(drain.try_recv().unwrap().message, "first");
+    }
+
+    #[test]
+    fn counts_dropped() {
+        let (mut log, drain) = logger(1);
+        assert!(log.log_str(Level::Info, "one"));
+        assert!(!log.log_str(Level::Info, "two"));
+        assert_eq!(drain.dropped(), 1);
+    }
+}
@@ -0,0 +1,184 @@
+use std::ops::{Deref, DerefMut};
+
+use crate::role;
+use crate::spsc;
+
+// Channel for values too large to store in ring slots, like impulse
+// responses or sample buffers. The control side boxes each value and only
+// the pointer goes through the ring, so the ring stays a few words per slot
+// whatever the size of `T`. The RT side hands each value back with
+// `release` once it's done with it, and the control side frees it in
+// `collect` (or the next `send`), so the RT path never allocates or frees.
+//
+// At most `slots` values are out at a time, counting the ones the RT side
+// still holds; that also guarantees the return queue always has room.
+pub struct ScatterSender<T> {
+    to_rt: spsc::Sender<Box<T>>,
+    from_rt: spsc::Receiver<Box<T>>,
+    in_flight: usize,
+    slots: usize,
+}
+
+pub struct ScatterReceiver<T> {
+    from_ctrl: spsc::Receiver<Box<T>>,
+    to_ctrl: spsc::Sender<Box<T>>,
+}
+
+// A value received on the RT side. Must be given back with
+// `ScatterReceiver::release`; dropping it frees the allocation in place,
+// which trips `role::assert_not_rt` on an RT thread.
+pub struct Large<T>(Option<Box<T>>);
+
+impl<T> ScatterSender<T> {
+    // Gives the value back if `slots` values are already out, or if the
+    // receiver has been dropped.
+    pub fn send(&mut self, value: T) -> Result<(), T> {
+        role::assert_not_rt("ScatterSender::send");
+        self.collect();
+        if self.in_flight == self.slots {
+            return Err(value);
+        }
+
+        self.to_rt
+            .try_send(Box::new(value))
+            .map_err(|e| *e.into_inner())?;
+        self.in_flight += 1;
+        Ok(())
+    }
+
+    // Frees the values the RT side has released. Returns how many there
+    // were.
+    pub fn collect(&mut self) -> usize {
+        role::assert_not_rt("ScatterSender::collect");
+        let mut freed = 0;
+        while self.from_rt.try_recv().is_ok() {
+            freed += 1;
+        }
+        self.in_flight -= freed;
+        freed
+    }
+
+    // Values sent and not yet collected.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+    }
+
+    pub fn is_receiver_active(&self) -> bool {
+        self.to_rt.is_receiver_active()
+    }
+}
+
+impl<T> ScatterReceiver<T> {
+    pub fn try_recv(&mut self) -> Option<Large<T>> {
+        self.from_ctrl
+            .try_recv()
+            .ok()
+            .map(|value| Large(Some(value)))
+    }
+
+    pub fn release(&mut self, mut value: Large<T>) {
+        // Can't be full, see `ScatterSender`. If the control side is gone
+        // the value is dropped here, which only happens during teardown.
+        if let Some(value) = value.0.take() {
+            let _ = self.to_ctrl.try_send(value);
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.from_ctrl.size()
+    }
+
+    pub fn is_sender_active(&self) -> bool {
+        self.from_ctrl.is_sender_active()
+    }
+}
+
+impl<T> Deref for Large<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.0.as_ref().unwrap()
+    }
+}
+
+impl<T> DerefMut for Large<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.0.as_mut().unwrap()
+    }
+}
+
+impl<T> Drop for Large<T> {
+    fn drop(&mut self) {
+        if self.0.is_some() {
+            role::assert_not_rt("dropping a scatter::Large");
+        }
+    }
+}
+
+pub fn scatter_channel<T>(slots: usize) -> (ScatterSender<T>, ScatterReceiver<T>) {
+    let (to_rt, from_ctrl) = spsc::channel(slots);
+    let (to_ctrl, from_rt) = spsc::channel(slots);
+
+    (
+        ScatterSender {
+            to_rt,
+            from_rt,
+            in_flight: 0,
+            slots,
+        },
+        ScatterReceiver { from_ctrl, to_ctrl },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn send_release_collect() {
+        let (mut send, mut recv) = scatter_channel::<[f32; 4096]>(2);
+        send.send([1.0; 4096]).unwrap();
+        send.send([2.0; 4096]).unwrap();
+        assert!(send.send([3.0; 4096]).is_err());
+
+        let first = recv.try_recv().unwrap();
+        assert_eq!(first[0], 1.0);
+        recv.release(first);
+        assert_eq!(send.in_flight(), 2);
+
+        // Sending collects first, which makes room.
+        send.send([3.0; 4096]).unwrap();
+        assert_eq!(send.in_flight(), 2);
+
+        let mut second = recv.try_recv().unwrap();
+        second[0] = 4.0;
+        recv.release(second);
+        let third = recv.try_recv().unwrap();
+        recv.release(third);
+        assert_eq!(send.collect(), 2);
+        assert_eq!(send.in_flight(), 0);
+        assert!(recv.try_recv().is_none());
+    }
+
+    #[test]
+    fn disconnected() {
+        let (mut send, recv) = scatter_channel(1);
+        drop(recv);
+        assert_eq!(send.send(vec![1u8]), Err(vec![1u8]));
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn drop_on_rt_thread() {
+        let (mut send, mut recv) = scatter_channel(1);
+        send.send(vec![0u8; 16]).unwrap();
+
+        std::thread::spawn(move || {
+            role::mark_current_thread_rt();
+            let value = recv.try_recv().unwrap();
+            assert!(std::panic::catch_unwind(move || drop(value)).is_err());
+        })
+        .join()
+        .unwrap();
+    }
+}
@@ -0,0 +1,115 @@
+use core::cell::UnsafeCell;
+use core::ptr;
+use core::sync::atomic::{fence, AtomicUsize, Ordering};
+
+// Sequence lock for small `Copy` values: readers never block the writer and
+// retry if a write happened while they were copying. An odd sequence number
+// means a write is in progress. Concurrent writers serialize on the sequence
+// number, but the intended use is a single publishing thread.
+pub struct SeqLock<T> {
+    seq: AtomicUsize,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: Copy + Send> Sync for SeqLock<T> {}
+
+impl<T: Copy> SeqLock<T> {
+    pub const fn new(value: T) -> Self {
+        SeqLock {
+            seq: AtomicUsize::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn write(&self, value: T) {
+        let seq = loop {
+            let seq = self.seq.load(Ordering::Relaxed);
+            if seq & 1 == 0
+                && self
+                    .seq
+                    .compare_exchange_weak(seq, seq + 1, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                break seq;
+            }
+            core::hint::spin_loop();
+        };
+        fence(Ordering::Release);
+
+        unsafe { ptr::write_volatile(self.value.get(), value) };
+
+        self.seq.store(seq + 2, Ordering::Release);
+    }
+
+    pub fn read(&self) -> T {
+        loop {
+            if let Some(value) = self.try_read() {
+                return value;
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    // A single attempt, failing if a write was in progress.
+    pub fn try_read(&self) -> Option<T> {
+        let before = self.seq.load(Ordering::Acquire);
+        if before & 1 != 0 {
+            return None;
+        }
+
+        let value = unsafe { ptr::read_volatile(self.value.get()) };
+
+        fence(Ordering::Acquire);
+        if self.seq.load(Ordering::Relaxed) != before {
+            return None;
+        }
+
+        Some(value)
+    }
+
+    // Number of completed writes.
+    pub fn version(&self) -> usize {
+        self.seq.load(Ordering::Acquire) / 2
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn read_write() {
+        static LOCK: SeqLock<(u32, u32)> = SeqLock::new((0, 0));
+
+        assert_eq!(LOCK.read(), (0, 0));
+        LOCK.write((1, 2));
+        assert_eq!(LOCK.try_read(), Some((1, 2)));
+        assert_eq!(LOCK.version(), 1);
+    }
+
+    #[test]
+    fn no_torn_reads() {
+        let lock = Arc::new(SeqLock::new([0u64; 8]));
+
+        let reader = thread::spawn({
+            let lock = lock.clone();
+            move || {
+                let mut last = 0;
+                while last < 10_000 {
+                    let value = lock.read();
+                    assert!(value.iter().all(|&v| v == value[0]));
+                    assert!(value[0] >= last);
+                    last = value[0];
+                }
+            }
+        });
+
+        for i in 1..=10_000 {
+            lock.write([i; 8]);
+        }
+        reader.join().unwrap();
+    }
+}
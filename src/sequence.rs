@@ -0,0 +1,170 @@
+use crate::spsc;
+
+// Opt-in sequence stamping for channels that can lose messages. The sender
+// stamps every message it attempts to send, including ones rejected because
+// the queue was full, so the receiver can count exactly what never arrived.
+// `GapDetector` can also be used on its own with any transport that carries
+// the sequence number along.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GapStats {
+    // Number of discontinuities seen.
+    pub gaps: u64,
+    // Total number of messages missing across all gaps.
+    pub lost: u64,
+    // Messages that arrived with a sequence older than one already seen.
+    pub stale: u64,
+}
+
+#[derive(Default)]
+pub struct GapDetector {
+    next: u64,
+    stats: GapStats,
+}
+
+impl GapDetector {
+    pub fn new() -> Self {
+        GapDetector::default()
+    }
+
+    // Returns the number of messages missing right before `sequence`.
+    pub fn observe(&mut self, sequence: u64) -> u64 {
+        if sequence < self.next {
+            self.stats.stale += 1;
+            return 0;
+        }
+
+        let missing = sequence - self.next;
+        if missing > 0 {
+            self.stats.gaps += 1;
+            self.stats.lost += missing;
+        }
+        self.next = sequence + 1;
+        missing
+    }
+
+    pub fn stats(&self) -> GapStats {
+        self.stats
+    }
+
+    pub fn reset(&mut self) {
+        self.stats = GapStats::default();
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sequenced<T> {
+    pub sequence: u64,
+    pub value: T,
+}
+
+pub struct SequencedSender<T> {
+    sender: spsc::Sender<Sequenced<T>>,
+    next: u64,
+}
+
+pub struct SequencedReceiver<T> {
+    receiver: spsc::Receiver<Sequenced<T>>,
+    detector: GapDetector,
+}
+
+impl<T> SequencedSender<T> {
+    // The sequence number is consumed even when the send fails.
+    pub fn try_send(&mut self, value: T) -> Result<(), T> {
+        let sequence = self.next;
+        self.next += 1;
+        self.sender
+            .try_send(Sequenced { sequence, value })
+            .map_err(|e| e.into_inner().value)
+    }
+
+    pub fn sequence(&self) -> u64 {
+        self.next
+    }
+
+    pub fn is_receiver_active(&self) -> bool {
+        self.sender.is_receiver_active()
+    }
+}
+
+impl<T> SequencedReceiver<T> {
+    pub fn try_recv(&mut self) -> Option<T> {
+        self.try_recv_sequenced().map(|s| s.value)
+    }
+
+    pub fn try_recv_sequenced(&mut self) -> Option<Sequenced<T>> {
+        let message = self.receiver.try_recv().ok()?;
+        self.detector.observe(message.sequence);
+        Some(message)
+    }
+
+    pub fn gaps(&self) -> GapStats {
+        self.detector.stats()
+    }
+
+    pub fn reset_gaps(&mut self) {
+        self.detector.reset();
+    }
+
+    pub fn is_sender_active(&self) -> bool {
+        self.receiver.is_sender_active()
+    }
+}
+
+pub fn sequenced_channel<T>(size: usize) -> (SequencedSender<T>, SequencedReceiver<T>) {
+    let (sender, receiver) = spsc::channel(size);
+
+    (
+        SequencedSender { sender, next: 0 },
+        SequencedReceiver {
+            receiver,
+            detector: GapDetector::new(),
+        },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn counts_rejected_sends() {
+        let (mut send, mut recv) = sequenced_channel(2);
+
+        send.try_send(0).unwrap();
+        send.try_send(1).unwrap();
+        assert_eq!(send.try_send(2), Err(2));
+        assert_eq!(send.try_send(3), Err(3));
+
+        assert_eq!(recv.try_recv(), Some(0));
+        assert_eq!(recv.try_recv(), Some(1));
+        send.try_send(4).unwrap();
+        assert_eq!(recv.try_recv(), Some(4));
+
+        assert_eq!(
+            recv.gaps(),
+            GapStats {
+                gaps: 1,
+                lost: 2,
+                stale: 0
+            }
+        );
+    }
+
+    #[test]
+    fn detector() {
+        let mut detector = GapDetector::new();
+        assert_eq!(detector.observe(0), 0);
+        assert_eq!(detector.observe(3), 2);
+        assert_eq!(detector.observe(2), 0);
+        assert_eq!(detector.observe(7), 3);
+
+        assert_eq!(
+            detector.stats(),
+            GapStats {
+                gaps: 2,
+                lost: 5,
+                stale: 1
+            }
+        );
+    }
+}
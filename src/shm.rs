@@ -0,0 +1,127 @@
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::ptr::{self, NonNull};
+
+// A file-backed `MAP_SHARED` mapping: the building block for primitives
+// that are shared between processes. Whoever creates the file sizes it;
+// other processes open it with the size it already has.
+pub struct SharedMapping {
+    ptr: NonNull<u8>,
+    len: usize,
+    _file: File,
+}
+
+unsafe impl Send for SharedMapping {}
+unsafe impl Sync for SharedMapping {}
+
+impl SharedMapping {
+    // Creates (or truncates) `path` to `len` zeroed bytes and maps it.
+    pub fn create<P: AsRef<Path>>(path: P, len: usize) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.set_len(len as u64)?;
+
+        SharedMapping::map(file, len)
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let len = file.metadata()?.len() as usize;
+
+        SharedMapping::map(file, len)
+    }
+
+    fn map(file: File, len: usize) -> io::Result<Self> {
+        if len == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "can not map an empty file",
+            ));
+        }
+
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(SharedMapping {
+            ptr: NonNull::new(ptr as *mut u8).unwrap(),
+            len,
+            _file: file,
+        })
+    }
+
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.ptr.as_ptr()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Drop for SharedMapping {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr.as_ptr() as *mut libc::c_void, self.len);
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn temp_path(name: &str) -> std::path::PathBuf {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    std::env::temp_dir().join(format!(
+        "rt_utils-{}-{}-{}",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed),
+        name
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn shared_between_mappings() {
+        let path = temp_path("shm");
+        let a = SharedMapping::create(&path, 4096).unwrap();
+        let b = SharedMapping::open(&path).unwrap();
+        assert_eq!(b.len(), 4096);
+
+        unsafe {
+            *a.as_ptr().add(100) = 42;
+            assert_eq!(*b.as_ptr().add(100), 42);
+        }
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn open_missing() {
+        assert!(SharedMapping::open(temp_path("missing")).is_err());
+    }
+}
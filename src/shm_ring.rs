@@ -0,0 +1,724 @@
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::ptr;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+use crate::layout::StableLayout;
+use crate::shm::SharedMapping;
+
+// Framed byte ring in a shared mapping, for passing messages between
+// processes. The mapping starts with a header describing the ring, which
+// `open` validates, followed by the two indices on their own cache lines
+// and the data.
+//
+// A peer process can't be trusted to leave the mapping intact, so the
+// consumer checks the indices and frame lengths before using them, and with
+// checksums enabled every frame also carries a CRC32 of its payload.
+// Problems are reported as `RecvError`s rather than handed to the caller.
+//
+// Host and plugin may be built against different versions of this crate.
+// The creator records the features it supports in the header; whoever
+// attaches narrows that set to what both sides support. Each frame says
+// whether it carries a checksum, so the set can shrink while frames are
+// queued. Only a mismatching element layout hash refuses the attach.
+//
+// The indices are 64 bits wide unless the creator asks for
+// `Features::NARROW_INDICES`, for peers without 64-bit atomics. That bit
+// describes the layout rather than an optional feature, so attaching never
+// clears it, and narrow rings are marked version 3 so that peers built
+// before the bit existed refuse them instead of misreading the indices.
+// Narrow indices wrap at 2^32, so the capacity has to be a power of two for
+// positions to map to the same offset on either side of the wrap.
+//
+// Every multi-byte field in the mapping is little-endian, header, indices
+// and frame words alike, so a big-endian peer (a DSP next to an x86 host,
+// say) reads the same ring. The atomics hold the little-endian
+// representation and are swapped on load and store, which compiles to
+// nothing on little-endian hosts.
+const MAGIC: [u8; 8] = *b"RTSHRING";
+const VERSION: u32 = 2;
+const NARROW_VERSION: u32 = 3;
+
+const FEATURES_OFFSET: usize = 12;
+const CAPACITY_OFFSET: usize = 16;
+const LAYOUT_OFFSET: usize = 24;
+
+const WRITE_OFFSET: usize = 64;
+const READ_OFFSET: usize = 128;
+const DATA_OFFSET: usize = 192;
+
+// Set in the length word of frames that carry a checksum.
+const CHECKSUM_BIT: u32 = 1 << 31;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Features(u32);
+
+impl Features {
+    pub const NONE: Features = Features(0);
+    pub const CHECKSUMS: Features = Features(1);
+    // 32-bit indices. Chosen by the creator; not part of `all()`, which
+    // lists the negotiable features.
+    pub const NARROW_INDICES: Features = Features(2);
+
+    pub const fn all() -> Self {
+        Features::CHECKSUMS
+    }
+
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    pub const fn from_bits(bits: u32) -> Self {
+        Features(bits)
+    }
+
+    pub const fn contains(self, other: Features) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn union(self, other: Features) -> Self {
+        Features(self.0 | other.0)
+    }
+}
+
+// What one side of the ring supports. `layout_hash` identifies the layout of
+// the messages sent through the ring, e.g. a hash of the type's definition;
+// zero means unknown and matches anything.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    pub features: Features,
+    pub layout_hash: u64,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Capabilities {
+            features: Features::all(),
+            layout_hash: 0,
+        }
+    }
+}
+
+impl Capabilities {
+    pub fn with_features(mut self, features: Features) -> Self {
+        self.features = features;
+        self
+    }
+
+    pub fn with_layout_hash(mut self, layout_hash: u64) -> Self {
+        self.layout_hash = layout_hash;
+        self
+    }
+
+    // Layout hash of the message type sent through the ring.
+    pub fn with_layout_of<T: StableLayout>(self) -> Self {
+        self.with_layout_hash(T::LAYOUT_HASH)
+    }
+}
+
+#[derive(Debug)]
+pub enum OpenError {
+    Io(io::Error),
+    BadMagic,
+    UnsupportedVersion(u32),
+    // Capacity doesn't match the size of the mapping.
+    BadCapacity(u64),
+    LayoutMismatch { ours: u64, theirs: u64 },
+}
+
+impl fmt::Display for OpenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OpenError::Io(e) => write!(f, "{}", e),
+            OpenError::BadMagic => f.write_str("not a shared memory ring"),
+            OpenError::UnsupportedVersion(v) => write!(f, "unsupported ring version {}", v),
+            OpenError::BadCapacity(c) => write!(f, "capacity {} doesn't match mapping", c),
+            OpenError::LayoutMismatch { ours, theirs } => write!(
+                f,
+                "message layout {:#x} doesn't match peer's {:#x}",
+                ours, theirs
+            ),
+        }
+    }
+}
+
+impl std::error::Error for OpenError {}
+
+impl From<io::Error> for OpenError {
+    fn from(e: io::Error) -> Self {
+        OpenError::Io(e)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecvError {
+    // The next frame doesn't fit in the buffer; it stays queued.
+    TooLarge(usize),
+    // The payload didn't match its checksum. The frame is skipped.
+    ChecksumMismatch,
+    // A frame header claims more data than is queued.
+    BadLength(usize),
+    // The indices are inconsistent with the capacity.
+    BadIndices,
+}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecvError::TooLarge(len) => write!(f, "frame of {} bytes too large", len),
+            RecvError::ChecksumMismatch => f.write_str("frame checksum mismatch"),
+            RecvError::BadLength(len) => write!(f, "corrupt frame length {}", len),
+            RecvError::BadIndices => f.write_str("corrupt ring indices"),
+        }
+    }
+}
+
+impl std::error::Error for RecvError {}
+
+struct Ring {
+    mapping: SharedMapping,
+    capacity: u64,
+    narrow: bool,
+}
+
+// Little-endian views of the atomics in the mapping.
+#[repr(transparent)]
+struct LeU32(AtomicU32);
+
+#[repr(transparent)]
+struct LeU64(AtomicU64);
+
+impl LeU32 {
+    fn load(&self, order: Ordering) -> u32 {
+        u32::from_le(self.0.load(order))
+    }
+
+    fn store(&self, value: u32, order: Ordering) {
+        self.0.store(value.to_le(), order)
+    }
+
+    fn fetch_and(&self, value: u32, order: Ordering) -> u32 {
+        u32::from_le(self.0.fetch_and(value.to_le(), order))
+    }
+}
+
+impl LeU64 {
+    fn load(&self, order: Ordering) -> u64 {
+        u64::from_le(self.0.load(order))
+    }
+
+    fn store(&self, value: u64, order: Ordering) {
+        self.0.store(value.to_le(), order)
+    }
+}
+
+impl Ring {
+    fn create<P: AsRef<Path>>(path: P, capacity: usize, caps: Capabilities) -> io::Result<Self> {
+        let narrow = caps.features.contains(Features::NARROW_INDICES);
+        if narrow {
+            assert!(
+                capacity.is_power_of_two() && capacity as u64 <= 1 << 31,
+                "Narrow ring capacity must be a power of two up to 2^31"
+            );
+        }
+
+        let mapping = SharedMapping::create(path, DATA_OFFSET + capacity)?;
+        let version = if narrow { NARROW_VERSION } else { VERSION };
+
+        unsafe {
+            let base = mapping.as_ptr();
+            ptr::copy_nonoverlapping(MAGIC.as_ptr(), base, 8);
+            write_bytes(base.add(8), &version.to_le_bytes());
+            write_bytes(base.add(CAPACITY_OFFSET), &(capacity as u64).to_le_bytes());
+            write_bytes(base.add(LAYOUT_OFFSET), &caps.layout_hash.to_le_bytes());
+        }
+
+        let ring = Ring {
+            mapping,
+            capacity: capacity as u64,
+            narrow,
+        };
+        ring.features()
+            .store(caps.features.bits(), Ordering::Release);
+        Ok(ring)
+    }
+
+    fn open<P: AsRef<Path>>(path: P, caps: Capabilities) -> Result<Self, OpenError> {
+        let mapping = SharedMapping::open(path)?;
+        if mapping.len() <= DATA_OFFSET {
+            return Err(OpenError::BadMagic);
+        }
+
+        let (magic, version, capacity, layout_hash) = unsafe {
+            let base = mapping.as_ptr();
+            let mut magic = [0; 8];
+            ptr::copy_nonoverlapping(base, magic.as_mut_ptr(), 8);
+            (
+                magic,
+                u32::from_le_bytes(read_bytes(base.add(8))),
+                u64::from_le_bytes(read_bytes(base.add(CAPACITY_OFFSET))),
+                u64::from_le_bytes(read_bytes(base.add(LAYOUT_OFFSET))),
+            )
+        };
+
+        if magic != MAGIC {
+            return Err(OpenError::BadMagic);
+        }
+        if version != VERSION && version != NARROW_VERSION {
+            return Err(OpenError::UnsupportedVersion(version));
+        }
+        if capacity == 0 || capacity != (mapping.len() - DATA_OFFSET) as u64 {
+            return Err(OpenError::BadCapacity(capacity));
+        }
+
+        let features =
+            unsafe { u32::from_le_bytes(read_bytes(mapping.as_ptr().add(FEATURES_OFFSET))) };
+        let narrow = Features(features).contains(Features::NARROW_INDICES);
+        if narrow != (version == NARROW_VERSION) {
+            return Err(OpenError::UnsupportedVersion(version));
+        }
+        if narrow && (!capacity.is_power_of_two() || capacity > 1 << 31) {
+            return Err(OpenError::BadCapacity(capacity));
+        }
+        if caps.layout_hash != 0 && layout_hash != 0 && caps.layout_hash != layout_hash {
+            return Err(OpenError::LayoutMismatch {
+                ours: caps.layout_hash,
+                theirs: layout_hash,
+            });
+        }
+
+        let ring = Ring {
+            mapping,
+            capacity,
+            narrow,
+        };
+        let keep = caps.features.union(Features::NARROW_INDICES);
+        ring.features().fetch_and(keep.bits(), Ordering::AcqRel);
+        Ok(ring)
+    }
+
+    fn features(&self) -> &LeU32 {
+        unsafe { &*(self.mapping.as_ptr().add(FEATURES_OFFSET) as *const LeU32) }
+    }
+
+    fn negotiated(&self) -> Features {
+        Features(self.features().load(Ordering::Acquire))
+    }
+
+    // Positions are kept as u64 either way; narrow indices store the low
+    // 32 bits, and `distance` compares them at that width.
+    fn load_index(&self, offset: usize, order: Ordering) -> u64 {
+        let index = unsafe { self.mapping.as_ptr().add(offset) };
+        if self.narrow {
+            unsafe { &*(index as *const LeU32) }.load(order) as u64
+        } else {
+            unsafe { &*(index as *const LeU64) }.load(order)
+        }
+    }
+
+    fn store_index(&self, offset: usize, value: u64, order: Ordering) {
+        let index = unsafe { self.mapping.as_ptr().add(offset) };
+        if self.narrow {
+            unsafe { &*(index as *const LeU32) }.store(value as u32, order)
+        } else {
+            unsafe { &*(index as *const LeU64) }.store(value, order)
+        }
+    }
+
+    fn distance(&self, from: u64, to: u64) -> u64 {
+        if self.narrow {
+            (to as u32).wrapping_sub(from as u32) as u64
+        } else {
+            to.wrapping_sub(from)
+        }
+    }
+
+    fn copy_in(&self, position: u64, data: &[u8]) {
+        let offset = (position % self.capacity) as usize;
+        let first = data.len().min(self.capacity as usize - offset);
+        unsafe {
+            let base = self.mapping.as_ptr().add(DATA_OFFSET);
+            ptr::copy_nonoverlapping(data.as_ptr(), base.add(offset), first);
+            ptr::copy_nonoverlapping(data.as_ptr().add(first), base, data.len() - first);
+        }
+    }
+
+    fn copy_out(&self, position: u64, out: &mut [u8]) {
+        let offset = (position % self.capacity) as usize;
+        let first = out.len().min(self.capacity as usize - offset);
+        unsafe {
+            let base = self.mapping.as_ptr().add(DATA_OFFSET);
+            ptr::copy_nonoverlapping(base.add(offset), out.as_mut_ptr(), first);
+            ptr::copy_nonoverlapping(base, out.as_mut_ptr().add(first), out.len() - first);
+        }
+    }
+
+    fn read_u32(&self, position: u64) -> u32 {
+        let mut word = [0; 4];
+        self.copy_out(position, &mut word);
+        u32::from_le_bytes(word)
+    }
+}
+
+unsafe fn write_bytes(dst: *mut u8, bytes: &[u8]) {
+    ptr::copy_nonoverlapping(bytes.as_ptr(), dst, bytes.len());
+}
+
+unsafe fn read_bytes<const N: usize>(src: *const u8) -> [u8; N] {
+    let mut bytes = [0; N];
+    ptr::copy_nonoverlapping(src, bytes.as_mut_ptr(), N);
+    bytes
+}
+
+pub struct ShmProducer {
+    ring: Ring,
+}
+
+pub struct ShmConsumer {
+    ring: Ring,
+}
+
+impl ShmProducer {
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        capacity: usize,
+        caps: Capabilities,
+    ) -> io::Result<Self> {
+        Ok(ShmProducer {
+            ring: Ring::create(path, capacity, caps)?,
+        })
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P, caps: Capabilities) -> Result<Self, OpenError> {
+        Ok(ShmProducer {
+            ring: Ring::open(path, caps)?,
+        })
+    }
+
+    // Returns false, sending nothing, if the frame doesn't fit right now.
+    pub fn send_frame(&mut self, payload: &[u8]) -> bool {
+        let ring = &self.ring;
+        assert!(payload.len() < CHECKSUM_BIT as usize, "Frame too large");
+
+        let write = ring.load_index(WRITE_OFFSET, Ordering::Relaxed);
+        let read = ring.load_index(READ_OFFSET, Ordering::Acquire);
+        let free = ring.capacity.saturating_sub(ring.distance(read, write));
+
+        let checksum = ring.negotiated().contains(Features::CHECKSUMS);
+        let header_len = if checksum { 8 } else { 4 };
+        if header_len + payload.len() as u64 > free {
+            return false;
+        }
+
+        let mut len = payload.len() as u32;
+        if checksum {
+            len |= CHECKSUM_BIT;
+            ring.copy_in(write + 4, &crc32(payload).to_le_bytes());
+        }
+        ring.copy_in(write, &len.to_le_bytes());
+        ring.copy_in(write + header_len, payload);
+
+        let write = write + header_len + payload.len() as u64;
+        ring.store_index(WRITE_OFFSET, write, Ordering::Release);
+        true
+    }
+
+    // The features both sides agreed on so far.
+    pub fn features(&self) -> Features {
+        self.ring.negotiated()
+    }
+}
+
+impl ShmConsumer {
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        capacity: usize,
+        caps: Capabilities,
+    ) -> io::Result<Self> {
+        Ok(ShmConsumer {
+            ring: Ring::create(path, capacity, caps)?,
+        })
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P, caps: Capabilities) -> Result<Self, OpenError> {
+        Ok(ShmConsumer {
+            ring: Ring::open(path, caps)?,
+        })
+    }
+
+    // Copies the next frame into `out` and returns its length, or `None` if
+    // nothing is queued.
+    pub fn recv_frame(&mut self, out: &mut [u8]) -> Result<Option<usize>, RecvError> {
+        let ring = &self.ring;
+        let read = ring.load_index(READ_OFFSET, Ordering::Relaxed);
+        let write = ring.load_index(WRITE_OFFSET, Ordering::Acquire);
+
+        let queued = ring.distance(read, write);
+        if queued > ring.capacity {
+            return Err(RecvError::BadIndices);
+        }
+        if queued == 0 {
+            return Ok(None);
+        }
+        if queued < 4 {
+            return Err(RecvError::BadIndices);
+        }
+
+        let word = ring.read_u32(read);
+        let checksum = word & CHECKSUM_BIT != 0;
+        let len = (word & !CHECKSUM_BIT) as usize;
+        let header_len = if checksum { 8 } else { 4 };
+
+        if header_len + len as u64 > queued {
+            return Err(RecvError::BadLength(len));
+        }
+        if len > out.len() {
+            return Err(RecvError::TooLarge(len));
+        }
+
+        ring.copy_out(read + header_len, &mut out[..len]);
+        let valid = !checksum || ring.read_u32(read + 4) == crc32(&out[..len]);
+
+        let read = read + header_len + len as u64;
+        ring.store_index(READ_OFFSET, read, Ordering::Release);
+
+        if valid {
+            Ok(Some(len))
+        } else {
+            Err(RecvError::ChecksumMismatch)
+        }
+    }
+
+    // Drops everything queued, to recover after `BadLength` or
+    // `BadIndices`.
+    pub fn resync(&mut self) {
+        let write = self.ring.load_index(WRITE_OFFSET, Ordering::Acquire);
+        self.ring.store_index(READ_OFFSET, write, Ordering::Release);
+    }
+
+    pub fn features(&self) -> Features {
+        self.ring.negotiated()
+    }
+}
+
+// CRC-32 (IEEE 802.3).
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0, |crc, &byte| {
+        CRC_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::shm::temp_path;
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn round_trip_across_wrap() {
+        let path = temp_path("shm_ring");
+        let caps = Capabilities::default();
+        let mut producer = ShmProducer::create(&path, 32, caps).unwrap();
+        let mut consumer = ShmConsumer::open(&path, caps).unwrap();
+        assert!(consumer.features().contains(Features::CHECKSUMS));
+
+        let mut out = [0; 32];
+        for i in 0..10u8 {
+            assert!(producer.send_frame(&[i; 10]));
+            assert_eq!(consumer.recv_frame(&mut out), Ok(Some(10)));
+            assert_eq!(out[..10], [i; 10]);
+        }
+        assert!(!producer.send_frame(&[0; 30]));
+        assert_eq!(consumer.recv_frame(&mut out), Ok(None));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn little_endian_layout() {
+        let path = temp_path("shm_ring_endian");
+        let caps = Capabilities::default()
+            .with_features(Features::NONE)
+            .with_layout_hash(0x0102_0304_0506_0708);
+        let mut producer = ShmProducer::create(&path, 64, caps).unwrap();
+        producer.send_frame(b"abc");
+
+        // What a peer of either byte order finds in the file.
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(bytes[8..12], VERSION.to_le_bytes());
+        assert_eq!(bytes[FEATURES_OFFSET..FEATURES_OFFSET + 4], [0; 4]);
+        assert_eq!(
+            bytes[CAPACITY_OFFSET..CAPACITY_OFFSET + 8],
+            64u64.to_le_bytes()
+        );
+        assert_eq!(
+            bytes[LAYOUT_OFFSET..LAYOUT_OFFSET + 8],
+            [8, 7, 6, 5, 4, 3, 2, 1]
+        );
+        assert_eq!(bytes[WRITE_OFFSET..WRITE_OFFSET + 8], 7u64.to_le_bytes());
+        assert_eq!(bytes[READ_OFFSET..READ_OFFSET + 8], [0; 8]);
+        assert_eq!(bytes[DATA_OFFSET..DATA_OFFSET + 7], *b"\x03\0\0\0abc");
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn narrow_indices() {
+        let path = temp_path("shm_ring_narrow");
+        let host =
+            Capabilities::default().with_features(Features::all().union(Features::NARROW_INDICES));
+        let mut producer = ShmProducer::create(&path, 32, host).unwrap();
+
+        // The layout bit survives a peer that doesn't ask for it.
+        let mut consumer = ShmConsumer::open(&path, Capabilities::default()).unwrap();
+        assert_eq!(consumer.features(), host.features);
+
+        // Start just before the indices wrap.
+        let peer = SharedMapping::open(&path).unwrap();
+        let start = (u32::MAX - 5).to_le_bytes();
+        for &offset in &[WRITE_OFFSET, READ_OFFSET] {
+            unsafe { write_bytes(peer.as_ptr().add(offset), &start) };
+        }
+
+        let mut out = [0; 32];
+        for i in 0..4u8 {
+            assert!(producer.send_frame(&[i; 10]));
+            assert_eq!(consumer.recv_frame(&mut out), Ok(Some(10)));
+            assert_eq!(out[..10], [i; 10]);
+        }
+
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(bytes[8..12], NARROW_VERSION.to_le_bytes());
+        assert_eq!(
+            bytes[WRITE_OFFSET..WRITE_OFFSET + 8],
+            [66, 0, 0, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(bytes[READ_OFFSET..READ_OFFSET + 4], 66u32.to_le_bytes());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn detects_corruption() {
+        let path = temp_path("shm_ring_corrupt");
+        let caps = Capabilities::default();
+        let mut producer = ShmProducer::create(&path, 64, caps).unwrap();
+        let mut consumer = ShmConsumer::open(&path, caps).unwrap();
+        let peer = SharedMapping::open(&path).unwrap();
+        let mut out = [0; 64];
+
+        producer.send_frame(b"gain 0.5");
+        unsafe { *peer.as_ptr().add(DATA_OFFSET + 8) ^= 0xff };
+        assert_eq!(
+            consumer.recv_frame(&mut out),
+            Err(RecvError::ChecksumMismatch)
+        );
+
+        producer.send_frame(b"gain 0.5");
+        unsafe { *peer.as_ptr().add(DATA_OFFSET + 16) = 0xff };
+        assert_eq!(
+            consumer.recv_frame(&mut out),
+            Err(RecvError::BadLength(0xff))
+        );
+        consumer.resync();
+        assert_eq!(consumer.recv_frame(&mut out), Ok(None));
+
+        unsafe { *peer.as_ptr() = b'X' };
+        assert!(matches!(
+            ShmConsumer::open(&path, caps),
+            Err(OpenError::BadMagic)
+        ));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn verifies_layout_on_attach() {
+        #[allow(dead_code)]
+        struct Old {
+            gain: f32,
+        }
+        #[allow(dead_code)]
+        struct New {
+            gain: f32,
+            pan: f32,
+        }
+        crate::stable_layout!(Old { gain: f32 });
+        crate::stable_layout!(New {
+            gain: f32,
+            pan: f32
+        });
+
+        let path = temp_path("shm_ring_layout");
+        let host = Capabilities::default().with_layout_of::<New>();
+        let _producer = ShmProducer::create(&path, 64, host).unwrap();
+
+        let plugin = Capabilities::default().with_layout_of::<Old>();
+        assert!(matches!(
+            ShmConsumer::open(&path, plugin),
+            Err(OpenError::LayoutMismatch { .. })
+        ));
+        assert!(ShmConsumer::open(&path, host).is_ok());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn negotiates_features() {
+        let path = temp_path("shm_ring_negotiate");
+        let host = Capabilities::default().with_layout_hash(0xabcd);
+        let mut producer = ShmProducer::create(&path, 64, host).unwrap();
+        let mut out = [0; 64];
+
+        // Queued before the older plugin attaches, still checksummed.
+        producer.send_frame(b"a");
+
+        let plugin = Capabilities::default().with_features(Features::NONE);
+        let mut consumer = ShmConsumer::open(&path, plugin).unwrap();
+        assert_eq!(producer.features(), Features::NONE);
+
+        producer.send_frame(b"b");
+        assert_eq!(consumer.recv_frame(&mut out), Ok(Some(1)));
+        assert_eq!(consumer.recv_frame(&mut out), Ok(Some(1)));
+        assert_eq!(&out[..1], b"b");
+
+        let other = Capabilities::default().with_layout_hash(0x1234);
+        assert!(matches!(
+            ShmConsumer::open(&path, other),
+            Err(OpenError::LayoutMismatch {
+                ours: 0x1234,
+                theirs: 0xabcd
+            })
+        ));
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,325 @@
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+// Deterministic simulation for integration tests of code built on this
+// crate. The engine's threads become tasks on a single thread, and `Sim`
+// decides which one runs next: in turn, in an order drawn from a seed, or
+// one picked explicitly with `step_task`. Since every task runs on the same
+// thread, each interleaving of channel and buffer operations is exactly
+// reproducible.
+//
+// Tasks must stick to the `try_*` operations; a blocking wait inside a
+// step could never be satisfied and panics instead. Time is virtual while a
+// `Sim` is alive: it only moves with `advance`, and primitives that stamp
+// their updates (the triple buffer, and `failsafe` on top of it) read it
+// when they are created on the simulation's thread.
+pub struct Sim<'a> {
+    tasks: Vec<Option<Box<dyn FnMut() -> Step + 'a>>>,
+    // Tasks that reported `Idle` since the last `Progress`.
+    idle: Vec<bool>,
+    schedule: Schedule,
+    rng: u64,
+    next: usize,
+    steps: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    // Did some work.
+    Progress,
+    // Nothing to do until another task runs.
+    Idle,
+    // Finished; the task is removed.
+    Done,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Schedule {
+    RoundRobin,
+    // Picks tasks pseudo-randomly; the same seed gives the same order.
+    Seeded(u64),
+}
+
+thread_local! {
+    static CLOCK: Cell<Option<Duration>> = const { Cell::new(None) };
+    static STEPPING: Cell<bool> = const { Cell::new(false) };
+}
+
+impl<'a> Sim<'a> {
+    // Starts the virtual clock at zero on this thread. Only one `Sim` can
+    // be alive per thread.
+    pub fn new(schedule: Schedule) -> Self {
+        CLOCK.with(|clock| {
+            assert!(
+                clock.get().is_none(),
+                "A simulation is already running on this thread"
+            );
+            clock.set(Some(Duration::ZERO));
+        });
+
+        let rng = match schedule {
+            // xorshift gets stuck at zero.
+            Schedule::Seeded(seed) => seed | 1,
+            Schedule::RoundRobin => 0,
+        };
+        Sim {
+            tasks: Vec::new(),
+            idle: Vec::new(),
+            schedule,
+            rng,
+            next: 0,
+            steps: 0,
+        }
+    }
+
+    pub fn spawn(&mut self, task: impl FnMut() -> Step + 'a) -> usize {
+        self.tasks.push(Some(Box::new(task)));
+        self.idle.push(false);
+        self.tasks.len() - 1
+    }
+
+    // Runs one step of the next task in the schedule. Returns the task and
+    // what it did, or `None` once all tasks are done.
+    pub fn step(&mut self) -> Option<(usize, Step)> {
+        let live = self.live_tasks();
+        if live == 0 {
+            return None;
+        }
+
+        let nth = match self.schedule {
+            Schedule::RoundRobin => {
+                let nth = self.next % live;
+                self.next = nth + 1;
+                nth
+            }
+            Schedule::Seeded(_) => (self.next_random() % live as u64) as usize,
+        };
+        let id = (0..self.tasks.len())
+            .filter(|&id| self.tasks[id].is_some())
+            .nth(nth)
+            .unwrap();
+
+        self.step_task(id).map(|step| (id, step))
+    }
+
+    // Runs one step of a specific task, for hand-written interleavings.
+    // `None` if the task doesn't exist or is done.
+    pub fn step_task(&mut self, id: usize) -> Option<Step> {
+        let task = self.tasks.get_mut(id)?.as_mut()?;
+
+        STEPPING.with(|stepping| stepping.set(true));
+        let step = task();
+        STEPPING.with(|stepping| stepping.set(false));
+        self.steps += 1;
+
+        match step {
+            Step::Progress => self.idle.iter_mut().for_each(|idle| *idle = false),
+            Step::Idle => self.idle[id] = true,
+            Step::Done => {
+                self.tasks[id] = None;
+                self.idle.iter_mut().for_each(|idle| *idle = false);
+            }
+        }
+        Some(step)
+    }
+
+    // Steps until every remaining task is idle or done, or `max_steps`
+    // have run. Returns the number of steps taken.
+    pub fn run_until_idle(&mut self, max_steps: u64) -> u64 {
+        let start = self.steps;
+        while self.steps - start < max_steps && !self.is_idle() {
+            self.step();
+        }
+        self.steps - start
+    }
+
+    // True when all remaining tasks reported `Idle` since anything last
+    // made progress.
+    pub fn is_idle(&self) -> bool {
+        self.tasks
+            .iter()
+            .zip(&self.idle)
+            .all(|(task, idle)| task.is_none() || *idle)
+    }
+
+    pub fn advance(&mut self, by: Duration) {
+        CLOCK.with(|clock| clock.set(clock.get().map(|now| now + by)));
+    }
+
+    // Virtual time since the simulation started.
+    pub fn now(&self) -> Duration {
+        CLOCK.with(|clock| clock.get()).unwrap_or_default()
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn live_tasks(&self) -> usize {
+        self.tasks.iter().filter(|task| task.is_some()).count()
+    }
+
+    fn next_random(&mut self) -> u64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        self.rng
+    }
+}
+
+impl Drop for Sim<'_> {
+    fn drop(&mut self) {
+        // Tasks may own primitives that read the clock when dropped.
+        self.tasks.clear();
+        CLOCK.with(|clock| clock.set(None));
+        STEPPING.with(|stepping| stepping.set(false));
+    }
+}
+
+// Stand-in for `Instant` in primitives that timestamp updates: virtual when
+// taken on a thread running a `Sim`, real otherwise.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Epoch {
+    Real(Instant),
+    Virtual(Duration),
+}
+
+impl Epoch {
+    pub(crate) fn now() -> Self {
+        match CLOCK.with(|clock| clock.get()) {
+            Some(now) => Epoch::Virtual(now),
+            None => Epoch::Real(Instant::now()),
+        }
+    }
+
+    // A virtual epoch read off the simulation's thread, or after it ended,
+    // doesn't move.
+    pub(crate) fn elapsed(&self) -> Duration {
+        match self {
+            Epoch::Real(instant) => instant.elapsed(),
+            Epoch::Virtual(start) => CLOCK
+                .with(|clock| clock.get())
+                .map_or(Duration::ZERO, |now| now.saturating_sub(*start)),
+        }
+    }
+}
+
+pub(crate) fn assert_not_stepping(operation: &str) {
+    if STEPPING.with(|stepping| stepping.get()) {
+        panic!(
+            "{} would never return inside a simulated step; use the try_ variant",
+            operation
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::cell::RefCell;
+
+    use crate::spsc::{self, TryRecvError};
+    use crate::triple_buffer::triple_buffer;
+    use crate::wait::WaitStrategy;
+
+    // Producer and consumer over a small queue; returns the order events
+    // happened in.
+    fn run(schedule: Schedule) -> Vec<(char, u32)> {
+        let trace = RefCell::new(Vec::new());
+        let (mut send, mut recv) = spsc::channel(2);
+        let mut next = 0;
+        let mut sim = Sim::new(schedule);
+
+        sim.spawn(|| {
+            if next == 10 {
+                return Step::Done;
+            }
+            match send.try_send(next) {
+                Ok(()) => {
+                    trace.borrow_mut().push(('s', next));
+                    next += 1;
+                    Step::Progress
+                }
+                Err(_) => Step::Idle,
+            }
+        });
+        sim.spawn(|| match recv.try_recv() {
+            Ok(value) => {
+                trace.borrow_mut().push(('r', value));
+                Step::Progress
+            }
+            Err(TryRecvError::Empty) => Step::Idle,
+            Err(TryRecvError::Disconnected) => Step::Done,
+        });
+
+        sim.run_until_idle(1000);
+        assert!(sim.is_idle());
+        drop(sim);
+        trace.into_inner()
+    }
+
+    #[test]
+    fn reproducible() {
+        let a = run(Schedule::Seeded(7));
+        assert_eq!(a, run(Schedule::Seeded(7)));
+        assert_eq!(a.len(), 20);
+
+        let received: Vec<_> = a
+            .iter()
+            .filter(|(op, _)| *op == 'r')
+            .map(|(_, v)| *v)
+            .collect();
+        assert_eq!(received, (0..10).collect::<Vec<_>>());
+
+        let round_robin = run(Schedule::RoundRobin);
+        assert_eq!(&round_robin[..4], [('s', 0), ('r', 0), ('s', 1), ('r', 1)]);
+    }
+
+    #[test]
+    fn explicit_steps() {
+        let mut count = 0;
+        let mut sim = Sim::new(Schedule::RoundRobin);
+        let task = sim.spawn(|| {
+            count += 1;
+            if count == 2 {
+                Step::Done
+            } else {
+                Step::Progress
+            }
+        });
+        assert_eq!(sim.step_task(task), Some(Step::Progress));
+        assert_eq!(sim.step_task(task), Some(Step::Done));
+        assert_eq!(sim.step_task(task), None);
+        assert_eq!(sim.step(), None);
+        assert_eq!(sim.steps(), 2);
+    }
+
+    #[test]
+    fn virtual_clock() {
+        let mut sim = Sim::new(Schedule::RoundRobin);
+        let (mut writer, mut reader) = triple_buffer(0);
+
+        sim.advance(Duration::from_secs(60));
+        assert_eq!(sim.now(), Duration::from_secs(60));
+        assert!(reader.read_fresh(Duration::from_secs(59)).is_err());
+
+        writer.write(1);
+        sim.advance(Duration::from_millis(5));
+        assert_eq!(reader.read_fresh(Duration::from_millis(5)), Ok(&1));
+        sim.advance(Duration::from_millis(1));
+        assert!(reader.read_fresh(Duration::from_millis(5)).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "simulated step")]
+    fn blocking_inside_step() {
+        let (_send, mut recv) = spsc::channel::<u32>(2);
+        let mut sim = Sim::new(Schedule::RoundRobin);
+        sim.spawn(|| {
+            recv.recv_blocking(WaitStrategy::BusySpin);
+            Step::Done
+        });
+        sim.step();
+    }
+}
@@ -0,0 +1,210 @@
+use crate::role;
+use crate::spsc;
+
+// Publishes large states (a game world, a sample bank) from a producer to a
+// reader without cloning or allocating per update. A fixed set of buffers
+// cycles between the two sides: the producer checks out a free buffer,
+// fills it in place and publishes it; the reader keeps the newest one it
+// has seen, and every snapshot it moves past goes back to the producer's
+// free list. Memory stays at `buffers` states, and no buffer is freed on
+// the reader's side.
+//
+// A checked-out buffer still holds whatever snapshot it last carried, so
+// the producer either overwrites it fully or updates it incrementally.
+pub struct SnapshotPool<T> {
+    to_reader: spsc::Sender<Box<T>>,
+    returned: spsc::Receiver<Box<T>>,
+    free: Vec<Box<T>>,
+    checked_out: Option<Box<T>>,
+}
+
+pub struct SnapshotReader<T> {
+    from_pool: spsc::Receiver<Box<T>>,
+    to_pool: spsc::Sender<Box<T>>,
+    current: Option<Box<T>>,
+}
+
+impl<T> SnapshotPool<T> {
+    // A free buffer to fill, or `None` if every buffer is queued or held by
+    // the reader. Calling it again before `publish` gives the same buffer.
+    pub fn checkout(&mut self) -> Option<&mut T> {
+        if self.checked_out.is_none() {
+            self.collect();
+            self.checked_out = Some(self.free.pop()?);
+        }
+        self.checked_out.as_deref_mut()
+    }
+
+    // Publishes the checked-out buffer. Returns false if nothing was
+    // checked out.
+    pub fn publish(&mut self) -> bool {
+        let snapshot = match self.checked_out.take() {
+            Some(snapshot) => snapshot,
+            None => return false,
+        };
+
+        // The queue has room for every buffer, so this only fails once the
+        // reader is gone.
+        if let Err(e) = self.to_reader.try_send(snapshot) {
+            self.free.push(e.into_inner());
+        }
+        true
+    }
+
+    // Checks out, fills and publishes in one go. Returns false if no buffer
+    // was free.
+    pub fn write_with(&mut self, fill: impl FnOnce(&mut T)) -> bool {
+        match self.checkout() {
+            Some(snapshot) => fill(snapshot),
+            None => return false,
+        }
+        self.publish()
+    }
+
+    // Free buffers, after taking back the ones the reader is done with.
+    pub fn available(&mut self) -> usize {
+        self.collect();
+        self.free.len()
+    }
+
+    pub fn is_reader_active(&self) -> bool {
+        self.to_reader.is_receiver_active()
+    }
+
+    fn collect(&mut self) {
+        while let Ok(snapshot) = self.returned.try_recv() {
+            self.free.push(snapshot);
+        }
+    }
+}
+
+impl<T> SnapshotReader<T> {
+    // The newest published snapshot. Older ones, including the one held
+    // until now, go back to the pool.
+    pub fn latest(&mut self) -> &T {
+        while let Ok(next) = self.from_pool.try_recv() {
+            if let Some(old) = self.current.replace(next) {
+                // Never full; fails only once the pool is gone, and then
+                // the buffer is dropped here during teardown.
+                let _ = self.to_pool.try_send(old);
+            }
+        }
+        self.current()
+    }
+
+    // The snapshot held right now, without looking for a newer one.
+    pub fn current(&self) -> &T {
+        self.current.as_deref().unwrap()
+    }
+
+    pub fn is_pool_active(&self) -> bool {
+        self.from_pool.is_sender_active()
+    }
+}
+
+impl<T> Drop for SnapshotReader<T> {
+    fn drop(&mut self) {
+        if let Some(current) = self.current.take() {
+            let _ = self.to_pool.try_send(current);
+        }
+    }
+}
+
+// `buffers` states are made up front with `make`, one of them the reader's
+// initial snapshot; at least two are needed for the producer to make
+// progress, three to always have one free while one is queued.
+pub fn snapshot_pool<T>(
+    buffers: usize,
+    mut make: impl FnMut() -> T,
+) -> (SnapshotPool<T>, SnapshotReader<T>) {
+    role::assert_not_rt("snapshot_pool");
+    assert!(buffers >= 2, "Snapshot pool needs at least two buffers");
+
+    let (to_reader, from_pool) = spsc::channel(buffers - 1);
+    let (to_pool, returned) = spsc::channel(buffers);
+    let free = (1..buffers).map(|_| Box::new(make())).collect();
+
+    (
+        SnapshotPool {
+            to_reader,
+            returned,
+            free,
+            checked_out: None,
+        },
+        SnapshotReader {
+            from_pool,
+            to_pool,
+            current: Some(Box::new(make())),
+        },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn recycles_buffers() {
+        let (mut pool, mut reader) = snapshot_pool(3, || vec![0u32; 4]);
+        assert_eq!(reader.latest(), &[0; 4]);
+        assert_eq!(pool.available(), 2);
+
+        pool.checkout().unwrap()[0] = 1;
+        assert!(pool.publish());
+        assert!(pool.write_with(|s| s[0] = 2));
+        assert_eq!(pool.available(), 0);
+        assert!(!pool.write_with(|s| s[0] = 3));
+
+        assert_eq!(reader.latest()[0], 2);
+        assert_eq!(pool.available(), 2);
+
+        // Buffers come back with their old contents.
+        let snapshot = pool.checkout().unwrap();
+        assert!(snapshot[0] < 2);
+        snapshot[0] = 3;
+        assert!(pool.publish());
+        assert!(!pool.publish());
+        assert_eq!(reader.current()[0], 2);
+        assert_eq!(reader.latest()[0], 3);
+    }
+
+    #[test]
+    fn reader_hands_back_on_drop() {
+        let (mut pool, reader) = snapshot_pool(2, || 0u64);
+        assert!(pool.write_with(|s| *s = 1));
+        assert_eq!(pool.available(), 0);
+        drop(reader);
+        assert!(!pool.is_reader_active());
+        assert_eq!(pool.available(), 1);
+
+        // Publishing to a dropped reader keeps the buffer.
+        assert!(pool.write_with(|s| *s = 2));
+        assert_eq!(pool.available(), 1);
+    }
+
+    #[test]
+    fn concurrent() {
+        let (mut pool, mut reader) = snapshot_pool(3, || [0u64; 64]);
+
+        let producer = std::thread::spawn(move || {
+            let mut tick = 1;
+            while tick <= 10_000 {
+                if pool.write_with(|s| s.iter_mut().for_each(|v| *v = tick)) {
+                    tick += 1;
+                } else {
+                    std::thread::yield_now();
+                }
+            }
+        });
+
+        let mut last = 0;
+        while last < 10_000 {
+            let snapshot = reader.latest();
+            assert!(snapshot.iter().all(|v| *v == snapshot[0]));
+            assert!(snapshot[0] >= last);
+            last = snapshot[0];
+            std::thread::yield_now();
+        }
+        producer.join().unwrap();
+    }
+}
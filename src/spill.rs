@@ -0,0 +1,274 @@
+use std::collections::VecDeque;
+use std::io;
+use std::path::Path;
+use std::slice;
+
+use crate::shm::SharedMapping;
+use crate::spsc;
+use crate::text::FixedString;
+
+// Control-side overflow for non-critical channels (logging, telemetry).
+// `pump` drains the RT channel into an in-memory queue of bounded length;
+// once that is full, further items are encoded into a memory-mapped file
+// used as a byte ring, and `pop` replays them in order after the in-memory
+// ones. Items are only dropped once the spill file is full too, so a burst
+// costs disk space instead of RAM.
+//
+// The file is scratch space, not a persistent log: its contents are only
+// meaningful to the queue that wrote them.
+pub trait SpillItem: Sized {
+    fn encode(&self, out: &mut Vec<u8>);
+    fn decode(bytes: &[u8]) -> Option<Self>;
+}
+
+const RECORD_HEADER: usize = 4;
+const WRAP_MARKER: u32 = u32::MAX;
+
+pub struct SpillQueue<T> {
+    receiver: spsc::Receiver<T>,
+    memory: VecDeque<T>,
+    memory_limit: usize,
+    file: SharedMapping,
+    head: usize,
+    tail: usize,
+    used: usize,
+    spilled: usize,
+    dropped: u64,
+    scratch: Vec<u8>,
+}
+
+impl<T: SpillItem> SpillQueue<T> {
+    pub fn new<P: AsRef<Path>>(
+        receiver: spsc::Receiver<T>,
+        memory_limit: usize,
+        path: P,
+        file_len: usize,
+    ) -> io::Result<Self> {
+        Ok(SpillQueue {
+            receiver,
+            memory: VecDeque::with_capacity(memory_limit),
+            memory_limit,
+            file: SharedMapping::create(path, file_len)?,
+            head: 0,
+            tail: 0,
+            used: 0,
+            spilled: 0,
+            dropped: 0,
+            scratch: Vec::new(),
+        })
+    }
+
+    // Moves everything queued on the channel into the queue. Returns the
+    // number of items received.
+    pub fn pump(&mut self) -> usize {
+        let mut received = 0;
+        while let Ok(item) = self.receiver.try_recv() {
+            received += 1;
+
+            // Once anything is spilled, newer items follow it into the file
+            // to keep the order.
+            if self.spilled == 0 && self.memory.len() < self.memory_limit {
+                self.memory.push_back(item);
+            } else if !self.spill(&item) {
+                self.dropped += 1;
+            }
+        }
+        received
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if let Some(item) = self.memory.pop_front() {
+            return Some(item);
+        }
+
+        while self.spilled > 0 {
+            if let Some(item) = self.unspill() {
+                return Some(item);
+            }
+            self.dropped += 1;
+        }
+
+        None
+    }
+
+    pub fn len(&self) -> usize {
+        self.memory.len() + self.spilled
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn spilled(&self) -> usize {
+        self.spilled
+    }
+
+    // Items lost because the spill file was full or a record didn't decode.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    fn bytes(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.file.as_ptr(), self.file.len()) }
+    }
+
+    fn spill(&mut self, item: &T) -> bool {
+        let mut scratch = std::mem::take(&mut self.scratch);
+        scratch.clear();
+        item.encode(&mut scratch);
+
+        let stored = self.store(&scratch);
+        self.scratch = scratch;
+        stored
+    }
+
+    fn store(&mut self, payload: &[u8]) -> bool {
+        let capacity = self.file.len();
+        let need = RECORD_HEADER + payload.len();
+        let to_end = capacity - self.tail;
+        let waste = if to_end < need { to_end } else { 0 };
+
+        if payload.len() >= WRAP_MARKER as usize || self.used + waste + need > capacity {
+            return false;
+        }
+
+        if waste > 0 {
+            let tail = self.tail;
+            if waste >= RECORD_HEADER {
+                self.bytes()[tail..tail + RECORD_HEADER]
+                    .copy_from_slice(&WRAP_MARKER.to_le_bytes());
+            }
+            self.tail = 0;
+            self.used += waste;
+        }
+
+        let tail = self.tail;
+        let bytes = self.bytes();
+        bytes[tail..tail + RECORD_HEADER].copy_from_slice(&(payload.len() as u32).to_le_bytes());
+        bytes[tail + RECORD_HEADER..tail + need].copy_from_slice(payload);
+
+        self.tail = (tail + need) % capacity;
+        self.used += need;
+        self.spilled += 1;
+        true
+    }
+
+    fn unspill(&mut self) -> Option<T> {
+        let capacity = self.file.len();
+        let to_end = capacity - self.head;
+        if to_end < RECORD_HEADER || self.read_len(self.head) == WRAP_MARKER {
+            self.head = 0;
+            self.used -= to_end;
+        }
+
+        let head = self.head;
+        let len = self.read_len(head) as usize;
+        let item = T::decode(&self.bytes()[head + RECORD_HEADER..head + RECORD_HEADER + len]);
+
+        self.head = (head + RECORD_HEADER + len) % capacity;
+        self.used -= RECORD_HEADER + len;
+        self.spilled -= 1;
+        if self.spilled == 0 {
+            self.head = 0;
+            self.tail = 0;
+            self.used = 0;
+        }
+
+        item
+    }
+
+    fn read_len(&mut self, offset: usize) -> u32 {
+        let mut len = [0; RECORD_HEADER];
+        len.copy_from_slice(&self.bytes()[offset..offset + RECORD_HEADER]);
+        u32::from_le_bytes(len)
+    }
+}
+
+impl<const N: usize> SpillItem for FixedString<N> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.as_bytes());
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        let mut s = FixedString::new();
+        s.push_str(std::str::from_utf8(bytes).ok()?);
+        Some(s)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::fmt::Write;
+
+    use crate::shm::temp_path;
+
+    fn line(i: usize) -> FixedString<32> {
+        let mut s = FixedString::new();
+        write!(s, "telemetry {}", i).unwrap();
+        s
+    }
+
+    #[test]
+    fn spills_and_replays_in_order() {
+        let path = temp_path("spill");
+        let (mut sender, receiver) = spsc::channel(64);
+        let mut queue = SpillQueue::new(receiver, 4, &path, 4096).unwrap();
+
+        for i in 0..10 {
+            sender.try_send(line(i)).unwrap();
+        }
+        assert_eq!(queue.pump(), 10);
+        assert_eq!(queue.len(), 10);
+        assert_eq!(queue.spilled(), 6);
+
+        for i in 0..5 {
+            assert_eq!(queue.pop(), Some(line(i)));
+        }
+
+        // Still spilling: new items queue up behind the spilled ones.
+        sender.try_send(line(10)).unwrap();
+        queue.pump();
+        for i in 5..11 {
+            assert_eq!(queue.pop(), Some(line(i)));
+        }
+        assert_eq!(queue.pop(), None);
+        assert_eq!(queue.dropped(), 0);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn wraps_and_drops_when_full() {
+        let path = temp_path("spill-full");
+        let (mut sender, receiver) = spsc::channel(64);
+        // Room for three 16-byte records ("telemetry NN" plus header).
+        let mut queue = SpillQueue::new(receiver, 0, &path, 50).unwrap();
+
+        for i in 10..14 {
+            sender.try_send(line(i)).unwrap();
+        }
+        queue.pump();
+        assert_eq!(queue.spilled(), 3);
+        assert_eq!(queue.dropped(), 1);
+
+        assert_eq!(queue.pop(), Some(line(10)));
+        assert_eq!(queue.pop(), Some(line(11)));
+
+        // Doesn't fit before the end of the file, wraps to the start.
+        sender.try_send(line(14)).unwrap();
+        sender.try_send(line(15)).unwrap();
+        queue.pump();
+        assert_eq!(queue.spilled(), 3);
+
+        for i in 12..16 {
+            if i != 13 {
+                assert_eq!(queue.pop(), Some(line(i)));
+            }
+        }
+        assert!(queue.is_empty());
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
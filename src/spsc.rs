@@ -1,168 +1,1704 @@
-use std::mem;
-use std::ptr::{self, NonNull};
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::cell::Cell;
+use core::error;
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem::{self, MaybeUninit};
+use core::ptr::{self, NonNull};
+use core::slice;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+
+#[cfg(feature = "std")]
+use crate::pi_detect;
+use crate::poison::{PoisonFlag, Poisoned};
+use crate::role;
+#[cfg(feature = "std")]
+use crate::wait::WaitStrategy;
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
 
 const CACHELINE_SIZE: usize = 64;
 
-pub struct Sender<T> {
-    buffer: Arc<RingBuffer<T>>,
+// Each end belongs to one thread at a time: the operations take `&mut self`,
+// and the ends aren't `Sync`, so two threads can't share one through a
+// reference.
+type NotSync = PhantomData<Cell<()>>;
+
+pub struct Sender<T, C: Counter = usize> {
+    buffer: Arc<RingBuffer<T, C>>,
+    _not_sync: NotSync,
+}
+
+pub struct Receiver<T, C: Counter = usize> {
+    buffer: Arc<RingBuffer<T, C>>,
+    _not_sync: NotSync,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TrySendError<T> {
+    Full(T),
+    Disconnected(T),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TryRecvError {
+    Empty,
+    Disconnected,
 }
 
-pub struct Receiver<T> {
-    buffer: Arc<RingBuffer<T>>,
+impl<T> TrySendError<T> {
+    pub fn into_inner(self) -> T {
+        match self {
+            TrySendError::Full(value) | TrySendError::Disconnected(value) => value,
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        matches!(self, TrySendError::Full(_))
+    }
+
+    pub fn is_disconnected(&self) -> bool {
+        matches!(self, TrySendError::Disconnected(_))
+    }
 }
 
-impl<T> Sender<T> {
-    pub fn try_send(&self, value: T) -> Result<(), T> {
-        self.buffer.try_write(value)
+// Doesn't require `T: Debug`, so `unwrap` works for any payload.
+impl<T> fmt::Debug for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TrySendError::Full(_) => f.write_str("Full(..)"),
+            TrySendError::Disconnected(_) => f.write_str("Disconnected(..)"),
+        }
+    }
+}
+
+impl<T> fmt::Display for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TrySendError::Full(_) => f.write_str("sending on a full channel"),
+            TrySendError::Disconnected(_) => f.write_str("sending on a disconnected channel"),
+        }
+    }
+}
+
+impl<T> error::Error for TrySendError<T> {}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TryRecvError::Empty => f.write_str("receiving on an empty channel"),
+            TryRecvError::Disconnected => f.write_str("receiving on a disconnected channel"),
+        }
+    }
+}
+
+impl error::Error for TryRecvError {}
+
+impl<T, C: Counter> Sender<T, C> {
+    // Fails with `Disconnected` as soon as the receiver is gone or the
+    // channel has been closed, even if there's room left in the queue.
+    pub fn try_send(&mut self, value: T) -> Result<(), TrySendError<T>> {
+        let result = self.send_uncounted(value);
+        if let Err(TrySendError::Full(_)) = result {
+            self.buffer.stats.overrun();
+        }
+        result
+    }
+
+    // `try_send` without counting a full queue as an overrun, for the
+    // blocking sends, which expect to find it full.
+    fn send_uncounted(&mut self, value: T) -> Result<(), TrySendError<T>> {
+        if !self.is_receiver_active() || self.is_closed() {
+            return Err(TrySendError::Disconnected(value));
+        }
+        self.buffer.try_write(value).map_err(TrySendError::Full)
+    }
+
+    // For non-RT threads: waits for space using `strategy`. Gives the value
+    // back if the receiver has been dropped.
+    #[cfg(feature = "std")]
+    pub fn send_blocking(&mut self, mut value: T, strategy: WaitStrategy) -> Result<(), T> {
+        role::assert_not_rt("spsc::Sender::send_blocking");
+        pi_detect::blocking("spsc::send_blocking");
+
+        let mut waiter = strategy.waiter();
+        loop {
+            value = match self.send_uncounted(value) {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Full(value)) => value,
+                Err(TrySendError::Disconnected(value)) => return Err(value),
+            };
+
+            waiter.wait();
+        }
+    }
+
+    // Like `send_blocking`, but gives up after `timeout`, returning the value
+    // in `Full`. Only this side waits; the receiver is never involved.
+    #[cfg(feature = "std")]
+    pub fn send_timeout(
+        &mut self,
+        mut value: T,
+        timeout: Duration,
+        strategy: WaitStrategy,
+    ) -> Result<(), TrySendError<T>> {
+        role::assert_not_rt("spsc::Sender::send_timeout");
+        pi_detect::blocking("spsc::send_timeout");
+
+        let deadline = Instant::now() + timeout;
+        let mut waiter = strategy.waiter();
+        loop {
+            value = match self.send_uncounted(value) {
+                Err(TrySendError::Full(value)) => value,
+                result => return result,
+            };
+
+            if !waiter.wait_until(deadline) {
+                return Err(TrySendError::Full(value));
+            }
+        }
     }
 
-    pub fn clear(&self) {
-        self.buffer.clear();
+    // Copies as many values from `data` as fit. Returns the number sent.
+    pub fn write_slice(&mut self, data: &[T]) -> usize
+    where
+        T: Copy,
+    {
+        let buffer = &*self.buffer;
+        let len = data.len().min(buffer.writable(data.len()));
+        if len < data.len() {
+            buffer.stats.overrun();
+        }
+        let ((start, first), (_, second)) = buffer.regions(buffer.indices.write_index(), len);
+
+        let entries = buffer.entries.as_ptr();
+        unsafe {
+            ptr::copy_nonoverlapping(data.as_ptr(), entries.add(start), first);
+            ptr::copy_nonoverlapping(data.as_ptr().add(first), entries, second);
+        }
+        buffer.commit_write(len);
+        len
+    }
+
+    // Reserves `len` slots to be written in place, in up to two regions.
+    // Nothing is sent until the chunk is committed.
+    pub fn write_chunk_uninit(
+        &mut self,
+        len: usize,
+    ) -> Result<WriteChunkUninit<'_, T, C>, ChunkError> {
+        let buffer = &*self.buffer;
+        let available = buffer.writable(len);
+        if len > available {
+            buffer.stats.overrun();
+            return Err(ChunkError::TooFewSlots(available));
+        }
+
+        let start = buffer.indices.write_index();
+        let (first, second) = buffer.regions(start, len);
+        Ok(WriteChunkUninit {
+            buffer,
+            first,
+            second,
+            _marker: PhantomData,
+        })
+    }
+
+    // Asks the receiver to drop everything sent so far. The receiver does
+    // the dropping on its next operation, so this never touches slots the
+    // receiver may be reading; until then the values still count against
+    // the capacity. Values sent after this call are kept.
+    pub fn clear(&mut self) {
+        self.buffer.request_clear();
+    }
+
+    // True while a `clear` hasn't been carried out by the receiver yet.
+    pub fn is_clear_pending(&self) -> bool {
+        self.buffer.is_clear_pending()
     }
 
     pub fn size(&self) -> usize {
         self.buffer.available_write()
     }
 
+    // Ends the stream without dropping the sender. The receiver still gets
+    // everything sent before, then `Disconnected`; sending afterwards
+    // fails. Dropping the sender closes the channel too.
+    pub fn close(&mut self) {
+        self.buffer.closed.store(true, Ordering::Release);
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.buffer.closed.load(Ordering::Relaxed)
+    }
+
     pub fn is_receiver_active(&self) -> bool {
-        Arc::strong_count(&self.buffer) == 2
+        !self.buffer.receiver_gone.load(Ordering::Acquire)
+    }
+
+    // Highest number of queued values seen by `try_send` since creation or
+    // the last reset.
+    pub fn high_water_mark(&self) -> usize {
+        self.buffer.indices.high_water()
+    }
+
+    pub fn reset_high_water_mark(&mut self) {
+        self.buffer.indices.reset_high_water();
+    }
+
+    #[cfg(feature = "channel-stats")]
+    pub fn stats(&self) -> ChannelStats {
+        self.buffer.channel_stats()
+    }
+
+    pub fn probe(&self) -> ChannelProbe
+    where
+        T: 'static,
+    {
+        ChannelProbe::new(&self.buffer)
+    }
+
+    pub fn poison_flag(&self) -> PoisonFlag {
+        self.buffer.poison.clone()
+    }
+
+    pub fn check_poisoned(&self) -> Result<(), Poisoned> {
+        self.buffer.poison.check()
+    }
+
+    // Checks the ring's counters for consistency, for tests and debugging
+    // aids. Safe to call while the receiver is in use.
+    pub fn validate(&self) -> Result<(), InvariantError> {
+        self.buffer.validate()
+    }
+}
+
+impl<T, C: Counter> Drop for Sender<T, C> {
+    fn drop(&mut self) {
+        self.close();
     }
 }
 
-impl<T> Receiver<T> {
-    pub fn try_recv(&self) -> Option<T> {
-        self.buffer.try_read()
+impl<T, C: Counter> Drop for Receiver<T, C> {
+    fn drop(&mut self) {
+        self.buffer.receiver_gone.store(true, Ordering::Release);
+    }
+}
+
+impl<T, C: Counter> Receiver<T, C> {
+    // Values sent before the sender was dropped are still received; only
+    // an empty queue with no sender is `Disconnected`.
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let result = self.recv_uncounted();
+        if let Err(TryRecvError::Empty) = result {
+            self.buffer.stats.underrun();
+        }
+        result
+    }
+
+    // `try_recv` without counting an empty queue as an underrun, for the
+    // blocking receives and `try_iter`, which run until it's empty.
+    fn recv_uncounted(&mut self) -> Result<T, TryRecvError> {
+        let sender_active = self.is_sender_active();
+        match self.buffer.try_read() {
+            Some(value) => Ok(value),
+            None if sender_active => Err(TryRecvError::Empty),
+            None => Err(TryRecvError::Disconnected),
+        }
+    }
+
+    // For non-RT threads: waits for a value using `strategy`. Returns `None`
+    // once the sender has been dropped and the queue is drained.
+    #[cfg(feature = "std")]
+    pub fn recv_blocking(&mut self, strategy: WaitStrategy) -> Option<T> {
+        role::assert_not_rt("spsc::Receiver::recv_blocking");
+        pi_detect::blocking("spsc::recv_blocking");
+
+        let mut waiter = strategy.waiter();
+        loop {
+            match self.recv_uncounted() {
+                Ok(value) => return Some(value),
+                Err(TryRecvError::Disconnected) => return None,
+                Err(TryRecvError::Empty) => {}
+            }
+
+            waiter.wait();
+        }
+    }
+
+    // Like `recv_blocking`, but gives up after `timeout` with `Empty`.
+    #[cfg(feature = "std")]
+    pub fn recv_timeout(
+        &mut self,
+        timeout: Duration,
+        strategy: WaitStrategy,
+    ) -> Result<T, TryRecvError> {
+        role::assert_not_rt("spsc::Receiver::recv_timeout");
+        pi_detect::blocking("spsc::recv_timeout");
+
+        let deadline = Instant::now() + timeout;
+        let mut waiter = strategy.waiter();
+        loop {
+            match self.recv_uncounted() {
+                Err(TryRecvError::Empty) => {}
+                result => return result,
+            }
+
+            if !waiter.wait_until(deadline) {
+                return Err(TryRecvError::Empty);
+            }
+        }
     }
 
     pub fn size(&self) -> usize {
         self.buffer.available_read()
     }
 
-    pub fn is_sender_active(&self) -> bool {
-        Arc::strong_count(&self.buffer) == 2
+    // Drops all queued values, returning how many there were. Also carries
+    // out a pending `Sender::clear`.
+    pub fn clear(&mut self) -> usize {
+        self.buffer.clear()
+    }
+
+    // False once the sender has closed the channel or been dropped. Values
+    // sent before are still queued until `try_recv` reports `Disconnected`,
+    // so empty-and-open is `Empty` and empty-and-closed is `Disconnected`.
+    pub fn is_sender_active(&self) -> bool {
+        !self.buffer.closed.load(Ordering::Acquire)
+    }
+
+    pub fn high_water_mark(&self) -> usize {
+        self.buffer.indices.high_water()
+    }
+
+    #[cfg(feature = "channel-stats")]
+    pub fn stats(&self) -> ChannelStats {
+        self.buffer.channel_stats()
+    }
+
+    pub fn probe(&self) -> ChannelProbe
+    where
+        T: 'static,
+    {
+        ChannelProbe::new(&self.buffer)
+    }
+
+    // Copies as many queued values into `out` as fit. Returns the number
+    // received.
+    pub fn read_slice(&mut self, out: &mut [T]) -> usize
+    where
+        T: Copy,
+    {
+        let buffer = &*self.buffer;
+        let len = out.len().min(buffer.readable(out.len()));
+        if len < out.len() {
+            buffer.stats.underrun();
+        }
+        let ((start, first), (_, second)) = buffer.regions(buffer.indices.read_index(), len);
+
+        let entries = buffer.entries.as_ptr();
+        unsafe {
+            ptr::copy_nonoverlapping(entries.add(start), out.as_mut_ptr(), first);
+            ptr::copy_nonoverlapping(entries, out.as_mut_ptr().add(first), second);
+        }
+        // Nothing to drop for `Copy` values.
+        buffer.commit_read(len);
+        len
+    }
+
+    // Borrows the oldest `len` values in place, in up to two regions. They
+    // are consumed when the chunk is dropped, unless `commit` says
+    // otherwise.
+    pub fn read_chunk(&mut self, len: usize) -> Result<ReadChunk<'_, T, C>, ChunkError> {
+        let buffer = &*self.buffer;
+        let available = buffer.readable(len);
+        if len > available {
+            buffer.stats.underrun();
+            return Err(ChunkError::TooFewSlots(available));
+        }
+
+        let start = buffer.indices.read_index();
+        let (first, second) = buffer.regions(start, len);
+        Ok(ReadChunk {
+            buffer,
+            first,
+            second,
+            _marker: PhantomData,
+        })
+    }
+
+    // Receives until the queue is empty. Values sent while iterating are
+    // picked up too.
+    pub fn try_iter(&mut self) -> TryIter<'_, T, C> {
+        TryIter { receiver: self }
+    }
+
+    // The oldest queued value, without consuming it. Takes `&mut self` so
+    // that the value can't be received while borrowed.
+    pub fn peek(&mut self) -> Option<&T> {
+        self.buffer.peek().map(|value| unsafe { &*value })
+    }
+
+    pub fn peek_mut(&mut self) -> Option<&mut T> {
+        self.buffer.peek().map(|value| unsafe { &mut *value })
+    }
+
+    // Visits the queued values, oldest first, without consuming them.
+    pub fn peek_each(&mut self, mut f: impl FnMut(&T)) {
+        self.buffer.peek_each(&mut f);
+    }
+
+    // Capacity and the read and write counters of the underlying ring.
+    pub fn ring_state(&self) -> (usize, usize, usize) {
+        (
+            self.buffer.capacity,
+            self.buffer.indices.read_index(),
+            self.buffer.indices.refresh_write(),
+        )
+    }
+
+    pub fn poison_flag(&self) -> PoisonFlag {
+        self.buffer.poison.clone()
+    }
+
+    pub fn check_poisoned(&self) -> Result<(), Poisoned> {
+        self.buffer.poison.check()
+    }
+
+    pub fn validate(&self) -> Result<(), InvariantError> {
+        self.buffer.validate()
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn crash_view(&self) -> CrashView<T, C> {
+        CrashView {
+            buffer: self.buffer.clone(),
+        }
+    }
+}
+
+// Read-only view of a channel's queue for crash dumps, see `crash`. It
+// keeps the ring allocated, so a dump never upgrades or drops a reference
+// inside the signal handler; liveness goes by the ends' flags, so it doesn't
+// affect `is_sender_active`/`is_receiver_active` either. A ring outliving
+// both ends this way is freed with the view.
+#[cfg(feature = "std")]
+pub(crate) struct CrashView<T, C: Counter = usize> {
+    buffer: Arc<RingBuffer<T, C>>,
+}
+
+#[cfg(feature = "std")]
+impl<T, C: Counter> CrashView<T, C> {
+    // The counters as they are now; `None` once both ends are gone.
+    pub(crate) fn ring(&self) -> Option<CrashRing<'_, T, C>> {
+        let buffer = &*self.buffer;
+        if buffer.closed.load(Ordering::Acquire) && buffer.receiver_gone.load(Ordering::Acquire) {
+            return None;
+        }
+
+        let read_index = C::load(&buffer.indices.reader.read_index, Ordering::Acquire);
+        let write_index = C::load(&buffer.indices.write_index, Ordering::Acquire);
+        Some(CrashRing {
+            capacity: buffer.capacity,
+            read_index,
+            write_index,
+            buffer,
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+pub(crate) struct CrashRing<'a, T, C: Counter = usize> {
+    buffer: &'a RingBuffer<T, C>,
+    pub(crate) capacity: usize,
+    pub(crate) read_index: usize,
+    pub(crate) write_index: usize,
+}
+
+#[cfg(feature = "std")]
+impl<T: Copy, C: Counter> CrashRing<'_, T, C> {
+    // Passes a copy of each value that was queued when the counters were
+    // loaded, oldest first. Only loads, so it can run in a signal handler
+    // while the ends are in use, but a value the receiver consumes and the
+    // sender overwrites meanwhile comes out torn.
+    pub(crate) fn each(&self, mut f: impl FnMut(T)) {
+        let queued = Indices::<C>::distance(self.read_index, self.write_index).min(self.capacity);
+        for i in 0..queued {
+            let slot = self.buffer.slot(self.read_index.wrapping_add(i));
+            f(unsafe { ptr::read_volatile(slot) });
+        }
+    }
+}
+
+pub struct TryIter<'a, T, C: Counter = usize> {
+    receiver: &'a mut Receiver<T, C>,
+}
+
+impl<T, C: Counter> Iterator for TryIter<'_, T, C> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv_uncounted().ok()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChunkError {
+    // Only this many slots were available.
+    TooFewSlots(usize),
+}
+
+impl fmt::Display for ChunkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChunkError::TooFewSlots(n) => write!(f, "only {} slots available", n),
+        }
+    }
+}
+
+impl error::Error for ChunkError {}
+
+// A broken ring invariant, see `Sender::validate`. Means memory corruption
+// or a second thread driving one end, never a full or empty queue.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InvariantError {
+    // More values queued than fit, or the read counter passed the write
+    // counter.
+    Overfilled { queued: usize, capacity: usize },
+    CachedReadAhead,
+    CachedWriteAhead,
+    HighWaterAboveCapacity { high_water: usize, capacity: usize },
+}
+
+impl fmt::Display for InvariantError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InvariantError::Overfilled { queued, capacity } => {
+                write!(f, "{} values queued in a ring of {}", queued, capacity)
+            }
+            InvariantError::CachedReadAhead => {
+                f.write_str("writer's cached read counter is ahead of the read counter")
+            }
+            InvariantError::CachedWriteAhead => {
+                f.write_str("reader's cached write counter is ahead of the write counter")
+            }
+            InvariantError::HighWaterAboveCapacity {
+                high_water,
+                capacity,
+            } => write!(
+                f,
+                "high water mark {} above the capacity {}",
+                high_water, capacity
+            ),
+        }
+    }
+}
+
+impl error::Error for InvariantError {}
+
+// Region pairs are (start index, length) into the entries; the second
+// region starts at index 0 and is empty unless the chunk wraps.
+type Regions = ((usize, usize), (usize, usize));
+
+pub struct WriteChunkUninit<'a, T, C: Counter = usize> {
+    buffer: &'a RingBuffer<T, C>,
+    first: (usize, usize),
+    second: (usize, usize),
+    _marker: PhantomData<&'a mut [T]>,
+}
+
+impl<T, C: Counter> WriteChunkUninit<'_, T, C> {
+    pub fn as_mut_slices(&mut self) -> (&mut [MaybeUninit<T>], &mut [MaybeUninit<T>]) {
+        let entries = self.buffer.entries.as_ptr() as *mut MaybeUninit<T>;
+        unsafe {
+            (
+                slice::from_raw_parts_mut(entries.add(self.first.0), self.first.1),
+                slice::from_raw_parts_mut(entries, self.second.1),
+            )
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.first.1 + self.second.1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Sends the first `count` slots of the chunk.
+    ///
+    /// # Safety
+    ///
+    /// Those slots must have been initialized through `as_mut_slices`.
+    pub unsafe fn commit(self, count: usize) {
+        assert!(count <= self.len(), "Commit past the end of the chunk");
+        self.buffer.commit_write(count);
+    }
+}
+
+pub struct ReadChunk<'a, T, C: Counter = usize> {
+    buffer: &'a RingBuffer<T, C>,
+    first: (usize, usize),
+    second: (usize, usize),
+    _marker: PhantomData<&'a [T]>,
+}
+
+impl<T, C: Counter> ReadChunk<'_, T, C> {
+    pub fn as_slices(&self) -> (&[T], &[T]) {
+        let entries = self.buffer.entries.as_ptr();
+        unsafe {
+            (
+                slice::from_raw_parts(entries.add(self.first.0), self.first.1),
+                slice::from_raw_parts(entries, self.second.1),
+            )
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.first.1 + self.second.1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Consumes only the first `count` values, leaving the rest queued.
+    pub fn commit(self, count: usize) {
+        assert!(count <= self.len(), "Commit past the end of the chunk");
+        let buffer = self.buffer;
+        mem::forget(self);
+        buffer.commit_read(count);
+    }
+}
+
+impl<T, C: Counter> Drop for ReadChunk<'_, T, C> {
+    fn drop(&mut self) {
+        self.buffer.commit_read(self.len());
+    }
+}
+
+// `std::io` over a byte channel, for encoders and decoders that speak the
+// `io` traits. These never wait: a full or empty queue is `WouldBlock`. A
+// closed or dropped receiver is `BrokenPipe`, and reading returns 0 once
+// the sender is gone and everything has been read.
+#[cfg(feature = "std")]
+impl<C: Counter> io::Write for Sender<u8, C> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if !self.is_receiver_active() || self.is_closed() {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+
+        match self.write_slice(buf) {
+            0 => Err(io::ErrorKind::WouldBlock.into()),
+            written => Ok(written),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<C: Counter> io::Read for Receiver<u8, C> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        // Checked before reading so that bytes sent right before the sender
+        // was dropped aren't missed.
+        let sender_active = self.is_sender_active();
+        match self.read_slice(buf) {
+            0 if sender_active => Err(io::ErrorKind::WouldBlock.into()),
+            read => Ok(read),
+        }
+    }
+}
+
+// Blocking counterparts for non-RT threads, from `Sender::blocking_writer`
+// and `Receiver::blocking_reader`. They wait with the given strategy until
+// at least one byte went through instead of returning `WouldBlock`.
+#[cfg(feature = "std")]
+pub struct BlockingWriter<'a, C: Counter = usize> {
+    sender: &'a mut Sender<u8, C>,
+    strategy: WaitStrategy,
+}
+
+#[cfg(feature = "std")]
+pub struct BlockingReader<'a, C: Counter = usize> {
+    receiver: &'a mut Receiver<u8, C>,
+    strategy: WaitStrategy,
+}
+
+#[cfg(feature = "std")]
+impl<C: Counter> Sender<u8, C> {
+    pub fn blocking_writer(&mut self, strategy: WaitStrategy) -> BlockingWriter<'_, C> {
+        BlockingWriter {
+            sender: self,
+            strategy,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<C: Counter> Receiver<u8, C> {
+    pub fn blocking_reader(&mut self, strategy: WaitStrategy) -> BlockingReader<'_, C> {
+        BlockingReader {
+            receiver: self,
+            strategy,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<C: Counter> io::Write for BlockingWriter<'_, C> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        role::assert_not_rt("spsc::BlockingWriter::write");
+        pi_detect::blocking("spsc::BlockingWriter::write");
+
+        let mut waiter = self.strategy.waiter();
+        loop {
+            match io::Write::write(self.sender, buf) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                result => return result,
+            }
+
+            waiter.wait();
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<C: Counter> io::Read for BlockingReader<'_, C> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        role::assert_not_rt("spsc::BlockingReader::read");
+        pi_detect::blocking("spsc::BlockingReader::read");
+
+        let mut waiter = self.strategy.waiter();
+        loop {
+            match io::Read::read(self.receiver, buf) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                result => return result,
+            }
+
+            waiter.wait();
+        }
+    }
+}
+
+pub fn channel<T>(size: usize) -> (Sender<T>, Receiver<T>) {
+    channel_with_counter(size)
+}
+
+// `channel` with counters of the given width, e.g. `u32` on targets without
+// 64-bit atomics; see `Counter`.
+pub fn channel_with_counter<T, C: Counter>(size: usize) -> (Sender<T, C>, Receiver<T, C>) {
+    role::assert_not_rt("spsc::channel");
+    let buffer = Arc::new(RingBuffer::new(size));
+    let sender = Sender {
+        buffer: buffer.clone(),
+        _not_sync: PhantomData,
+    };
+    let receiver = Receiver {
+        buffer,
+        _not_sync: PhantomData,
+    };
+
+    (sender, receiver)
+}
+
+// Channel where a full queue drops its oldest value to make room instead
+// of rejecting the new one, for meters and telemetry where only recent
+// values matter. The sender evicts by advancing the read index with a CAS,
+// and the receiver claims each value with a CAS as well, discarding its copy
+// if the sender got there first. Since the sender moves the read index, the
+// receiver can't lend out references into the ring, so it only has the
+// by-value operations.
+pub struct OverwriteSender<T, C: Counter = usize> {
+    buffer: Arc<RingBuffer<T, C>>,
+    _not_sync: NotSync,
+}
+
+pub struct OverwriteReceiver<T, C: Counter = usize> {
+    buffer: Arc<RingBuffer<T, C>>,
+    _not_sync: NotSync,
+}
+
+impl<T, C: Counter> OverwriteSender<T, C> {
+    // Always succeeds. Returns the value evicted to make room, if any.
+    pub fn send_overwrite(&mut self, value: T) -> Option<T> {
+        self.buffer.write_overwrite(value)
+    }
+
+    pub fn size(&self) -> usize {
+        self.buffer.available_write()
+    }
+
+    pub fn is_receiver_active(&self) -> bool {
+        !self.buffer.receiver_gone.load(Ordering::Acquire)
+    }
+}
+
+impl<T, C: Counter> Drop for OverwriteSender<T, C> {
+    fn drop(&mut self) {
+        self.buffer.closed.store(true, Ordering::Release);
+    }
+}
+
+impl<T, C: Counter> OverwriteReceiver<T, C> {
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let sender_active = self.is_sender_active();
+        match self.buffer.read_contended() {
+            Some(value) => Ok(value),
+            None if sender_active => Err(TryRecvError::Empty),
+            None => Err(TryRecvError::Disconnected),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.buffer.available_read()
+    }
+
+    pub fn is_sender_active(&self) -> bool {
+        !self.buffer.closed.load(Ordering::Acquire)
+    }
+}
+
+impl<T, C: Counter> Drop for OverwriteReceiver<T, C> {
+    fn drop(&mut self) {
+        self.buffer.receiver_gone.store(true, Ordering::Release);
+    }
+}
+
+pub fn overwrite_channel<T>(size: usize) -> (OverwriteSender<T>, OverwriteReceiver<T>) {
+    overwrite_channel_with_counter(size)
+}
+
+pub fn overwrite_channel_with_counter<T, C: Counter>(
+    size: usize,
+) -> (OverwriteSender<T, C>, OverwriteReceiver<T, C>) {
+    role::assert_not_rt("spsc::overwrite_channel");
+    let buffer = Arc::new(RingBuffer::new(size));
+    let sender = OverwriteSender {
+        buffer: buffer.clone(),
+        _not_sync: PhantomData,
+    };
+    let receiver = OverwriteReceiver {
+        buffer,
+        _not_sync: PhantomData,
+    };
+
+    (sender, receiver)
+}
+
+// Type-erased view of a channel's capacity and occupancy for monitoring. It
+// holds a weak reference, so it neither keeps the channel alive nor affects
+// `is_sender_active`/`is_receiver_active`.
+#[derive(Clone)]
+pub struct ChannelProbe {
+    buffer: Weak<dyn ChannelState + Send + Sync>,
+}
+
+trait ChannelState {
+    fn capacity(&self) -> usize;
+    fn len(&self) -> usize;
+    fn high_water(&self) -> usize;
+    #[cfg(feature = "channel-stats")]
+    fn stats(&self) -> ChannelStats;
+}
+
+impl<T, C: Counter> ChannelState for RingBuffer<T, C> {
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    // Probes run on other threads, so this leaves the cached counters alone.
+    fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    fn high_water(&self) -> usize {
+        self.indices.high_water()
+    }
+
+    #[cfg(feature = "channel-stats")]
+    fn stats(&self) -> ChannelStats {
+        self.channel_stats()
+    }
+}
+
+impl ChannelProbe {
+    fn new<T: 'static, C: Counter>(buffer: &Arc<RingBuffer<T, C>>) -> Self {
+        let buffer: Weak<dyn ChannelState + Send + Sync> = Arc::downgrade(buffer) as _;
+        ChannelProbe { buffer }
+    }
+
+    // `None` once both ends of the channel are gone.
+    pub fn capacity(&self) -> Option<usize> {
+        self.buffer.upgrade().map(|b| b.capacity())
+    }
+
+    pub fn queued(&self) -> Option<usize> {
+        self.buffer.upgrade().map(|b| b.len())
+    }
+
+    pub fn high_water_mark(&self) -> Option<usize> {
+        self.buffer.upgrade().map(|b| b.high_water())
+    }
+
+    #[cfg(feature = "channel-stats")]
+    pub fn stats(&self) -> Option<ChannelStats> {
+        self.buffer.upgrade().map(|b| b.stats())
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.buffer.strong_count() > 0
+    }
+}
+
+// Traffic through a channel since it was created, from `Sender::stats`,
+// `Receiver::stats` or a probe, with the `channel-stats` feature. Each
+// side counts its own operations with relaxed loads and stores on a cache
+// line of its own, so counting costs the hot path no atomic
+// read-modify-writes and no sharing; the other side's counts may lag
+// slightly behind.
+#[cfg(feature = "channel-stats")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ChannelStats {
+    pub sent: u64,
+    pub received: u64,
+    // `try_send`, `write_slice` and `write_chunk_uninit` calls that found
+    // less room than they needed.
+    pub overruns: u64,
+    // `try_recv`, `read_slice` and `read_chunk` calls that found fewer
+    // values than they asked for.
+    pub underruns: u64,
+    pub queued: usize,
+    pub high_water: usize,
+}
+
+#[cfg(all(feature = "channel-stats", target_has_atomic = "64"))]
+type AtomicStat = core::sync::atomic::AtomicU64;
+#[cfg(all(feature = "channel-stats", not(target_has_atomic = "64")))]
+type AtomicStat = AtomicUsize;
+
+#[cfg(feature = "channel-stats")]
+struct Stats {
+    sender: SideStats,
+    receiver: SideStats,
+}
+
+// Without the feature the counting compiles to nothing.
+#[cfg(not(feature = "channel-stats"))]
+struct Stats;
+
+#[cfg(feature = "channel-stats")]
+#[repr(align(64))]
+#[derive(Default)]
+struct SideStats {
+    done: AtomicStat,
+    missed: AtomicStat,
+}
+
+#[cfg(feature = "channel-stats")]
+impl SideStats {
+    // Only this side writes its counters, so no read-modify-write needed.
+    #[allow(clippy::unnecessary_cast)] // `AtomicStat` may be `AtomicUsize`
+    fn add(counter: &AtomicStat, count: usize) {
+        let value = counter.load(Ordering::Relaxed);
+        counter.store(value.wrapping_add(count as _), Ordering::Relaxed);
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    fn get(counter: &AtomicStat) -> u64 {
+        counter.load(Ordering::Relaxed) as u64
+    }
+}
+
+#[cfg(feature = "channel-stats")]
+impl Stats {
+    fn new() -> Self {
+        Stats {
+            sender: SideStats::default(),
+            receiver: SideStats::default(),
+        }
+    }
+
+    fn sent(&self, count: usize) {
+        SideStats::add(&self.sender.done, count);
+    }
+
+    fn received(&self, count: usize) {
+        SideStats::add(&self.receiver.done, count);
+    }
+
+    fn overrun(&self) {
+        SideStats::add(&self.sender.missed, 1);
+    }
+
+    fn underrun(&self) {
+        SideStats::add(&self.receiver.missed, 1);
+    }
+}
+
+#[cfg(not(feature = "channel-stats"))]
+impl Stats {
+    fn new() -> Self {
+        Stats
+    }
+
+    fn sent(&self, _count: usize) {}
+
+    fn received(&self, _count: usize) {}
+
+    fn overrun(&self) {}
+
+    fn underrun(&self) {}
+}
+
+const PADDING1_SIZE: usize = CACHELINE_SIZE
+    - mem::size_of::<usize>()
+    - mem::size_of::<usize>()
+    - mem::size_of::<usize>()
+    - mem::size_of::<PoisonFlag>()
+    - mem::size_of::<ClearRequest>()
+    - mem::size_of::<AtomicBool>()
+    - mem::size_of::<AtomicBool>();
+
+// The indices are free-running counters that wrap around `usize`; the slot
+// of a counter is `counter & mask`. The slot count is the capacity rounded
+// up to a power of two, so there's no modulo on the hot path, and the
+// difference of the counters is the fill level, so no slot is left unused
+// to tell full from empty.
+#[repr(C)]
+struct RingBuffer<T, C: Counter = usize> {
+    entries: NonNull<T>,            // size_of::<usize>()
+    mask: usize,                    // size_of::<usize>()
+    capacity: usize,                // size_of::<usize>()
+    poison: PoisonFlag,             // size_of::<usize>()
+    clear: ClearRequest,            // 3 * size_of::<usize>()
+    closed: AtomicBool,             // set by the sender, see `Sender::close`
+    receiver_gone: AtomicBool,      // set when the receiver is dropped
+    _padding1: [u8; PADDING1_SIZE], // pad up to next cache line
+    indices: Indices<C>,
+    stats: Stats,
+}
+
+// `Sender::clear` handshake. The sender records its write counter and bumps
+// `requested`; the reader notices `requested` moving past `acked` on its
+// next operation, drops everything before the recorded counter and acks.
+// Only the reader ever moves the read counter, so a clear can't race a
+// read in progress. Shares the header cache line, which is only written
+// when clearing.
+struct ClearRequest {
+    upto: AtomicUsize,
+    requested: AtomicUsize,
+    acked: AtomicUsize,
+}
+
+// Counters of a ring, one cache line per side. Shared with
+// `static_spsc::StaticSpscQueue`, which only differs in where the slots
+// live.
+//
+// Each side also keeps its last view of the other side's counter on its own
+// cache line, and only reloads the shared one when that view says the ring
+// is full (writer) or empty (reader). In steady state that keeps the
+// cache line of the other counter from bouncing on every operation.
+#[repr(C)]
+pub(crate) struct Indices<C: Counter = usize> {
+    write_index: C::Atomic,
+    high_water: C::Atomic,  // writer only
+    cached_read: C::Atomic, // writer only
+    reader: ReaderIndices<C>,
+}
+
+// Aligned to start the reader's cache line.
+#[repr(C, align(64))]
+struct ReaderIndices<C: Counter> {
+    read_index: C::Atomic,
+    cached_write: C::Atomic,
+}
+
+// Width of a ring's counters. `usize` by default; `u32` makes each counter
+// four bytes on 64-bit targets and only needs 32-bit atomics. Narrow
+// counters just wrap sooner: only their differences and low bits are used,
+// and those stay exact as long as the capacity is below half their range.
+// Picked with `channel_with_counter` or `StaticSpscQueue`'s `C` parameter.
+pub trait Counter: Copy + Send + Sync + 'static + private::Sealed {
+    #[doc(hidden)]
+    type Atomic: Send + Sync;
+    #[doc(hidden)]
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ZERO: Self::Atomic;
+    // Largest capacity the counters can track.
+    const MAX_CAPACITY: usize;
+
+    #[doc(hidden)]
+    fn load(atomic: &Self::Atomic, order: Ordering) -> usize;
+    #[doc(hidden)]
+    fn store(atomic: &Self::Atomic, value: usize, order: Ordering);
+    #[doc(hidden)]
+    fn swap(atomic: &Self::Atomic, value: usize, order: Ordering) -> usize;
+    #[doc(hidden)]
+    fn compare_exchange(
+        atomic: &Self::Atomic,
+        current: usize,
+        new: usize,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<usize, usize>;
+    // Reduces a wrapped `usize` result to the counter's range.
+    #[doc(hidden)]
+    fn wrap(value: usize) -> usize;
+}
+
+mod private {
+    pub trait Sealed {}
+
+    impl Sealed for usize {}
+    impl Sealed for u32 {}
+}
+
+impl Counter for usize {
+    type Atomic = AtomicUsize;
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ZERO: AtomicUsize = AtomicUsize::new(0);
+    const MAX_CAPACITY: usize = usize::MAX / 2;
+
+    fn load(atomic: &AtomicUsize, order: Ordering) -> usize {
+        atomic.load(order)
+    }
+
+    fn store(atomic: &AtomicUsize, value: usize, order: Ordering) {
+        atomic.store(value, order)
+    }
+
+    fn swap(atomic: &AtomicUsize, value: usize, order: Ordering) -> usize {
+        atomic.swap(value, order)
+    }
+
+    fn compare_exchange(
+        atomic: &AtomicUsize,
+        current: usize,
+        new: usize,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<usize, usize> {
+        atomic.compare_exchange(current, new, success, failure)
+    }
+
+    fn wrap(value: usize) -> usize {
+        value
+    }
+}
+
+impl Counter for u32 {
+    type Atomic = AtomicU32;
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ZERO: AtomicU32 = AtomicU32::new(0);
+    const MAX_CAPACITY: usize = (u32::MAX / 2) as usize;
+
+    fn load(atomic: &AtomicU32, order: Ordering) -> usize {
+        atomic.load(order) as usize
+    }
+
+    fn store(atomic: &AtomicU32, value: usize, order: Ordering) {
+        atomic.store(value as u32, order)
+    }
+
+    fn swap(atomic: &AtomicU32, value: usize, order: Ordering) -> usize {
+        atomic.swap(value as u32, order) as usize
+    }
+
+    fn compare_exchange(
+        atomic: &AtomicU32,
+        current: usize,
+        new: usize,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<usize, usize> {
+        atomic
+            .compare_exchange(current as u32, new as u32, success, failure)
+            .map(|value| value as usize)
+            .map_err(|value| value as usize)
+    }
+
+    fn wrap(value: usize) -> usize {
+        value as u32 as usize
+    }
+}
+
+unsafe impl<T, C: Counter> Sync for RingBuffer<T, C> {}
+unsafe impl<T, C: Counter> Send for RingBuffer<T, C> {}
+
+impl<C: Counter> Indices<C> {
+    pub(crate) const fn new() -> Self {
+        Indices {
+            write_index: C::ZERO,
+            high_water: C::ZERO,
+            cached_read: C::ZERO,
+            reader: ReaderIndices {
+                read_index: C::ZERO,
+                cached_write: C::ZERO,
+            },
+        }
+    }
+
+    // Writer side: the counter of the next slot to write.
+    pub(crate) fn write_index(&self) -> usize {
+        C::load(&self.write_index, Ordering::Relaxed)
+    }
+
+    // Reader side: the counter of the next slot to read.
+    pub(crate) fn read_index(&self) -> usize {
+        C::load(&self.reader.read_index, Ordering::Relaxed)
+    }
+
+    // The bytes holding each side's counters, for cache maintenance on
+    // memory that isn't coherent. Each side only ever writes its own.
+    pub(crate) fn writer_counters(&self) -> (*const u8, usize) {
+        let start = self as *const Self as *const u8;
+        let len = &self.reader as *const ReaderIndices<C> as usize - start as usize;
+        (start, len)
+    }
+
+    pub(crate) fn reader_counters(&self) -> (*const u8, usize) {
+        (
+            &self.reader as *const ReaderIndices<C> as *const u8,
+            mem::size_of::<ReaderIndices<C>>(),
+        )
+    }
+
+    fn refresh_read(&self) -> usize {
+        let read_index = C::load(&self.reader.read_index, Ordering::Acquire);
+        C::store(&self.cached_read, read_index, Ordering::Relaxed);
+        read_index
+    }
+
+    fn refresh_write(&self) -> usize {
+        let write_index = C::load(&self.write_index, Ordering::Acquire);
+        C::store(&self.reader.cached_write, write_index, Ordering::Relaxed);
+        write_index
+    }
+
+    // Values between two counters.
+    fn distance(from: usize, to: usize) -> usize {
+        C::wrap(to.wrapping_sub(from))
+    }
+
+    // Free slots, reloading the read counter only if the cached one shows
+    // fewer than `wanted`.
+    pub(crate) fn writable(&self, capacity: usize, wanted: usize) -> usize {
+        let write_index = self.write_index();
+        let cached_read = C::load(&self.cached_read, Ordering::Relaxed);
+        let free = capacity - Self::distance(cached_read, write_index);
+        if free >= wanted {
+            return free;
+        }
+        capacity - Self::distance(self.refresh_read(), write_index)
+    }
+
+    // Queued values, reloading the write counter only if the cached one
+    // shows fewer than `wanted`.
+    pub(crate) fn readable(&self, wanted: usize) -> usize {
+        let read_index = self.read_index();
+        let cached_write = C::load(&self.reader.cached_write, Ordering::Relaxed);
+        let queued = Self::distance(read_index, cached_write);
+        if queued >= wanted {
+            return queued;
+        }
+        Self::distance(read_index, self.refresh_write())
+    }
+
+    // Fresh views for size queries, which may be made while the cached
+    // counters are stale in ways `writable`/`readable` don't expect (see
+    // `RingBuffer::write_overwrite`).
+    pub(crate) fn available_write(&self, capacity: usize) -> usize {
+        capacity - Self::distance(self.refresh_read(), self.write_index())
+    }
+
+    pub(crate) fn available_read(&self) -> usize {
+        Self::distance(self.read_index(), self.refresh_write())
+    }
+
+    // For observers on other threads; leaves the cached counters alone.
+    fn len(&self) -> usize {
+        let read_index = C::load(&self.reader.read_index, Ordering::Acquire);
+        Self::distance(read_index, C::load(&self.write_index, Ordering::Acquire))
+    }
+
+    // Publishes `count` written slots. Debug builds check that nothing else
+    // moved the write counter in the meantime; only the producer may.
+    pub(crate) fn commit_write(&self, count: usize) {
+        let previous = self.write_index();
+        let write_index = C::wrap(previous.wrapping_add(count));
+        if cfg!(debug_assertions) {
+            let moved = C::swap(&self.write_index, write_index, Ordering::Release);
+            assert_eq!(moved, previous, "write counter moved by another thread");
+        } else {
+            C::store(&self.write_index, write_index, Ordering::Release);
+        }
+
+        self.update_high_water(write_index);
+    }
+
+    // Releases `count` read slots, which the reader must have seen queued.
+    pub(crate) fn commit_read(&self, count: usize) {
+        let previous = self.read_index();
+        debug_assert!(
+            count
+                <= Self::distance(
+                    previous,
+                    C::load(&self.reader.cached_write, Ordering::Relaxed)
+                ),
+            "released more values than were queued"
+        );
+        let read_index = C::wrap(previous.wrapping_add(count));
+        if cfg!(debug_assertions) {
+            let moved = C::swap(&self.reader.read_index, read_index, Ordering::Release);
+            assert_eq!(moved, previous, "read counter moved by another thread");
+        } else {
+            C::store(&self.reader.read_index, read_index, Ordering::Release);
+        }
+    }
+
+    // Checks the counters against each other:
+    // - at most `capacity` values are queued, which also rules out the read
+    //   counter being ahead of the write counter;
+    // - neither side's cached view of the other counter is ahead of it;
+    // - the high water mark is within the capacity.
+    //
+    // Each side only ever moves its own counter forward, so this holds at
+    // any point and can be called from either end while the other one runs.
+    pub(crate) fn validate(&self, capacity: usize) -> Result<(), InvariantError> {
+        // Caches are loaded before the counters they copy, which can only
+        // have moved forward since.
+        let cached_read = C::load(&self.cached_read, Ordering::Acquire);
+        let cached_write = C::load(&self.reader.cached_write, Ordering::Acquire);
+        let read_index = C::load(&self.reader.read_index, Ordering::Acquire);
+        let write_index = C::load(&self.write_index, Ordering::Acquire);
+
+        let queued = Self::distance(read_index, write_index);
+        if queued > capacity {
+            return Err(InvariantError::Overfilled { queued, capacity });
+        }
+        // The counters wrap, so "behind" means less than half the range back.
+        if Self::distance(cached_read, read_index) > C::MAX_CAPACITY {
+            return Err(InvariantError::CachedReadAhead);
+        }
+        if Self::distance(cached_write, write_index) > C::MAX_CAPACITY {
+            return Err(InvariantError::CachedWriteAhead);
+        }
+        let high_water = self.high_water();
+        if high_water > capacity {
+            return Err(InvariantError::HighWaterAboveCapacity {
+                high_water,
+                capacity,
+            });
+        }
+        Ok(())
+    }
+
+    // Takes the write counter after a write. The cached read counter may be
+    // behind and overstate the fill level, so the shared one is only loaded
+    // when the mark would go up.
+    fn update_high_water(&self, write_index: usize) {
+        let high_water = self.high_water();
+        let cached_read = C::load(&self.cached_read, Ordering::Relaxed);
+        if Self::distance(cached_read, write_index) <= high_water {
+            return;
+        }
+
+        let queued = Self::distance(self.refresh_read(), write_index);
+        if queued > high_water {
+            C::store(&self.high_water, queued, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn high_water(&self) -> usize {
+        C::load(&self.high_water, Ordering::Relaxed)
+    }
+
+    pub(crate) fn reset_high_water(&self) {
+        C::store(&self.high_water, 0, Ordering::Relaxed);
+    }
+}
+
+impl<T, C: Counter> RingBuffer<T, C> {
+    fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "Can not create channel with zero size");
+        assert!(
+            capacity <= C::MAX_CAPACITY,
+            "Channel size too large for the counters"
+        );
+
+        let slots = capacity.next_power_of_two();
+        let mut entries_vec = Vec::with_capacity(slots);
+        let entries = entries_vec.as_mut_ptr();
+
+        mem::forget(entries_vec);
+
+        RingBuffer {
+            entries: NonNull::new(entries).unwrap(),
+            mask: slots - 1,
+            capacity,
+            poison: PoisonFlag::new(),
+            clear: ClearRequest {
+                upto: AtomicUsize::new(0),
+                requested: AtomicUsize::new(0),
+                acked: AtomicUsize::new(0),
+            },
+            closed: AtomicBool::new(false),
+            receiver_gone: AtomicBool::new(false),
+            _padding1: [0; PADDING1_SIZE],
+            indices: Indices::new(),
+            stats: Stats::new(),
+        }
+    }
+
+    fn slot(&self, index: usize) -> *mut T {
+        unsafe { self.entries.as_ptr().add(index & self.mask) }
+    }
+
+    fn request_clear(&self) {
+        let clear = &self.clear;
+        clear
+            .upto
+            .store(self.indices.write_index(), Ordering::Release);
+        clear.requested.fetch_add(1, Ordering::Release);
+    }
+
+    fn is_clear_pending(&self) -> bool {
+        self.clear.requested.load(Ordering::Acquire) != self.clear.acked.load(Ordering::Acquire)
+    }
+
+    // Reader side: carries out a pending `Sender::clear`, if any.
+    fn take_clear(&self) {
+        let clear = &self.clear;
+        let acked = clear.acked.load(Ordering::Relaxed);
+        if clear.requested.load(Ordering::Relaxed) == acked {
+            return;
+        }
+
+        // A newer request may have replaced `upto` since `requested` was
+        // loaded; that one is acked on the next call. Loading `upto` with
+        // Acquire makes the writes before whichever request stored it
+        // visible, and the count is clamped to what is queued anyway, so
+        // only written slots are ever dropped.
+        let requested = clear.requested.load(Ordering::Acquire);
+        let upto = clear.upto.load(Ordering::Acquire);
+        let read_index = self.indices.read_index();
+        let count = Indices::<C>::distance(read_index, upto);
+        // Skip if the reader already got past `upto` on its own.
+        if count <= self.capacity {
+            let queued = Indices::<C>::distance(read_index, self.indices.refresh_write());
+            self.discard(count.min(queued));
+        }
+        clear.acked.store(requested, Ordering::Release);
+    }
+
+    fn clear(&self) -> usize {
+        self.take_clear();
+        let count = self.indices.available_read();
+        self.discard(count);
+        count
+    }
+
+    fn try_write(&self, value: T) -> Result<(), T> {
+        if self.indices.writable(self.capacity, 1) == 0 {
+            return Err(value);
+        }
+
+        unsafe { ptr::write(self.slot(self.indices.write_index()), value) };
+        self.indices.commit_write(1);
+        self.stats.sent(1);
+        debug_assert_eq!(self.validate(), Ok(()));
+
+        Ok(())
     }
-}
-
-pub fn channel<T>(size: usize) -> (Sender<T>, Receiver<T>) {
-    let buffer = Arc::new(RingBuffer::new(size));
-    let sender = Sender {
-        buffer: buffer.clone(),
-    };
-    let receiver = Receiver { buffer };
 
-    (sender, receiver)
-}
+    fn write_overwrite(&self, value: T) -> Option<T> {
+        let indices = &self.indices;
+        let write_index = indices.write_index();
 
-const PADDING1_SIZE: usize = CACHELINE_SIZE - mem::size_of::<usize>() - mem::size_of::<usize>();
-const PADDING2_SIZE: usize = CACHELINE_SIZE - mem::size_of::<usize>();
+        let mut evicted = None;
+        loop {
+            let read_index = C::load(&indices.reader.read_index, Ordering::Acquire);
+            if Indices::<C>::distance(read_index, write_index) < self.capacity {
+                break;
+            }
 
-#[repr(C)]
-struct RingBuffer<T> {
-    entries: NonNull<T>,                // size_of::<usize>()
-    size: usize,                        // size_of::<usize>()
-    _padding1: [u8; PADDING1_SIZE],     // pad up to next cache line
-    pub(self) write_index: AtomicUsize, // size_of::<usize>()
-    _padding2: [u8; PADDING2_SIZE],     // pad up to next cache line
-    pub(self) read_index: AtomicUsize,
-}
+            // Full: claim the oldest value, unless the reader just took it.
+            let next = C::wrap(read_index.wrapping_add(1));
+            if C::compare_exchange(
+                &indices.reader.read_index,
+                read_index,
+                next,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .is_ok()
+            {
+                evicted = Some(unsafe { ptr::read(self.slot(read_index)) });
+                break;
+            }
+        }
 
-unsafe impl<T> Sync for RingBuffer<T> {}
-unsafe impl<T> Send for RingBuffer<T> {}
+        unsafe { ptr::write(self.slot(write_index), value) };
+        indices.commit_write(1);
+        self.stats.sent(1);
 
-impl<T> RingBuffer<T> {
-    fn new(size: usize) -> Self {
-        assert!(size > 0, "Can not create channel with zero size");
+        evicted
+    }
 
-        let mut entries_vec = Vec::with_capacity(size + 1);
-        let entries = entries_vec.as_mut_ptr();
+    // Reader side of the overwrite protocol. The copy is taken before the
+    // CAS and thrown away if the writer evicted the slot in the meantime,
+    // in which case it may be torn and must not be used or dropped.
+    fn read_contended(&self) -> Option<T> {
+        let indices = &self.indices;
+        loop {
+            let read_index = C::load(&indices.reader.read_index, Ordering::Acquire);
+            let write_index = C::load(&indices.write_index, Ordering::Acquire);
+            if read_index == write_index {
+                return None;
+            }
 
-        mem::forget(entries_vec);
+            let slot = self.slot(read_index) as *const MaybeUninit<T>;
+            let value = unsafe { ptr::read_volatile(slot) };
 
-        RingBuffer {
-            entries: NonNull::new(entries).unwrap(),
-            size: size + 1,
-            _padding1: [0; PADDING1_SIZE],
-            read_index: AtomicUsize::new(0),
-            _padding2: [0; PADDING2_SIZE],
-            write_index: AtomicUsize::new(0),
+            let next = C::wrap(read_index.wrapping_add(1));
+            if C::compare_exchange(
+                &indices.reader.read_index,
+                read_index,
+                next,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .is_ok()
+            {
+                self.stats.received(1);
+                return Some(unsafe { value.assume_init() });
+            }
         }
     }
 
-    fn clear(&self) {
-        self.write_index.store(0, Ordering::SeqCst);
-        self.read_index.store(0, Ordering::SeqCst);
+    fn try_read(&self) -> Option<T> {
+        self.take_clear();
+        if self.indices.readable(1) == 0 {
+            return None;
+        }
+
+        let value = unsafe { ptr::read(self.slot(self.indices.read_index())) };
+        self.indices.commit_read(1);
+        self.stats.received(1);
+
+        Some(value)
     }
 
-    fn try_write(&self, value: T) -> Result<(), T> {
-        let write_index = self.write_index.load(Ordering::Relaxed);
-        let read_index = self.read_index.load(Ordering::Acquire);
+    fn regions(&self, start: usize, len: usize) -> Regions {
+        let start = start & self.mask;
+        let first = len.min(self.mask + 1 - start);
+        ((start, first), (0, len - first))
+    }
 
-        if available_write(write_index, read_index, self.size) == 0 {
-            return Err(value);
+    fn writable(&self, wanted: usize) -> usize {
+        if self.closed.load(Ordering::Relaxed) {
+            return 0;
         }
+        self.indices.writable(self.capacity, wanted)
+    }
 
-        unsafe { ptr::write(self.entries.as_ptr().add(write_index), value) };
+    fn readable(&self, wanted: usize) -> usize {
+        self.take_clear();
+        self.indices.readable(wanted)
+    }
 
-        self.write_index
-            .store((write_index + 1) % self.size, Ordering::Release);
+    fn commit_write(&self, count: usize) {
+        self.indices.commit_write(count);
+        self.stats.sent(count);
+        debug_assert_eq!(self.validate(), Ok(()));
+    }
 
-        Ok(())
+    fn commit_read(&self, count: usize) {
+        self.discard(count);
+        self.stats.received(count);
     }
 
-    fn try_read(&self) -> Option<T> {
-        let write_index = self.write_index.load(Ordering::Acquire);
-        let read_index = self.read_index.load(Ordering::Relaxed);
+    // Drops the `count` oldest values in place and releases their slots.
+    fn discard(&self, count: usize) {
+        let read_index = self.indices.read_index();
+        for i in 0..count {
+            unsafe { ptr::drop_in_place(self.slot(read_index.wrapping_add(i))) };
+        }
+        self.indices.commit_read(count);
+    }
 
-        if available_read(write_index, read_index, self.size) == 0 {
+    fn peek(&self) -> Option<*mut T> {
+        self.take_clear();
+        if self.indices.readable(1) == 0 {
             return None;
         }
+        Some(self.slot(self.indices.read_index()))
+    }
 
-        let value = unsafe { ptr::read(self.entries.as_ptr().add(read_index)) };
-
-        self.read_index
-            .store((read_index + 1) % self.size, Ordering::Release);
+    fn peek_each(&self, f: &mut impl FnMut(&T)) {
+        self.take_clear();
+        let write_index = self.indices.refresh_write();
+        let mut index = self.indices.read_index();
 
-        Some(value)
+        while index != write_index {
+            f(unsafe { &*self.slot(index) });
+            index = C::wrap(index.wrapping_add(1));
+        }
     }
 
     fn available_write(&self) -> usize {
-        let write_index = self.write_index.load(Ordering::Relaxed);
-        let read_index = self.read_index.load(Ordering::Acquire);
-
-        available_write(write_index, read_index, self.size)
+        self.indices.available_write(self.capacity)
     }
 
     fn available_read(&self) -> usize {
-        let write_index = self.write_index.load(Ordering::Acquire);
-        let read_index = self.read_index.load(Ordering::Relaxed);
-
-        available_read(write_index, read_index, self.size)
+        self.take_clear();
+        self.indices.available_read()
     }
-}
-
-impl<T> Drop for RingBuffer<T> {
-    fn drop(&mut self) {
-        while self.try_read().is_some() {}
 
-        let _entries_vec = unsafe { Vec::from_raw_parts(self.entries.as_ptr(), 0, self.size + 1) };
+    fn validate(&self) -> Result<(), InvariantError> {
+        self.indices.validate(self.capacity)
     }
-}
 
-fn available_read(write_index: usize, read_index: usize, size: usize) -> usize {
-    if write_index >= read_index {
-        write_index - read_index
-    } else {
-        write_index + size - read_index
+    // Safe from any thread, like the probe.
+    #[cfg(feature = "channel-stats")]
+    fn channel_stats(&self) -> ChannelStats {
+        let stats = &self.stats;
+        ChannelStats {
+            sent: SideStats::get(&stats.sender.done),
+            received: SideStats::get(&stats.receiver.done),
+            overruns: SideStats::get(&stats.sender.missed),
+            underruns: SideStats::get(&stats.receiver.missed),
+            queued: self.indices.len(),
+            high_water: self.indices.high_water(),
+        }
     }
 }
 
-fn available_write(write_index: usize, read_index: usize, size: usize) -> usize {
-    if write_index >= read_index {
-        read_index + size - write_index - 1
-    } else {
-        read_index - write_index - 1
+impl<T, C: Counter> Drop for RingBuffer<T, C> {
+    fn drop(&mut self) {
+        // The overwrite sender may have moved the read counter past the
+        // reader's cached write counter.
+        self.indices.refresh_write();
+        while self.try_read().is_some() {}
+
+        let _entries_vec = unsafe { Vec::from_raw_parts(self.entries.as_ptr(), 0, self.mask + 1) };
     }
 }
 
@@ -174,8 +1710,9 @@ mod test {
 
     #[test]
     fn verify_no_false_sharing() {
-        let write_index_offset = offset_of!(RingBuffer<u8>, write_index);
-        let read_index_offset = offset_of!(RingBuffer<u8>, read_index);
+        let indices_offset = offset_of!(RingBuffer<u8>, indices);
+        let write_index_offset = indices_offset + offset_of!(Indices<usize>, write_index);
+        let read_index_offset = indices_offset + offset_of!(Indices<usize>, reader);
 
         assert!(
             write_index_offset == CACHELINE_SIZE,
@@ -191,58 +1728,58 @@ mod test {
 
     #[test]
     fn new() {
-        let (_send, recv) = channel::<i32>(4);
-        assert_eq!(recv.try_recv(), None);
+        let (_send, mut recv) = channel::<i32>(4);
+        assert_eq!(recv.try_recv(), Err(TryRecvError::Empty));
     }
 
     #[test]
     fn single() {
-        let (send, recv) = channel(4);
+        let (mut send, mut recv) = channel(4);
         assert!(send.try_send(4).is_ok());
-        assert_eq!(recv.try_recv(), Some(4));
+        assert_eq!(recv.try_recv(), Ok(4));
     }
 
     #[test]
     fn multiple() {
-        let (send, recv) = channel(4);
+        let (mut send, mut recv) = channel(4);
         assert!(send.try_send(4).is_ok());
         assert!(send.try_send(5).is_ok());
-        assert_eq!(recv.try_recv(), Some(4));
-        assert_eq!(recv.try_recv(), Some(5));
+        assert_eq!(recv.try_recv(), Ok(4));
+        assert_eq!(recv.try_recv(), Ok(5));
     }
 
     #[test]
     fn interleaved() {
-        let (send, recv) = channel(4);
+        let (mut send, mut recv) = channel(4);
         assert!(send.try_send(4).is_ok());
-        assert_eq!(recv.try_recv(), Some(4));
+        assert_eq!(recv.try_recv(), Ok(4));
         assert!(send.try_send(5).is_ok());
-        assert_eq!(recv.try_recv(), Some(5));
+        assert_eq!(recv.try_recv(), Ok(5));
     }
 
     #[test]
     fn drain() {
-        let (send, recv) = channel(4);
+        let (mut send, mut recv) = channel(4);
         assert!(send.try_send(4).is_ok());
         assert!(send.try_send(5).is_ok());
-        assert_eq!(recv.try_recv(), Some(4));
-        assert_eq!(recv.try_recv(), Some(5));
-        assert_eq!(recv.try_recv(), None);
+        assert_eq!(recv.try_recv(), Ok(4));
+        assert_eq!(recv.try_recv(), Ok(5));
+        assert_eq!(recv.try_recv(), Err(TryRecvError::Empty));
     }
 
     #[test]
     fn full() {
-        let (send, recv) = channel(4);
+        let (mut send, mut recv) = channel(4);
         assert!(send.try_send(4).is_ok());
         assert!(send.try_send(5).is_ok());
         assert!(send.try_send(6).is_ok());
         assert!(send.try_send(7).is_ok());
-        assert_eq!(send.try_send(8), Err(8));
-        assert_eq!(recv.try_recv(), Some(4));
-        assert_eq!(recv.try_recv(), Some(5));
-        assert_eq!(recv.try_recv(), Some(6));
-        assert_eq!(recv.try_recv(), Some(7));
-        assert_eq!(recv.try_recv(), None);
+        assert_eq!(send.try_send(8), Err(TrySendError::Full(8)));
+        assert_eq!(recv.try_recv(), Ok(4));
+        assert_eq!(recv.try_recv(), Ok(5));
+        assert_eq!(recv.try_recv(), Ok(6));
+        assert_eq!(recv.try_recv(), Ok(7));
+        assert_eq!(recv.try_recv(), Err(TryRecvError::Empty));
     }
 
     #[test]
@@ -261,14 +1798,14 @@ mod test {
         let drop_count = Rc::new(Cell::new(0));
 
         {
-            let (send, recv) = channel(4);
+            let (mut send, mut recv) = channel(4);
             assert!(send.try_send(WithDrop(drop_count.clone())).is_ok());
             assert!(send.try_send(WithDrop(drop_count.clone())).is_ok());
             assert!(send.try_send(WithDrop(drop_count.clone())).is_ok());
 
             {
                 let v = recv.try_recv();
-                assert!(v.is_some());
+                assert!(v.is_ok());
             }
 
             assert_eq!(drop_count.get(), 1);
@@ -277,6 +1814,49 @@ mod test {
         assert_eq!(drop_count.get(), 3);
     }
 
+    #[test]
+    fn peek_each() {
+        let (mut send, mut recv) = channel(3);
+        for i in 0..3 {
+            send.try_send(i).unwrap();
+        }
+        recv.try_recv().unwrap();
+        send.try_send(3).unwrap();
+
+        let mut seen = Vec::new();
+        recv.peek_each(|&v| seen.push(v));
+        assert_eq!(seen, [1, 2, 3]);
+        assert_eq!(recv.size(), 3);
+        assert_eq!(recv.ring_state(), (3, 1, 4));
+    }
+
+    #[test]
+    fn high_water_mark() {
+        let (mut send, mut recv) = channel(4);
+        let probe = recv.probe();
+        assert_eq!(probe.capacity(), Some(4));
+
+        send.try_send(1).unwrap();
+        send.try_send(2).unwrap();
+        recv.try_recv().unwrap();
+        send.try_send(3).unwrap();
+        assert_eq!(send.high_water_mark(), 2);
+        assert_eq!(probe.queued(), Some(2));
+
+        send.try_send(4).unwrap();
+        send.try_send(5).unwrap();
+        assert_eq!(send.try_send(6), Err(TrySendError::Full(6)));
+        assert_eq!(recv.high_water_mark(), 4);
+
+        send.reset_high_water_mark();
+        assert_eq!(probe.high_water_mark(), Some(0));
+
+        assert!(send.is_receiver_active());
+        drop((send, recv));
+        assert!(!probe.is_alive());
+        assert_eq!(probe.capacity(), None);
+    }
+
     #[test]
     fn is_receiver_active() {
         let (send, recv) = channel::<i8>(4);
@@ -285,6 +1865,16 @@ mod test {
         assert!(!send.is_receiver_active());
     }
 
+    #[test]
+    fn poisoned() {
+        let (send, recv) = channel::<i8>(4);
+        assert_eq!(send.check_poisoned(), Ok(()));
+
+        recv.poison_flag().poison();
+        assert_eq!(send.check_poisoned(), Err(Poisoned));
+        assert_eq!(recv.check_poisoned(), Err(Poisoned));
+    }
+
     #[test]
     fn is_sender_active() {
         let (send, recv) = channel::<i8>(4);
@@ -292,4 +1882,603 @@ mod test {
         drop(send);
         assert!(!recv.is_sender_active());
     }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn blocking_round_trip() {
+        let (mut send, mut recv) = channel::<u32>(2);
+
+        let consumer = std::thread::spawn(move || {
+            let strategy = WaitStrategy::SpinThenYield { spins: 16 };
+            let mut sum = 0;
+            while let Some(value) = recv.recv_blocking(strategy) {
+                sum += value;
+            }
+            sum
+        });
+
+        for i in 0..100 {
+            send.send_blocking(i, WaitStrategy::default()).unwrap();
+        }
+        drop(send);
+
+        assert_eq!(consumer.join().unwrap(), (0..100).sum::<u32>());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn send_blocking_without_receiver() {
+        let (mut send, recv) = channel::<u32>(1);
+        send.try_send(0).unwrap();
+        drop(recv);
+        assert_eq!(send.send_blocking(1, WaitStrategy::BusySpin), Err(1));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn timeouts() {
+        let (mut send, mut recv) = channel::<u32>(1);
+        let strategy = WaitStrategy::default();
+        let timeout = Duration::from_millis(5);
+
+        let start = Instant::now();
+        assert_eq!(
+            recv.recv_timeout(timeout, strategy),
+            Err(TryRecvError::Empty)
+        );
+        assert!(start.elapsed() >= timeout);
+
+        send.try_send(1).unwrap();
+        assert_eq!(
+            send.send_timeout(2, timeout, strategy),
+            Err(TrySendError::Full(2))
+        );
+        assert_eq!(recv.recv_timeout(timeout, strategy), Ok(1));
+
+        let sender = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(5));
+            send.try_send(3).unwrap();
+        });
+        assert_eq!(recv.recv_timeout(Duration::from_secs(10), strategy), Ok(3));
+        sender.join().unwrap();
+        assert_eq!(
+            recv.recv_timeout(timeout, strategy),
+            Err(TryRecvError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn disconnected() {
+        let (mut send, mut recv) = channel(4);
+        send.try_send(1).unwrap();
+        drop(send);
+
+        assert_eq!(recv.try_recv(), Ok(1));
+        assert_eq!(recv.try_recv(), Err(TryRecvError::Disconnected));
+
+        let (mut send, recv) = channel(4);
+        drop(recv);
+        assert_eq!(send.try_send(1), Err(TrySendError::Disconnected(1)));
+    }
+
+    #[test]
+    fn chunks() {
+        let (mut send, mut recv) = channel::<u32>(4);
+        send.try_send(0).unwrap();
+        send.try_send(0).unwrap();
+        send.try_send(0).unwrap();
+        recv.read_chunk(3).unwrap();
+
+        let mut chunk = send.write_chunk_uninit(4).unwrap();
+        let (first, second) = chunk.as_mut_slices();
+        assert_eq!((first.len(), second.len()), (1, 3));
+        for (i, slot) in first.iter_mut().chain(second).enumerate() {
+            slot.write(i as u32);
+        }
+        unsafe { chunk.commit(3) };
+        assert_eq!(
+            send.write_chunk_uninit(2).err(),
+            Some(ChunkError::TooFewSlots(1))
+        );
+
+        let chunk = recv.read_chunk(3).unwrap();
+        assert_eq!(chunk.as_slices(), (&[0][..], &[1, 2][..]));
+        chunk.commit(1);
+
+        let chunk = recv.read_chunk(2).unwrap();
+        assert_eq!(chunk.as_slices(), (&[1, 2][..], &[][..]));
+        drop(chunk);
+        assert_eq!(recv.try_recv(), Err(TryRecvError::Empty));
+        assert_eq!(send.high_water_mark(), 3);
+    }
+
+    #[test]
+    fn read_chunk_drops_values() {
+        use std::rc::Rc;
+
+        let (mut send, mut recv) = channel(4);
+        let value = Rc::new(());
+        send.try_send(value.clone()).unwrap();
+        send.try_send(value.clone()).unwrap();
+        assert_eq!(Rc::strong_count(&value), 3);
+
+        recv.read_chunk(2).unwrap();
+        assert_eq!(Rc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn slices() {
+        let (mut send, mut recv) = channel::<f32>(4);
+        let mut out = [0.0; 8];
+
+        assert_eq!(send.write_slice(&[1.0, 2.0, 3.0]), 3);
+        assert_eq!(recv.read_slice(&mut out[..2]), 2);
+
+        assert_eq!(send.write_slice(&[4.0, 5.0, 6.0, 7.0]), 3);
+        assert_eq!(recv.read_slice(&mut out), 4);
+        assert_eq!(out[..4], [3.0, 4.0, 5.0, 6.0]);
+        assert_eq!(recv.read_slice(&mut out), 0);
+    }
+
+    #[test]
+    fn peek() {
+        let (mut send, mut recv) = channel(4);
+        assert_eq!(recv.peek(), None);
+
+        send.try_send(1).unwrap();
+        send.try_send(2).unwrap();
+        assert_eq!(recv.peek(), Some(&1));
+
+        *recv.peek_mut().unwrap() = 10;
+        assert_eq!(recv.try_recv(), Ok(10));
+        assert_eq!(recv.peek(), Some(&2));
+    }
+
+    #[test]
+    fn try_iter() {
+        let (mut send, mut recv) = channel(4);
+        for i in 0..4 {
+            send.try_send(i).unwrap();
+        }
+
+        assert_eq!(recv.try_iter().take(2).collect::<Vec<_>>(), [0, 1]);
+        assert_eq!(recv.try_iter().collect::<Vec<_>>(), [2, 3]);
+        assert_eq!(recv.try_iter().next(), None);
+    }
+
+    #[test]
+    fn overwrite_oldest() {
+        let (mut send, mut recv) = overwrite_channel(3);
+
+        for i in 0..3 {
+            assert_eq!(send.send_overwrite(i), None);
+        }
+        assert_eq!(send.send_overwrite(3), Some(0));
+        assert_eq!(send.send_overwrite(4), Some(1));
+
+        assert_eq!(recv.try_recv(), Ok(2));
+        assert_eq!(send.send_overwrite(5), None);
+        assert_eq!(recv.try_recv(), Ok(3));
+        assert_eq!(recv.try_recv(), Ok(4));
+        assert_eq!(recv.try_recv(), Ok(5));
+        assert_eq!(recv.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test]
+    fn overwrite_concurrent() {
+        let (mut send, mut recv) = overwrite_channel::<u64>(8);
+
+        let producer = std::thread::spawn(move || {
+            let mut evicted = 0;
+            for i in 0..100_000 {
+                if send.send_overwrite(i).is_some() {
+                    evicted += 1;
+                }
+            }
+            evicted
+        });
+
+        let mut received = 0;
+        let mut last = None;
+        loop {
+            match recv.try_recv() {
+                Ok(value) => {
+                    assert!(last.is_none_or(|last| value > last));
+                    last = Some(value);
+                    received += 1;
+                }
+                Err(TryRecvError::Empty) => std::thread::yield_now(),
+                Err(TryRecvError::Disconnected) => break,
+            }
+        }
+
+        assert_eq!(received + producer.join().unwrap(), 100_000);
+        assert_eq!(last, Some(99_999));
+    }
+
+    #[test]
+    fn counters_wrap() {
+        let (mut send, mut recv) = channel(3);
+        send.buffer
+            .indices
+            .write_index
+            .store(usize::MAX - 1, Ordering::Relaxed);
+        send.buffer
+            .indices
+            .reader
+            .read_index
+            .store(usize::MAX - 1, Ordering::Relaxed);
+        send.buffer
+            .indices
+            .cached_read
+            .store(usize::MAX - 1, Ordering::Relaxed);
+        send.buffer
+            .indices
+            .reader
+            .cached_write
+            .store(usize::MAX - 1, Ordering::Relaxed);
+
+        for i in 0..3 {
+            send.try_send(i).unwrap();
+        }
+        assert_eq!(send.try_send(3), Err(TrySendError::Full(3)));
+        assert_eq!(recv.size(), 3);
+        assert_eq!(recv.try_iter().collect::<Vec<_>>(), [0, 1, 2]);
+    }
+
+    #[test]
+    fn cached_counters() {
+        let (mut send, mut recv) = channel(2);
+        send.try_send(1).unwrap();
+        send.try_send(2).unwrap();
+        assert_eq!(send.buffer.indices.cached_read.load(Ordering::Relaxed), 0);
+
+        // The reader only looked at the write counter once for both values.
+        assert_eq!(recv.try_recv(), Ok(1));
+        assert_eq!(
+            recv.buffer
+                .indices
+                .reader
+                .cached_write
+                .load(Ordering::Relaxed),
+            2
+        );
+        assert_eq!(recv.try_recv(), Ok(2));
+
+        // The writer picks up the reader's progress once it runs out of room.
+        send.try_send(3).unwrap();
+        assert_eq!(send.buffer.indices.cached_read.load(Ordering::Relaxed), 2);
+        assert_eq!(recv.try_recv(), Ok(3));
+        assert_eq!(recv.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test]
+    fn validate() {
+        let (mut send, mut recv) = channel(3);
+        assert_eq!(send.validate(), Ok(()));
+        for i in 0..3 {
+            send.try_send(i).unwrap();
+        }
+        assert_eq!(recv.try_recv(), Ok(0));
+        assert_eq!(recv.validate(), Ok(()));
+
+        let indices = &send.buffer.indices;
+        indices.write_index.store(5, Ordering::Relaxed);
+        assert_eq!(
+            send.validate(),
+            Err(InvariantError::Overfilled {
+                queued: 4,
+                capacity: 3
+            })
+        );
+        indices.write_index.store(0, Ordering::Relaxed);
+        assert!(matches!(
+            send.validate(),
+            Err(InvariantError::Overfilled { .. })
+        ));
+        indices.write_index.store(3, Ordering::Relaxed);
+        indices.cached_read.store(2, Ordering::Relaxed);
+        assert_eq!(send.validate(), Err(InvariantError::CachedReadAhead));
+        indices.cached_read.store(1, Ordering::Relaxed);
+        indices.reader.cached_write.store(4, Ordering::Relaxed);
+        assert_eq!(send.validate(), Err(InvariantError::CachedWriteAhead));
+        indices.reader.cached_write.store(3, Ordering::Relaxed);
+        assert_eq!(send.validate(), Ok(()));
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "released more values than were queued")]
+    fn over_release() {
+        let (mut send, recv) = channel(4);
+        send.try_send(1).unwrap();
+        recv.buffer.indices.refresh_write();
+        recv.buffer.indices.commit_read(2);
+    }
+
+    #[test]
+    fn clear() {
+        let (mut send, mut recv) = channel(4);
+        send.try_send(1).unwrap();
+        send.try_send(2).unwrap();
+        assert_eq!(recv.try_recv(), Ok(1));
+        send.try_send(3).unwrap();
+        assert_eq!(recv.clear(), 2);
+        assert_eq!(recv.try_recv(), Err(TryRecvError::Empty));
+
+        // A sender-side clear only takes effect once the receiver runs.
+        send.try_send(4).unwrap();
+        send.try_send(5).unwrap();
+        send.clear();
+        send.try_send(6).unwrap();
+        assert!(send.is_clear_pending());
+        assert_eq!(send.size(), 1);
+        assert_eq!(recv.try_recv(), Ok(6));
+        assert!(!send.is_clear_pending());
+        assert_eq!(send.size(), 4);
+
+        // Nothing left to drop if the receiver got there first.
+        send.try_send(7).unwrap();
+        send.clear();
+        assert_eq!(recv.peek(), None);
+        assert_eq!(recv.validate(), Ok(()));
+    }
+
+    #[test]
+    fn clear_concurrent() {
+        let (mut send, mut recv) = channel(8);
+
+        let reader = std::thread::spawn(move || {
+            let mut last = None;
+            loop {
+                match recv.try_recv() {
+                    Ok(value) => {
+                        // Clearing skips values but never reorders them.
+                        assert!(last < Some(value));
+                        last = Some(value);
+                    }
+                    Err(TryRecvError::Empty) => std::thread::yield_now(),
+                    Err(TryRecvError::Disconnected) => return last,
+                }
+            }
+        });
+
+        for i in 0..10_000 {
+            if i % 7 == 0 {
+                send.clear();
+            }
+            while send.try_send(i).is_err() {
+                std::thread::yield_now();
+            }
+        }
+        drop(send);
+        assert_eq!(reader.join().unwrap(), Some(9_999));
+    }
+
+    #[test]
+    fn clear_concurrent_drops() {
+        use std::sync::atomic::AtomicUsize;
+
+        struct Counted(Arc<AtomicUsize>);
+
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let (mut send, mut recv) = channel(8);
+
+        let reader = std::thread::spawn(move || loop {
+            match recv.try_recv() {
+                Ok(value) => drop(value),
+                Err(TryRecvError::Empty) => std::thread::yield_now(),
+                Err(TryRecvError::Disconnected) => return,
+            }
+        });
+
+        // Every value is dropped exactly once, whether received, cleared or
+        // handed back by a full queue.
+        for i in 0..10_000 {
+            if i % 3 == 0 {
+                send.clear();
+            }
+            if send.try_send(Counted(drops.clone())).is_err() {
+                std::thread::yield_now();
+            }
+        }
+        drop(send);
+        reader.join().unwrap();
+        assert_eq!(drops.load(Ordering::Relaxed), 10_000);
+    }
+
+    #[test]
+    fn probe_does_not_affect_liveness() {
+        let (mut send, mut recv) = channel(4);
+        let probe = send.probe();
+        // As if a monitoring thread were in the middle of `queued`.
+        let held = probe.buffer.upgrade().unwrap();
+
+        assert!(send.is_receiver_active());
+        assert_eq!(recv.try_recv(), Err(TryRecvError::Empty));
+        send.try_send(1).unwrap();
+        assert_eq!(recv.try_recv(), Ok(1));
+
+        drop(recv);
+        assert!(!send.is_receiver_active());
+        assert_eq!(send.try_send(2), Err(TrySendError::Disconnected(2)));
+
+        let (send, mut recv) = overwrite_channel::<i32>(2);
+        let held_overwrite = send.buffer.clone();
+        assert!(send.is_receiver_active());
+        assert_eq!(recv.try_recv(), Err(TryRecvError::Empty));
+        drop(send);
+        assert_eq!(recv.try_recv(), Err(TryRecvError::Disconnected));
+        drop((held, held_overwrite));
+    }
+
+    #[test]
+    fn close() {
+        let (mut send, mut recv) = channel(4);
+        send.try_send(1).unwrap();
+        send.try_send(2).unwrap();
+        send.close();
+        assert!(send.is_closed());
+        assert_eq!(send.try_send(3), Err(TrySendError::Disconnected(3)));
+        assert_eq!(send.write_slice(&[3]), 0);
+
+        // Buffered values come out before the disconnect.
+        assert!(!recv.is_sender_active());
+        assert_eq!(recv.try_recv(), Ok(1));
+        assert_eq!(recv.try_recv(), Ok(2));
+        assert_eq!(recv.try_recv(), Err(TryRecvError::Disconnected));
+
+        let (_send, mut recv) = channel::<i32>(4);
+        assert_eq!(recv.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn io_traits() {
+        use std::io::{ErrorKind, Read, Write};
+
+        let (mut send, mut recv) = channel::<u8>(4);
+        let mut buf = [0; 8];
+        assert_eq!(
+            recv.read(&mut buf).unwrap_err().kind(),
+            ErrorKind::WouldBlock
+        );
+        assert_eq!(send.write(b"hello").unwrap(), 4);
+        assert_eq!(send.write(b"o").unwrap_err().kind(), ErrorKind::WouldBlock);
+        assert_eq!(recv.read(&mut buf).unwrap(), 4);
+        assert_eq!(&buf[..4], b"hell");
+
+        send.write_all(b"o").unwrap();
+        drop(send);
+        assert_eq!(recv.read(&mut buf).unwrap(), 1);
+        assert_eq!(recv.read(&mut buf).unwrap(), 0);
+
+        let (mut send, recv) = channel::<u8>(4);
+        drop(recv);
+        assert_eq!(send.write(b"x").unwrap_err().kind(), ErrorKind::BrokenPipe);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn blocking_io() {
+        use std::io::{Read, Write};
+
+        let (mut send, mut recv) = channel::<u8>(16);
+        let data: Vec<u8> = (0..=255).cycle().take(10_000).collect();
+        let expected = data.clone();
+
+        let writer = std::thread::spawn(move || {
+            let strategy = WaitStrategy::SpinThenYield { spins: 16 };
+            send.blocking_writer(strategy).write_all(&data).unwrap();
+        });
+
+        let mut received = Vec::new();
+        recv.blocking_reader(WaitStrategy::SpinThenYield { spins: 16 })
+            .read_to_end(&mut received)
+            .unwrap();
+        writer.join().unwrap();
+        assert_eq!(received, expected);
+    }
+
+    #[test]
+    fn narrow_counters_wrap() {
+        let indices = Indices::<u32>::new();
+        let start = u32::MAX as usize - 1;
+        for counter in [&indices.write_index, &indices.cached_read] {
+            counter.store(start as u32, Ordering::Relaxed);
+        }
+        for counter in [&indices.reader.read_index, &indices.reader.cached_write] {
+            counter.store(start as u32, Ordering::Relaxed);
+        }
+
+        assert_eq!(indices.writable(4, 4), 4);
+        indices.commit_write(3);
+        assert_eq!(indices.write_index(), 1);
+        assert_eq!(indices.readable(1), 3);
+        assert_eq!(indices.writable(4, 1), 1);
+        assert_eq!(indices.high_water(), 3);
+        indices.commit_read(3);
+        assert_eq!(indices.read_index(), 1);
+        assert_eq!(indices.available_write(4), 4);
+        assert_eq!(indices.validate(4), Ok(()));
+    }
+
+    fn set_counters<C: Counter>(indices: &Indices<C>, value: usize) {
+        for counter in [&indices.write_index, &indices.cached_read] {
+            C::store(counter, value, Ordering::Relaxed);
+        }
+        for counter in [&indices.reader.read_index, &indices.reader.cached_write] {
+            C::store(counter, value, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn narrow_channel_wraps() {
+        let (mut send, mut recv) = channel_with_counter::<u32, u32>(3);
+        set_counters(&send.buffer.indices, u32::MAX as usize - 1);
+
+        for i in 0..3 {
+            send.try_send(i).unwrap();
+        }
+        assert_eq!(send.try_send(3), Err(TrySendError::Full(3)));
+        let mut peeked = Vec::new();
+        recv.peek_each(|&v| peeked.push(v));
+        assert_eq!(peeked, [0, 1, 2]);
+        assert_eq!(recv.try_iter().collect::<Vec<_>>(), [0, 1, 2]);
+        assert_eq!(recv.ring_state(), (3, 1, 1));
+
+        send.try_send(4).unwrap();
+        send.clear();
+        assert_eq!(recv.try_recv(), Err(TryRecvError::Empty));
+
+        let (mut send, mut recv) = overwrite_channel_with_counter::<u32, u32>(2);
+        set_counters(&send.buffer.indices, u32::MAX as usize);
+        assert_eq!(send.send_overwrite(1), None);
+        assert_eq!(send.send_overwrite(2), None);
+        assert_eq!(send.send_overwrite(3), Some(1));
+        assert_eq!(recv.try_recv(), Ok(2));
+        assert_eq!(recv.try_recv(), Ok(3));
+        assert_eq!(recv.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[cfg(feature = "channel-stats")]
+    #[test]
+    fn stats() {
+        let (mut send, mut recv) = channel::<u32>(4);
+        let probe = send.probe();
+
+        assert_eq!(recv.try_recv(), Err(TryRecvError::Empty));
+        send.try_send(1).unwrap();
+        assert_eq!(send.write_slice(&[2, 3, 4, 5]), 3);
+        assert!(send.try_send(6).is_err());
+        assert_eq!(recv.try_recv(), Ok(1));
+        assert_eq!(recv.try_iter().count(), 3);
+
+        let stats = recv.stats();
+        assert_eq!(
+            stats,
+            ChannelStats {
+                sent: 4,
+                received: 4,
+                overruns: 2,
+                underruns: 1,
+                queued: 0,
+                high_water: 4,
+            }
+        );
+        assert_eq!(send.stats(), stats);
+        assert_eq!(probe.stats(), Some(stats));
+
+        // Cleared values aren't received.
+        send.write_slice(&[1, 2]);
+        assert_eq!(recv.clear(), 2);
+        assert_eq!(recv.stats().received, 4);
+        assert_eq!(recv.stats().sent, 6);
+    }
 }
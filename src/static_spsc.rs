@@ -0,0 +1,287 @@
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+use core::ptr;
+
+use crate::cache::{self, CacheMaintenance, Coherent};
+use crate::spsc::{Counter, Indices, InvariantError};
+
+// Heap-free SPSC queue with inline storage, for targets without an
+// allocator. `new` is a `const fn`, so the queue can live in a `static`;
+// `split` hands out the two ends as borrows of it. The counters and their
+// ordering are the ones `spsc` uses, cached opposite counter included.
+//
+// `N` must be a power of two and is also the capacity. `C` is the width of
+// the counters, `usize` unless given; `u32` shrinks the counters on 64-bit
+// targets and gets by with 32-bit atomics, see `spsc::Counter`. `M` is
+// the cache maintenance for memory that isn't coherent, none by default,
+// see `cache::CacheMaintenance`.
+pub struct StaticSpscQueue<T, const N: usize, C: Counter = usize, M: CacheMaintenance = Coherent> {
+    indices: Indices<C>,
+    slots: UnsafeCell<MaybeUninit<[T; N]>>,
+    maintenance: PhantomData<M>,
+}
+
+pub struct Producer<'a, T, const N: usize, C: Counter = usize, M: CacheMaintenance = Coherent> {
+    queue: &'a StaticSpscQueue<T, N, C, M>,
+}
+
+pub struct Consumer<'a, T, const N: usize, C: Counter = usize, M: CacheMaintenance = Coherent> {
+    queue: &'a StaticSpscQueue<T, N, C, M>,
+}
+
+unsafe impl<T: Send, const N: usize, C: Counter, M: CacheMaintenance> Sync
+    for StaticSpscQueue<T, N, C, M>
+{
+}
+
+impl<T, const N: usize, C: Counter, M: CacheMaintenance> StaticSpscQueue<T, N, C, M> {
+    pub const fn new() -> Self {
+        assert!(N.is_power_of_two(), "Queue size must be a power of two");
+        assert!(
+            N <= C::MAX_CAPACITY,
+            "Queue size too large for the counters"
+        );
+
+        StaticSpscQueue {
+            indices: Indices::new(),
+            slots: UnsafeCell::new(MaybeUninit::uninit()),
+            maintenance: PhantomData,
+        }
+    }
+
+    // The ends can't outlive the borrow, so a queue in a `static` needs
+    // `static mut` (or a cell handing out `&'static mut`) to get `'static`
+    // ends.
+    pub fn split(&mut self) -> (Producer<'_, T, N, C, M>, Consumer<'_, T, N, C, M>) {
+        let queue = &*self;
+        (Producer { queue }, Consumer { queue })
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    fn slot(&self, index: usize) -> *mut T {
+        unsafe { (self.slots.get() as *mut T).add(index & (N - 1)) }
+    }
+}
+
+impl<T, const N: usize, C: Counter, M: CacheMaintenance> Default for StaticSpscQueue<T, N, C, M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize, C: Counter, M: CacheMaintenance> Drop for StaticSpscQueue<T, N, C, M> {
+    fn drop(&mut self) {
+        let mut consumer = Consumer { queue: &*self };
+        while consumer.try_recv().is_some() {}
+    }
+}
+
+// Before looking at the other side's counter each end invalidates it, and
+// after writing a slot or its own counter it cleans them.
+impl<T, const N: usize, C: Counter, M: CacheMaintenance> Producer<'_, T, N, C, M> {
+    pub fn try_send(&mut self, value: T) -> Result<(), T> {
+        let indices = &self.queue.indices;
+        self.invalidate_reader();
+        if indices.writable(N, 1) == 0 {
+            return Err(value);
+        }
+
+        let slot = self.queue.slot(indices.write_index());
+        unsafe { ptr::write(slot, value) };
+        cache::clean::<M, T>(slot);
+        indices.commit_write(1);
+        let (counters, len) = indices.writer_counters();
+        M::clean(counters, len);
+        Ok(())
+    }
+
+    pub fn size(&self) -> usize {
+        self.invalidate_reader();
+        self.queue.indices.available_write(N)
+    }
+
+    pub fn high_water_mark(&self) -> usize {
+        self.queue.indices.high_water()
+    }
+
+    pub fn validate(&self) -> Result<(), InvariantError> {
+        self.invalidate_reader();
+        self.queue.indices.validate(N)
+    }
+
+    fn invalidate_reader(&self) {
+        let (counters, len) = self.queue.indices.reader_counters();
+        M::invalidate(counters, len);
+    }
+}
+
+impl<T, const N: usize, C: Counter, M: CacheMaintenance> Consumer<'_, T, N, C, M> {
+    pub fn try_recv(&mut self) -> Option<T> {
+        let slot = self.next()?;
+        let value = unsafe { ptr::read(slot) };
+        let indices = &self.queue.indices;
+        indices.commit_read(1);
+        let (counters, len) = indices.reader_counters();
+        M::clean(counters, len);
+        Some(value)
+    }
+
+    pub fn peek(&mut self) -> Option<&T> {
+        self.next().map(|slot| unsafe { &*slot })
+    }
+
+    pub fn size(&self) -> usize {
+        self.invalidate_writer();
+        self.queue.indices.available_read()
+    }
+
+    pub fn validate(&self) -> Result<(), InvariantError> {
+        self.invalidate_writer();
+        self.queue.indices.validate(N)
+    }
+
+    // The slot to read next, fetched fresh from memory; `None` if empty.
+    fn next(&self) -> Option<*mut T> {
+        let indices = &self.queue.indices;
+        self.invalidate_writer();
+        if indices.readable(1) == 0 {
+            return None;
+        }
+
+        let slot = self.queue.slot(indices.read_index());
+        cache::invalidate::<M, T>(slot);
+        Some(slot)
+    }
+
+    fn invalidate_writer(&self) {
+        let (counters, len) = self.queue.indices.writer_counters();
+        M::invalidate(counters, len);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    static IDLE: StaticSpscQueue<u32, 8> = StaticSpscQueue::new();
+
+    #[test]
+    fn const_new() {
+        assert_eq!(IDLE.capacity(), 8);
+    }
+
+    #[test]
+    fn send_recv() {
+        let mut queue = StaticSpscQueue::<u32, 2>::new();
+        let (mut producer, mut consumer) = queue.split();
+
+        assert_eq!(consumer.try_recv(), None);
+        producer.try_send(1).unwrap();
+        producer.try_send(2).unwrap();
+        assert_eq!(producer.try_send(3), Err(3));
+        assert_eq!(consumer.peek(), Some(&1));
+        assert_eq!(consumer.try_recv(), Some(1));
+        producer.try_send(3).unwrap();
+        assert_eq!(consumer.size(), 2);
+        assert_eq!(consumer.try_recv(), Some(2));
+        assert_eq!(consumer.try_recv(), Some(3));
+        assert_eq!(producer.high_water_mark(), 2);
+        assert_eq!(consumer.validate(), Ok(()));
+    }
+
+    #[test]
+    fn across_threads() {
+        let mut queue = StaticSpscQueue::<u64, 4>::new();
+        let (mut producer, mut consumer) = queue.split();
+
+        std::thread::scope(|s| {
+            s.spawn(move || {
+                for i in 0..1000 {
+                    while producer.try_send(i).is_err() {
+                        std::thread::yield_now();
+                    }
+                }
+            });
+
+            let mut expected = 0;
+            while expected < 1000 {
+                if let Some(value) = consumer.try_recv() {
+                    assert_eq!(value, expected);
+                    expected += 1;
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn drops_queued() {
+        use std::rc::Rc;
+
+        let value = Rc::new(());
+        {
+            let mut queue = StaticSpscQueue::<_, 4>::new();
+            let (mut producer, _) = queue.split();
+            producer.try_send(value.clone()).unwrap();
+            producer.try_send(value.clone()).unwrap();
+            assert_eq!(Rc::strong_count(&value), 3);
+        }
+        assert_eq!(Rc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn narrow_counters() {
+        static QUEUE: StaticSpscQueue<u8, 4, u32> = StaticSpscQueue::new();
+        assert_eq!(QUEUE.capacity(), 4);
+
+        let mut queue = StaticSpscQueue::<u8, 2, u32>::new();
+        let (mut producer, mut consumer) = queue.split();
+        for i in 0..10 {
+            producer.try_send(i).unwrap();
+            assert_eq!(consumer.try_recv(), Some(i));
+        }
+        assert_eq!(consumer.validate(), Ok(()));
+    }
+
+    #[test]
+    fn cache_maintenance() {
+        use std::sync::Mutex;
+
+        static LOG: Mutex<Vec<(&str, usize)>> = Mutex::new(Vec::new());
+
+        struct Logged;
+
+        impl CacheMaintenance for Logged {
+            fn clean(_ptr: *const u8, len: usize) {
+                LOG.lock().unwrap().push(("clean", len));
+            }
+
+            fn invalidate(_ptr: *const u8, len: usize) {
+                LOG.lock().unwrap().push(("invalidate", len));
+            }
+        }
+
+        let mut queue = StaticSpscQueue::<u64, 4, usize, Logged>::new();
+        let (mut producer, mut consumer) = queue.split();
+        producer.try_send(1).unwrap();
+        assert_eq!(
+            LOG.lock().unwrap().drain(..).collect::<Vec<_>>(),
+            [("invalidate", 64), ("clean", 8), ("clean", 64)]
+        );
+
+        assert_eq!(consumer.try_recv(), Some(1));
+        assert_eq!(
+            LOG.lock().unwrap().drain(..).collect::<Vec<_>>(),
+            [("invalidate", 64), ("invalidate", 8), ("clean", 64)]
+        );
+
+        assert_eq!(consumer.try_recv(), None);
+        assert_eq!(
+            LOG.lock().unwrap().drain(..).collect::<Vec<_>>(),
+            [("invalidate", 64)]
+        );
+    }
+}
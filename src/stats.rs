@@ -0,0 +1,895 @@
+use std::fmt;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::role;
+use crate::spsc::ChannelProbe;
+
+// Monotonic event counter, safe to bump from the RT thread. All accesses are
+// relaxed: counters are for observation only and never order other memory.
+pub struct Counter {
+    value: AtomicU64,
+}
+
+impl Counter {
+    pub const fn new() -> Self {
+        Counter {
+            value: AtomicU64::new(0),
+        }
+    }
+
+    pub fn add(&self, n: u64) {
+        self.value.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn increment(&self) {
+        self.add(1);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for Counter {
+    fn default() -> Self {
+        Counter::new()
+    }
+}
+
+// Point-in-time value such as a queue depth, set by whichever side owns it.
+pub struct Gauge {
+    value: AtomicI64,
+}
+
+impl Gauge {
+    pub const fn new() -> Self {
+        Gauge {
+            value: AtomicI64::new(0),
+        }
+    }
+
+    pub fn set(&self, value: i64) {
+        self.value.store(value, Ordering::Relaxed);
+    }
+
+    pub fn add(&self, delta: i64) {
+        self.value.fetch_add(delta, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> i64 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for Gauge {
+    fn default() -> Self {
+        Gauge::new()
+    }
+}
+
+#[derive(Clone)]
+pub enum Metric {
+    Counter(Arc<Counter>),
+    Gauge(Arc<Gauge>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Counter(u64),
+    Gauge(i64),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sample {
+    pub name: String,
+    pub help: String,
+    pub value: Value,
+}
+
+struct Entry {
+    name: String,
+    help: String,
+    metric: Metric,
+}
+
+// Control-side directory of named metrics. Registration allocates and locks,
+// so do it while wiring up the engine; the returned handles are then updated
+// lock-free from any thread.
+pub struct Registry {
+    entries: Mutex<Vec<Entry>>,
+    channels: Mutex<Vec<(String, ChannelProbe)>>,
+}
+
+// Suggested capacity change for a channel, based on its high-water mark.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Recommendation {
+    pub channel: String,
+    pub capacity: usize,
+    pub peak: usize,
+    pub suggested_capacity: usize,
+}
+
+// Channels peaking at or above this share of their capacity should grow;
+// those never getting past `SHRINK_BELOW` of a large capacity could shrink.
+const GROW_AT: f64 = 0.9;
+const SHRINK_BELOW: f64 = 0.25;
+const MIN_SHRINK_CAPACITY: usize = 64;
+
+impl Registry {
+    pub const fn new() -> Self {
+        Registry {
+            entries: Mutex::new(Vec::new()),
+            channels: Mutex::new(Vec::new()),
+        }
+    }
+
+    // Tracks a channel's occupancy for `recommendations`. Channels whose
+    // ends have both been dropped are forgotten.
+    pub fn register_channel(&self, name: &str, probe: ChannelProbe) {
+        role::assert_not_rt("Registry::register_channel");
+        self.channels.lock().unwrap().push((name.to_owned(), probe));
+    }
+
+    // Capacity advice from the high-water marks seen so far: channels that
+    // came close to filling up get a doubled peak, oversized ones get twice
+    // their peak (but not below 64).
+    pub fn recommendations(&self) -> Vec<Recommendation> {
+        let mut channels = self.channels.lock().unwrap();
+        channels.retain(|(_, probe)| probe.is_alive());
+
+        channels
+            .iter()
+            .filter_map(|(name, probe)| {
+                let capacity = probe.capacity()?;
+                let peak = probe.high_water_mark()?;
+                let usage = peak as f64 / capacity as f64;
+
+                let suggested_capacity = if usage >= GROW_AT {
+                    (peak * 2).next_power_of_two()
+                } else if usage < SHRINK_BELOW && capacity > MIN_SHRINK_CAPACITY {
+                    (peak * 2).next_power_of_two().max(MIN_SHRINK_CAPACITY)
+                } else {
+                    return None;
+                };
+
+                Some(Recommendation {
+                    channel: name.clone(),
+                    capacity,
+                    peak,
+                    suggested_capacity,
+                })
+            })
+            .collect()
+    }
+
+    pub fn counter(&self, name: &str, help: &str) -> Arc<Counter> {
+        match self.get_or_insert(name, help, || Metric::Counter(Arc::new(Counter::new()))) {
+            Metric::Counter(counter) => counter,
+            Metric::Gauge(_) => panic!("Metric {} is already registered as a gauge", name),
+        }
+    }
+
+    pub fn gauge(&self, name: &str, help: &str) -> Arc<Gauge> {
+        match self.get_or_insert(name, help, || Metric::Gauge(Arc::new(Gauge::new()))) {
+            Metric::Gauge(gauge) => gauge,
+            Metric::Counter(_) => panic!("Metric {} is already registered as a counter", name),
+        }
+    }
+
+    pub fn register(&self, name: &str, help: &str, metric: Metric) {
+        role::assert_not_rt("Registry::register");
+        assert!(valid_metric_name(name), "Invalid metric name {:?}", name);
+
+        let mut entries = self.entries.lock().unwrap();
+        assert!(
+            entries.iter().all(|e| e.name != name),
+            "Metric {} is already registered",
+            name
+        );
+
+        entries.push(Entry {
+            name: name.to_owned(),
+            help: help.to_owned(),
+            metric,
+        });
+    }
+
+    pub fn snapshot(&self) -> Vec<Sample> {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|e| Sample {
+                name: e.name.clone(),
+                help: e.help.clone(),
+                value: match &e.metric {
+                    Metric::Counter(c) => Value::Counter(c.get()),
+                    Metric::Gauge(g) => Value::Gauge(g.get()),
+                },
+            })
+            .collect()
+    }
+
+    fn get_or_insert(&self, name: &str, help: &str, make: impl FnOnce() -> Metric) -> Metric {
+        role::assert_not_rt("Registry::get_or_insert");
+        assert!(valid_metric_name(name), "Invalid metric name {:?}", name);
+
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(entry) = entries.iter().find(|e| e.name == name) {
+            return entry.metric.clone();
+        }
+
+        let metric = make();
+        entries.push(Entry {
+            name: name.to_owned(),
+            help: help.to_owned(),
+            metric: metric.clone(),
+        });
+
+        metric
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Registry::new()
+    }
+}
+
+#[cfg(feature = "prometheus")]
+impl Registry {
+    // Prometheus text exposition format, version 0.0.4. Registered
+    // channels are exported as `rt_channel_*` families labelled with the
+    // channel name.
+    pub fn encode_prometheus<W: std::fmt::Write>(&self, out: &mut W) -> std::fmt::Result {
+        for sample in self.snapshot() {
+            let (kind, value) = match sample.value {
+                Value::Counter(v) => ("counter", v.to_string()),
+                Value::Gauge(v) => ("gauge", v.to_string()),
+            };
+
+            if !sample.help.is_empty() {
+                write!(out, "# HELP {} ", sample.name)?;
+                write_escaped(out, &sample.help, false)?;
+                out.write_char('\n')?;
+            }
+
+            writeln!(out, "# TYPE {} {}", sample.name, kind)?;
+            writeln!(out, "{} {}", sample.name, value)?;
+        }
+
+        let mut channels = self.channels.lock().unwrap();
+        channels.retain(|(_, probe)| probe.is_alive());
+        if channels.is_empty() {
+            return Ok(());
+        }
+
+        for (name, kind, help, read) in CHANNEL_FAMILIES.iter().chain(CHANNEL_STATS_FAMILIES) {
+            writeln!(out, "# HELP {} {}", name, help)?;
+            writeln!(out, "# TYPE {} {}", name, kind)?;
+            for (channel, probe) in channels.iter() {
+                if let Some(value) = read(probe) {
+                    write!(out, "{}{{channel=\"", name)?;
+                    write_escaped(out, channel, true)?;
+                    writeln!(out, "\"}} {}", value)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Name, type, help and how to read it off a probe.
+#[cfg(feature = "prometheus")]
+type ChannelFamily = (
+    &'static str,
+    &'static str,
+    &'static str,
+    fn(&ChannelProbe) -> Option<u64>,
+);
+
+#[cfg(feature = "prometheus")]
+const CHANNEL_FAMILIES: &[ChannelFamily] = &[
+    (
+        "rt_channel_depth",
+        "gauge",
+        "Values queued in the channel",
+        |probe| probe.queued().map(|v| v as u64),
+    ),
+    (
+        "rt_channel_capacity",
+        "gauge",
+        "Capacity of the channel",
+        |probe| probe.capacity().map(|v| v as u64),
+    ),
+    (
+        "rt_channel_high_water",
+        "gauge",
+        "Most values ever queued in the channel",
+        |probe| probe.high_water_mark().map(|v| v as u64),
+    ),
+];
+
+#[cfg(all(feature = "prometheus", feature = "channel-stats"))]
+const CHANNEL_STATS_FAMILIES: &[ChannelFamily] = &[
+    ("rt_channel_sent_total", "counter", "Values sent", |probe| {
+        probe.stats().map(|s| s.sent)
+    }),
+    (
+        "rt_channel_received_total",
+        "counter",
+        "Values received",
+        |probe| probe.stats().map(|s| s.received),
+    ),
+    (
+        "rt_channel_overruns_total",
+        "counter",
+        "Sends that found the channel full",
+        |probe| probe.stats().map(|s| s.overruns),
+    ),
+    (
+        "rt_channel_underruns_total",
+        "counter",
+        "Receives that found the channel empty",
+        |probe| probe.stats().map(|s| s.underruns),
+    ),
+];
+
+#[cfg(all(feature = "prometheus", not(feature = "channel-stats")))]
+const CHANNEL_STATS_FAMILIES: &[ChannelFamily] = &[];
+
+// Escapes help text, or label values with `quoted`.
+#[cfg(feature = "prometheus")]
+fn write_escaped<W: std::fmt::Write>(out: &mut W, text: &str, quoted: bool) -> std::fmt::Result {
+    for c in text.chars() {
+        match c {
+            '\\' => out.write_str("\\\\")?,
+            '\n' => out.write_str("\\n")?,
+            '"' if quoted => out.write_str("\\\"")?,
+            c => out.write_char(c)?,
+        }
+    }
+    Ok(())
+}
+
+impl fmt::Display for Recommendation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "queue '{}' peaked at {}% of capacity ({}/{}), consider a capacity of {}",
+            self.channel,
+            self.peak * 100 / self.capacity,
+            self.peak,
+            self.capacity,
+            self.suggested_capacity
+        )
+    }
+}
+
+fn valid_metric_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' || c == ':' => {}
+        _ => return false,
+    }
+
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+}
+
+// Allocation-free log-linear histogram in the spirit of HdrHistogram. Values
+// below 4 get exact buckets; above that, every power of two is split into
+// four linear sub-buckets, bounding the relative error at 25%. Values past
+// the last bucket are clamped into it.
+//
+// `record` is a couple of relaxed atomic ops and safe on the RT thread;
+// `snapshot` and the percentile math are for the control thread.
+pub struct RtHistogram<const BUCKETS: usize> {
+    buckets: [AtomicU64; BUCKETS],
+    max: AtomicU64,
+}
+
+const SUB_BUCKET_BITS: u32 = 2;
+const SUB_BUCKETS: usize = 1 << SUB_BUCKET_BITS;
+
+impl<const BUCKETS: usize> RtHistogram<BUCKETS> {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ZERO: AtomicU64 = AtomicU64::new(0);
+
+    pub const fn new() -> Self {
+        assert!(BUCKETS > 0, "Can not create histogram with zero buckets");
+
+        RtHistogram {
+            buckets: [Self::ZERO; BUCKETS],
+            max: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record(&self, value: u64) {
+        let bucket = bucket_index(value).min(BUCKETS - 1);
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.max.fetch_max(value, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> HistogramSnapshot<BUCKETS> {
+        let mut counts = [0; BUCKETS];
+        for (count, bucket) in counts.iter_mut().zip(self.buckets.iter()) {
+            *count = bucket.load(Ordering::Relaxed);
+        }
+
+        HistogramSnapshot {
+            counts,
+            max: self.max.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn reset(&self) {
+        for bucket in self.buckets.iter() {
+            bucket.store(0, Ordering::Relaxed);
+        }
+        self.max.store(0, Ordering::Relaxed);
+    }
+}
+
+impl<const BUCKETS: usize> Default for RtHistogram<BUCKETS> {
+    fn default() -> Self {
+        RtHistogram::new()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistogramSnapshot<const BUCKETS: usize> {
+    pub counts: [u64; BUCKETS],
+    pub max: u64,
+}
+
+impl<const BUCKETS: usize> HistogramSnapshot<BUCKETS> {
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    // Smallest value `v` such that at least `percentile` percent of recorded
+    // values are below `v`, rounded up to the containing bucket's upper
+    // bound and capped at the largest recorded value.
+    pub fn percentile(&self, percentile: f64) -> Option<u64> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+
+        let rank = ((percentile.clamp(0.0, 100.0) / 100.0) * count as f64).ceil() as u64;
+        let rank = rank.max(1);
+
+        let mut seen = 0;
+        for (bucket, &n) in self.counts.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                let upper = if bucket + 1 == BUCKETS {
+                    self.max
+                } else {
+                    bucket_lower_bound(bucket + 1) - 1
+                };
+                return Some(upper.min(self.max));
+            }
+        }
+
+        Some(self.max)
+    }
+
+    pub fn bucket_range(bucket: usize) -> (u64, Option<u64>) {
+        let upper = if bucket + 1 >= BUCKETS {
+            None
+        } else {
+            Some(bucket_lower_bound(bucket + 1))
+        };
+
+        (bucket_lower_bound(bucket), upper)
+    }
+}
+
+fn bucket_index(value: u64) -> usize {
+    if value < SUB_BUCKETS as u64 {
+        return value as usize;
+    }
+
+    let exponent = 63 - value.leading_zeros();
+    let sub = (value >> (exponent - SUB_BUCKET_BITS)) as usize & (SUB_BUCKETS - 1);
+
+    SUB_BUCKETS + (exponent - SUB_BUCKET_BITS) as usize * SUB_BUCKETS + sub
+}
+
+fn bucket_lower_bound(bucket: usize) -> u64 {
+    if bucket < SUB_BUCKETS {
+        return bucket as u64;
+    }
+
+    let octave = (bucket - SUB_BUCKETS) / SUB_BUCKETS;
+    let sub = (bucket - SUB_BUCKETS) % SUB_BUCKETS;
+
+    ((SUB_BUCKETS + sub) as u64) << octave
+}
+
+// Items/sec over a sliding window, computed lazily on the reading side.
+//
+// The RT side only ever bumps the shared `Counter`. Every call to `rate` (or
+// `rate_at`) samples the counter, attributes the delta since the previous
+// sample to the bucket that was current since then, and rotates out buckets
+// that have fallen out of the window. Sample at least once per bucket for
+// accurate attribution.
+pub struct Throughput {
+    counter: Arc<Counter>,
+    buckets: Box<[u64]>,
+    bucket_duration: Duration,
+    current: usize,
+    filled: usize,
+    bucket_start: Instant,
+    last_total: u64,
+}
+
+impl Throughput {
+    pub fn new(counter: Arc<Counter>, window: Duration, bucket_count: usize) -> Self {
+        Throughput::new_at(counter, window, bucket_count, Instant::now())
+    }
+
+    pub fn new_at(
+        counter: Arc<Counter>,
+        window: Duration,
+        bucket_count: usize,
+        now: Instant,
+    ) -> Self {
+        assert!(
+            bucket_count > 0,
+            "Can not track throughput with zero buckets"
+        );
+
+        let bucket_duration = window / bucket_count as u32;
+        assert!(
+            bucket_duration > Duration::from_secs(0),
+            "Throughput window too short for bucket count"
+        );
+
+        let last_total = counter.get();
+
+        Throughput {
+            counter,
+            buckets: vec![0; bucket_count].into_boxed_slice(),
+            bucket_duration,
+            current: 0,
+            filled: 1,
+            bucket_start: now,
+            last_total,
+        }
+    }
+
+    pub fn counter(&self) -> &Arc<Counter> {
+        &self.counter
+    }
+
+    pub fn rate(&mut self) -> f64 {
+        self.rate_at(Instant::now())
+    }
+
+    pub fn rate_at(&mut self, now: Instant) -> f64 {
+        self.advance(now);
+
+        let items: u64 = self.buckets.iter().sum();
+        let covered = self.bucket_duration * (self.filled as u32 - 1)
+            + now.saturating_duration_since(self.bucket_start);
+
+        if covered == Duration::from_secs(0) {
+            return 0.0;
+        }
+
+        items as f64 / covered.as_secs_f64()
+    }
+
+    pub fn window_items(&mut self, now: Instant) -> u64 {
+        self.advance(now);
+        self.buckets.iter().sum()
+    }
+
+    fn advance(&mut self, now: Instant) {
+        let total = self.counter.get();
+        self.buckets[self.current] += total.wrapping_sub(self.last_total);
+        self.last_total = total;
+
+        let elapsed = now.saturating_duration_since(self.bucket_start);
+        let bucket_nanos = self.bucket_duration.as_nanos();
+        let steps = elapsed.as_nanos() / bucket_nanos;
+
+        if steps > 0 {
+            let len = self.buckets.len();
+            let clear = steps.min(len as u128) as usize;
+
+            for _ in 0..clear {
+                self.current = (self.current + 1) % len;
+                self.buckets[self.current] = 0;
+            }
+
+            self.filled = (self.filled + clear).min(len);
+            let into_bucket = (elapsed.as_nanos() % bucket_nanos) as u64;
+            self.bucket_start = now - Duration::from_nanos(into_bucket);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn static_metrics() {
+        static XRUNS: Counter = Counter::new();
+        static DEPTH: Gauge = Gauge::new();
+        static LATENCY: RtHistogram<32> = RtHistogram::new();
+        static REGISTRY: Registry = Registry::new();
+
+        XRUNS.increment();
+        DEPTH.set(3);
+        LATENCY.record(10);
+        REGISTRY.gauge("depth", "").set(DEPTH.get());
+
+        assert_eq!(XRUNS.get(), 1);
+        assert_eq!(LATENCY.snapshot().count(), 1);
+        assert_eq!(REGISTRY.snapshot()[0].value, Value::Gauge(3));
+    }
+
+    #[test]
+    fn counter() {
+        let counter = Counter::new();
+        counter.increment();
+        counter.add(4);
+        assert_eq!(counter.get(), 5);
+    }
+
+    #[test]
+    fn gauge() {
+        let gauge = Gauge::new();
+        gauge.set(10);
+        gauge.add(-3);
+        assert_eq!(gauge.get(), 7);
+    }
+
+    #[test]
+    fn registry_get_or_insert() {
+        let registry = Registry::new();
+        registry.counter("xruns", "Audio callback overruns").add(2);
+        registry.counter("xruns", "").increment();
+        registry.gauge("midi_in_depth", "").set(5);
+
+        assert_eq!(
+            registry.snapshot(),
+            vec![
+                Sample {
+                    name: "xruns".to_owned(),
+                    help: "Audio callback overruns".to_owned(),
+                    value: Value::Counter(3),
+                },
+                Sample {
+                    name: "midi_in_depth".to_owned(),
+                    help: "".to_owned(),
+                    value: Value::Gauge(5),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn registry_kind_mismatch() {
+        let registry = Registry::new();
+        registry.counter("xruns", "");
+        registry.gauge("xruns", "");
+    }
+
+    #[test]
+    #[should_panic]
+    fn registry_invalid_name() {
+        Registry::new().counter("midi in", "");
+    }
+
+    #[cfg(feature = "prometheus")]
+    #[test]
+    fn prometheus_text() {
+        let registry = Registry::new();
+        registry
+            .counter("xruns", "Audio\\callback\noverruns")
+            .add(2);
+        registry.gauge("depth", "").set(-1);
+
+        let mut out = String::new();
+        registry.encode_prometheus(&mut out).unwrap();
+
+        assert_eq!(
+            out,
+            "# HELP xruns Audio\\\\callback\\noverruns\n\
+             # TYPE xruns counter\n\
+             xruns 2\n\
+             # TYPE depth gauge\n\
+             depth -1\n"
+        );
+    }
+
+    #[cfg(feature = "prometheus")]
+    #[test]
+    fn prometheus_channels() {
+        let registry = Registry::new();
+        let (mut send, _recv) = crate::spsc::channel::<u32>(4);
+        registry.register_channel("midi \"in\"", send.probe());
+        send.try_send(1).unwrap();
+
+        let mut out = String::new();
+        registry.encode_prometheus(&mut out).unwrap();
+
+        assert!(out.starts_with(
+            "# HELP rt_channel_depth Values queued in the channel\n\
+             # TYPE rt_channel_depth gauge\n\
+             rt_channel_depth{channel=\"midi \\\"in\\\"\"} 1\n"
+        ));
+        assert!(out.contains("rt_channel_capacity{channel=\"midi \\\"in\\\"\"} 4\n"));
+        #[cfg(feature = "channel-stats")]
+        assert!(out.contains("rt_channel_sent_total{channel=\"midi \\\"in\\\"\"} 1\n"));
+    }
+
+    #[test]
+    fn histogram_bucket_bounds() {
+        for value in (0..10_000).chain(vec![u64::MAX / 3, u64::MAX]) {
+            let bucket = bucket_index(value);
+            assert!(bucket_lower_bound(bucket) <= value, "{}", value);
+            if bucket + 1 < bucket_index(u64::MAX) {
+                assert!(bucket_lower_bound(bucket + 1) > value, "{}", value);
+            }
+        }
+    }
+
+    #[test]
+    fn histogram_percentiles() {
+        let histogram = RtHistogram::<64>::new();
+        for value in 1..=100 {
+            histogram.record(value);
+        }
+
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.count(), 100);
+        assert_eq!(snapshot.max, 100);
+        assert_eq!(snapshot.percentile(100.0), Some(100));
+
+        let median = snapshot.percentile(50.0).unwrap();
+        assert!((50..=63).contains(&median), "{}", median);
+
+        let p99 = snapshot.percentile(99.0).unwrap();
+        assert!((99..=100).contains(&p99), "{}", p99);
+    }
+
+    #[test]
+    fn histogram_clamps_overflow() {
+        let histogram = RtHistogram::<8>::new();
+        histogram.record(3);
+        histogram.record(1_000_000);
+
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.counts[3], 1);
+        assert_eq!(snapshot.counts[7], 1);
+        assert_eq!(snapshot.percentile(100.0), Some(1_000_000));
+        assert_eq!(HistogramSnapshot::<8>::bucket_range(7), (7, None));
+    }
+
+    #[test]
+    fn histogram_empty_and_reset() {
+        let histogram = RtHistogram::<16>::new();
+        assert_eq!(histogram.snapshot().percentile(50.0), None);
+
+        histogram.record(5);
+        histogram.reset();
+        assert_eq!(histogram.snapshot().count(), 0);
+    }
+
+    #[test]
+    fn idle() {
+        let start = Instant::now();
+        let mut tp = Throughput::new_at(Arc::new(Counter::new()), ms(1000), 10, start);
+        assert_eq!(tp.rate_at(start), 0.0);
+        assert_eq!(tp.rate_at(start + ms(500)), 0.0);
+    }
+
+    #[test]
+    fn steady_rate() {
+        let start = Instant::now();
+        let counter = Arc::new(Counter::new());
+        let mut tp = Throughput::new_at(counter.clone(), ms(1000), 10, start);
+
+        for i in 1..=20 {
+            counter.add(10);
+            tp.rate_at(start + ms(i * 100));
+        }
+
+        let rate = tp.rate_at(start + ms(2000));
+        assert!((rate - 100.0).abs() < 1e-6, "{}", rate);
+    }
+
+    #[test]
+    fn partial_window() {
+        let start = Instant::now();
+        let counter = Arc::new(Counter::new());
+        let mut tp = Throughput::new_at(counter.clone(), ms(1000), 10, start);
+
+        counter.add(50);
+        let rate = tp.rate_at(start + ms(250));
+        assert!((rate - 200.0).abs() < 1e-6, "{}", rate);
+    }
+
+    #[test]
+    fn old_buckets_expire() {
+        let start = Instant::now();
+        let counter = Arc::new(Counter::new());
+        let mut tp = Throughput::new_at(counter.clone(), ms(1000), 10, start);
+
+        counter.add(1000);
+        tp.rate_at(start + ms(50));
+        assert_eq!(tp.window_items(start + ms(900)), 1000);
+        assert_eq!(tp.window_items(start + ms(1050)), 0);
+    }
+
+    #[test]
+    fn long_idle_gap() {
+        let start = Instant::now();
+        let counter = Arc::new(Counter::new());
+        let mut tp = Throughput::new_at(counter.clone(), ms(1000), 10, start);
+
+        counter.add(1000);
+        tp.rate_at(start + ms(50));
+        counter.add(30);
+        assert_eq!(tp.window_items(start + ms(3_600_000)), 0);
+        counter.add(30);
+        assert_eq!(tp.window_items(start + ms(3_600_050)), 30);
+    }
+
+    #[test]
+    fn recommendations() {
+        let registry = Registry::new();
+        let (mut midi_in, _midi_rx) = crate::spsc::channel::<u32>(64);
+        let (mut levels, _levels_rx) = crate::spsc::channel::<f32>(1024);
+        let (mut fine, _fine_rx) = crate::spsc::channel::<u8>(16);
+        registry.register_channel("midi_in", midi_in.probe());
+        registry.register_channel("levels", levels.probe());
+        registry.register_channel("fine", fine.probe());
+
+        for i in 0..62 {
+            midi_in.try_send(i).unwrap();
+        }
+        levels.try_send(0.0).unwrap();
+        fine.try_send(0).unwrap();
+
+        let recommendations = registry.recommendations();
+        assert_eq!(
+            recommendations,
+            vec![
+                Recommendation {
+                    channel: "midi_in".to_owned(),
+                    capacity: 64,
+                    peak: 62,
+                    suggested_capacity: 128,
+                },
+                Recommendation {
+                    channel: "levels".to_owned(),
+                    capacity: 1024,
+                    peak: 1,
+                    suggested_capacity: 64,
+                },
+            ]
+        );
+        assert_eq!(
+            recommendations[0].to_string(),
+            "queue 'midi_in' peaked at 96% of capacity (62/64), consider a capacity of 128"
+        );
+
+        drop((midi_in, _midi_rx));
+        assert_eq!(registry.recommendations().len(), 1);
+    }
+}
@@ -0,0 +1,486 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::panic_guard::{self, PanicMonitor, PANIC_MESSAGE_LEN};
+use crate::poison::PoisonFlag;
+use crate::role;
+use crate::text::FixedString;
+use crate::watchdog::{self, Status, Watchdog};
+
+// How long a stopped worker gets to finish its current iteration when no
+// watchdog timeout is set.
+const STOP_TIMEOUT: Duration = Duration::from_millis(100);
+
+// Keeps an RT worker thread alive across panics and hangs.
+//
+// The user factory builds a fresh set of channels on every (re)start and
+// splits them into a control-side bundle `C`, which the supervisor hands
+// out, and the worker closure, which runs on the supervised thread. The
+// worker is called in a loop, once per block, until it returns false or the
+// supervisor stops it. Supervision is driven by calling `poll` periodically
+// from the control thread.
+pub struct Supervisor<C> {
+    factory: Factory<C>,
+    carry_over: Option<CarryOver<C>>,
+    thread_name: String,
+    stack_size: Option<usize>,
+    watchdog_timeout: Option<Duration>,
+    max_restarts: Option<u32>,
+    restarts: u32,
+    running: Option<Running<C>>,
+}
+
+pub type Worker = Box<dyn FnMut() -> bool + Send>;
+
+type Factory<C> = Box<dyn FnMut(&mut Setup) -> (C, Worker)>;
+type CarryOver<C> = Box<dyn FnMut(&C, &mut C)>;
+
+// Passed to the factory so it can attach the fresh channels' poison flags to
+// the panic guard of the thread about to be started.
+pub struct Setup {
+    poisons: Vec<PoisonFlag>,
+}
+
+impl Setup {
+    pub fn poison_on_panic(&mut self, flag: PoisonFlag) {
+        self.poisons.push(flag);
+    }
+}
+
+struct Running<C> {
+    control: C,
+    monitor: PanicMonitor,
+    watchdog: Option<Watchdog>,
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RestartReason {
+    Panicked(FixedString<PANIC_MESSAGE_LEN>),
+    Stalled(Duration),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    Restarted(RestartReason),
+    GaveUp(RestartReason),
+    Exited,
+}
+
+impl<C> Supervisor<C> {
+    pub fn new<F>(factory: F) -> Self
+    where
+        F: FnMut(&mut Setup) -> (C, Worker) + 'static,
+    {
+        Supervisor {
+            factory: Box::new(factory),
+            carry_over: None,
+            thread_name: "rt-worker".to_owned(),
+            stack_size: None,
+            watchdog_timeout: None,
+            max_restarts: None,
+            restarts: 0,
+            running: None,
+        }
+    }
+
+    pub fn thread_name(mut self, name: &str) -> Self {
+        self.thread_name = name.to_owned();
+        self
+    }
+
+    pub fn stack_size(mut self, size: usize) -> Self {
+        self.stack_size = Some(size);
+        self
+    }
+
+    // Treat the worker as hung if it doesn't complete an iteration within
+    // `timeout`. A hung thread can't be reclaimed: once told to stop it gets
+    // `timeout` again to finish, then it is detached and left to finish (or
+    // not) on its own. Also bounds the wait in `shutdown`.
+    pub fn watchdog_timeout(mut self, timeout: Duration) -> Self {
+        self.watchdog_timeout = Some(timeout);
+        self
+    }
+
+    pub fn max_restarts(mut self, max_restarts: u32) -> Self {
+        self.max_restarts = Some(max_restarts);
+        self
+    }
+
+    // On restart, `carry_over` is called with the crashed instance's control
+    // bundle and the fresh one before the new worker starts, so the state
+    // last published to the old engine (e.g. `Writer::last_written`,
+    // `ParamWriter::published`) can be replayed into the new channels.
+    pub fn restore_with<F>(mut self, carry_over: F) -> Self
+    where
+        F: FnMut(&C, &mut C) + 'static,
+    {
+        self.carry_over = Some(Box::new(carry_over));
+        self
+    }
+
+    pub fn restarts(&self) -> u32 {
+        self.restarts
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.is_some()
+    }
+
+    pub fn control(&mut self) -> Option<&mut C> {
+        self.running.as_mut().map(|r| &mut r.control)
+    }
+
+    pub fn start(&mut self) -> io::Result<()> {
+        role::assert_not_rt("Supervisor::start");
+        assert!(self.running.is_none(), "Supervisor already running");
+        self.spawn(None)
+    }
+
+    fn spawn(&mut self, previous: Option<C>) -> io::Result<()> {
+        let mut setup = Setup {
+            poisons: Vec::new(),
+        };
+        let (mut control, mut worker) = (self.factory)(&mut setup);
+
+        if let (Some(previous), Some(carry_over)) = (&previous, &mut self.carry_over) {
+            carry_over(previous, &mut control);
+        }
+        drop(previous);
+
+        let (mut guard, monitor) = panic_guard::guard();
+        for flag in setup.poisons {
+            guard = guard.poisons(flag);
+        }
+
+        let (heartbeat, watchdog) = match self.watchdog_timeout {
+            Some(timeout) => {
+                let (heartbeat, watchdog) = watchdog::watchdog(timeout);
+                (Some(heartbeat), Some(watchdog))
+            }
+            None => (None, None),
+        };
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+
+        let mut builder = thread::Builder::new().name(self.thread_name.clone());
+        if let Some(size) = self.stack_size {
+            builder = builder.stack_size(size);
+        }
+
+        let handle = builder.spawn(move || {
+            while !thread_stop.load(Ordering::Relaxed) {
+                if let Some(heartbeat) = &heartbeat {
+                    heartbeat.beat();
+                }
+
+                match guard.run(&mut worker) {
+                    Some(true) => {}
+                    Some(false) | None => break,
+                }
+            }
+        })?;
+
+        self.running = Some(Running {
+            control,
+            monitor,
+            watchdog,
+            stop,
+            handle,
+        });
+
+        Ok(())
+    }
+
+    // Checks the worker's health, restarting it if it panicked or stalled.
+    pub fn poll(&mut self) -> io::Result<Option<Event>> {
+        let reason = {
+            let running = match self.running.as_mut() {
+                Some(running) => running,
+                None => return Ok(None),
+            };
+
+            if let Some(message) = running.monitor.message() {
+                RestartReason::Panicked(message)
+            } else if let Some(Status::Stalled { stalled_for, .. }) =
+                running.watchdog.as_mut().map(|w| w.check())
+            {
+                RestartReason::Stalled(stalled_for)
+            } else if running.handle.is_finished() {
+                self.shutdown();
+                return Ok(Some(Event::Exited));
+            } else {
+                return Ok(None);
+            }
+        };
+
+        let previous = self.stop_worker();
+
+        if self.max_restarts.is_some_and(|max| self.restarts >= max) {
+            return Ok(Some(Event::GaveUp(reason)));
+        }
+
+        self.restarts += 1;
+        self.spawn(previous)?;
+
+        Ok(Some(Event::Restarted(reason)))
+    }
+
+    // Stops the worker and waits for it to finish its current iteration,
+    // for up to the watchdog timeout (100ms without one). A worker still
+    // running by then is detached.
+    pub fn shutdown(&mut self) {
+        self.stop_worker();
+    }
+
+    fn stop_worker(&mut self) -> Option<C> {
+        let running = self.running.take()?;
+        running.stop.store(true, Ordering::Relaxed);
+
+        let deadline = Instant::now() + self.watchdog_timeout.unwrap_or(STOP_TIMEOUT);
+        while !running.handle.is_finished() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        if running.handle.is_finished() {
+            let _ = running.handle.join();
+        }
+
+        Some(running.control)
+    }
+}
+
+impl<C> Drop for Supervisor<C> {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::poison::Poisoned;
+    use crate::spsc;
+
+    fn poll_until_event<C>(supervisor: &mut Supervisor<C>) -> Event {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            if let Some(event) = supervisor.poll().unwrap() {
+                return event;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        panic!("no supervisor event");
+    }
+
+    #[test]
+    fn restarts_after_panic_with_fresh_channels() {
+        let mut generation = 0;
+        let mut supervisor = Supervisor::new(move |setup| {
+            generation += 1;
+            let this_generation = generation;
+            let (mut send, recv) = spsc::channel::<i32>(4);
+            setup.poison_on_panic(send.poison_flag());
+
+            let worker: Worker = Box::new(move || {
+                if this_generation == 1 {
+                    panic!("first generation fails");
+                }
+                let _ = send.try_send(this_generation);
+                thread::sleep(Duration::from_millis(1));
+                true
+            });
+
+            (recv, worker)
+        });
+
+        supervisor.start().unwrap();
+        let poison = supervisor.control().unwrap().poison_flag();
+
+        assert_eq!(
+            poll_until_event(&mut supervisor),
+            Event::Restarted(RestartReason::Panicked({
+                let mut s = FixedString::new();
+                s.push_str("first generation fails");
+                s
+            }))
+        );
+        assert_eq!(poison.check(), Err(Poisoned));
+        assert_eq!(supervisor.restarts(), 1);
+
+        let recv = supervisor.control().unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let value = loop {
+            if let Ok(value) = recv.try_recv() {
+                break value;
+            }
+            assert!(Instant::now() < deadline);
+            thread::sleep(Duration::from_millis(1));
+        };
+        assert_eq!(value, 2);
+        assert_eq!(recv.check_poisoned(), Ok(()));
+    }
+
+    #[test]
+    fn carries_over_published_state() {
+        use crate::param_bank::{self, ParamWriter};
+        use crate::triple_buffer::{self, Writer};
+
+        struct Control {
+            gain: Writer<f32>,
+            params: ParamWriter<2>,
+            observed: spsc::Receiver<(f32, [f32; 2])>,
+        }
+
+        let mut starts = 0;
+        let mut supervisor = Supervisor::new(move |_| {
+            starts += 1;
+            let crash = starts == 1;
+
+            let (gain, mut gain_reader) = triple_buffer::triple_buffer(0.0);
+            let (params, mut param_reader) = param_bank::param_bank([0.0; 2]);
+            let (mut report, observed) = spsc::channel(64);
+
+            let worker: Worker = Box::new(move || {
+                thread::sleep(Duration::from_millis(1));
+                let gain = *gain_reader.read();
+                let params = param_reader.read().values;
+                if crash && gain > 0.0 {
+                    panic!("crash after configuration");
+                }
+                let _ = report.try_send((gain, params));
+                true
+            });
+
+            (
+                Control {
+                    gain,
+                    params,
+                    observed,
+                },
+                worker,
+            )
+        })
+        .restore_with(|old: &Control, new: &mut Control| {
+            new.gain.write(*old.gain.last_written());
+            new.params.set_all(&old.params.published().values);
+            new.params.publish();
+        });
+
+        supervisor.start().unwrap();
+        {
+            let control = supervisor.control().unwrap();
+            control.params.set(1, 0.25);
+            control.params.publish();
+            control.gain.write(0.5);
+        }
+
+        assert!(matches!(
+            poll_until_event(&mut supervisor),
+            Event::Restarted(RestartReason::Panicked(_))
+        ));
+
+        let control = supervisor.control().unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            if let Ok(observed) = control.observed.try_recv() {
+                assert_eq!(observed, (0.5, [0.0, 0.25]));
+                break;
+            }
+            assert!(Instant::now() < deadline);
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn gives_up_after_max_restarts() {
+        let mut supervisor = Supervisor::new(|_| {
+            let worker: Worker = Box::new(|| panic!("always"));
+            ((), worker)
+        })
+        .max_restarts(1);
+
+        supervisor.start().unwrap();
+        assert!(matches!(
+            poll_until_event(&mut supervisor),
+            Event::Restarted(_)
+        ));
+        assert!(matches!(
+            poll_until_event(&mut supervisor),
+            Event::GaveUp(_)
+        ));
+        assert!(!supervisor.is_running());
+    }
+
+    #[test]
+    fn restarts_stalled_worker() {
+        let mut starts = 0;
+        let mut supervisor = Supervisor::new(move |_| {
+            starts += 1;
+            let stall = starts == 1;
+            let worker: Worker = Box::new(move || {
+                if stall {
+                    thread::sleep(Duration::from_millis(200));
+                }
+                thread::sleep(Duration::from_millis(1));
+                true
+            });
+            ((), worker)
+        })
+        .watchdog_timeout(Duration::from_millis(50))
+        .max_restarts(1);
+
+        supervisor.start().unwrap();
+        assert!(matches!(
+            poll_until_event(&mut supervisor),
+            Event::Restarted(RestartReason::Stalled(_))
+        ));
+    }
+
+    #[test]
+    fn shutdown_waits_for_iteration() {
+        struct Exited(Arc<AtomicBool>);
+
+        impl Drop for Exited {
+            fn drop(&mut self) {
+                self.0.store(true, Ordering::Release);
+            }
+        }
+
+        let exited = Arc::new(AtomicBool::new(false));
+        let flag = exited.clone();
+        let mut supervisor = Supervisor::new(move |_| {
+            let exited = Exited(flag.clone());
+            let worker: Worker = Box::new(move || {
+                let _ = &exited;
+                thread::sleep(Duration::from_millis(20));
+                true
+            });
+            ((), worker)
+        });
+
+        supervisor.start().unwrap();
+        thread::sleep(Duration::from_millis(5));
+        supervisor.shutdown();
+        assert!(exited.load(Ordering::Acquire));
+        assert!(!supervisor.is_running());
+    }
+
+    #[test]
+    fn reports_clean_exit() {
+        let mut supervisor = Supervisor::new(|_| {
+            let worker: Worker = Box::new(|| false);
+            ((), worker)
+        });
+
+        supervisor.start().unwrap();
+        assert_eq!(poll_until_event(&mut supervisor), Event::Exited);
+        assert!(!supervisor.is_running());
+    }
+}
@@ -0,0 +1,139 @@
+use crate::spsc;
+
+// Pairs up items from two timestamped streams produced on different threads
+// (audio blocks and video frames, audio and haptics, ...). Timestamps are in
+// a shared unit, typically frames, and must increase within each stream.
+//
+// Whichever stream runs ahead stays queued until the other catches up. Two
+// items pair when their timestamps are within `tolerance` of each other; an
+// item the other stream has already moved past can never pair and is
+// dropped, which `dropped` counts per stream.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stamped<T> {
+    pub timestamp: u64,
+    pub value: T,
+}
+
+pub struct Synchronizer<A, B> {
+    a: spsc::Receiver<Stamped<A>>,
+    b: spsc::Receiver<Stamped<B>>,
+    pending_a: Option<Stamped<A>>,
+    pending_b: Option<Stamped<B>>,
+    tolerance: u64,
+    dropped: (u64, u64),
+}
+
+impl<A, B> Synchronizer<A, B> {
+    pub fn new(
+        a: spsc::Receiver<Stamped<A>>,
+        b: spsc::Receiver<Stamped<B>>,
+        tolerance: u64,
+    ) -> Self {
+        Synchronizer {
+            a,
+            b,
+            pending_a: None,
+            pending_b: None,
+            tolerance,
+            dropped: (0, 0),
+        }
+    }
+
+    // The next aligned pair, or `None` if one of the streams has nothing
+    // queued yet.
+    pub fn next_pair(&mut self) -> Option<(Stamped<A>, Stamped<B>)> {
+        loop {
+            if self.pending_a.is_none() {
+                self.pending_a = self.a.try_recv().ok();
+            }
+            if self.pending_b.is_none() {
+                self.pending_b = self.b.try_recv().ok();
+            }
+
+            let ta = self.pending_a.as_ref()?.timestamp;
+            let tb = self.pending_b.as_ref()?.timestamp;
+
+            if ta.max(tb) - ta.min(tb) <= self.tolerance {
+                return Some((self.pending_a.take()?, self.pending_b.take()?));
+            } else if ta < tb {
+                self.pending_a = None;
+                self.dropped.0 += 1;
+            } else {
+                self.pending_b = None;
+                self.dropped.1 += 1;
+            }
+        }
+    }
+
+    // Unpairable items dropped from stream A and stream B.
+    pub fn dropped(&self) -> (u64, u64) {
+        self.dropped
+    }
+
+    pub fn tolerance(&self) -> u64 {
+        self.tolerance
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn synchronizer<A, B>(
+    capacity: usize,
+    tolerance: u64,
+) -> (
+    spsc::Sender<Stamped<A>>,
+    spsc::Sender<Stamped<B>>,
+    Synchronizer<A, B>,
+) {
+    let (send_a, recv_a) = spsc::channel(capacity);
+    let (send_b, recv_b) = spsc::channel(capacity);
+    (send_a, send_b, Synchronizer::new(recv_a, recv_b, tolerance))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn stamped<T>(timestamp: u64, value: T) -> Stamped<T> {
+        Stamped { timestamp, value }
+    }
+
+    #[test]
+    fn buffers_stream_ahead() {
+        let (mut audio, mut haptics, mut sync) = synchronizer::<u32, &str>(8, 0);
+
+        for i in 0..3 {
+            audio.try_send(stamped(i * 64, i as u32)).unwrap();
+        }
+        assert_eq!(sync.next_pair(), None);
+
+        haptics.try_send(stamped(0, "tap")).unwrap();
+        assert_eq!(sync.next_pair(), Some((stamped(0, 0), stamped(0, "tap"))));
+        assert_eq!(sync.next_pair(), None);
+
+        haptics.try_send(stamped(64, "buzz")).unwrap();
+        haptics.try_send(stamped(128, "tap")).unwrap();
+        assert_eq!(
+            sync.next_pair(),
+            Some((stamped(64, 1), stamped(64, "buzz")))
+        );
+        assert_eq!(
+            sync.next_pair(),
+            Some((stamped(128, 2), stamped(128, "tap")))
+        );
+        assert_eq!(sync.dropped(), (0, 0));
+    }
+
+    #[test]
+    fn drops_unpairable_items() {
+        let (mut video, mut audio, mut sync) = synchronizer::<u8, u8>(8, 10);
+
+        video.try_send(stamped(100, 1)).unwrap();
+        video.try_send(stamped(200, 2)).unwrap();
+        audio.try_send(stamped(0, 1)).unwrap();
+        audio.try_send(stamped(50, 2)).unwrap();
+        audio.try_send(stamped(195, 3)).unwrap();
+
+        assert_eq!(sync.next_pair(), Some((stamped(200, 2), stamped(195, 3))));
+        assert_eq!(sync.dropped(), (1, 2));
+    }
+}
@@ -0,0 +1,261 @@
+use std::error;
+use std::fmt;
+
+use crate::triple_buffer::{self, Reader, Writer};
+
+// Tempo map with constant-tempo segments, edited on the control thread and
+// published to the RT thread through a triple buffer. Every copy keeps the
+// same preallocated segment capacity, so publishing an edit never
+// allocates, and lookups on the RT side are a binary search over the
+// segments.
+pub struct TempoMap {
+    sample_rate: f64,
+    segments: Vec<TempoSegment>,
+    capacity: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TempoSegment {
+    pub frame: u64,
+    pub beat: f64,
+    pub bpm: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TempoMapFull;
+
+pub struct TempoMapWriter {
+    writer: Writer<TempoMap>,
+}
+
+pub struct TempoMapReader {
+    reader: Reader<TempoMap>,
+}
+
+impl TempoMap {
+    pub fn new(sample_rate: f64, bpm: f64, max_segments: usize) -> Self {
+        assert!(max_segments > 0, "Tempo map needs at least one segment");
+        assert!(bpm > 0.0, "Tempo must be positive");
+
+        let mut segments = Vec::with_capacity(max_segments);
+        segments.push(TempoSegment {
+            frame: 0,
+            beat: 0.0,
+            bpm,
+        });
+
+        TempoMap {
+            sample_rate,
+            segments,
+            capacity: max_segments,
+        }
+    }
+
+    // Changes the tempo from `frame` onwards, until the next tempo change.
+    pub fn set_tempo(&mut self, frame: u64, bpm: f64) -> Result<(), TempoMapFull> {
+        assert!(bpm > 0.0, "Tempo must be positive");
+
+        let index = match self.segments.binary_search_by_key(&frame, |s| s.frame) {
+            Ok(index) => {
+                self.segments[index].bpm = bpm;
+                index
+            }
+            Err(index) => {
+                if self.segments.len() == self.capacity {
+                    return Err(TempoMapFull);
+                }
+                self.segments.insert(
+                    index,
+                    TempoSegment {
+                        frame,
+                        beat: 0.0,
+                        bpm,
+                    },
+                );
+                index
+            }
+        };
+
+        self.rebeat(index);
+        Ok(())
+    }
+
+    // Removes the tempo change at exactly `frame`. The initial tempo at
+    // frame 0 can be changed but not removed.
+    pub fn remove_tempo(&mut self, frame: u64) -> bool {
+        match self.segments.binary_search_by_key(&frame, |s| s.frame) {
+            Ok(index) if index > 0 => {
+                self.segments.remove(index);
+                self.rebeat(index);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn beats_at(&self, frame: u64) -> f64 {
+        let segment = &self.segments[self.segment_index(frame)];
+        segment.beat + self.beats_in(frame - segment.frame, segment.bpm)
+    }
+
+    pub fn tempo_at(&self, frame: u64) -> f64 {
+        self.segments[self.segment_index(frame)].bpm
+    }
+
+    // First frame at or after `beat`.
+    pub fn frame_at(&self, beat: f64) -> u64 {
+        let index = match self
+            .segments
+            .binary_search_by(|s| s.beat.partial_cmp(&beat).unwrap())
+        {
+            Ok(index) => return self.segments[index].frame,
+            Err(index) => index.max(1) - 1,
+        };
+
+        let segment = &self.segments[index];
+        let seconds = (beat - segment.beat) * 60.0 / segment.bpm;
+        segment.frame + (seconds * self.sample_rate).ceil() as u64
+    }
+
+    pub fn segments(&self) -> &[TempoSegment] {
+        &self.segments
+    }
+
+    pub fn sample_rate(&self) -> f64 {
+        self.sample_rate
+    }
+
+    fn segment_index(&self, frame: u64) -> usize {
+        match self.segments.binary_search_by_key(&frame, |s| s.frame) {
+            Ok(index) => index,
+            Err(index) => index - 1,
+        }
+    }
+
+    fn beats_in(&self, frames: u64, bpm: f64) -> f64 {
+        frames as f64 / self.sample_rate * bpm / 60.0
+    }
+
+    fn rebeat(&mut self, from: usize) {
+        for i in from.max(1)..self.segments.len() {
+            let previous = self.segments[i - 1];
+            let frames = self.segments[i].frame - previous.frame;
+            self.segments[i].beat = previous.beat + self.beats_in(frames, previous.bpm);
+        }
+    }
+}
+
+// Clones keep the full segment capacity, and `clone_from` reuses it.
+impl Clone for TempoMap {
+    fn clone(&self) -> Self {
+        let mut segments = Vec::with_capacity(self.capacity);
+        segments.extend_from_slice(&self.segments);
+
+        TempoMap {
+            sample_rate: self.sample_rate,
+            segments,
+            capacity: self.capacity,
+        }
+    }
+
+    fn clone_from(&mut self, source: &Self) {
+        self.sample_rate = source.sample_rate;
+        self.capacity = source.capacity;
+        self.segments.clear();
+        self.segments.extend_from_slice(&source.segments);
+    }
+}
+
+impl TempoMapWriter {
+    pub fn publish(&mut self, map: &TempoMap) {
+        self.writer.get_mut().clone_from(map);
+    }
+
+    pub fn published(&self) -> &TempoMap {
+        self.writer.last_written()
+    }
+}
+
+impl TempoMapReader {
+    pub fn read(&mut self) -> &TempoMap {
+        self.reader.read()
+    }
+
+    pub fn beats_at(&mut self, frame: u64) -> f64 {
+        self.reader.read().beats_at(frame)
+    }
+}
+
+pub fn tempo_map(initial: TempoMap) -> (TempoMapWriter, TempoMapReader) {
+    let (writer, reader) = triple_buffer::triple_buffer(initial);
+    (TempoMapWriter { writer }, TempoMapReader { reader })
+}
+
+impl fmt::Display for TempoMapFull {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "tempo map has no free segments")
+    }
+}
+
+impl error::Error for TempoMapFull {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn constant_tempo() {
+        let map = TempoMap::new(48000.0, 120.0, 4);
+        assert_eq!(map.beats_at(0), 0.0);
+        assert_eq!(map.beats_at(24000), 1.0);
+        assert_eq!(map.beats_at(96000), 4.0);
+        assert_eq!(map.frame_at(2.0), 48000);
+    }
+
+    #[test]
+    fn tempo_changes() {
+        let mut map = TempoMap::new(48000.0, 120.0, 4);
+        map.set_tempo(48000, 60.0).unwrap();
+        map.set_tempo(96000, 240.0).unwrap();
+
+        assert_eq!(map.beats_at(48000), 2.0);
+        assert_eq!(map.beats_at(72000), 2.5);
+        assert_eq!(map.beats_at(96000), 3.0);
+        assert_eq!(map.beats_at(108000), 4.0);
+        assert_eq!(map.tempo_at(100000), 240.0);
+
+        assert_eq!(map.frame_at(2.5), 72000);
+        assert_eq!(map.frame_at(3.0), 96000);
+        assert_eq!(map.frame_at(4.0), 108000);
+
+        // Editing an earlier segment moves later beats.
+        map.set_tempo(0, 60.0).unwrap();
+        assert_eq!(map.beats_at(96000), 2.0);
+
+        assert!(map.remove_tempo(48000));
+        assert!(!map.remove_tempo(0));
+        assert_eq!(map.segments().len(), 2);
+        assert_eq!(map.beats_at(96000), 2.0);
+    }
+
+    #[test]
+    fn full() {
+        let mut map = TempoMap::new(48000.0, 120.0, 2);
+        map.set_tempo(100, 60.0).unwrap();
+        assert_eq!(map.set_tempo(200, 90.0), Err(TempoMapFull));
+        assert_eq!(map.set_tempo(100, 90.0), Ok(()));
+    }
+
+    #[test]
+    fn publish_keeps_capacity() {
+        let mut map = TempoMap::new(48000.0, 120.0, 8);
+        let (mut writer, mut reader) = tempo_map(map.clone());
+        assert_eq!(reader.beats_at(24000), 1.0);
+
+        map.set_tempo(0, 60.0).unwrap();
+        writer.publish(&map);
+        assert_eq!(writer.published().tempo_at(0), 60.0);
+        assert_eq!(reader.beats_at(24000), 0.5);
+        assert_eq!(reader.read().segments.capacity(), 8);
+    }
+}
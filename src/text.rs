@@ -0,0 +1,210 @@
+use std::fmt;
+use std::ops::Deref;
+use std::str;
+
+use crate::spsc::{self, TrySendError};
+
+// Inline, fixed-capacity UTF-8 string. Writing through `fmt::Write` never
+// allocates; text that doesn't fit is truncated at a character boundary.
+#[derive(Clone, Copy)]
+pub struct FixedString<const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> FixedString<N> {
+    pub const fn new() -> Self {
+        FixedString {
+            bytes: [0; N],
+            len: 0,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        unsafe { str::from_utf8_unchecked(&self.bytes[..self.len]) }
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    // Appends as much of `s` as fits. Returns false if anything was cut off.
+    pub fn push_str(&mut self, s: &str) -> bool {
+        let available = N - self.len;
+        let mut take = s.len().min(available);
+        while !s.is_char_boundary(take) {
+            take -= 1;
+        }
+
+        self.bytes[self.len..self.len + take].copy_from_slice(&s.as_bytes()[..take]);
+        self.len += take;
+
+        take == s.len()
+    }
+}
+
+impl<const N: usize> Default for FixedString<N> {
+    fn default() -> Self {
+        FixedString::new()
+    }
+}
+
+impl<const N: usize> fmt::Write for FixedString<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_str(s);
+        Ok(())
+    }
+}
+
+impl<const N: usize> Deref for FixedString<N> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<const N: usize> PartialEq for FixedString<N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl<const N: usize> PartialEq<str> for FixedString<N> {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl<'a, const N: usize> PartialEq<&'a str> for FixedString<N> {
+    fn eq(&self, other: &&'a str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl<const N: usize> Eq for FixedString<N> {}
+
+impl<const N: usize> fmt::Debug for FixedString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl<const N: usize> fmt::Display for FixedString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// RT side of a status line ring. Supports `write!(sender, ...)`, which
+// formats straight into a stack slot and queues it; the result is `Err` only
+// when the ring is full and the line was dropped.
+pub struct TextSender<const N: usize> {
+    sender: spsc::Sender<FixedString<N>>,
+}
+
+// UI side of a status line ring.
+pub struct TextReceiver<const N: usize> {
+    receiver: spsc::Receiver<FixedString<N>>,
+}
+
+impl<const N: usize> TextSender<N> {
+    pub fn write_fmt(&mut self, args: fmt::Arguments) -> fmt::Result {
+        let mut line = FixedString::new();
+        fmt::Write::write_fmt(&mut line, args)?;
+        self.try_send(line).map_err(|_| fmt::Error)
+    }
+
+    pub fn try_send(&mut self, line: FixedString<N>) -> Result<(), FixedString<N>> {
+        self.sender.try_send(line).map_err(TrySendError::into_inner)
+    }
+
+    pub fn try_send_str(&mut self, s: &str) -> bool {
+        let mut line = FixedString::new();
+        line.push_str(s);
+        self.try_send(line).is_ok()
+    }
+}
+
+impl<const N: usize> TextReceiver<N> {
+    pub fn try_recv(&mut self) -> Option<FixedString<N>> {
+        self.receiver.try_recv().ok()
+    }
+
+    // Drains the ring, returning only the most recent line.
+    pub fn latest(&mut self) -> Option<FixedString<N>> {
+        let mut latest = None;
+        while let Ok(line) = self.receiver.try_recv() {
+            latest = Some(line);
+        }
+        latest
+    }
+}
+
+pub fn channel<const N: usize>(slots: usize) -> (TextSender<N>, TextReceiver<N>) {
+    let (sender, receiver) = spsc::channel(slots);
+    (TextSender { sender }, TextReceiver { receiver })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::fmt::Write;
+
+    #[test]
+    fn fixed_string_write() {
+        let mut s = FixedString::<16>::new();
+        write!(s, "cpu {}%", 42).unwrap();
+        assert_eq!(s, "cpu 42%");
+        assert_eq!(s.len(), 7);
+    }
+
+    #[test]
+    fn fixed_string_const() {
+        const EMPTY: FixedString<8> = FixedString::new();
+        assert_eq!(EMPTY, "");
+        assert_eq!(EMPTY.capacity(), 8);
+    }
+
+    #[test]
+    fn fixed_string_truncates() {
+        let mut s = FixedString::<4>::new();
+        assert!(!s.push_str("abcdef"));
+        assert_eq!(s, "abcd");
+        assert!(!s.push_str("x"));
+    }
+
+    #[test]
+    fn fixed_string_truncates_on_char_boundary() {
+        let mut s = FixedString::<4>::new();
+        s.push_str("ab");
+        assert!(!s.push_str("åå"));
+        assert_eq!(s, "abå");
+    }
+
+    #[test]
+    fn ring() {
+        let (mut send, mut recv) = channel::<32>(2);
+        write!(send, "block {}", 1).unwrap();
+        write!(send, "block {}", 2).unwrap();
+        assert!(write!(send, "block {}", 3).is_err());
+
+        assert_eq!(recv.try_recv().unwrap(), "block 1");
+        assert_eq!(recv.try_recv().unwrap(), "block 2");
+        assert_eq!(recv.try_recv(), None);
+    }
+
+    #[test]
+    fn latest() {
+        let (mut send, mut recv) = channel::<8>(4);
+        assert!(send.try_send_str("one"));
+        assert!(send.try_send_str("two"));
+        assert_eq!(recv.latest().unwrap(), "two");
+        assert_eq!(recv.latest(), None);
+    }
+}
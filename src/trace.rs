@@ -0,0 +1,625 @@
+use std::cell::RefCell;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
+use crate::dump::{DumpKind, DumpWriter, RingState};
+use crate::spsc;
+
+// Scoped timing markers for RT code.
+//
+// Each thread that wants to be traced registers itself once, off the hot
+// path, which allocates a private event ring. `rt_scope!` guards then push
+// enter/exit events into that ring without locking or allocating; threads
+// that never registered pay for a thread-local lookup and nothing else. The
+// control thread drains all rings through the owning `Collector` and merges
+// them into a single timeline.
+
+#[macro_export]
+macro_rules! rt_scope {
+    ($name:expr) => {
+        let _rt_scope_guard = $crate::trace::ScopeGuard::enter($name);
+    };
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventKind {
+    Enter,
+    Exit,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Event {
+    pub kind: EventKind,
+    pub name: &'static str,
+    pub timestamp_ns: u64,
+    pub depth: u16,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Record {
+    pub thread: u32,
+    pub event: Event,
+}
+
+struct LocalTrace {
+    sender: spsc::Sender<Event>,
+    depth: u16,
+}
+
+struct Source {
+    thread: u32,
+    name: String,
+    receiver: spsc::Receiver<Event>,
+}
+
+thread_local! {
+    static LOCAL: RefCell<Option<LocalTrace>> = const { RefCell::new(None) };
+}
+
+static NEXT_THREAD: AtomicU32 = AtomicU32::new(1);
+static EPOCH: OnceLock<Instant> = OnceLock::new();
+
+fn now_ns() -> u64 {
+    let epoch = EPOCH.get_or_init(Instant::now);
+    Instant::now().saturating_duration_since(*epoch).as_nanos() as u64
+}
+
+pub struct ScopeGuard {
+    name: &'static str,
+}
+
+impl ScopeGuard {
+    pub fn enter(name: &'static str) -> Self {
+        record(EventKind::Enter, name);
+        ScopeGuard { name }
+    }
+}
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        record(EventKind::Exit, self.name);
+    }
+}
+
+fn record(kind: EventKind, name: &'static str) {
+    let _ = LOCAL.try_with(|local| {
+        let mut local = local.borrow_mut();
+        let local = match local.as_mut() {
+            Some(local) => local,
+            None => return,
+        };
+
+        if kind == EventKind::Exit {
+            local.depth = local.depth.saturating_sub(1);
+        }
+
+        let event = Event {
+            kind,
+            name,
+            timestamp_ns: now_ns(),
+            depth: local.depth,
+        };
+
+        // A full ring drops the event; the drained trace will show the
+        // scope as unbalanced rather than blocking the RT thread.
+        let _ = local.sender.try_send(event);
+
+        if kind == EventKind::Enter {
+            local.depth = local.depth.saturating_add(1);
+        }
+    });
+}
+
+pub struct Collector {
+    sources: Mutex<Vec<Source>>,
+}
+
+impl Collector {
+    pub const fn new() -> Self {
+        Collector {
+            sources: Mutex::new(Vec::new()),
+        }
+    }
+
+    // Starts tracing the calling thread into a ring of `capacity` events,
+    // replacing any previous registration of this thread. Allocates; call
+    // during thread setup rather than from the RT callback.
+    pub fn register_current_thread(&self, name: &str, capacity: usize) -> u32 {
+        let thread = NEXT_THREAD.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = spsc::channel(capacity);
+
+        now_ns();
+        LOCAL.with(|local| {
+            *local.borrow_mut() = Some(LocalTrace { sender, depth: 0 });
+        });
+
+        self.sources.lock().unwrap().push(Source {
+            thread,
+            name: name.to_owned(),
+            receiver,
+        });
+
+        thread
+    }
+
+    // Drains every registered ring and merges the events by timestamp.
+    // Threads that have exited are forgotten once their ring is empty.
+    pub fn drain(&self) -> Trace {
+        let mut sources = self.sources.lock().unwrap();
+        let mut trace = Trace {
+            threads: Vec::with_capacity(sources.len()),
+            records: Vec::new(),
+        };
+
+        for source in sources.iter_mut() {
+            trace.threads.push((source.thread, source.name.clone()));
+            while let Ok(event) = source.receiver.try_recv() {
+                trace.records.push(Record {
+                    thread: source.thread,
+                    event,
+                });
+            }
+        }
+
+        sources.retain(|source| source.receiver.is_sender_active());
+        trace.records.sort_by_key(|r| r.event.timestamp_ns);
+
+        trace
+    }
+
+    // Thread id, name and queue of every ring registered so far.
+    pub(crate) fn crash_views(&self) -> Vec<(u32, String, spsc::CrashView<Event>)> {
+        let sources = self.sources.lock().unwrap();
+        sources
+            .iter()
+            .map(|source| {
+                (
+                    source.thread,
+                    source.name.clone(),
+                    source.receiver.crash_view(),
+                )
+            })
+            .collect()
+    }
+
+    // Writes the events still queued in every ring to `path` without
+    // draining them; see `dump::read_dump`. Ring ids are thread ids.
+    pub fn dump_to<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut sources = self.sources.lock().unwrap();
+        let mut dump = DumpWriter::create(path, DumpKind::Trace, sources.len() as u32)?;
+
+        for source in sources.iter_mut() {
+            let (capacity, read_index, write_index) = source.receiver.ring_state();
+            let mut events = Vec::new();
+            source.receiver.peek_each(|e| events.push(*e));
+
+            dump.ring(
+                &RingState {
+                    id: source.thread,
+                    name: source.name.clone(),
+                    capacity,
+                    read_index,
+                    write_index,
+                    dropped: 0,
+                },
+                events.len() as u32,
+            )?;
+            for event in &events {
+                dump.trace_record(event.kind, event.depth, event.timestamp_ns, event.name)?;
+            }
+        }
+
+        dump.finish()
+    }
+}
+
+impl Default for Collector {
+    fn default() -> Self {
+        Collector::new()
+    }
+}
+
+pub fn global() -> &'static Collector {
+    static GLOBAL: Collector = Collector::new();
+    &GLOBAL
+}
+
+pub fn register_current_thread(name: &str, capacity: usize) -> u32 {
+    global().register_current_thread(name, capacity)
+}
+
+pub struct Trace {
+    pub threads: Vec<(u32, String)>,
+    pub records: Vec<Record>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Scope {
+    pub thread: u32,
+    pub name: &'static str,
+    pub start_ns: u64,
+    pub duration_ns: u64,
+    pub depth: u16,
+}
+
+impl Trace {
+    // Pairs enter/exit events into completed scopes, ordered by start time.
+    // Scopes still open when the trace was drained, or whose events were
+    // dropped by a full ring, are left out.
+    pub fn scopes(&self) -> Vec<Scope> {
+        let mut open: Vec<(u32, Event)> = Vec::new();
+        let mut scopes = Vec::new();
+
+        for record in &self.records {
+            match record.event.kind {
+                EventKind::Enter => open.push((record.thread, record.event)),
+                EventKind::Exit => {
+                    let matching = open.iter().rposition(|(thread, event)| {
+                        *thread == record.thread
+                            && event.name == record.event.name
+                            && event.depth == record.event.depth
+                    });
+
+                    if let Some(index) = matching {
+                        let (thread, enter) = open.remove(index);
+                        scopes.push(Scope {
+                            thread,
+                            name: enter.name,
+                            start_ns: enter.timestamp_ns,
+                            duration_ns: record.event.timestamp_ns - enter.timestamp_ns,
+                            depth: enter.depth,
+                        });
+                    }
+                }
+            }
+        }
+
+        scopes.sort_by_key(|s| s.start_ns);
+        scopes
+    }
+
+    pub fn write_chrome_json<W: io::Write>(&self, out: &mut W) -> io::Result<()> {
+        ChromeExporter::new(out).export(self)
+    }
+}
+
+pub trait Exporter {
+    fn export(&mut self, trace: &Trace) -> io::Result<()>;
+}
+
+// Writes the Trace Event Format understood by chrome://tracing and Perfetto.
+// By default every enter/exit becomes a begin/end event; complete events
+// halve the file size and are what Tracy's `import-chrome` tool expects.
+pub struct ChromeExporter<W> {
+    out: W,
+    complete_events: bool,
+}
+
+impl<W: io::Write> ChromeExporter<W> {
+    pub fn new(out: W) -> Self {
+        ChromeExporter {
+            out,
+            complete_events: false,
+        }
+    }
+
+    pub fn complete_events(mut self) -> Self {
+        self.complete_events = true;
+        self
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    fn write_separator(&mut self, first: &mut bool) -> io::Result<()> {
+        if !*first {
+            self.out.write_all(b",")?;
+        }
+        *first = false;
+        Ok(())
+    }
+}
+
+impl<W: io::Write> Exporter for ChromeExporter<W> {
+    fn export(&mut self, trace: &Trace) -> io::Result<()> {
+        self.out.write_all(b"{\"traceEvents\":[")?;
+
+        let mut first = true;
+        for (thread, name) in &trace.threads {
+            self.write_separator(&mut first)?;
+
+            write!(
+                self.out,
+                "{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":{},\"args\":{{\"name\":",
+                thread
+            )?;
+            write_json_string(&mut self.out, name)?;
+            self.out.write_all(b"}}")?;
+        }
+
+        if self.complete_events {
+            for scope in trace.scopes() {
+                self.write_separator(&mut first)?;
+
+                self.out.write_all(b"{\"name\":")?;
+                write_json_string(&mut self.out, scope.name)?;
+                write!(
+                    self.out,
+                    ",\"ph\":\"X\",\"ts\":{}.{:03},\"dur\":{}.{:03},\"pid\":1,\"tid\":{}}}",
+                    scope.start_ns / 1000,
+                    scope.start_ns % 1000,
+                    scope.duration_ns / 1000,
+                    scope.duration_ns % 1000,
+                    scope.thread
+                )?;
+            }
+        } else {
+            for record in &trace.records {
+                self.write_separator(&mut first)?;
+
+                let phase = match record.event.kind {
+                    EventKind::Enter => "B",
+                    EventKind::Exit => "E",
+                };
+
+                self.out.write_all(b"{\"name\":")?;
+                write_json_string(&mut self.out, record.event.name)?;
+                write!(
+                    self.out,
+                    ",\"ph\":\"{}\",\"ts\":{}.{:03},\"pid\":1,\"tid\":{}}}",
+                    phase,
+                    record.event.timestamp_ns / 1000,
+                    record.event.timestamp_ns % 1000,
+                    record.thread
+                )?;
+            }
+        }
+
+        self.out.write_all(b"]}")?;
+        self.out.flush()
+    }
+}
+
+fn write_json_string<W: io::Write>(out: &mut W, s: &str) -> io::Result<()> {
+    out.write_all(b"\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => out.write_all(b"\\\"")?,
+            '\\' => out.write_all(b"\\\\")?,
+            '\n' => out.write_all(b"\\n")?,
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => write!(out, "{}", c)?,
+        }
+    }
+    out.write_all(b"\"")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::thread;
+
+    #[test]
+    fn unregistered_thread_is_noop() {
+        let collector = Collector::new();
+        thread::spawn(|| {
+            rt_scope!("ignored");
+        })
+        .join()
+        .unwrap();
+
+        assert!(collector.drain().records.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dump() {
+        use crate::dump::{read_dump, DumpEntry};
+        use crate::shm::temp_path;
+
+        let collector = Collector::new();
+        let thread = collector.register_current_thread("audio", 16);
+        {
+            rt_scope!("callback");
+        }
+
+        let path = temp_path("trace-dump");
+        collector.dump_to(&path).unwrap();
+        let dump = read_dump(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(dump.kind, DumpKind::Trace);
+        assert_eq!(dump.rings.len(), 1);
+        assert_eq!(dump.rings[0].id, thread);
+        assert_eq!(dump.rings[0].name, "audio");
+        let events: Vec<_> = dump.rings[0]
+            .entries
+            .iter()
+            .map(|e| match e {
+                DumpEntry::Trace { kind, name, .. } => (*kind, name.as_str()),
+                _ => panic!("unexpected entry"),
+            })
+            .collect();
+        assert_eq!(
+            events,
+            [
+                (EventKind::Enter, "callback"),
+                (EventKind::Exit, "callback")
+            ]
+        );
+
+        assert_eq!(collector.drain().records.len(), 2);
+    }
+
+    #[test]
+    fn nested_scopes() {
+        let collector = Collector::new();
+        let thread = collector.register_current_thread("audio", 16);
+
+        {
+            rt_scope!("callback");
+            {
+                rt_scope!("mixer");
+            }
+        }
+
+        let trace = collector.drain();
+        assert_eq!(trace.threads, vec![(thread, "audio".to_owned())]);
+
+        let events: Vec<_> = trace
+            .records
+            .iter()
+            .map(|r| (r.event.kind, r.event.name, r.event.depth))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                (EventKind::Enter, "callback", 0),
+                (EventKind::Enter, "mixer", 1),
+                (EventKind::Exit, "mixer", 1),
+                (EventKind::Exit, "callback", 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn full_ring_drops_events() {
+        let collector = Collector::new();
+        collector.register_current_thread("audio", 2);
+
+        {
+            rt_scope!("a");
+            rt_scope!("b");
+        }
+
+        assert_eq!(collector.drain().records.len(), 2);
+    }
+
+    #[test]
+    fn merges_threads_and_forgets_exited() {
+        let collector = std::sync::Arc::new(Collector::new());
+
+        let handles: Vec<_> = (0..2)
+            .map(|i| {
+                let collector = collector.clone();
+                thread::spawn(move || {
+                    collector.register_current_thread(&format!("worker {}", i), 8);
+                    rt_scope!("work");
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let trace = collector.drain();
+        assert_eq!(trace.threads.len(), 2);
+        assert_eq!(trace.records.len(), 4);
+        assert!(trace
+            .records
+            .windows(2)
+            .all(|w| w[0].event.timestamp_ns <= w[1].event.timestamp_ns));
+
+        assert!(collector.drain().threads.is_empty());
+    }
+
+    fn event(thread: u32, kind: EventKind, name: &'static str, ns: u64, depth: u16) -> Record {
+        Record {
+            thread,
+            event: Event {
+                kind,
+                name,
+                timestamp_ns: ns,
+                depth,
+            },
+        }
+    }
+
+    #[test]
+    fn scopes() {
+        let trace = Trace {
+            threads: vec![],
+            records: vec![
+                event(1, EventKind::Enter, "callback", 100, 0),
+                event(2, EventKind::Enter, "disk", 150, 0),
+                event(1, EventKind::Enter, "mixer", 200, 1),
+                event(1, EventKind::Exit, "mixer", 300, 1),
+                event(1, EventKind::Exit, "callback", 400, 0),
+                event(1, EventKind::Enter, "callback", 500, 0),
+            ],
+        };
+
+        let scopes: Vec<_> = trace
+            .scopes()
+            .iter()
+            .map(|s| (s.thread, s.name, s.start_ns, s.duration_ns, s.depth))
+            .collect();
+        assert_eq!(
+            scopes,
+            vec![(1, "callback", 100, 300, 0), (1, "mixer", 200, 100, 1)]
+        );
+    }
+
+    #[test]
+    fn chrome_complete_events() {
+        let trace = Trace {
+            threads: vec![],
+            records: vec![
+                event(3, EventKind::Enter, "mix", 1_500, 0),
+                event(3, EventKind::Exit, "mix", 12_250, 0),
+            ],
+        };
+
+        let mut exporter = ChromeExporter::new(Vec::new()).complete_events();
+        exporter.export(&trace).unwrap();
+
+        assert_eq!(
+            String::from_utf8(exporter.into_inner()).unwrap(),
+            "{\"traceEvents\":[\
+             {\"name\":\"mix\",\"ph\":\"X\",\"ts\":1.500,\"dur\":10.750,\"pid\":1,\"tid\":3}]}"
+        );
+    }
+
+    #[test]
+    fn chrome_json() {
+        let trace = Trace {
+            threads: vec![(3, "audio \"main\"".to_owned())],
+            records: vec![
+                Record {
+                    thread: 3,
+                    event: Event {
+                        kind: EventKind::Enter,
+                        name: "mix",
+                        timestamp_ns: 1_500,
+                        depth: 0,
+                    },
+                },
+                Record {
+                    thread: 3,
+                    event: Event {
+                        kind: EventKind::Exit,
+                        name: "mix",
+                        timestamp_ns: 12_250,
+                        depth: 0,
+                    },
+                },
+            ],
+        };
+
+        let mut out = Vec::new();
+        trace.write_chrome_json(&mut out).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"traceEvents\":[\
+             {\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":3,\"args\":{\"name\":\"audio \\\"main\\\"\"}},\
+             {\"name\":\"mix\",\"ph\":\"B\",\"ts\":1.500,\"pid\":1,\"tid\":3},\
+             {\"name\":\"mix\",\"ph\":\"E\",\"ts\":12.250,\"pid\":1,\"tid\":3}]}"
+        );
+    }
+}
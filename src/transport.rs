@@ -0,0 +1,346 @@
+use std::sync::Arc;
+
+use crate::frame;
+use crate::seqlock::SeqLock;
+use crate::spsc;
+
+// Play/stop/record/loop state shared between the engine and everyone else.
+//
+// The control side sends `Command`s, optionally scheduled at an engine frame
+// (the RT thread's running frame counter, e.g. a `FramePosition`). The RT
+// side applies them in `run_block`, which splits each block into segments at
+// command and loop boundaries so transitions land on the exact frame, and
+// publishes a `Snapshot` through a seqlock at the end of every block.
+// Readers extrapolate the timeline position for any engine frame from the
+// snapshot with `position_at`.
+//
+// Scheduled commands must be sent in frame order; a command scheduled in the
+// past applies at the start of the next block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlayState {
+    Stopped,
+    Playing,
+    Recording,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    Play,
+    Stop,
+    Record,
+    Locate(u64),
+    // Loop region as a half-open range of timeline frames.
+    SetLoop(Option<(u64, u64)>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    pub state: PlayState,
+    // Timeline position at `engine_frame`.
+    pub position: u64,
+    pub engine_frame: u64,
+    pub loop_range: Option<(u64, u64)>,
+}
+
+// A stretch of a block with constant transport state. `position` is the
+// timeline position of its first frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Segment {
+    pub offset: u32,
+    pub len: u32,
+    pub state: PlayState,
+    pub position: u64,
+}
+
+pub struct TransportControl {
+    commands: spsc::Sender<(Command, Option<u64>)>,
+    snapshot: Arc<SeqLock<Snapshot>>,
+}
+
+pub struct Transport {
+    commands: spsc::Receiver<(Command, Option<u64>)>,
+    snapshot: Arc<SeqLock<Snapshot>>,
+    pending: Option<(Command, Option<u64>)>,
+    state: Snapshot,
+}
+
+impl PlayState {
+    pub fn is_rolling(self) -> bool {
+        self != PlayState::Stopped
+    }
+}
+
+impl Snapshot {
+    pub fn position_at(&self, engine_frame: u64) -> u64 {
+        if !self.state.is_rolling() {
+            return self.position;
+        }
+
+        let delta = frame::frames_between(self.engine_frame, engine_frame);
+        let position = if delta < 0 {
+            self.position.saturating_sub(delta.unsigned_abs())
+        } else {
+            self.position + delta as u64
+        };
+
+        match self.loop_range {
+            Some((start, end)) if self.position < end && position >= end => {
+                start + (position - end) % (end - start)
+            }
+            _ => position,
+        }
+    }
+}
+
+impl TransportControl {
+    pub fn send(&mut self, command: Command) -> Result<(), Command> {
+        self.commands
+            .try_send((command, None))
+            .map_err(|e| e.into_inner().0)
+    }
+
+    pub fn send_at(&mut self, command: Command, engine_frame: u64) -> Result<(), Command> {
+        self.commands
+            .try_send((command, Some(engine_frame)))
+            .map_err(|e| e.into_inner().0)
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        self.snapshot.read()
+    }
+
+    pub fn position_at(&self, engine_frame: u64) -> u64 {
+        self.snapshot().position_at(engine_frame)
+    }
+}
+
+impl Transport {
+    // Processes one block starting at `engine_frame`, calling `segment` for
+    // each stretch with constant transport state, in order.
+    pub fn run_block(&mut self, engine_frame: u64, len: u32, mut segment: impl FnMut(Segment)) {
+        let end = engine_frame.wrapping_add(u64::from(len));
+        let mut cursor = engine_frame;
+
+        loop {
+            let mut next = end;
+            while let Some((command, at)) = self.peek() {
+                match at {
+                    Some(at) if frame::is_before(cursor, at) => {
+                        if frame::is_before(at, next) {
+                            next = at;
+                        }
+                        break;
+                    }
+                    _ => {
+                        self.pending = None;
+                        self.apply(command);
+                    }
+                }
+            }
+
+            let rolling = self.state.state.is_rolling();
+            if let Some((_, loop_end)) = self.state.loop_range {
+                if rolling && self.state.position < loop_end {
+                    let at = cursor.wrapping_add(loop_end - self.state.position);
+                    if frame::is_before(at, next) {
+                        next = at;
+                    }
+                }
+            }
+
+            if frame::is_before(cursor, next) {
+                segment(Segment {
+                    offset: cursor.wrapping_sub(engine_frame) as u32,
+                    len: next.wrapping_sub(cursor) as u32,
+                    state: self.state.state,
+                    position: self.state.position,
+                });
+
+                if rolling {
+                    self.state.position += next.wrapping_sub(cursor);
+                }
+            }
+
+            if let Some((loop_start, loop_end)) = self.state.loop_range {
+                if rolling && self.state.position == loop_end {
+                    self.state.position = loop_start;
+                }
+            }
+
+            cursor = next;
+            if cursor == end {
+                break;
+            }
+        }
+
+        self.state.engine_frame = end;
+        self.snapshot.write(self.state);
+    }
+
+    pub fn state(&self) -> &Snapshot {
+        &self.state
+    }
+
+    fn peek(&mut self) -> Option<(Command, Option<u64>)> {
+        if self.pending.is_none() {
+            self.pending = self.commands.try_recv().ok();
+        }
+        self.pending
+    }
+
+    fn apply(&mut self, command: Command) {
+        match command {
+            Command::Play => self.state.state = PlayState::Playing,
+            Command::Stop => self.state.state = PlayState::Stopped,
+            Command::Record => self.state.state = PlayState::Recording,
+            Command::Locate(position) => self.state.position = position,
+            Command::SetLoop(Some((start, end))) if start >= end => {}
+            Command::SetLoop(loop_range) => self.state.loop_range = loop_range,
+        }
+    }
+}
+
+pub fn transport(command_capacity: usize) -> (TransportControl, Transport) {
+    let (sender, receiver) = spsc::channel(command_capacity);
+    let state = Snapshot {
+        state: PlayState::Stopped,
+        position: 0,
+        engine_frame: 0,
+        loop_range: None,
+    };
+    let snapshot = Arc::new(SeqLock::new(state));
+
+    let control = TransportControl {
+        commands: sender,
+        snapshot: snapshot.clone(),
+    };
+    let transport = Transport {
+        commands: receiver,
+        snapshot,
+        pending: None,
+        state,
+    };
+
+    (control, transport)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn segments(transport: &mut Transport, engine_frame: u64, len: u32) -> Vec<Segment> {
+        let mut segments = Vec::new();
+        transport.run_block(engine_frame, len, |s| segments.push(s));
+        segments
+    }
+
+    fn segment(offset: u32, len: u32, state: PlayState, position: u64) -> Segment {
+        Segment {
+            offset,
+            len,
+            state,
+            position,
+        }
+    }
+
+    #[test]
+    fn immediate_commands() {
+        let (mut control, mut transport) = transport(8);
+        assert_eq!(
+            segments(&mut transport, 0, 64),
+            vec![segment(0, 64, PlayState::Stopped, 0)]
+        );
+
+        control.send(Command::Play).unwrap();
+        assert_eq!(
+            segments(&mut transport, 64, 64),
+            vec![segment(0, 64, PlayState::Playing, 0)]
+        );
+        assert_eq!(control.snapshot().position, 64);
+        assert_eq!(control.snapshot().engine_frame, 128);
+    }
+
+    #[test]
+    fn sample_accurate_commands() {
+        let (mut control, mut transport) = transport(8);
+        control.send_at(Command::Play, 10).unwrap();
+        control.send_at(Command::Record, 40).unwrap();
+        control.send_at(Command::Stop, 100).unwrap();
+
+        assert_eq!(
+            segments(&mut transport, 0, 64),
+            vec![
+                segment(0, 10, PlayState::Stopped, 0),
+                segment(10, 30, PlayState::Playing, 0),
+                segment(40, 24, PlayState::Recording, 30),
+            ]
+        );
+        assert_eq!(
+            segments(&mut transport, 64, 64),
+            vec![
+                segment(0, 36, PlayState::Recording, 54),
+                segment(36, 28, PlayState::Stopped, 90),
+            ]
+        );
+    }
+
+    #[test]
+    fn loop_wraps_within_block() {
+        let (mut control, mut transport) = transport(8);
+        control.send(Command::SetLoop(Some((100, 130)))).unwrap();
+        control.send(Command::Locate(110)).unwrap();
+        control.send(Command::Play).unwrap();
+
+        assert_eq!(
+            segments(&mut transport, 0, 64),
+            vec![
+                segment(0, 20, PlayState::Playing, 110),
+                segment(20, 30, PlayState::Playing, 100),
+                segment(50, 14, PlayState::Playing, 100),
+            ]
+        );
+        assert_eq!(transport.state().position, 114);
+    }
+
+    #[test]
+    fn block_across_frame_wrap() {
+        let (mut control, mut transport) = transport(8);
+        let start = u64::MAX - 31;
+        control.send(Command::SetLoop(Some((100, 130)))).unwrap();
+        control.send(Command::Locate(110)).unwrap();
+        control.send_at(Command::Play, start + 8).unwrap();
+        control.send_at(Command::Stop, 20).unwrap();
+
+        assert_eq!(
+            segments(&mut transport, start, 64),
+            vec![
+                segment(0, 8, PlayState::Stopped, 110),
+                segment(8, 20, PlayState::Playing, 110),
+                segment(28, 24, PlayState::Playing, 100),
+                segment(52, 12, PlayState::Stopped, 124),
+            ]
+        );
+        assert_eq!(control.snapshot().engine_frame, 32);
+    }
+
+    #[test]
+    fn effective_position() {
+        let (mut control, mut transport) = transport(8);
+        control.send(Command::SetLoop(Some((0, 100)))).unwrap();
+        control.send(Command::Locate(50)).unwrap();
+        control.send(Command::Play).unwrap();
+        transport.run_block(1000, 10, |_| {});
+
+        let snapshot = control.snapshot();
+        assert_eq!(snapshot.position, 60);
+        assert_eq!(snapshot.position_at(1010), 60);
+        assert_eq!(snapshot.position_at(1030), 80);
+        assert_eq!(snapshot.position_at(1050), 0);
+        assert_eq!(snapshot.position_at(1075), 25);
+        assert_eq!(snapshot.position_at(1005), 55);
+
+        control.send(Command::Stop).unwrap();
+        transport.run_block(1010, 10, |_| {});
+        assert_eq!(control.position_at(5000), 60);
+    }
+}
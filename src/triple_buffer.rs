@@ -1,16 +1,46 @@
-use std::cell::UnsafeCell;
-use std::mem::ManuallyDrop;
-use std::ops::{Deref, DerefMut};
-use std::ptr;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
-
-const INDEX_MASK: usize = 0b0011;
-const COMMIT_BIT: usize = 0b0100;
+use alloc::sync::Arc;
+use core::cell::UnsafeCell;
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "std")]
+use core::{fmt, time::Duration};
+#[cfg(feature = "std")]
+use std::error;
+#[cfg(feature = "std")]
+use std::sync::atomic::AtomicU64;
+#[cfg(all(feature = "std", not(feature = "sim")))]
+use std::time::Instant;
+
+use crate::poison::{PoisonFlag, Poisoned};
+use crate::role;
+#[cfg(all(debug_assertions, feature = "std"))]
+use crate::rtlog::Level;
+#[cfg(feature = "std")]
+use crate::rtlog::Logger;
+#[cfg(feature = "sim")]
+use crate::sim::Epoch as Instant;
+
+pub(crate) const INDEX_MASK: usize = 0b0011;
+pub(crate) const COMMIT_BIT: usize = 0b0100;
 
 struct Internal<T> {
     buffers: [UnsafeCell<ManuallyDrop<T>>; 3],
     committed: AtomicUsize,
+    poison: PoisonFlag,
+    // When each buffer was committed, in nanoseconds since `epoch`. The
+    // initial values count as committed at creation.
+    #[cfg(feature = "std")]
+    epoch: Instant,
+    #[cfg(feature = "std")]
+    committed_at: [AtomicU64; 3],
+    // The buffer committed last, whoever holds it now; see `Latest`.
+    #[cfg(feature = "std")]
+    published: AtomicUsize,
+    // Commits so far, for `ReadGuard`'s check.
+    #[cfg(all(debug_assertions, feature = "std"))]
+    commits: AtomicUsize,
 }
 
 unsafe impl<T> Sync for Internal<T> {}
@@ -19,6 +49,7 @@ unsafe impl<T> Send for Internal<T> {}
 pub struct Writer<T> {
     internal: Arc<Internal<T>>,
     write_index: usize,
+    published_index: usize,
 }
 
 pub struct WriteGuard<'a, T> {
@@ -29,6 +60,26 @@ pub struct WriteGuard<'a, T> {
 pub struct Reader<T> {
     internal: Arc<Internal<T>>,
     read_index: usize,
+    #[cfg(feature = "std")]
+    log: Option<Logger>,
+}
+
+// Returned by `Reader::read_fresh` when the latest value is too old.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Stale {
+    pub age: Duration,
+}
+
+// A snapshot from `Reader::read_guard`. The value it points at stays put
+// while the writer moves on, so holding it for long only means reading
+// stale data; in debug builds with `std`, dropping a guard that was held
+// across more than one commit warns through the reader's log, if it has
+// one.
+pub struct ReadGuard<'a, T> {
+    reader: &'a mut Reader<T>,
+    #[cfg(all(debug_assertions, feature = "std"))]
+    commits: usize,
 }
 
 impl<T> Writer<T> {
@@ -44,15 +95,20 @@ impl<T> Writer<T> {
             ManuallyDrop::drop(value_ptr);
             ptr::write(value_ptr, ManuallyDrop::new(value))
         }
+        #[cfg(feature = "std")]
+        self.internal.stamp(self.write_index);
 
         let last_committed = self
             .internal
             .committed
             .swap(self.write_index | COMMIT_BIT, Ordering::Release);
+        self.published_index = self.write_index;
         self.write_index = last_committed & INDEX_MASK;
+        #[cfg(all(debug_assertions, feature = "std"))]
+        self.internal.commits.fetch_add(1, Ordering::Relaxed);
     }
 
-    pub fn get_mut(&mut self) -> WriteGuard<T> {
+    pub fn get_mut(&mut self) -> WriteGuard<'_, T> {
         let value_ptr = unsafe {
             self.internal.buffers[self.write_index]
                 .get()
@@ -66,13 +122,42 @@ impl<T> Writer<T> {
         }
     }
 
+    // The most recently committed value. The slot it lives in is only ever
+    // read by the reader and is not handed back to the writer until the next
+    // commit, which the borrow on `self` rules out.
+    pub fn last_written(&self) -> &T {
+        unsafe {
+            self.internal.buffers[self.published_index]
+                .get()
+                .as_ref()
+                .unwrap()
+        }
+    }
+
+    pub fn poison_flag(&self) -> PoisonFlag {
+        self.internal.poison.clone()
+    }
+
+    pub fn check_poisoned(&self) -> Result<(), Poisoned> {
+        self.internal.poison.check()
+    }
+
     fn commit_write_guard<'a>(guard: &mut WriteGuard<'a, T>) {
+        #[cfg(feature = "std")]
+        guard.writer.internal.stamp(guard.writer.write_index);
         let last_committed = guard
             .writer
             .internal
             .committed
             .swap(guard.writer.write_index | COMMIT_BIT, Ordering::Release);
+        guard.writer.published_index = guard.writer.write_index;
         guard.writer.write_index = last_committed & INDEX_MASK;
+        #[cfg(all(debug_assertions, feature = "std"))]
+        guard
+            .writer
+            .internal
+            .commits
+            .fetch_add(1, Ordering::Relaxed);
     }
 }
 
@@ -97,6 +182,19 @@ impl<'a, T> Drop for WriteGuard<'a, T> {
 }
 
 impl<T> Reader<T> {
+    #[cfg(feature = "std")]
+    pub(crate) fn latest(&self) -> Latest<T> {
+        Latest {
+            internal: self.internal.clone(),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub fn with_log(mut self, log: Logger) -> Self {
+        self.log = Some(log);
+        self
+    }
+
     pub fn read(&mut self) -> &T {
         if self.internal.committed.load(Ordering::Relaxed) & COMMIT_BIT != 0 {
             let last_committed = self
@@ -107,6 +205,43 @@ impl<T> Reader<T> {
             self.read_index = last_committed & INDEX_MASK;
         }
 
+        self.current()
+    }
+
+    // Like `read`, but fails if the latest value was committed more than
+    // `max_age` ago, for consumers that must notice a dead or stalled
+    // writer instead of running on an old snapshot.
+    #[cfg(feature = "std")]
+    pub fn read_fresh(&mut self, max_age: Duration) -> Result<&T, Stale> {
+        self.read();
+        let internal = &self.internal;
+        let committed_at = internal.committed_at[self.read_index].load(Ordering::Relaxed);
+        let age = internal
+            .epoch
+            .elapsed()
+            .saturating_sub(Duration::from_nanos(committed_at));
+        if age > max_age {
+            return Err(Stale { age });
+        }
+        Ok(self.current())
+    }
+
+    // Like `read`, but the guard keeps track of how long it's held.
+    pub fn read_guard(&mut self) -> ReadGuard<'_, T> {
+        self.read();
+        ReadGuard {
+            #[cfg(all(debug_assertions, feature = "std"))]
+            commits: self.internal.commits.load(Ordering::Relaxed),
+            reader: self,
+        }
+    }
+
+    // The value the last `read` returned, without looking for a newer one.
+    pub fn last_read(&self) -> &T {
+        self.current()
+    }
+
+    fn current(&self) -> &T {
         unsafe {
             self.internal.buffers[self.read_index]
                 .get()
@@ -114,8 +249,86 @@ impl<T> Reader<T> {
                 .unwrap()
         }
     }
+
+    pub fn poison_flag(&self) -> PoisonFlag {
+        self.internal.poison.clone()
+    }
+
+    pub fn check_poisoned(&self) -> Result<(), Poisoned> {
+        self.internal.poison.check()
+    }
+}
+
+impl<T> Deref for ReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.reader.current()
+    }
+}
+
+impl<T> Drop for ReadGuard<'_, T> {
+    fn drop(&mut self) {
+        #[cfg(all(debug_assertions, feature = "std"))]
+        {
+            let commits = self
+                .reader
+                .internal
+                .commits
+                .load(Ordering::Relaxed)
+                .wrapping_sub(self.commits);
+            if let (true, Some(log)) = (commits > 1, &mut self.reader.log) {
+                crate::rtlog!(
+                    log,
+                    Level::Warn,
+                    "triple buffer read guard held across {} commits",
+                    commits
+                );
+            }
+        }
+    }
+}
+
+// The most recently committed value, read without swapping, for crash
+// dumps; see `crash::TripleBufferSource`.
+#[cfg(feature = "std")]
+pub(crate) struct Latest<T> {
+    internal: Arc<Internal<T>>,
+}
+
+#[cfg(feature = "std")]
+impl<T: Copy> Latest<T> {
+    // The writer publishes the index before the commit that hands the
+    // previous buffer back to itself, so the buffer read here is never the
+    // one being filled, unless the writer commits again and starts filling
+    // it between the load and the copy.
+    pub(crate) fn get(&self) -> T {
+        let index = self.internal.published.load(Ordering::Acquire);
+        unsafe { ptr::read_volatile(self.internal.buffers[index].get() as *const T) }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T> Internal<T> {
+    // Called by the writer before publishing `index`; the release swap
+    // that publishes it orders the stamp too.
+    fn stamp(&self, index: usize) {
+        let now = self.epoch.elapsed().as_nanos() as u64;
+        self.committed_at[index].store(now, Ordering::Relaxed);
+        self.published.store(index, Ordering::Release);
+    }
 }
 
+#[cfg(feature = "std")]
+impl fmt::Display for Stale {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "latest value is {}us old", self.age.as_micros())
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for Stale {}
+
 impl<T> Drop for Internal<T> {
     fn drop(&mut self) {
         for v in self.buffers.iter_mut() {
@@ -125,6 +338,7 @@ impl<T> Drop for Internal<T> {
 }
 
 pub fn triple_buffer_explicit<T>(initial_values: (T, T, T)) -> (Writer<T>, Reader<T>) {
+    role::assert_not_rt("triple_buffer");
     let internal = Arc::new(Internal {
         buffers: [
             UnsafeCell::new(ManuallyDrop::new(initial_values.0)),
@@ -132,15 +346,27 @@ pub fn triple_buffer_explicit<T>(initial_values: (T, T, T)) -> (Writer<T>, Reade
             UnsafeCell::new(ManuallyDrop::new(initial_values.2)),
         ],
         committed: AtomicUsize::new(1),
+        poison: PoisonFlag::new(),
+        #[cfg(feature = "std")]
+        epoch: Instant::now(),
+        #[cfg(feature = "std")]
+        committed_at: [AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0)],
+        #[cfg(feature = "std")]
+        published: AtomicUsize::new(1),
+        #[cfg(all(debug_assertions, feature = "std"))]
+        commits: AtomicUsize::new(0),
     });
 
     let writer = Writer {
         internal: internal.clone(),
         write_index: 2,
+        published_index: 1,
     };
     let reader = Reader {
         internal,
         read_index: 0,
+        #[cfg(feature = "std")]
+        log: None,
     };
 
     (writer, reader)
@@ -203,6 +429,80 @@ mod test {
         assert_eq!(reader.read(), &567);
     }
 
+    #[test]
+    fn last_written() {
+        let (mut writer, mut reader) = triple_buffer(1);
+        assert_eq!(writer.last_written(), &1);
+
+        writer.write(2);
+        assert_eq!(writer.last_written(), &2);
+        assert_eq!(reader.read(), &2);
+
+        *writer.get_mut() = 3;
+        assert_eq!(writer.last_written(), &3);
+        writer.write(4);
+        assert_eq!(writer.last_written(), &4);
+        assert_eq!(reader.read(), &4);
+        assert_eq!(writer.last_written(), &4);
+    }
+
+    #[cfg(all(debug_assertions, feature = "std"))]
+    #[test]
+    fn long_read_guard() {
+        use crate::rtlog;
+
+        let (log, mut drain) = rtlog::logger(4);
+        let (mut writer, reader) = triple_buffer(0);
+        let mut reader = reader.with_log(log);
+
+        {
+            let guard = reader.read_guard();
+            writer.write(1);
+            assert_eq!(*guard, 0);
+        }
+        assert!(drain.try_recv().is_none());
+
+        {
+            let _guard = reader.read_guard();
+            writer.write(2);
+            writer.write(3);
+        }
+        let record = drain.try_recv().unwrap();
+        assert_eq!(record.level, rtlog::Level::Warn);
+        assert_eq!(
+            record.message,
+            "triple buffer read guard held across 2 commits"
+        );
+        assert_eq!(*reader.read_guard(), 3);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn read_fresh() {
+        use std::thread;
+
+        let (mut writer, mut reader) = triple_buffer(0);
+        let max_age = Duration::from_millis(50);
+        assert_eq!(reader.read_fresh(max_age), Ok(&0));
+
+        thread::sleep(Duration::from_millis(60));
+        assert!(reader.read_fresh(max_age).unwrap_err().age >= Duration::from_millis(60));
+
+        writer.write(1);
+        assert_eq!(reader.read_fresh(max_age), Ok(&1));
+        *writer.get_mut() = 2;
+        assert_eq!(reader.read_fresh(max_age), Ok(&2));
+    }
+
+    #[test]
+    fn poisoned() {
+        let (writer, reader) = triple_buffer(1);
+        assert_eq!(reader.check_poisoned(), Ok(()));
+
+        writer.poison_flag().poison();
+        assert_eq!(reader.check_poisoned(), Err(Poisoned));
+    }
+
     mod drop {
         use super::*;
 
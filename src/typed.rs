@@ -0,0 +1,166 @@
+use std::fmt;
+use std::marker::PhantomData;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::framed::{self, FrameReceiver, FrameSender};
+
+// Typed messages over the framed byte ring, serialized with postcard. Both
+// ends own a scratch buffer of `max_message_len` bytes allocated up front,
+// so encoding never allocates; whether decoding does depends on `T`.
+#[derive(Debug)]
+pub enum TypedError {
+    Encode(postcard::Error),
+    Decode(postcard::Error),
+    // No room in the ring for the encoded message right now.
+    Full,
+    // The queued frame is larger than the receiver's scratch buffer.
+    TooLarge(usize),
+}
+
+impl fmt::Display for TypedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TypedError::Encode(e) => write!(f, "encoding failed: {}", e),
+            TypedError::Decode(e) => write!(f, "decoding failed: {}", e),
+            TypedError::Full => f.write_str("ring full"),
+            TypedError::TooLarge(len) => write!(f, "frame of {} bytes too large", len),
+        }
+    }
+}
+
+impl std::error::Error for TypedError {}
+
+pub struct TypedSender<T> {
+    frames: FrameSender,
+    scratch: Vec<u8>,
+    _marker: PhantomData<fn(T)>,
+}
+
+pub struct TypedReceiver<T> {
+    frames: FrameReceiver,
+    scratch: Vec<u8>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T: Serialize> TypedSender<T> {
+    pub fn new(frames: FrameSender, max_message_len: usize) -> Self {
+        TypedSender {
+            frames,
+            scratch: vec![0; max_message_len],
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn try_send(&mut self, value: &T) -> Result<(), TypedError> {
+        let encoded = postcard::to_slice(value, &mut self.scratch).map_err(TypedError::Encode)?;
+
+        if self.frames.send_frame(encoded) {
+            Ok(())
+        } else {
+            Err(TypedError::Full)
+        }
+    }
+}
+
+impl<T: DeserializeOwned> TypedReceiver<T> {
+    pub fn new(frames: FrameReceiver, max_message_len: usize) -> Self {
+        TypedReceiver {
+            frames,
+            scratch: vec![0; max_message_len],
+            _marker: PhantomData,
+        }
+    }
+
+    // `Ok(None)` when nothing is queued. A message that fails to decode is
+    // still consumed.
+    pub fn try_recv(&mut self) -> Result<Option<T>, TypedError> {
+        let len = match self.frames.next_frame_len() {
+            Some(len) => len,
+            None => return Ok(None),
+        };
+        if len > self.scratch.len() {
+            self.frames.skip_frame();
+            return Err(TypedError::TooLarge(len));
+        }
+
+        self.frames.recv_frame(&mut self.scratch);
+        postcard::from_bytes(&self.scratch[..len])
+            .map(Some)
+            .map_err(TypedError::Decode)
+    }
+}
+
+pub fn typed_channel<T: Serialize + DeserializeOwned>(
+    capacity: usize,
+    max_message_len: usize,
+) -> (TypedSender<T>, TypedReceiver<T>) {
+    let (sender, receiver) = framed::frame_ring(capacity);
+    (
+        TypedSender::new(sender, max_message_len),
+        TypedReceiver::new(receiver, max_message_len),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Control {
+        SetGain(f32),
+        Load { slot: u8, name: String },
+    }
+
+    #[test]
+    fn round_trip() {
+        let (mut send, mut recv) = typed_channel(64, 32);
+
+        send.try_send(&Control::SetGain(0.5)).unwrap();
+        send.try_send(&Control::Load {
+            slot: 2,
+            name: "kick".into(),
+        })
+        .unwrap();
+
+        assert_eq!(recv.try_recv().unwrap(), Some(Control::SetGain(0.5)));
+        assert_eq!(
+            recv.try_recv().unwrap(),
+            Some(Control::Load {
+                slot: 2,
+                name: "kick".into()
+            })
+        );
+        assert_eq!(recv.try_recv().unwrap(), None);
+    }
+
+    #[test]
+    fn oversized_message() {
+        let (mut send, _recv) = typed_channel::<Control>(64, 4);
+        let result = send.try_send(&Control::Load {
+            slot: 0,
+            name: "too long".into(),
+        });
+        assert!(matches!(result, Err(TypedError::Encode(_))));
+    }
+
+    #[test]
+    fn skips_too_large() {
+        let (frames_send, frames_recv) = framed::frame_ring(64);
+        let mut send = TypedSender::new(frames_send, 32);
+        let mut recv = TypedReceiver::<Control>::new(frames_recv, 8);
+
+        send.try_send(&Control::Load {
+            slot: 0,
+            name: "much too long".into(),
+        })
+        .unwrap();
+        send.try_send(&Control::SetGain(1.0)).unwrap();
+
+        assert!(matches!(recv.try_recv(), Err(TypedError::TooLarge(_))));
+        assert_eq!(recv.try_recv().unwrap(), Some(Control::SetGain(1.0)));
+    }
+}
@@ -0,0 +1,290 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::triple_buffer::{COMMIT_BIT, INDEX_MASK};
+
+// Exhaustive check of the triple buffer's index protocol, for auditing the
+// unsafe code in `triple_buffer` without trusting a test run to hit the
+// rare interleavings. The writer and reader are modelled as small programs
+// whose steps are the individual atomic operations on `committed` plus the
+// buffer accesses between them, and every interleaving of those steps is
+// explored, up to `commits` writes and `reads` reads.
+//
+// Each step is taken as sequentially consistent, so this covers the
+// protocol (who owns which buffer, and what the reader gets to see), not
+// the memory orderings chosen for the atomics.
+//
+// At every state it checks that:
+// - the writer's buffer, the committed buffer and the reader's buffer are
+//   three different buffers, so neither side ever touches a buffer the
+//   other is using;
+// - the writer's last published buffer isn't the one it writes next;
+// - the reader never sees an older value than it saw before, and a read
+//   after the commit bit was seen returns the newest commit.
+#[derive(Clone, Copy, Debug)]
+pub struct Model {
+    pub commits: u8,
+    pub reads: u8,
+    // Deliberately broken variant for testing the checker: the reader swaps
+    // without checking the commit bit first.
+    pub skip_commit_check: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    WriterFill,
+    WriterCommit,
+    ReaderLoad,
+    ReaderSwap,
+    ReaderRead,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Violation {
+    pub message: &'static str,
+    // The steps from the initial state to the violation.
+    pub trace: Vec<Step>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Report {
+    pub states: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum WriterPc {
+    Fill,
+    Commit,
+    Done,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum ReaderPc {
+    Load,
+    Swap,
+    Read,
+    Done,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct State {
+    committed: usize,
+    // The version of the value in each buffer; 0 for the initial value.
+    versions: [u8; 3],
+    writer: WriterPc,
+    write_index: usize,
+    published_index: usize,
+    commits: u8,
+    reader: ReaderPc,
+    read_index: usize,
+    reads: u8,
+    // Newest version the reader has seen.
+    seen: u8,
+    // Newest version committed when the reader last swapped; its next read
+    // must return at least this.
+    expected: u8,
+}
+
+impl Model {
+    pub fn new(commits: u8, reads: u8) -> Self {
+        Model {
+            commits,
+            reads,
+            skip_commit_check: false,
+        }
+    }
+
+    pub fn check(&self) -> Result<Report, Violation> {
+        // Same initial layout as `triple_buffer_explicit`.
+        let initial = State {
+            committed: 1,
+            versions: [0; 3],
+            writer: self.writer_start(),
+            write_index: 2,
+            published_index: 1,
+            commits: 0,
+            reader: self.reader_start(),
+            read_index: 0,
+            reads: 0,
+            seen: 0,
+            expected: 0,
+        };
+
+        let mut visited = HashSet::new();
+        let mut trace = Vec::new();
+        self.explore(initial, &mut visited, &mut trace)?;
+        Ok(Report {
+            states: visited.len(),
+        })
+    }
+
+    fn explore(
+        &self,
+        state: State,
+        visited: &mut HashSet<State>,
+        trace: &mut Vec<Step>,
+    ) -> Result<(), Violation> {
+        if !visited.insert(state) {
+            return Ok(());
+        }
+        if let Err(message) = invariants(&state) {
+            return Err(Violation {
+                message,
+                trace: trace.clone(),
+            });
+        }
+
+        for step in [
+            Step::WriterFill,
+            Step::WriterCommit,
+            Step::ReaderLoad,
+            Step::ReaderSwap,
+            Step::ReaderRead,
+        ] {
+            let next = match self.apply(state, step) {
+                Ok(Some(next)) => next,
+                Ok(None) => continue,
+                Err(message) => {
+                    trace.push(step);
+                    return Err(Violation {
+                        message,
+                        trace: trace.clone(),
+                    });
+                }
+            };
+
+            trace.push(step);
+            self.explore(next, visited, trace)?;
+            trace.pop();
+        }
+        Ok(())
+    }
+
+    // The state after `step`, `None` if it isn't enabled.
+    fn apply(&self, mut s: State, step: Step) -> Result<Option<State>, &'static str> {
+        match (step, s.writer, s.reader) {
+            (Step::WriterFill, WriterPc::Fill, _) => {
+                s.versions[s.write_index] = s.commits + 1;
+                s.writer = WriterPc::Commit;
+            }
+            (Step::WriterCommit, WriterPc::Commit, _) => {
+                let last_committed = s.committed;
+                s.committed = s.write_index | COMMIT_BIT;
+                s.published_index = s.write_index;
+                s.write_index = last_committed & INDEX_MASK;
+                s.commits += 1;
+                s.writer = self.writer_next(s.commits);
+            }
+            (Step::ReaderLoad, _, ReaderPc::Load) => {
+                s.reader = if s.committed & COMMIT_BIT != 0 {
+                    ReaderPc::Swap
+                } else {
+                    ReaderPc::Read
+                };
+            }
+            (Step::ReaderSwap, _, ReaderPc::Swap) => {
+                let last_committed = s.committed;
+                s.committed = s.read_index;
+                s.read_index = last_committed & INDEX_MASK;
+                s.expected = s.commits;
+                s.reader = ReaderPc::Read;
+            }
+            (Step::ReaderRead, _, ReaderPc::Read) => {
+                let version = s.versions[s.read_index];
+                if version < s.seen {
+                    return Err("reader went back to an older value");
+                }
+                if version < s.expected {
+                    return Err("reader missed the newest commit");
+                }
+                s.seen = version;
+                s.reads += 1;
+                s.reader = self.reader_next(s.reads);
+            }
+            _ => return Ok(None),
+        }
+        Ok(Some(s))
+    }
+
+    fn writer_start(&self) -> WriterPc {
+        self.writer_next(0)
+    }
+
+    fn writer_next(&self, commits: u8) -> WriterPc {
+        if commits < self.commits {
+            WriterPc::Fill
+        } else {
+            WriterPc::Done
+        }
+    }
+
+    fn reader_start(&self) -> ReaderPc {
+        self.reader_next(0)
+    }
+
+    fn reader_next(&self, reads: u8) -> ReaderPc {
+        match (reads < self.reads, self.skip_commit_check) {
+            (false, _) => ReaderPc::Done,
+            (true, false) => ReaderPc::Load,
+            (true, true) => ReaderPc::Swap,
+        }
+    }
+}
+
+fn invariants(s: &State) -> Result<(), &'static str> {
+    let committed = s.committed & INDEX_MASK;
+    if s.write_index == committed || s.write_index == s.read_index || committed == s.read_index {
+        return Err("two sides own the same buffer");
+    }
+    if s.writer != WriterPc::Done && s.published_index == s.write_index {
+        return Err("writer would overwrite its published value");
+    }
+    Ok(())
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} after {:?}", self.message, self.trace)
+    }
+}
+
+impl std::error::Error for Violation {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn small_model() {
+        let report = Model::new(2, 2).check().unwrap();
+        assert!(report.states > 10);
+    }
+
+    #[test]
+    fn finds_broken_reader() {
+        let mut model = Model::new(2, 3);
+        model.skip_commit_check = true;
+        let violation = model.check().unwrap_err();
+        assert_eq!(violation.message, "reader went back to an older value");
+        assert_eq!(violation.trace.last(), Some(&Step::ReaderRead));
+    }
+
+    // The exhaustive runs over larger bounds; run them with `--ignored`.
+    #[test]
+    #[ignore]
+    fn triple_buffer_protocol() {
+        for commits in 0..=6 {
+            for reads in 0..=6 {
+                if let Err(violation) = Model::new(commits, reads).check() {
+                    panic!("{} commits, {} reads: {}", commits, reads, violation);
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn triple_buffer_protocol_long() {
+        Model::new(12, 12).check().unwrap();
+    }
+}
@@ -0,0 +1,118 @@
+use std::hint;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How a non-RT thread waits for a wait-free endpoint to become ready. None
+// of these involve the other side: the RT thread is never asked to wake
+// anybody up, so it keeps using the plain `try_*` calls.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WaitStrategy {
+    // Lowest latency, burns a core.
+    BusySpin,
+    // Spins `spins` times, then yields to the scheduler on every retry.
+    SpinThenYield { spins: u32 },
+    // Parks for `min`, doubling on every retry up to `max`.
+    Park { min: Duration, max: Duration },
+}
+
+impl Default for WaitStrategy {
+    fn default() -> Self {
+        WaitStrategy::Park {
+            min: Duration::from_micros(10),
+            max: Duration::from_millis(1),
+        }
+    }
+}
+
+impl WaitStrategy {
+    pub fn waiter(self) -> Waiter {
+        Waiter {
+            strategy: self,
+            attempt: 0,
+        }
+    }
+}
+
+// Backoff state for a single wait.
+pub struct Waiter {
+    strategy: WaitStrategy,
+    attempt: u32,
+}
+
+impl Waiter {
+    pub fn wait(&mut self) {
+        self.wait_capped(Duration::MAX);
+    }
+
+    // Like `wait`, but never parks past `deadline`. Returns false once the
+    // deadline has passed, without waiting.
+    pub fn wait_until(&mut self, deadline: Instant) -> bool {
+        let now = Instant::now();
+        if now >= deadline {
+            return false;
+        }
+        self.wait_capped(deadline - now);
+        true
+    }
+
+    fn wait_capped(&mut self, cap: Duration) {
+        #[cfg(feature = "sim")]
+        crate::sim::assert_not_stepping("Blocking wait");
+
+        match self.strategy {
+            WaitStrategy::BusySpin => hint::spin_loop(),
+            WaitStrategy::SpinThenYield { spins } => {
+                if self.attempt < spins {
+                    hint::spin_loop();
+                } else {
+                    thread::yield_now();
+                }
+            }
+            WaitStrategy::Park { min, max } => {
+                let shift = self.attempt.min(16);
+                thread::park_timeout((min * (1 << shift)).min(max).min(cap));
+            }
+        }
+        self.attempt = self.attempt.saturating_add(1);
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn park_backs_off_to_max() {
+        let mut waiter = WaitStrategy::Park {
+            min: Duration::from_micros(1),
+            max: Duration::from_millis(2),
+        }
+        .waiter();
+
+        for _ in 0..20 {
+            waiter.wait();
+        }
+
+        let start = Instant::now();
+        waiter.wait();
+        assert!(start.elapsed() >= Duration::from_millis(1));
+    }
+
+    #[test]
+    fn wait_until_caps_park() {
+        let mut waiter = WaitStrategy::Park {
+            min: Duration::from_secs(10),
+            max: Duration::from_secs(10),
+        }
+        .waiter();
+
+        let start = Instant::now();
+        assert!(waiter.wait_until(start + Duration::from_millis(5)));
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(!waiter.wait_until(start));
+    }
+}
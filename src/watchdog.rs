@@ -0,0 +1,333 @@
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[cfg(unix)]
+use std::io;
+#[cfg(unix)]
+use std::path::Path;
+
+use crate::intern::{Interner, Symbol};
+#[cfg(unix)]
+use crate::shm::SharedMapping;
+
+const BLOCK_START: u32 = 0;
+
+#[repr(C)]
+struct Shared {
+    progress: AtomicU64,
+    point: AtomicU32,
+}
+
+// The heartbeat state lives either in this process or in a shared memory
+// file watched by another process.
+#[derive(Clone)]
+enum Storage {
+    Local(Arc<Shared>),
+    #[cfg(unix)]
+    Mapped(Arc<SharedMapping>),
+}
+
+impl Deref for Storage {
+    type Target = Shared;
+
+    fn deref(&self) -> &Shared {
+        match self {
+            Storage::Local(shared) => shared,
+            #[cfg(unix)]
+            Storage::Mapped(mapping) => unsafe { &(*(mapping.as_ptr() as *const Header)).shared },
+        }
+    }
+}
+
+// RT side: call `beat` at the start of every block and `mark` at interesting
+// points inside it. Both are a pair of relaxed stores.
+pub struct Heartbeat {
+    shared: Storage,
+}
+
+// Monitor side, polled from a non-RT thread.
+pub struct Watchdog {
+    shared: Storage,
+    timeout: Duration,
+    points: Interner,
+    last_progress: u64,
+    last_change: Instant,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Alive,
+    // No progress for at least `stalled_for`. `after` is the last watchpoint
+    // passed, or `None` if the thread stalled before its first mark of the
+    // current block.
+    Stalled {
+        stalled_for: Duration,
+        after: Option<Symbol>,
+    },
+}
+
+impl Heartbeat {
+    pub fn beat(&self) {
+        self.shared.point.store(BLOCK_START, Ordering::Relaxed);
+        self.shared.progress.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn mark(&self, point: Symbol) {
+        self.shared
+            .point
+            .store(point.index() + 1, Ordering::Relaxed);
+        self.shared.progress.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl Watchdog {
+    // Registers a named watchpoint. Do this before handing the symbol to the
+    // RT thread.
+    pub fn watchpoint(&mut self, name: &str) -> Symbol {
+        self.points.intern(name)
+    }
+
+    pub fn point_name(&self, point: Symbol) -> Option<&str> {
+        self.points.resolve(point)
+    }
+
+    pub const fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn check(&mut self) -> Status {
+        self.check_at(Instant::now())
+    }
+
+    pub fn check_at(&mut self, now: Instant) -> Status {
+        let progress = self.shared.progress.load(Ordering::Relaxed);
+        if progress != self.last_progress {
+            self.last_progress = progress;
+            self.last_change = now;
+            return Status::Alive;
+        }
+
+        let stalled_for = now.saturating_duration_since(self.last_change);
+        if stalled_for < self.timeout {
+            return Status::Alive;
+        }
+
+        let after = match self.shared.point.load(Ordering::Relaxed) {
+            BLOCK_START => None,
+            point => Some(Symbol::from_index(point - 1)),
+        };
+
+        Status::Stalled { stalled_for, after }
+    }
+}
+
+pub fn watchdog(timeout: Duration) -> (Heartbeat, Watchdog) {
+    watchdog_at(timeout, Instant::now())
+}
+
+pub fn watchdog_at(timeout: Duration, now: Instant) -> (Heartbeat, Watchdog) {
+    let shared = Storage::Local(Arc::new(Shared {
+        progress: AtomicU64::new(0),
+        point: AtomicU32::new(BLOCK_START),
+    }));
+
+    (
+        Heartbeat {
+            shared: shared.clone(),
+        },
+        Watchdog::with_storage(shared, timeout, now),
+    )
+}
+
+impl Watchdog {
+    fn with_storage(shared: Storage, timeout: Duration, now: Instant) -> Self {
+        let last_progress = shared.progress.load(Ordering::Relaxed);
+
+        Watchdog {
+            shared,
+            timeout,
+            points: Interner::new(),
+            last_progress,
+            last_change: now,
+        }
+    }
+}
+
+// Cross-process heartbeats. The RT process creates the heartbeat file and
+// beats into it; a separate monitor process opens the same file and can
+// detect a hung process even when the whole process is wedged. Watchpoint
+// symbols are plain indices, so the monitor must register the same names in
+// the same order as the RT process to resolve them.
+#[cfg(unix)]
+const HEARTBEAT_MAGIC: u64 = 0x7274_6862_6561_7401;
+
+#[cfg(unix)]
+#[repr(C)]
+struct Header {
+    magic: AtomicU64,
+    pid: AtomicU32,
+    shared: Shared,
+}
+
+#[cfg(unix)]
+impl Heartbeat {
+    pub fn create_shared<P: AsRef<Path>>(path: P) -> io::Result<Heartbeat> {
+        let mapping = SharedMapping::create(path, std::mem::size_of::<Header>())?;
+        let header = unsafe { &*(mapping.as_ptr() as *const Header) };
+
+        header.pid.store(std::process::id(), Ordering::Relaxed);
+        header.magic.store(HEARTBEAT_MAGIC, Ordering::Release);
+
+        Ok(Heartbeat {
+            shared: Storage::Mapped(Arc::new(mapping)),
+        })
+    }
+}
+
+#[cfg(unix)]
+impl Watchdog {
+    pub fn open_shared<P: AsRef<Path>>(path: P, timeout: Duration) -> io::Result<Watchdog> {
+        let mapping = SharedMapping::open(path)?;
+
+        if mapping.len() < std::mem::size_of::<Header>() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "heartbeat file too small",
+            ));
+        }
+
+        let header = unsafe { &*(mapping.as_ptr() as *const Header) };
+        if header.magic.load(Ordering::Acquire) != HEARTBEAT_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a heartbeat file",
+            ));
+        }
+
+        Ok(Watchdog::with_storage(
+            Storage::Mapped(Arc::new(mapping)),
+            timeout,
+            Instant::now(),
+        ))
+    }
+
+    // Process id of the heartbeat's owner, for recovery actions. Only set for
+    // shared heartbeats.
+    pub fn pid(&self) -> Option<u32> {
+        match &self.shared {
+            Storage::Local(_) => None,
+            Storage::Mapped(mapping) => {
+                let header = unsafe { &*(mapping.as_ptr() as *const Header) };
+                Some(header.pid.load(Ordering::Relaxed))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn alive_while_beating() {
+        let start = Instant::now();
+        let (heartbeat, mut watchdog) = watchdog_at(ms(100), start);
+
+        for i in 1..10 {
+            heartbeat.beat();
+            assert_eq!(watchdog.check_at(start + ms(i * 80)), Status::Alive);
+        }
+    }
+
+    #[test]
+    fn stalls_without_beats() {
+        let start = Instant::now();
+        let (heartbeat, mut watchdog) = watchdog_at(ms(100), start);
+
+        heartbeat.beat();
+        assert_eq!(watchdog.check_at(start + ms(10)), Status::Alive);
+        assert_eq!(watchdog.check_at(start + ms(50)), Status::Alive);
+        assert_eq!(
+            watchdog.check_at(start + ms(150)),
+            Status::Stalled {
+                stalled_for: ms(140),
+                after: None
+            }
+        );
+    }
+
+    #[test]
+    fn reports_last_watchpoint() {
+        let start = Instant::now();
+        let (heartbeat, mut watchdog) = watchdog_at(ms(100), start);
+        let mix = watchdog.watchpoint("mix");
+        let reverb = watchdog.watchpoint("reverb");
+
+        heartbeat.beat();
+        heartbeat.mark(mix);
+        heartbeat.mark(reverb);
+        watchdog.check_at(start);
+
+        match watchdog.check_at(start + ms(200)) {
+            Status::Stalled { after, .. } => {
+                assert_eq!(after, Some(reverb));
+                assert_eq!(watchdog.point_name(after.unwrap()), Some("reverb"));
+            }
+            status => panic!("unexpected {:?}", status),
+        }
+
+        heartbeat.beat();
+        assert_eq!(watchdog.check_at(start + ms(210)), Status::Alive);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shared_heartbeat() {
+        let path = crate::shm::temp_path("heartbeat");
+        let heartbeat = Heartbeat::create_shared(&path).unwrap();
+        let mut watchdog = Watchdog::open_shared(&path, ms(100)).unwrap();
+        let mix = watchdog.watchpoint("mix");
+        assert_eq!(watchdog.pid(), Some(std::process::id()));
+
+        let start = Instant::now();
+        heartbeat.beat();
+        heartbeat.mark(mix);
+        assert_eq!(watchdog.check_at(start), Status::Alive);
+        assert_eq!(
+            watchdog.check_at(start + ms(150)),
+            Status::Stalled {
+                stalled_for: ms(150),
+                after: Some(mix)
+            }
+        );
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shared_rejects_foreign_file() {
+        let path = crate::shm::temp_path("not-heartbeat");
+        std::fs::write(&path, vec![0u8; 64]).unwrap();
+        assert!(Watchdog::open_shared(&path, ms(100)).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn marks_count_as_progress() {
+        let start = Instant::now();
+        let (heartbeat, mut watchdog) = watchdog_at(ms(100), start);
+        let slow = watchdog.watchpoint("slow");
+
+        heartbeat.beat();
+        watchdog.check_at(start);
+        heartbeat.mark(slow);
+        assert_eq!(watchdog.check_at(start + ms(150)), Status::Alive);
+    }
+}
@@ -0,0 +1,460 @@
+use std::any::Any;
+use std::error;
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem;
+use std::sync::Arc;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::meter::{Ewma, PeakHold};
+use crate::param_bank::{self, ParamReader, ParamWriter};
+use crate::spsc;
+use crate::triple_buffer::{self, Reader, Writer};
+
+// Declares all the plumbing between an engine's control and RT threads in
+// one place. Every declaration returns a typed `Handle`; after `build`, the
+// RT-side endpoint of each primitive is taken out of the `RtBundle` and the
+// control-side endpoint out of the `ControlBundle` with that handle.
+//
+// An optional memory budget bounds the total memory preallocated for queue
+// slots, buffer copies and meters; `build` fails instead of handing out
+// plumbing that exceeds it.
+#[derive(Default)]
+pub struct EngineWiring {
+    budget: Option<usize>,
+    entries: Vec<Entry>,
+}
+
+struct Entry {
+    name: String,
+    bytes: usize,
+    rt: Option<Box<dyn Any + Send>>,
+    control: Option<Box<dyn Any + Send>>,
+}
+
+pub struct Handle<R, C> {
+    index: usize,
+    _endpoints: PhantomData<fn() -> (R, C)>,
+}
+
+impl<R, C> Clone for Handle<R, C> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<R, C> Copy for Handle<R, C> {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WiringError {
+    OverBudget { required: usize, budget: usize },
+    DuplicateName(String),
+    UnknownType(String),
+}
+
+// Declarative description of an engine's queues, e.g. loaded from a project
+// file. Queue element types are given as tags naming primitive types
+// ("f32", "u8", ...). Endpoints built from a spec are taken out of the
+// bundles by name with `take_named`.
+#[cfg(feature = "serde")]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WiringSpec {
+    #[serde(default)]
+    pub memory_budget: Option<usize>,
+    pub channels: Vec<ChannelSpec>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChannelSpec {
+    pub name: String,
+    pub direction: Direction,
+    #[serde(rename = "type")]
+    pub type_tag: String,
+    pub capacity: usize,
+    #[serde(default)]
+    pub policy: Policy,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    ToRt,
+    FromRt,
+}
+
+// What happens when a queue is full.
+#[cfg(feature = "serde")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Policy {
+    // `try_send` fails and the sender keeps the value.
+    #[default]
+    RejectNew,
+}
+
+pub struct RtBundle {
+    entries: Vec<(String, Option<Box<dyn Any + Send>>)>,
+}
+
+pub struct ControlBundle {
+    entries: Vec<(String, Option<Box<dyn Any + Send>>)>,
+}
+
+impl EngineWiring {
+    pub fn new() -> Self {
+        EngineWiring::default()
+    }
+
+    pub fn memory_budget(mut self, bytes: usize) -> Self {
+        self.budget = Some(bytes);
+        self
+    }
+
+    // Commands flowing from the control thread to the RT thread.
+    pub fn queue_to_rt<T: Send + 'static>(
+        &mut self,
+        name: &str,
+        capacity: usize,
+    ) -> Handle<spsc::Receiver<T>, spsc::Sender<T>> {
+        let (sender, receiver) = spsc::channel(capacity);
+        self.add(name, queue_bytes::<T>(capacity), receiver, sender)
+    }
+
+    // Events flowing from the RT thread back to the control thread.
+    pub fn queue_from_rt<T: Send + 'static>(
+        &mut self,
+        name: &str,
+        capacity: usize,
+    ) -> Handle<spsc::Sender<T>, spsc::Receiver<T>> {
+        let (sender, receiver) = spsc::channel(capacity);
+        self.add(name, queue_bytes::<T>(capacity), sender, receiver)
+    }
+
+    // Latest-value state published by the control thread, read by RT.
+    pub fn triple_buffer<T: Clone + Send + 'static>(
+        &mut self,
+        name: &str,
+        initial: T,
+    ) -> Handle<Reader<T>, Writer<T>> {
+        let (writer, reader) = triple_buffer::triple_buffer(initial);
+        self.add(name, 3 * mem::size_of::<T>(), reader, writer)
+    }
+
+    pub fn param_bank<const N: usize>(
+        &mut self,
+        name: &str,
+        initial: [f32; N],
+    ) -> Handle<ParamReader<N>, ParamWriter<N>> {
+        let (writer, reader) = param_bank::param_bank(initial);
+        self.add(name, 4 * mem::size_of::<[f32; N]>(), reader, writer)
+    }
+
+    // Meters are fed by the RT thread and read by the UI.
+    pub fn peak_meter(
+        &mut self,
+        name: &str,
+        hold: u32,
+        decay: f32,
+    ) -> Handle<Arc<PeakHold>, Arc<PeakHold>> {
+        let meter = Arc::new(PeakHold::new(hold, decay));
+        self.add(name, mem::size_of::<PeakHold>(), meter.clone(), meter)
+    }
+
+    pub fn ewma_meter(&mut self, name: &str, alpha: f32) -> Handle<Arc<Ewma>, Arc<Ewma>> {
+        let meter = Arc::new(Ewma::new(alpha, 0.0));
+        self.add(name, mem::size_of::<Ewma>(), meter.clone(), meter)
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn from_spec(spec: &WiringSpec) -> Result<Self, WiringError> {
+        let mut wiring = EngineWiring::new();
+        wiring.budget = spec.memory_budget;
+
+        for channel in &spec.channels {
+            macro_rules! queue {
+                ($t:ty) => {
+                    match channel.direction {
+                        Direction::ToRt => {
+                            wiring.queue_to_rt::<$t>(&channel.name, channel.capacity);
+                        }
+                        Direction::FromRt => {
+                            wiring.queue_from_rt::<$t>(&channel.name, channel.capacity);
+                        }
+                    }
+                };
+            }
+
+            // Exhaustive so that new policies have to be wired up here.
+            match channel.policy {
+                Policy::RejectNew => {}
+            }
+
+            match channel.type_tag.as_str() {
+                "u8" => queue!(u8),
+                "u16" => queue!(u16),
+                "u32" => queue!(u32),
+                "u64" => queue!(u64),
+                "i8" => queue!(i8),
+                "i16" => queue!(i16),
+                "i32" => queue!(i32),
+                "i64" => queue!(i64),
+                "f32" => queue!(f32),
+                "f64" => queue!(f64),
+                "bool" => queue!(bool),
+                other => return Err(WiringError::UnknownType(other.to_owned())),
+            }
+        }
+
+        Ok(wiring)
+    }
+
+    pub fn preallocated_bytes(&self) -> usize {
+        self.entries.iter().map(|e| e.bytes).sum()
+    }
+
+    pub fn build(self) -> Result<(RtBundle, ControlBundle), WiringError> {
+        for (i, entry) in self.entries.iter().enumerate() {
+            if self.entries[..i].iter().any(|e| e.name == entry.name) {
+                return Err(WiringError::DuplicateName(entry.name.clone()));
+            }
+        }
+
+        let required = self.preallocated_bytes();
+        if let Some(budget) = self.budget {
+            if required > budget {
+                return Err(WiringError::OverBudget { required, budget });
+            }
+        }
+
+        let mut rt = Vec::with_capacity(self.entries.len());
+        let mut control = Vec::with_capacity(self.entries.len());
+        for entry in self.entries {
+            rt.push((entry.name.clone(), entry.rt));
+            control.push((entry.name, entry.control));
+        }
+
+        Ok((RtBundle { entries: rt }, ControlBundle { entries: control }))
+    }
+
+    fn add<R: Send + 'static, C: Send + 'static>(
+        &mut self,
+        name: &str,
+        bytes: usize,
+        rt: R,
+        control: C,
+    ) -> Handle<R, C> {
+        self.entries.push(Entry {
+            name: name.to_owned(),
+            bytes,
+            rt: Some(Box::new(rt)),
+            control: Some(Box::new(control)),
+        });
+
+        Handle {
+            index: self.entries.len() - 1,
+            _endpoints: PhantomData,
+        }
+    }
+}
+
+// Matches the slot count `spsc::channel` allocates.
+fn queue_bytes<T>(capacity: usize) -> usize {
+    capacity.next_power_of_two() * mem::size_of::<T>()
+}
+
+fn take_named<T: 'static>(entries: &mut [(String, Option<Box<dyn Any + Send>>)], name: &str) -> T {
+    let index = entries
+        .iter()
+        .position(|(n, _)| n == name)
+        .unwrap_or_else(|| panic!("No endpoint named {}", name));
+    take(entries, index)
+}
+
+fn take<T: 'static>(entries: &mut [(String, Option<Box<dyn Any + Send>>)], index: usize) -> T {
+    let (name, slot) = &mut entries[index];
+    let endpoint = slot
+        .take()
+        .unwrap_or_else(|| panic!("Endpoint {} already taken", name));
+
+    *endpoint
+        .downcast()
+        .unwrap_or_else(|_| panic!("Handle for {} used with a different wiring", name))
+}
+
+impl RtBundle {
+    pub fn take<R: 'static, C>(&mut self, handle: Handle<R, C>) -> R {
+        take(&mut self.entries, handle.index)
+    }
+
+    pub fn take_named<R: 'static>(&mut self, name: &str) -> R {
+        take_named(&mut self.entries, name)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.iter().all(|(_, e)| e.is_none())
+    }
+}
+
+impl ControlBundle {
+    pub fn take<R, C: 'static>(&mut self, handle: Handle<R, C>) -> C {
+        take(&mut self.entries, handle.index)
+    }
+
+    pub fn take_named<C: 'static>(&mut self, name: &str) -> C {
+        take_named(&mut self.entries, name)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.iter().all(|(_, e)| e.is_none())
+    }
+}
+
+impl fmt::Display for WiringError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WiringError::OverBudget { required, budget } => write!(
+                f,
+                "engine wiring needs {} bytes, over the budget of {}",
+                required, budget
+            ),
+            WiringError::DuplicateName(name) => write!(f, "duplicate wiring name {:?}", name),
+            WiringError::UnknownType(tag) => write!(f, "unknown channel type {:?}", tag),
+        }
+    }
+}
+
+impl error::Error for WiringError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::thread;
+
+    #[test]
+    fn split_bundles() {
+        let mut wiring = EngineWiring::new();
+        let commands = wiring.queue_to_rt::<u32>("commands", 8);
+        let events = wiring.queue_from_rt::<u32>("events", 8);
+        let gain = wiring.triple_buffer("gain", 1.0f32);
+        let level = wiring.peak_meter("level", 0, 0.5);
+
+        let (mut rt, mut control) = wiring.build().unwrap();
+
+        let rt_thread = thread::spawn(move || {
+            let mut commands = rt.take(commands);
+            let mut events = rt.take(events);
+            let mut gain = rt.take(gain);
+            let level = rt.take(level);
+            assert!(rt.is_empty());
+
+            while commands.try_recv().is_err() {
+                thread::yield_now();
+            }
+            level.update(0.75);
+            events.try_send((*gain.read() * 10.0) as u32).unwrap();
+        });
+
+        let mut gain = control.take(gain);
+        gain.write(2.0);
+        control.take(commands).try_send(1).unwrap();
+        rt_thread.join().unwrap();
+
+        assert_eq!(control.take(events).try_recv(), Ok(20));
+        assert_eq!(control.take(level).get(), 0.75);
+        assert!(control.is_empty());
+    }
+
+    #[test]
+    fn budget() {
+        let mut wiring = EngineWiring::new().memory_budget(64);
+        wiring.queue_to_rt::<u64>("small", 3);
+        assert_eq!(wiring.preallocated_bytes(), 32);
+        assert!(wiring.build().is_ok());
+
+        let mut wiring = EngineWiring::new().memory_budget(64);
+        wiring.queue_to_rt::<u64>("small", 3);
+        wiring.triple_buffer("big", [0u8; 16]);
+        assert_eq!(
+            wiring.build().err(),
+            Some(WiringError::OverBudget {
+                required: 80,
+                budget: 64
+            })
+        );
+    }
+
+    #[test]
+    fn duplicate_names() {
+        let mut wiring = EngineWiring::new();
+        wiring.queue_to_rt::<u8>("midi", 4);
+        wiring.queue_from_rt::<u8>("midi", 4);
+        assert_eq!(
+            wiring.build().err(),
+            Some(WiringError::DuplicateName("midi".to_owned()))
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn take_twice() {
+        let mut wiring = EngineWiring::new();
+        let params = wiring.param_bank("params", [0.0; 4]);
+        let (mut rt, _control) = wiring.build().unwrap();
+        rt.take(params);
+        rt.take(params);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn from_spec() {
+        let spec: WiringSpec = serde_json::from_str(
+            r#"{
+                "memory_budget": 4096,
+                "channels": [
+                    {"name": "midi_in", "direction": "to_rt", "type": "u32", "capacity": 64},
+                    {"name": "levels", "direction": "from_rt", "type": "f32", "capacity": 16,
+                     "policy": "reject_new"}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let wiring = EngineWiring::from_spec(&spec).unwrap();
+        assert_eq!(wiring.preallocated_bytes(), 64 * 4 + 16 * 4);
+        let (mut rt, mut control) = wiring.build().unwrap();
+
+        let mut midi_in = control.take_named::<spsc::Sender<u32>>("midi_in");
+        let mut rt_midi_in = rt.take_named::<spsc::Receiver<u32>>("midi_in");
+        midi_in.try_send(0x90).unwrap();
+        assert_eq!(rt_midi_in.try_recv(), Ok(0x90));
+
+        let mut levels = rt.take_named::<spsc::Sender<f32>>("levels");
+        levels.try_send(0.5).unwrap();
+        let mut control_levels = control.take_named::<spsc::Receiver<f32>>("levels");
+        assert_eq!(control_levels.try_recv(), Ok(0.5));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn spec_errors() {
+        let spec = WiringSpec {
+            memory_budget: None,
+            channels: vec![ChannelSpec {
+                name: "blob".to_owned(),
+                direction: Direction::ToRt,
+                type_tag: "String".to_owned(),
+                capacity: 4,
+                policy: Policy::RejectNew,
+            }],
+        };
+        assert_eq!(
+            EngineWiring::from_spec(&spec).err(),
+            Some(WiringError::UnknownType("String".to_owned()))
+        );
+    }
+}